@@ -0,0 +1,165 @@
+//! Benchmarks of the hot paths of multi-GB syncs: bz2 decoding, listing parsing and href normalization
+//! The program is a binary, so the modules under test are compiled into the benchmark from their files
+//!
+//! Run with `cargo bench` (add `--features zstd,lzma` to build the other decoders too)
+
+use bzip2::{write::BzEncoder, Compression};
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use select::{document::Document, predicate::Name};
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::PathBuf,
+};
+use url::Url;
+
+#[allow(dead_code)]
+#[path = "../src/bz2_file.rs"]
+mod bz2_file;
+#[allow(dead_code)]
+#[path = "../src/decoder.rs"]
+mod decoder;
+#[allow(dead_code)]
+#[path = "../src/href.rs"]
+mod href;
+#[cfg(feature = "lzma")]
+#[allow(dead_code)]
+#[path = "../src/xz_file.rs"]
+mod xz_file;
+#[cfg(feature = "zstd")]
+#[allow(dead_code)]
+#[path = "../src/zstd_file.rs"]
+mod zstd_file;
+
+/// Sizes of the decoded files (a small material, an average map, a large map)
+const DECODED_SIZES: &[usize] = &[256 * 1024, 8 * 1024 * 1024, 64 * 1024 * 1024];
+/// Number of links in the generated listing (the maps directory of a large fastdl)
+const LISTING_LINKS: usize = 5000;
+
+/// Returns `len` bytes that compress about as well as a BSP (repetitive lumps with noise in between)
+fn map_like_bytes(len: usize) -> Vec<u8> {
+    let mut state = 0x2545_f491_u32;
+    (0..len)
+        .map(|i| {
+            // xorshift noise every few bytes, runs of the same lump data otherwise
+            if i % 7 == 0 {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            } else {
+                (i / 64) as u8
+            }
+        })
+        .collect()
+}
+
+/// Writes the bz2 copy of a `len` byte file to the temporary directory, returning its path
+fn write_bz2(len: usize) -> io::Result<PathBuf> {
+    let path = std::env::temp_dir().join(format!("fastdl_bench_{}.bsp.bz2", len));
+    let mut encoder = BzEncoder::new(File::create(&path)?, Compression::default());
+    encoder.write_all(&map_like_bytes(len))?;
+    encoder.finish()?;
+
+    Ok(path)
+}
+
+/// Returns an Apache-style listing of `links` maps, with the entities and relative hrefs real listings have
+fn listing(links: usize) -> String {
+    let mut html = String::from(
+        "<html><head><title>Index of /fastdl/cstrike/maps</title></head><body><h1>Index of /fastdl/cstrike/maps</h1><table>\
+         <tr><td><a href=\"?C=N;O=D\">Name</a></td></tr><tr><td><a href=\"../\">Parent Directory</a></td></tr>",
+    );
+    for i in 0..links {
+        html.push_str(&format!(
+            "<tr><td><a href=\"./ze_map&amp;friends_v{}.bsp.bz2\">ze_map&amp;friends_v{}.bsp.bz2</a></td>\
+             <td align=\"right\">2023-08-14 10:20</td><td align=\"right\">{}M</td></tr>",
+            i,
+            i,
+            i % 200
+        ));
+    }
+    html.push_str("</table></body></html>");
+
+    html
+}
+
+fn bench_decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("bz2_decode");
+    group.sample_size(10);
+
+    for &len in DECODED_SIZES {
+        let path = write_bz2(len).expect("Failed to write the bz2 sample");
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::from_parameter(len), &path, |b, path| {
+            b.iter(|| {
+                let mut file = decoder::Format::Bz2
+                    .decoder(File::open(path).unwrap())
+                    .unwrap();
+                file.decode_into(&mut io::sink(), &mut |n| {
+                    black_box(n);
+                })
+            })
+        });
+        let _ = fs::remove_file(&path);
+    }
+
+    group.finish();
+}
+
+fn bench_listing(c: &mut Criterion) {
+    let html = listing(LISTING_LINKS);
+    let mut group = c.benchmark_group("listing");
+    group.throughput(Throughput::Elements(LISTING_LINKS as u64));
+
+    group.bench_function("parse", |b| {
+        b.iter(|| {
+            Document::from(black_box(html.as_str()))
+                .find(Name("a"))
+                .filter_map(|node| node.attr("href"))
+                .count()
+        })
+    });
+
+    group.finish();
+}
+
+fn bench_hrefs(c: &mut Criterion) {
+    let root_url = Url::parse("https://fastdl.gflclan.com/gflfastdlv2/cstrike/").unwrap();
+    let dir_url = root_url.join("maps/").unwrap();
+    let hrefs = (0..LISTING_LINKS)
+        .map(|i| match i % 4 {
+            0 => format!("./ze_map&amp;friends_v{}.bsp.bz2", i),
+            1 => format!("ze_map%20v{}.bsp.bz2#top", i),
+            2 => format!("../maps/./ze_map_v{}.bsp.bz2?C=M;O=A", i),
+            _ => format!("/gflfastdlv2/cstrike/maps/ze_map&#39;s_v{}.bsp.bz2", i),
+        })
+        .collect::<Vec<_>>();
+
+    let mut group = c.benchmark_group("hrefs");
+    group.throughput(Throughput::Elements(hrefs.len() as u64));
+
+    group.bench_function("decode_entities", |b| {
+        b.iter(|| {
+            hrefs
+                .iter()
+                .map(|href| href::decode_entities(black_box(href)).len())
+                .sum::<usize>()
+        })
+    });
+    group.bench_function("resolve_href", |b| {
+        b.iter(|| {
+            hrefs
+                .iter()
+                .filter_map(|href| {
+                    href::resolve_href(&dir_url, &root_url, black_box(href), "gflfastdlv2")
+                })
+                .count()
+        })
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_decode, bench_listing, bench_hrefs);
+criterion_main!(benches);
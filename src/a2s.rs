@@ -0,0 +1,132 @@
+use std::{
+    io::{self, Error, ErrorKind},
+    net::UdpSocket,
+    time::Duration,
+};
+
+/// Header of every single-packet A2S response
+const SINGLE_PACKET: &[u8] = &[0xFF, 0xFF, 0xFF, 0xFF];
+/// Response type of a challenge (the query must be sent again with the challenge appended)
+const S2C_CHALLENGE: u8 = 0x41;
+/// Response type of A2S_INFO
+const S2A_INFO: u8 = 0x49;
+/// Response type of A2S_RULES
+const S2A_RULES: u8 = 0x45;
+/// Time to wait for the game server to answer
+const TIMEOUT: Duration = Duration::from_secs(3);
+
+/// ServerMaps is the map a game server is playing and the one it will change to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerMaps {
+    /// Map that is currently played
+    pub current: String,
+    /// Next map (from the `sm_nextmap` rule that SourceMod exposes), if the server shares it
+    pub next: Option<String>,
+}
+
+/// Queries the current and next map of the game server at `addr`
+///
+/// # Arguments
+/// * `addr`    -   Address of the game server (e.g. "1.2.3.4:27015")
+pub fn query_maps(addr: &str) -> io::Result<ServerMaps> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.set_read_timeout(Some(TIMEOUT))?;
+    socket.connect(addr)?;
+
+    let mut info_query = SINGLE_PACKET.to_vec();
+    info_query.push(0x54);
+    info_query.extend_from_slice(b"Source Engine Query\0");
+
+    let info = send_query(&socket, info_query, S2A_INFO)?;
+    let current = parse_info_map(&info)
+        .ok_or_else(|| Error::new(ErrorKind::InvalidData, "Malformed A2S_INFO response"))?;
+
+    // Not every server answers A2S_RULES, the next map is a bonus
+    let mut rules_query = SINGLE_PACKET.to_vec();
+    rules_query.push(0x56);
+    rules_query.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0xFF]);
+
+    let next = send_query(&socket, rules_query, S2A_RULES)
+        .ok()
+        .and_then(|rules| parse_rule(&rules, "sm_nextmap"))
+        .filter(|map| !map.is_empty());
+
+    Ok(ServerMaps { current, next })
+}
+
+/// Sends `query` and answers a challenge if the server asks for one
+/// Returns the payload of the response (after the response type)
+///
+/// # Arguments
+/// * `socket`      -   Socket connected to the game server
+/// * `query`       -   Query that is sent
+/// * `expected`    -   Response type that is expected
+fn send_query(socket: &UdpSocket, mut query: Vec<u8>, expected: u8) -> io::Result<Vec<u8>> {
+    let mut buf = [0u8; 1400];
+
+    // One try with the query as is and one with the challenge
+    for _ in 0..2 {
+        socket.send(&query)?;
+        let len = socket.recv(&mut buf)?;
+        let resp = &buf[..len];
+
+        if !resp.starts_with(SINGLE_PACKET) || resp.len() < 5 {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                "Unsupported A2S response",
+            ));
+        }
+
+        match resp[4] {
+            S2C_CHALLENGE if resp.len() >= 9 => {
+                // A2S_INFO appends the challenge, A2S_RULES replaces the placeholder challenge
+                if expected == S2A_RULES {
+                    query.truncate(5);
+                }
+                query.extend_from_slice(&resp[5..9]);
+            }
+            kind if kind == expected => return Ok(resp[5..].to_vec()),
+            _ => break,
+        }
+    }
+
+    Err(Error::new(
+        ErrorKind::InvalidData,
+        "Unexpected A2S response",
+    ))
+}
+
+/// Returns the null terminated string at the start of `bytes` and the bytes after it
+fn read_cstr(bytes: &[u8]) -> Option<(String, &[u8])> {
+    let end = bytes.iter().position(|b| *b == 0)?;
+
+    Some((
+        String::from_utf8_lossy(&bytes[..end]).to_string(),
+        &bytes[end + 1..],
+    ))
+}
+
+/// Returns the map of an A2S_INFO payload (protocol byte, name, map, ...)
+fn parse_info_map(payload: &[u8]) -> Option<String> {
+    let (_name, rest) = read_cstr(payload.get(1..)?)?;
+    let (map, _) = read_cstr(rest)?;
+
+    Some(map)
+}
+
+/// Returns the value of the rule `name` of an A2S_RULES payload (rule count, then name/value pairs)
+fn parse_rule(payload: &[u8], name: &str) -> Option<String> {
+    let mut rest = payload.get(2..)?;
+
+    while let Some((rule, after_rule)) = read_cstr(rest) {
+        let (value, after_value) = read_cstr(after_rule)?;
+
+        if rule == name {
+            return Some(value);
+        }
+
+        rest = after_value;
+    }
+
+    None
+}
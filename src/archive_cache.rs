@@ -0,0 +1,90 @@
+use crate::mover;
+use crate::scan::{self, ScanPolicy};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+/// Seconds in a day
+const DAY_SECS: u64 = 24 * 60 * 60;
+
+/// EvictSummary counts the archives removed by `evict`
+#[derive(Debug, Default)]
+pub struct EvictSummary {
+    /// Number of archives removed
+    pub evicted: usize,
+    /// Bytes freed
+    pub freed: u64,
+}
+
+/// Returns where a decoded compressed file is kept inside of the archive directory
+/// The path of the file relative to `base` is kept inside of `archive_dir`
+///
+/// # Arguments
+/// * `path`        -   Compressed file that was decoded
+/// * `base`        -   Directory the compressed file was found in (the decode root)
+/// * `archive_dir` -   Directory the compressed files are kept in (`--keep-archives <dir>`)
+pub fn target(path: &Path, base: &Path, archive_dir: &Path) -> PathBuf {
+    archive_dir.join(path.strip_prefix(base).unwrap_or(path))
+}
+
+/// Moves a decoded compressed file into the archive directory instead of deleting it
+/// The archive directory may be on another drive, the file is copied there then
+///
+/// # Arguments
+/// * `path`        -   Compressed file that was decoded
+/// * `base`        -   Directory the compressed file was found in (the decode root)
+/// * `archive_dir` -   Directory the compressed files are kept in (`--keep-archives <dir>`)
+pub fn archive(path: &Path, base: &Path, archive_dir: &Path) -> io::Result<()> {
+    mover::move_file(path, &target(path, base, archive_dir), &mut |_| {})
+}
+
+/// Removes the least recently used archives until the archive directory fits in `max_size`,
+/// and every archive that was not used for `max_age_days` days
+/// Archives are ordered by their access time (their modification time where it is not recorded)
+///
+/// # Arguments
+/// * `archive_dir`     -   Directory the compressed files are kept in
+/// * `max_size`        -   Largest size of the archive directory in bytes (`--archive-max-size <size>`)
+/// * `max_age_days`    -   Days an archive is kept after it was last used (`--archive-max-age <days>`)
+/// * `policy`          -   Which symlinks and hidden directories are walked into
+pub fn evict(
+    archive_dir: &Path,
+    max_size: Option<u64>,
+    max_age_days: Option<u64>,
+    policy: ScanPolicy,
+) -> EvictSummary {
+    let mut archives = scan::find_files(archive_dir, policy, |_| true)
+        .into_iter()
+        .filter_map(|path| {
+            let metadata = fs::metadata(&path).ok()?;
+            let used = metadata.accessed().or_else(|_| metadata.modified()).ok()?;
+            Some((used, metadata.len(), path))
+        })
+        .collect::<Vec<_>>();
+    // Least recently used first
+    archives.sort();
+
+    let oldest_kept = max_age_days
+        .and_then(|days| SystemTime::now().checked_sub(Duration::from_secs(days * DAY_SECS)));
+    let mut total = archives.iter().map(|(_, size, _)| size).sum::<u64>();
+    let mut summary = EvictSummary::default();
+
+    for (used, size, path) in archives {
+        let too_old = oldest_kept.is_some_and(|oldest| used < oldest);
+        let too_big = max_size.is_some_and(|max| total > max);
+        // Every later archive was used more recently and the total only shrinks
+        if !too_old && !too_big {
+            break;
+        }
+
+        if fs::remove_file(&path).is_ok() {
+            total -= size;
+            summary.evicted += 1;
+            summary.freed += size;
+        }
+    }
+
+    summary
+}
@@ -0,0 +1,138 @@
+use crate::events::{self, Event};
+use reqwest::{blocking::Response, header::RETRY_AFTER, StatusCode};
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+
+/// Pause after the first rate-limited response without a `Retry-After`, doubled on every strike
+const BASE_PAUSE: Duration = Duration::from_secs(2);
+/// Longest pause, whatever the server asks for
+const MAX_PAUSE: Duration = Duration::from_secs(600);
+/// Time between two new requests right after a pause (a single request at a time)
+const RAMP_START: Duration = Duration::from_secs(1);
+/// Spacing under which the host is back to full speed
+const RAMP_END: Duration = Duration::from_millis(20);
+
+/// HostBackoff is the backoff state of a host that rate-limited the requests
+struct HostBackoff {
+    /// No request is sent before this
+    paused_until: Instant,
+    /// Time between two new requests, shrunk on every successful response
+    spacing: Duration,
+    /// Earliest time of the next request
+    next_slot: Instant,
+    /// Rate-limited responses in a row (without a success in between)
+    strikes: u32,
+}
+
+/// Backoff pauses every request to a host that answers 429 or 503, then ramps the request rate back up
+/// Shared by every worker, so a rate-limited server sees all of them back off instead of each one retrying
+pub struct Backoff {
+    /// Host -> backoff state, only for hosts that rate-limited the requests and are not back to full speed
+    hosts: Mutex<HashMap<String, HostBackoff>>,
+}
+
+/// Returns the pause a rate-limited response asks for (`Retry-After` in seconds or as an HTTP date)
+fn retry_after(resp: &Response) -> Option<Duration> {
+    let value = resp.headers().get(RETRY_AFTER)?.to_str().ok()?.trim();
+
+    match value.parse::<u64>() {
+        Ok(secs) => Some(Duration::from_secs(secs)),
+        Err(_) => httpdate::parse_http_date(value)
+            .ok()?
+            .duration_since(SystemTime::now())
+            .ok(),
+    }
+}
+
+impl Backoff {
+    /// Returns a Backoff with no host paused
+    pub fn new() -> Self {
+        Self {
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Waits until a new request may be sent to `host`
+    ///
+    /// # Arguments
+    /// * `host`    -   Host of the request
+    pub fn wait(&self, host: &str) {
+        loop {
+            let wake_at = {
+                let mut hosts = self.hosts.lock().unwrap();
+                let Some(state) = hosts.get_mut(host) else {
+                    return;
+                };
+                let now = Instant::now();
+
+                if state.paused_until > now {
+                    // Checked again after the pause, another worker may have extended it
+                    state.paused_until
+                } else {
+                    // Every worker takes its own slot, so requests restart one at a time
+                    let slot = state.next_slot.max(now);
+                    state.next_slot = slot + state.spacing;
+                    drop(hosts);
+
+                    std::thread::sleep(slot.saturating_duration_since(now));
+                    return;
+                }
+            };
+
+            std::thread::sleep(wake_at.saturating_duration_since(Instant::now()));
+        }
+    }
+
+    /// Pauses `host` if `resp` says the requests are rate-limited, otherwise speeds it back up
+    ///
+    /// # Arguments
+    /// * `host`    -   Host of the request
+    /// * `resp`    -   Response of the request
+    pub fn observe(&self, host: &str, resp: &Response) {
+        let status = resp.status();
+        let mut hosts = self.hosts.lock().unwrap();
+
+        if status == StatusCode::TOO_MANY_REQUESTS || status == StatusCode::SERVICE_UNAVAILABLE {
+            let now = Instant::now();
+            let state = hosts.entry(host.to_string()).or_insert(HostBackoff {
+                paused_until: now,
+                spacing: RAMP_START,
+                next_slot: now,
+                strikes: 0,
+            });
+            // Workers that sent their request before the pause started don't extend it again
+            if state.paused_until > now {
+                return;
+            }
+
+            state.strikes += 1;
+            let pause = retry_after(resp)
+                .unwrap_or_else(|| BASE_PAUSE * 2u32.saturating_pow(state.strikes - 1))
+                .min(MAX_PAUSE);
+            state.paused_until = now + pause;
+            state.next_slot = state.paused_until;
+            state.spacing = RAMP_START;
+
+            events::emit(&Event::RateLimited {
+                host,
+                status: status.as_u16(),
+                pause_secs: pause.as_secs(),
+            });
+        } else if let Some(state) = hosts.get_mut(host) {
+            state.strikes = 0;
+            state.spacing = state.spacing * 3 / 4;
+            if state.spacing < RAMP_END {
+                hosts.remove(host);
+            }
+        }
+    }
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,44 @@
+use crate::MB_SIZE;
+use serde::{Deserialize, Serialize};
+use std::ops::AddAssign;
+
+/// Bandwidth is what the syncs of a mirror downloaded, and what they did not have to thanks to the manifest
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Bandwidth {
+    /// Bytes downloaded
+    pub downloaded: u64,
+    /// Bytes of the files that were already mirrored and unchanged, which a full re-download would fetch again
+    pub saved: u64,
+}
+
+impl AddAssign for Bandwidth {
+    fn add_assign(&mut self, other: Self) {
+        self.downloaded += other.downloaded;
+        self.saved += other.saved;
+    }
+}
+
+/// Returns `bytes` in MB, or GB once it's over 1024 MB
+fn human(bytes: u64) -> String {
+    let mb = bytes as f64 / MB_SIZE as f64;
+    if mb >= 1024.0 {
+        format!("{:.2} GB", mb / 1024.0)
+    } else {
+        format!("{:.2} MB", mb)
+    }
+}
+
+/// Returns the bandwidth line of the summary
+///
+/// # Arguments
+/// * `run`     -   Bandwidth of this run
+/// * `total`   -   Bandwidth of every run of the mirrors, this one included
+pub fn summary(run: Bandwidth, total: Bandwidth) -> String {
+    format!(
+        "{} downloaded, saved {} vs a full re-download thanks to the incremental sync (all runs: {} downloaded, {} saved)",
+        human(run.downloaded),
+        human(run.saved),
+        human(total.downloaded),
+        human(total.saved)
+    )
+}
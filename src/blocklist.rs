@@ -0,0 +1,141 @@
+use crate::files::{LocalFile, RemoteFile};
+use crate::scan::{self, ScanPolicy};
+use sha2::{Digest, Sha256};
+use std::{collections::HashSet, fs, io, path::Path};
+
+/// Blocklist stores files known to be corrupt or removed-for-cause
+/// Each line of the blocklist file is either a sha256 hash or a file name
+pub struct Blocklist {
+    /// Lowercased file names (without ".bz2")
+    names: HashSet<String>,
+    /// Lowercased sha256 hashes of the (decoded) files
+    hashes: HashSet<String>,
+}
+
+/// Returns the lowercase hex sha256 hash of `bytes`
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(bytes))
+}
+
+/// Returns the lowercased file name of a link or path without the ".bz2" suffix
+fn plain_name(path: &str) -> String {
+    let name = path
+        .rsplit(['/', '\\'])
+        .next()
+        .unwrap_or(path)
+        .to_lowercase();
+
+    match name.strip_suffix(".bz2") {
+        Some(name) => name.to_string(),
+        None => name,
+    }
+}
+
+impl Blocklist {
+    /// Returns the Blocklist stored in `path`
+    /// Empty lines and lines starting with "#" are ignored
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the blocklist file
+    pub fn load(path: &Path) -> io::Result<Self> {
+        let mut blocklist = Self {
+            names: HashSet::new(),
+            hashes: HashSet::new(),
+        };
+
+        for line in fs::read_to_string(path)?.lines().map(|line| line.trim()) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if line.len() == 64 && line.chars().all(|c| c.is_ascii_hexdigit()) {
+                blocklist.hashes.insert(line.to_lowercase());
+            } else {
+                blocklist.names.insert(plain_name(line));
+            }
+        }
+
+        Ok(blocklist)
+    }
+
+    /// Returns true if the file name of `path` is blocked
+    pub fn blocks_name(&self, path: &str) -> bool {
+        self.names.contains(&plain_name(path))
+    }
+
+    /// Returns true if the hex sha256 `sha256` of a file is blocked
+    pub fn blocks_sha256(&self, sha256: &str) -> bool {
+        self.hashes.contains(sha256)
+    }
+
+    /// Removes every blocked file from `links`, returning the files that were removed
+    ///
+    /// # Arguments
+    /// * `links`   -   Files found during the crawl
+    pub fn retain_links(&self, links: &mut HashSet<RemoteFile>) -> Vec<RemoteFile> {
+        let blocked = links
+            .iter()
+            .filter(|file| self.blocks_name(&file.url))
+            .cloned()
+            .collect::<Vec<_>>();
+
+        for file in &blocked {
+            links.remove(file);
+        }
+
+        blocked
+    }
+
+    /// Returns every blocked file that is already present in `dir` (snapshots are not part of the mirror)
+    ///
+    /// # Arguments
+    /// * `dir`     -   Directory of the local mirror
+    /// * `policy`  -   Which symlinks and hidden entries are walked into
+    pub fn scan_local(&self, dir: &Path, policy: ScanPolicy) -> Vec<LocalFile> {
+        scan::find_files(dir, policy, |_| true)
+            .into_iter()
+            .filter_map(|path| LocalFile::read(&path).ok())
+            .filter_map(|mut file| {
+                let blocked = self.blocks_name(file.file_name())
+                    || (!self.hashes.is_empty()
+                        && file.hash().is_ok_and(|hash| self.hashes.contains(hash)));
+
+                blocked.then_some(file)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{meta_dir::META_DIR, snapshot::SNAPSHOTS_DIR};
+
+    #[test]
+    fn scan_ignores_snapshot_copies() {
+        let dir = std::env::temp_dir().join(format!("fastdl_blocklist_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let maps = dir.join("maps");
+        let snapshot = dir
+            .join(META_DIR)
+            .join(SNAPSHOTS_DIR)
+            .join("1700000000")
+            .join("maps");
+        fs::create_dir_all(&maps).unwrap();
+        fs::create_dir_all(&snapshot).unwrap();
+        fs::write(maps.join("ze_bad.bsp"), b"bad").unwrap();
+        fs::write(maps.join("ze_good.bsp"), b"good").unwrap();
+        fs::write(snapshot.join("ze_bad.bsp"), b"bad").unwrap();
+
+        let list = dir.join("blocklist.txt");
+        fs::write(&list, format!("ze_bad.bsp\n{}\n", sha256_hex(b"bad"))).unwrap();
+        let blocked = Blocklist::load(&list)
+            .unwrap()
+            .scan_local(&dir, ScanPolicy::default());
+
+        let paths = blocked.iter().map(|file| &file.path).collect::<Vec<_>>();
+        assert_eq!(paths, [&maps.join("ze_bad.bsp")]);
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
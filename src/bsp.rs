@@ -0,0 +1,328 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    fmt,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Magic bytes of a Source engine map
+const VBSP_MAGIC: &[u8] = b"VBSP";
+/// Number of lumps in the header of a map
+const LUMP_COUNT: usize = 64;
+/// Size of the header: magic, version, the lump directory and the map revision
+const HEADER_SIZE: usize = 8 + LUMP_COUNT * 16 + 4;
+/// Lump holding the entities as KeyValues text
+const LUMP_ENTITIES: usize = 0;
+/// Lump holding the files packed into the map (a zip archive)
+const LUMP_PAKFILE: usize = 40;
+/// Lump holding the positions the cubemaps are built at
+const LUMP_CUBEMAPS: usize = 42;
+/// Size of a cubemap sample in the cubemaps lump
+const CUBEMAP_SAMPLE_SIZE: u64 = 16;
+/// Magic bytes of a lump compressed by the map compiler
+const LZMA_MAGIC: &[u8] = b"LZMA";
+/// Signature of the end of central directory record of a zip archive
+const ZIP_END: u32 = 0x0605_4b50;
+/// Signature of a central directory entry of a zip archive
+const ZIP_ENTRY: u32 = 0x0201_4b50;
+/// Longest zip comment, so the end record is within the last bytes of the archive
+const ZIP_MAX_COMMENT: u64 = 0xFFFF;
+
+/// EntityScan is what the entities and packed files of a map need from the server
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct EntityScan {
+    /// SourceMod commands the map sends through `point_servercommand` (e.g. "sm_setcooldown")
+    pub commands: BTreeSet<String>,
+    /// Server configs the map executes (e.g. "cfg/ze_example.cfg")
+    pub configs: BTreeSet<String>,
+    /// The entities mention Stripper:Source, so the map expects a stripper config on the server
+    pub stripper: bool,
+    /// Soundscript entries played by the map (sounds named without a file extension)
+    pub soundscripts: BTreeSet<String>,
+    /// The map packs a soundscript (its soundscript entries are only found if it does)
+    pub packed_soundscripts: bool,
+    /// The map has cubemaps but their textures were never built into it (shiny surfaces render pink or black)
+    pub missing_cubemaps: bool,
+    /// The entities lump is compressed and was not scanned
+    pub compressed: bool,
+}
+
+impl fmt::Display for EntityScan {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.compressed {
+            return write!(f, "compressed entities");
+        }
+
+        let mut parts = Vec::new();
+        if self.stripper {
+            parts.push(String::from("stripper config"));
+        }
+        if !self.configs.is_empty() {
+            parts.push(format!(
+                "configs: {}",
+                self.configs.iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if !self.commands.is_empty() {
+            parts.push(format!(
+                "commands: {}",
+                self.commands.iter().cloned().collect::<Vec<_>>().join(", ")
+            ));
+        }
+        if !self.soundscripts.is_empty() && !self.packed_soundscripts {
+            parts.push(format!(
+                "{} soundscript entries (not packed)",
+                self.soundscripts.len()
+            ));
+        }
+        if self.missing_cubemaps {
+            parts.push(String::from("cubemaps not built"));
+        }
+
+        if parts.is_empty() {
+            write!(f, "-")
+        } else {
+            write!(f, "{}", parts.join("; "))
+        }
+    }
+}
+
+/// Lump is the position of a lump in the map file
+#[derive(Clone, Copy, Debug)]
+struct Lump {
+    /// Offset of the lump from the start of the file
+    offset: u64,
+    /// Size of the lump in bytes
+    len: u64,
+}
+
+/// Returns the little-endian u32 at `pos` of `bytes` (0 past the end)
+fn u32_at(bytes: &[u8], pos: usize) -> u32 {
+    bytes
+        .get(pos..pos + 4)
+        .map_or(0, |b| u32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+}
+
+/// Returns the little-endian u16 at `pos` of `bytes` (0 past the end)
+fn u16_at(bytes: &[u8], pos: usize) -> u16 {
+    bytes
+        .get(pos..pos + 2)
+        .map_or(0, |b| u16::from_le_bytes([b[0], b[1]]))
+}
+
+/// Reads `len` bytes at `offset` of `file`
+fn read_at(file: &mut File, offset: u64, len: u64) -> io::Result<Vec<u8>> {
+    let mut bytes = vec![0u8; len as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Returns the tokens of the entities lump: quoted strings and braces
+fn tokens(text: &str) -> Vec<&str> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while let Some(start) = rest.find(['"', '{', '}']) {
+        match &rest[start..start + 1] {
+            "\"" => {
+                let Some(end) = rest[start + 1..].find('"') else {
+                    break;
+                };
+                tokens.push(&rest[start + 1..start + 1 + end]);
+                rest = &rest[start + end + 2..];
+            }
+            brace => {
+                tokens.push(brace);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Returns every entity of the entities lump as its key/value pairs (keys lowercased, repeated keys kept)
+fn entities(text: &str) -> Vec<Vec<(String, &str)>> {
+    let mut entities = Vec::new();
+    let mut current = None::<Vec<(String, &str)>>;
+    let mut tokens = tokens(text).into_iter();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            "{" => current = Some(Vec::new()),
+            "}" => entities.extend(current.take()),
+            key => {
+                if let (Some(entity), Some(value)) = (current.as_mut(), tokens.next()) {
+                    entity.push((key.to_lowercase(), value));
+                }
+            }
+        }
+    }
+
+    entities
+}
+
+/// Returns the names of the files in the pakfile lump, read from the central directory of its zip archive
+fn packed_names(file: &mut File, pakfile: Lump) -> io::Result<Vec<String>> {
+    if pakfile.len < 22 {
+        return Ok(Vec::new());
+    }
+
+    // The end record is in the last bytes, before a comment of at most 64 KB
+    let tail_len = pakfile.len.min(ZIP_MAX_COMMENT + 22);
+    let tail = read_at(file, pakfile.offset + pakfile.len - tail_len, tail_len)?;
+    let Some(end) = (0..=tail.len() - 22)
+        .rev()
+        .find(|&pos| u32_at(&tail, pos) == ZIP_END)
+    else {
+        return Ok(Vec::new());
+    };
+
+    let dir_len = u32_at(&tail, end + 12) as u64;
+    let dir_offset = u32_at(&tail, end + 16) as u64;
+    if dir_offset + dir_len > pakfile.len {
+        return Ok(Vec::new());
+    }
+    let dir = read_at(file, pakfile.offset + dir_offset, dir_len)?;
+
+    let mut names = Vec::new();
+    let mut pos = 0;
+    while u32_at(&dir, pos) == ZIP_ENTRY {
+        let name_len = u16_at(&dir, pos + 28) as usize;
+        let extra_len = u16_at(&dir, pos + 30) as usize;
+        let comment_len = u16_at(&dir, pos + 32) as usize;
+        let Some(name) = dir.get(pos + 46..pos + 46 + name_len) else {
+            break;
+        };
+        names.push(
+            String::from_utf8_lossy(name)
+                .to_lowercase()
+                .replace('\\', "/"),
+        );
+        pos += 46 + name_len + extra_len + comment_len;
+    }
+
+    Ok(names)
+}
+
+/// Returns the command and its parameter of an entity output ("target,input,parameter,delay,times")
+/// Newer compilers separate the fields with ESC instead of commas
+fn output_input(value: &str) -> Option<(&str, &str, &str)> {
+    let separator = if value.contains('\u{1b}') {
+        '\u{1b}'
+    } else {
+        ','
+    };
+    let mut fields = value.splitn(5, separator);
+
+    Some((fields.next()?, fields.next()?, fields.next()?))
+}
+
+/// Scans the entities and packed files of the map at `path` for what it needs from the server
+///
+/// # Arguments
+/// * `path`    -   Decoded map (.bsp)
+pub fn scan(path: &Path) -> io::Result<EntityScan> {
+    let mut file = File::open(path)?;
+    let map_name = path
+        .file_stem()
+        .and_then(|stem| stem.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    let mut header = vec![0u8; HEADER_SIZE];
+    file.read_exact(&mut header)?;
+    if &header[..4] != VBSP_MAGIC {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a Source map",
+        ));
+    }
+    let lump = |index: usize| Lump {
+        offset: u32_at(&header, 8 + index * 16) as u64,
+        len: u32_at(&header, 8 + index * 16 + 4) as u64,
+    };
+
+    let mut scan = EntityScan::default();
+
+    // Cubemaps are sampled at compile time but their textures are only packed by `buildcubemaps`
+    let packed = packed_names(&mut file, lump(LUMP_PAKFILE))?;
+    let cubemap_dir = format!("materials/maps/{}/c", map_name);
+    let has_cubemaps = lump(LUMP_CUBEMAPS).len >= CUBEMAP_SAMPLE_SIZE;
+    scan.missing_cubemaps = has_cubemaps
+        && !packed
+            .iter()
+            .any(|name| name.starts_with(&cubemap_dir) && name.ends_with(".vtf"));
+    scan.packed_soundscripts = packed.iter().any(|name| {
+        name.starts_with("scripts/") && name.ends_with(".txt") && name.contains("sound")
+            || name == &format!("maps/{}_level_sounds.txt", map_name)
+    });
+
+    let entities_lump = lump(LUMP_ENTITIES);
+    let text = read_at(&mut file, entities_lump.offset, entities_lump.len)?;
+    if text.starts_with(LZMA_MAGIC) {
+        scan.compressed = true;
+        return Ok(scan);
+    }
+    let text = String::from_utf8_lossy(&text);
+    let entities = entities(&text);
+
+    // Outputs name their target, which is only known to be a point_servercommand by its targetname
+    let classes = entities
+        .iter()
+        .flat_map(|entity| {
+            let class = entity.iter().find(|(key, _)| key == "classname")?.1;
+            let name = entity.iter().find(|(key, _)| key == "targetname")?.1;
+            Some((name.to_lowercase(), class.to_lowercase()))
+        })
+        .collect::<HashMap<_, _>>();
+
+    for entity in &entities {
+        let class = entity
+            .iter()
+            .find(|(key, _)| key == "classname")
+            .map_or("", |(_, class)| class);
+
+        for (key, value) in entity {
+            if value.to_lowercase().contains("stripper") {
+                scan.stripper = true;
+            }
+
+            // ambient_generic plays soundscript entries by name, files by their path
+            if class.eq_ignore_ascii_case("ambient_generic")
+                && key == "message"
+                && !value.is_empty()
+                && !value.contains('.')
+            {
+                scan.soundscripts.insert(value.to_string());
+            }
+
+            let Some((target, input, parameter)) = output_input(value) else {
+                continue;
+            };
+            let is_server_command = classes.get(&target.to_lowercase()).map(String::as_str)
+                == Some("point_servercommand");
+            if !is_server_command || !input.eq_ignore_ascii_case("command") {
+                continue;
+            }
+
+            let mut words = parameter.split_whitespace();
+            match words.next().map(str::to_lowercase).as_deref() {
+                Some("exec") => {
+                    if let Some(config) = words.next() {
+                        let config = config.trim_end_matches(".cfg");
+                        scan.configs.insert(format!("cfg/{}.cfg", config));
+                    }
+                }
+                Some(command) if command.starts_with("sm_") => {
+                    scan.commands.insert(command.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    Ok(scan)
+}
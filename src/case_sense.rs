@@ -0,0 +1,96 @@
+use std::{
+    borrow::Cow,
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Name of the file created to probe the case sensitivity of a filesystem (removed right away)
+const PROBE_NAME: &str = "fastdl_case_probe.tmp";
+
+/// Returns true if the filesystem of `dir` treats names that only differ by case as the same file
+/// (NTFS and APFS by default, case-insensitive mounts and folders on Linux)
+/// Falls back to the platform default if `dir` can't be written to
+///
+/// # Arguments
+/// * `dir`     -   Directory on the filesystem (the output directory)
+pub fn is_case_insensitive(dir: &Path) -> bool {
+    let probe = dir.join(PROBE_NAME);
+    let created = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&probe)
+        .is_ok();
+    if !created {
+        return cfg!(any(windows, target_os = "macos"));
+    }
+
+    let insensitive = dir.join(PROBE_NAME.to_uppercase()).exists();
+    let _ = fs::remove_file(&probe);
+
+    insensitive
+}
+
+/// Returns the key two paths or urls are compared by on a filesystem
+/// (names that only differ by case are the same file on a case-insensitive filesystem)
+///
+/// # Arguments
+/// * `path`            -   Path or url
+/// * `insensitive`     -   The filesystem is case-insensitive
+pub fn key(path: &str, insensitive: bool) -> Cow<'_, str> {
+    if insensitive {
+        Cow::Owned(path.to_lowercase())
+    } else {
+        Cow::Borrowed(path)
+    }
+}
+
+/// CaseFold is how the local paths of the synced files are normalized (`--fold-case <paths|ext>`)
+/// Source content is looked up by lowercase paths, so a fastdl serving `Maps/ZE_Map.BSP.bz2` leaves
+/// files a Linux server never finds, and a second copy when the fastdl fixes the case
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaseFold {
+    /// Lowercase the whole local path (`paths`)
+    Paths,
+    /// Lowercase the extensions only (`ext`), e.g. `ZE_Map.BSP.BZ2` -> `ZE_Map.bsp.bz2`
+    Extensions,
+}
+
+impl FromStr for CaseFold {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "paths" => Ok(Self::Paths),
+            "ext" => Ok(Self::Extensions),
+            _ => Err(format!(
+                "Unknown case folding: {} (expected paths or ext)",
+                s
+            )),
+        }
+    }
+}
+
+impl CaseFold {
+    /// Returns `path` with the folding applied
+    ///
+    /// # Arguments
+    /// * `path`    -   Local path of a file or directory
+    pub fn apply(self, path: &Path) -> PathBuf {
+        match self {
+            Self::Paths => PathBuf::from(path.to_string_lossy().to_lowercase()),
+            Self::Extensions => {
+                let Some(name) = path.file_name().and_then(|name| name.to_str()) else {
+                    return path.to_path_buf();
+                };
+                // Everything after the first dot, so both extensions of `.BSP.BZ2` are folded
+                let name = match name.split_once('.') {
+                    Some((stem, exts)) => format!("{}.{}", stem, exts.to_lowercase()),
+                    None => name.to_string(),
+                };
+
+                path.with_file_name(name)
+            }
+        }
+    }
+}
@@ -0,0 +1,295 @@
+use crate::{
+    files::RemoteFile,
+    fingerprint::{self, HostFingerprint},
+    schema, MB_SIZE,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt::Write as _,
+    fs, io,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// Name of the previous crawl stored in the output directory
+pub const CRAWL_FILE: &str = "fastdl_crawl.json";
+/// Number of changes kept for the feed
+pub const MAX_HISTORY: usize = 200;
+
+/// ListedFile is what a crawl learned about a remote file
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListedFile {
+    /// Size in bytes (None if the crawl did not probe the file)
+    pub size: Option<u64>,
+    /// Unix time the file was last modified on the server
+    pub mtime: Option<u64>,
+    /// Entity tag the server sent for the file
+    pub etag: Option<String>,
+}
+
+/// ChangeKind is how a remote file changed between two crawls
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// The file is new
+    Added,
+    /// The file is no longer listed
+    Removed,
+    /// The size, modification time or entity tag of the file changed
+    Modified,
+}
+
+/// Change is a remote file that changed between two crawls
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Change {
+    /// Unix time of the crawl that noticed the change
+    pub time: u64,
+    /// How the file changed
+    pub kind: ChangeKind,
+    /// Download link of the file
+    pub url: String,
+    /// Size of the file in bytes (the last known size for removed files)
+    pub size: Option<u64>,
+}
+
+/// CrawlSnapshot is the previous crawl and the changes noticed so far (versioned by `schema::CRAWL`)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct CrawlSnapshot {
+    /// Version of the format the snapshot was written in
+    #[serde(default)]
+    schema_version: u32,
+    /// Unix time of the crawl (0 if there was none)
+    pub crawled_at: u64,
+    /// Download link -> what the crawl learned about the file
+    pub files: BTreeMap<String, ListedFile>,
+    /// Newest changes last, at most `MAX_HISTORY`
+    pub history: Vec<Change>,
+    /// Host -> what every crawl learned about it (server, listing style, HEAD and range support)
+    #[serde(default)]
+    pub hosts: BTreeMap<String, HostFingerprint>,
+}
+
+impl From<&RemoteFile> for ListedFile {
+    fn from(file: &RemoteFile) -> Self {
+        Self {
+            size: file.size,
+            mtime: file.mtime,
+            etag: file.etag.clone(),
+        }
+    }
+}
+
+impl ListedFile {
+    /// Returns true if `self` and `other` are known to be different versions of a file
+    /// Fields only one of the crawls knows (e.g. sizes with `--fast-crawl`) are not compared
+    fn differs(&self, other: &Self) -> bool {
+        fn known_and_different<T: PartialEq>(a: &Option<T>, b: &Option<T>) -> bool {
+            matches!((a, b), (Some(a), Some(b)) if a != b)
+        }
+
+        known_and_different(&self.size, &other.size)
+            || known_and_different(&self.mtime, &other.mtime)
+            || known_and_different(&self.etag, &other.etag)
+    }
+}
+
+impl CrawlSnapshot {
+    /// Returns the snapshot stored at `path` (an empty one if there was no previous crawl)
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the snapshot
+    pub fn load(path: &Path) -> Result<Self, String> {
+        Ok(schema::CRAWL.load(path)?.unwrap_or_default())
+    }
+
+    /// Writes the snapshot to `path` with every host fingerprint known so far
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the snapshot
+    pub fn save(&mut self, path: &Path) -> io::Result<()> {
+        self.schema_version = schema::CRAWL.version();
+        self.hosts = fingerprint::all();
+
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Replaces the previous crawl with `files`, returning what changed since it
+    /// The first crawl only records a baseline, otherwise every file would show up as added
+    ///
+    /// # Arguments
+    /// * `files`   -   Files found by the current crawl
+    /// * `now`     -   Unix time of the current crawl
+    pub fn update(&mut self, files: &HashSet<RemoteFile>, now: u64) -> Vec<Change> {
+        let current = files
+            .iter()
+            .map(|file| (file.url.clone(), ListedFile::from(file)))
+            .collect::<BTreeMap<_, _>>();
+        let change = |kind, url: &str, size| Change {
+            time: now,
+            kind,
+            url: url.to_string(),
+            size,
+        };
+
+        let mut changes = Vec::new();
+        if self.crawled_at > 0 {
+            for (url, file) in &current {
+                match self.files.get(url) {
+                    None => changes.push(change(ChangeKind::Added, url, file.size)),
+                    Some(previous) if previous.differs(file) => {
+                        changes.push(change(ChangeKind::Modified, url, file.size))
+                    }
+                    Some(_) => {}
+                }
+            }
+            for (url, file) in &self.files {
+                if !current.contains_key(url) {
+                    changes.push(change(ChangeKind::Removed, url, file.size));
+                }
+            }
+        }
+
+        self.history.extend(changes.iter().cloned());
+        let excess = self.history.len().saturating_sub(MAX_HISTORY);
+        self.history.drain(..excess);
+        self.files = current;
+        self.crawled_at = now;
+
+        changes
+    }
+}
+
+/// Prints every change, one per line ("+" added, "-" removed, "~" modified)
+///
+/// # Arguments
+/// * `changes` -   Changes noticed by the current crawl
+pub fn print(changes: &[Change]) {
+    for change in changes {
+        let sign = match change.kind {
+            ChangeKind::Added => '+',
+            ChangeKind::Removed => '-',
+            ChangeKind::Modified => '~',
+        };
+        match change.size {
+            Some(size) => println!(
+                "{} {} ({:.2} MB)",
+                sign,
+                change.url,
+                size as f32 / MB_SIZE as f32
+            ),
+            None => println!("{} {}", sign, change.url),
+        }
+    }
+}
+
+/// Returns `text` with the characters XML reserves escaped
+pub fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Returns a unix time as an RFC 3339 date in UTC (e.g. "2023-08-14T10:20:30Z")
+pub fn rfc3339(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // Civil date of a day count since 1970-01-01 (proleptic Gregorian calendar)
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+/// Returns the title of a change in the feed (e.g. "Added ze_example_v1.bsp.bz2")
+fn title(change: &Change) -> String {
+    let name = change.url.rsplit('/').next().unwrap_or(&change.url);
+    let kind = match change.kind {
+        ChangeKind::Added => "Added",
+        ChangeKind::Removed => "Removed",
+        ChangeKind::Modified => "Updated",
+    };
+
+    format!("{} {}", kind, name)
+}
+
+/// Writes the changes as a feed communities can subscribe to, newest first
+/// The feed is RSS 2.0 if `path` ends with ".rss", Atom otherwise
+///
+/// # Arguments
+/// * `path`        -   Path of the feed
+/// * `source`      -   Fastdl urls the changes are from (the title and id of the feed)
+/// * `history`     -   Every change that is published
+pub fn write_feed(path: &Path, source: &str, history: &[Change]) -> io::Result<()> {
+    let is_rss = path.extension().is_some_and(|ext| ext == "rss");
+    let updated = history.iter().map(|change| change.time).max().unwrap_or(0);
+    let mut feed = String::from("<?xml version=\"1.0\" encoding=\"utf-8\"?>\n");
+
+    // Writing to a String can't fail
+    if is_rss {
+        let date = |secs| httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(secs));
+        let _ = writeln!(
+            feed,
+            "<rss version=\"2.0\"><channel>\n<title>FastDL changes: {}</title>\n<link>{}</link>\n<description>Files added, updated and removed on {}</description>\n<lastBuildDate>{}</lastBuildDate>",
+            escape(source),
+            escape(source),
+            escape(source),
+            date(updated)
+        );
+        for change in history.iter().rev() {
+            let _ = writeln!(
+                feed,
+                "<item><title>{}</title><link>{}</link><guid isPermaLink=\"false\">{}#{}</guid><pubDate>{}</pubDate></item>",
+                escape(&title(change)),
+                escape(&change.url),
+                escape(&change.url),
+                change.time,
+                date(change.time)
+            );
+        }
+        feed.push_str("</channel></rss>\n");
+    } else {
+        let _ = writeln!(
+            feed,
+            "<feed xmlns=\"http://www.w3.org/2005/Atom\">\n<title>FastDL changes: {}</title>\n<id>{}</id>\n<link href=\"{}\"/>\n<updated>{}</updated>",
+            escape(source),
+            escape(source),
+            escape(source),
+            rfc3339(updated)
+        );
+        for change in history.iter().rev() {
+            let _ = writeln!(
+                feed,
+                "<entry><title>{}</title><id>{}#{}</id><link href=\"{}\"/><updated>{}</updated><author><name>{}</name></author></entry>",
+                escape(&title(change)),
+                escape(&change.url),
+                change.time,
+                escape(&change.url),
+                rfc3339(change.time),
+                escape(source)
+            );
+        }
+        feed.push_str("</feed>\n");
+    }
+
+    fs::write(path, feed)
+}
@@ -0,0 +1,103 @@
+use crate::split;
+use std::{
+    fs::File,
+    io::Read,
+    path::{Path, PathBuf},
+};
+
+/// Magic bytes at the start of every bz2 stream ("BZh" followed by the block size digit)
+const BZ2_MAGIC: &[u8] = b"BZh";
+/// Magic bytes at the start of every Source BSP
+const BSP_MAGIC: &[u8] = b"VBSP";
+/// Number of bytes that are read from a file to sniff its type
+const SNIFF_LEN: usize = 16;
+
+/// FileKind is the type of a file, decided by its content instead of its extension
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FileKind {
+    /// bz2 compressed file
+    Bz2,
+    /// Source map
+    Bsp,
+    /// HTML page (directory listings, error pages)
+    Html,
+    /// Anything else (sounds, materials, models, ...)
+    Unknown,
+}
+
+/// Returns the FileKind announced by a `Content-Type` header
+///
+/// # Arguments
+/// * `content_type`    -   Value of the `Content-Type` header
+pub fn from_content_type(content_type: &str) -> FileKind {
+    let mime = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_lowercase();
+
+    match mime.as_str() {
+        "application/x-bzip2" | "application/x-bzip" | "application/bzip2" => FileKind::Bz2,
+        "text/html" => FileKind::Html,
+        _ => FileKind::Unknown,
+    }
+}
+
+/// Returns the FileKind of `bytes` by looking at its magic bytes
+///
+/// # Arguments
+/// * `bytes`   -   Start of the file (at least the first few bytes)
+pub fn sniff(bytes: &[u8]) -> FileKind {
+    if bytes.starts_with(BZ2_MAGIC) && bytes.get(3).is_some_and(|b| (b'1'..=b'9').contains(b)) {
+        FileKind::Bz2
+    } else if bytes.starts_with(BSP_MAGIC) {
+        FileKind::Bsp
+    } else {
+        let head = String::from_utf8_lossy(bytes).trim_start().to_lowercase();
+
+        if head.starts_with("<!doctype html") || head.starts_with("<html") {
+            FileKind::Html
+        } else {
+            FileKind::Unknown
+        }
+    }
+}
+
+/// Returns the FileKind of the file at `path` by looking at its magic bytes
+///
+/// # Arguments
+/// * `path`    -   Path of the file that will be sniffed
+pub fn sniff_file(path: &Path) -> std::io::Result<FileKind> {
+    let mut head = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut head)?;
+
+    Ok(sniff(&head))
+}
+
+/// Returns the path a file should be stored at so its extension matches its content
+/// bz2 files get a ".bz2" suffix (so they're decoded) and misnamed ".bz2" files lose it (so they're not)
+///
+/// # Arguments
+/// * `path`    -   Path derived from the download link
+/// * `kind`    -   FileKind sniffed from the content of the file
+pub fn path_for_kind(path: &Path, kind: FileKind) -> PathBuf {
+    // Parts of split archives keep their name, only the joined archive is decoded
+    if path
+        .to_str()
+        .is_some_and(|path| split::part_of(path).is_some())
+    {
+        return path.to_path_buf();
+    }
+
+    let is_bz2_ext = path.extension().is_some_and(|ext| ext == "bz2");
+
+    match (is_bz2_ext, kind) {
+        (true, FileKind::Bz2) => path.to_path_buf(),
+        (true, _) => path.with_extension(""),
+        (false, FileKind::Bz2) => PathBuf::from(format!("{}.bz2", path.display())),
+        (false, _) => path.to_path_buf(),
+    }
+}
@@ -0,0 +1,61 @@
+use crate::files::RemoteFile;
+use std::collections::{BTreeMap, HashSet};
+
+/// Returns the groups of links whose paths only differ by case
+/// e.g. `Materials/Foo.vmt` and `materials/foo.vmt` collide on case-insensitive filesystems (Windows)
+///
+/// # Arguments
+/// * `links`   -   Download links found during the crawl (or relative paths of a tree)
+pub fn find_collisions<'a, I: IntoIterator<Item = &'a str>>(links: I) -> Vec<Vec<String>> {
+    // BTreeMap keeps the groups (and the report) in a deterministic order
+    let mut groups = BTreeMap::<String, Vec<String>>::new();
+
+    for link in links {
+        groups
+            .entry(link.to_lowercase())
+            .or_default()
+            .push(link.to_string());
+    }
+
+    groups
+        .into_values()
+        .filter(|group| group.len() > 1)
+        .map(|mut group| {
+            group.sort();
+            group
+        })
+        .collect()
+}
+
+/// Picks the link to keep out of a group of colliding links
+/// The all-lowercase variant wins (Source lowercases paths itself), otherwise the smallest link is kept
+///
+/// # Arguments
+/// * `group`   -   Sorted links that only differ by case
+pub fn pick_winner(group: &[String]) -> &String {
+    group
+        .iter()
+        .find(|link| **link == link.to_lowercase())
+        .unwrap_or(&group[0])
+}
+
+/// Removes the losing files of every case-insensitive collision from `links`
+/// Returns the files that were removed so they can be reported
+///
+/// # Arguments
+/// * `links`   -   Files found during the crawl
+pub fn resolve_collisions(links: &mut HashSet<RemoteFile>) -> Vec<RemoteFile> {
+    let mut removed = Vec::new();
+
+    for group in find_collisions(links.iter().map(|file| file.url.as_str())) {
+        let winner = pick_winner(&group).clone();
+
+        for link in group {
+            if link != winner {
+                removed.extend(links.take(link.as_str()));
+            }
+        }
+    }
+
+    removed
+}
@@ -0,0 +1,124 @@
+use crate::lint::Entry;
+use crate::net;
+use crate::probe::{self, HostProbe};
+use rayon::prelude::*;
+use reqwest::Url;
+use std::collections::{BTreeMap, HashSet};
+
+/// MirrorDiff is what a target mirror lacks compared to its source
+#[derive(Debug, Default)]
+pub struct MirrorDiff {
+    /// Files of the source that the target doesn't have
+    pub missing: Vec<String>,
+    /// Files whose size differs: (path, source size, target size)
+    pub different: Vec<(String, u64, u64)>,
+    /// Files of the target that the source doesn't have (removed or renamed on the source)
+    pub extra: Vec<String>,
+    /// Number of files both mirrors have with the same size
+    pub identical: usize,
+}
+
+/// Fetches the size of every file of `entries` that `wanted` returns true for
+/// Files whose size the crawl already probed are skipped
+///
+/// # Arguments
+/// * `entries`     -   Files of a crawled mirror (see `lint::remote_entries`)
+/// * `root_url`    -   Url the crawl started at
+/// * `wanted`      -   Returns true for the paths whose size is needed
+fn probe_sizes<F: Fn(&str) -> bool + Sync>(entries: &mut [Entry], root_url: &str, wanted: F) {
+    let client = net::client();
+    let host_probe = HostProbe::new();
+
+    entries
+        .par_iter_mut()
+        .filter(|e| e.size.is_none() && wanted(&e.path))
+        .for_each(|entry| {
+            entry.size = Url::parse(root_url)
+                .and_then(|root| root.join(&entry.path))
+                .ok()
+                .and_then(|url| host_probe.send(&client, url).ok())
+                .and_then(|resp| probe::remote_size(&resp));
+        });
+}
+
+/// Compares the files of two crawled mirrors by path, then by size for the files both have
+///
+/// # Arguments
+/// * `source`      -   Files of the primary mirror
+/// * `source_url`  -   Url the crawl of the primary mirror started at
+/// * `target`      -   Files of the secondary mirror
+/// * `target_url`  -   Url the crawl of the secondary mirror started at
+pub fn compare(
+    mut source: Vec<Entry>,
+    source_url: &str,
+    mut target: Vec<Entry>,
+    target_url: &str,
+) -> MirrorDiff {
+    let source_paths = source
+        .iter()
+        .map(|e| e.path.clone())
+        .collect::<HashSet<_>>();
+    let target_paths = target
+        .iter()
+        .map(|e| e.path.clone())
+        .collect::<HashSet<_>>();
+
+    // Only the files both mirrors have need a request
+    probe_sizes(&mut source, source_url, |path| target_paths.contains(path));
+    probe_sizes(&mut target, target_url, |path| source_paths.contains(path));
+
+    let source = source
+        .into_iter()
+        .map(|e| (e.path, e.size))
+        .collect::<BTreeMap<_, _>>();
+    let target = target
+        .into_iter()
+        .map(|e| (e.path, e.size))
+        .collect::<BTreeMap<_, _>>();
+    let mut diff = MirrorDiff::default();
+
+    for (path, size) in &source {
+        match (target.get(path), size) {
+            (None, _) => diff.missing.push(path.clone()),
+            (Some(Some(target_size)), Some(source_size)) if target_size != source_size => diff
+                .different
+                .push((path.clone(), *source_size, *target_size)),
+            _ => diff.identical += 1,
+        }
+    }
+
+    diff.extra = target
+        .keys()
+        .filter(|path| !source.contains_key(*path))
+        .cloned()
+        .collect();
+
+    diff
+}
+
+/// Prints the files the target lacks and a summary
+///
+/// # Arguments
+/// * `diff`    -   Differences returned by `compare`
+pub fn print(diff: &MirrorDiff) {
+    for path in &diff.missing {
+        println!("missing:\t{}", path);
+    }
+    for (path, source_size, target_size) in &diff.different {
+        println!(
+            "different:\t{}\t({} bytes on the source, {} on the target)",
+            path, source_size, target_size
+        );
+    }
+    for path in &diff.extra {
+        println!("extra:\t{}", path);
+    }
+
+    println!(
+        "\n{} missing, {} different, {} extra, {} identical",
+        diff.missing.len(),
+        diff.different.len(),
+        diff.extra.len(),
+        diff.identical,
+    );
+}
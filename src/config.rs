@@ -0,0 +1,669 @@
+use crate::case_sense::CaseFold;
+use crate::deletion;
+use crate::destinations::Destinations;
+use crate::discovery::ProviderSpec;
+use crate::durability;
+use crate::events::EventFormat;
+use crate::layout::Layout;
+use crate::monitor;
+use crate::net::{self, IpVersion};
+use crate::overlay::ProgressEndpoint;
+use crate::path_template::PathTemplate;
+use crate::rewrite::Rewrites;
+use crate::scan::ScanPolicy;
+use crate::schedule::Schedule;
+use crate::snapshot;
+use crate::sound::DuplicateRule;
+use crate::storage::StorageTarget;
+use crate::throttle::parse_rate;
+use crate::ui::ProgressMode;
+use crate::variants::Variant;
+use crate::zip_archive::ArchivePasswords;
+use std::{collections::HashMap, path::PathBuf};
+
+/// Default minutes between two polls of the followed game server
+const DEFAULT_FOLLOW_INTERVAL: u64 = 5;
+/// Default seconds between two plain status lines
+const DEFAULT_PROGRESS_INTERVAL: u64 = 5;
+
+/// Command is what the program does
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum Command {
+    /// Crawl, download and decode the fastdl (default, `sync`)
+    #[default]
+    Sync,
+    /// Crawl the fastdl and write the download links to the link list (`crawl [--links <file>]`)
+    Crawl,
+    /// Download the links of the link list (`download [--links <file>]`)
+    Download,
+    /// Decode the bz2 files of the given directories or files (`decode [path]...`)
+    /// Without paths, the directories the fastdl urls download into are decoded
+    Decode(Vec<PathBuf>),
+    /// Print file-type statistics of the local mirror (`stats [dir]`)
+    Stats(PathBuf),
+    /// Verify the mirror against the manifest and repair damaged files (`verify`)
+    Verify,
+    /// Check a local directory or a fastdl url against the in-game downloader's rules (`lint [dir|url]`)
+    Lint(String),
+    /// Sync, then link the mirror into the `download` folder of every game directory (`link-install <game_dir>...`)
+    LinkInstall(Vec<PathBuf>),
+    /// Report the files a mirror lacks compared to another one (`compare --source <url> --target <url>`)
+    Compare,
+    /// Register watch mode as a background service (`install-service --watch <secs> ...`)
+    InstallService,
+    /// Remove the background service (`uninstall-service`)
+    UninstallService,
+    /// Print the remote files added, removed or modified since the last run (`changes [--feed <file>]`)
+    Changes,
+    /// Compress a server's game directory into a FastDL tree (`prepare --game-dir <dir> [--out <dir>]`)
+    Prepare,
+    /// Replace the running binary with the latest verified GitHub release (`self-update`)
+    SelfUpdate,
+    /// Create, restore, delete or list hardlinked snapshots of the mirror
+    /// (`snapshot <create|restore|delete> <name>`, `snapshot list`)
+    Snapshot(Vec<String>),
+    /// Check the fastdls, output directories, open files limit and terminal, printing fixes (`doctor`)
+    Doctor,
+}
+
+/// Config stores the options passed on the command line
+#[derive(Clone, Debug, Default)]
+pub struct Config {
+    /// What the program does
+    pub command: Command,
+    /// Only examine directories modified since the last successful sync (`--since-last-run`)
+    pub since_last_run: bool,
+    /// Copy that is downloaded when a file is served both raw and as `.bz2` (`--prefer <bz2|raw>`)
+    pub prefer: Variant,
+    /// Files dropped in favor of a copy with another extension (`--drop-duplicate <ext>:<ext>`)
+    pub drop_duplicates: Vec<DuplicateRule>,
+    /// Size of the dedicated pool small files (e.g. sounds) are downloaded by (`--small-file-workers <n>`)
+    pub small_file_workers: Option<usize>,
+    /// Path of the synced maps report (`--report <file.md|file.html>`)
+    pub report: Option<PathBuf>,
+    /// Url template of a community map database page used to enrich the report (`--map-db <url>`)
+    pub map_db: Option<String>,
+    /// Directory of the static HTML map browser built after the sync (`--map-browser <dir>`)
+    pub map_browser: Option<PathBuf>,
+    /// Serve the local mirror over HTTP instead of syncing (`--serve <addr>`)
+    pub serve: Option<String>,
+    /// Sync again every N seconds instead of exiting (`--watch <secs>`)
+    pub watch: Option<u64>,
+    /// Address of the Prometheus metrics endpoint in watch mode (`--metrics <addr>`)
+    pub metrics: Option<String>,
+    /// File listing the only map names that are synced (`--maps <file>`)
+    pub maps: Option<PathBuf>,
+    /// Nominations or rotation list exported by SourceMod's mapchooser plugins (`--nominations <file>`)
+    pub nominations: Option<PathBuf>,
+    /// Game server whose current/next map is fetched right away in watch mode (`--follow <addr>`)
+    pub follow: Option<String>,
+    /// Minutes between two polls of the followed game server (`--follow-interval <mins>`)
+    pub follow_interval: u64,
+    /// Url the watch mode alerts are posted to when a fastdl goes down or recovers (`--alert-webhook <url>`)
+    pub alert_webhook: Option<String>,
+    /// Seconds between two probes of an unreachable fastdl in watch mode (`--monitor-interval <secs>`)
+    pub monitor_interval: u64,
+    /// Start CS:S through Steam after a successful sync (`--launch`)
+    pub launch: bool,
+    /// Game server CS:S connects to when it's launched (`--connect <addr>`)
+    pub connect: Option<String>,
+    /// File of sha256 hashes or file names that are never synced (`--blocklist <file>`)
+    pub blocklist: Option<PathBuf>,
+    /// Files whose remote size is larger are skipped (`--max-file-size <size>`)
+    pub max_file_size: Option<u64>,
+    /// The only extensions that are downloaded, every extension if empty (`--allow-ext <ext,...>`)
+    pub allowed_extensions: Vec<String>,
+    /// Bytes per second shared by every download (`--limit-rate <rate>`)
+    pub limit_rate: Option<u64>,
+    /// Host -> bytes per second of the downloads from that host (`--host-limit <host>=<rate>`)
+    pub host_limits: HashMap<String, u64>,
+    /// Keep only the N newest versions of every map family locally (`--prune-keep <n>`)
+    pub prune_keep: Option<usize>,
+    /// Don't download map versions that would be pruned (`--skip-old-versions`)
+    pub skip_old_versions: bool,
+    /// Address of the control interface (pause/resume/status/requeue) (`--control <addr>`)
+    pub control: Option<String>,
+    /// Directory the decoded files are written to, keeping their relative paths (`--extract-to <dir>`)
+    pub extract_to: Option<PathBuf>,
+    /// Directories the decoded files of some categories are written to (`--category-dest <category>=<dir>`)
+    pub destinations: Destinations,
+    /// Which symlinks and hidden directories the local scans walk into
+    /// (`--follow-symlinks`, `--skip-hidden`)
+    pub scan: ScanPolicy,
+    /// Write a local crash report on panics and fatal errors (`--crash-reports`)
+    pub crash_reports: bool,
+    /// Game directory of the server that is prepared (`--game-dir <dir>`)
+    pub game_dir: Option<PathBuf>,
+    /// Directory the prepared FastDL tree is written to (`--out <dir>`)
+    pub out: Option<PathBuf>,
+    /// Named profile of the profiles file that is synced (`--profile <name>`)
+    pub profile: Option<String>,
+    /// Sync every profile of the profiles file back-to-back (`--all-profiles`)
+    pub all_profiles: bool,
+    /// Path of the profiles file (`--profiles <file>`, `fastdl_profiles.json` by default)
+    pub profiles_file: Option<PathBuf>,
+    /// Format of the structured events (`--events ndjson`)
+    pub events: Option<EventFormat>,
+    /// File the events are written to instead of stdout (`--events-file <path>`)
+    pub events_file: Option<PathBuf>,
+    /// How progress is shown (`--progress <plain|fancy|none>`, detected from the terminal by default)
+    pub progress: Option<ProgressMode>,
+    /// Seconds between two plain status lines (`--progress-interval <secs>`)
+    pub progress_interval: u64,
+    /// Where stream overlays read the progress from (`--progress-endpoint <addr|path>`)
+    pub progress_endpoint: Option<ProgressEndpoint>,
+    /// Link list written by `crawl` and read by `download` (`--links <file>`, "-" for stdout/stdin)
+    pub links: Option<PathBuf>,
+    /// Trust the listings instead of sending a request per link while crawling (`--fast-crawl`)
+    pub fast_crawl: bool,
+    /// Minisign secret key the published checksums of the mirror are signed with (`--publish-sums <key>`)
+    pub publish_sums: Option<PathBuf>,
+    /// Only download the files with no local copy, without comparing sizes, dates or tags (`--only-missing`)
+    pub only_missing: bool,
+    /// When watch mode may sync (`--sync-window <window>`, `--quiet-hours <window>`)
+    pub schedule: Schedule,
+    /// Directory the compressed files are moved to once decoded instead of being deleted (`--keep-archives <dir>`)
+    pub keep_archives: Option<PathBuf>,
+    /// Largest size of the archive directory in bytes, least recently used first (`--archive-max-size <size>`)
+    pub archive_max_size: Option<u64>,
+    /// Days an archive is kept after it was last used (`--archive-max-age <days>`)
+    pub archive_max_age: Option<u64>,
+    /// Fastdl url of the primary mirror (`--source <url>`)
+    pub source: Option<String>,
+    /// Fastdl url of the secondary mirror (`--target <url>`)
+    pub target: Option<String>,
+    /// Running as the background service: syncs are logged to the native log (`--service`)
+    pub service: bool,
+    /// Seconds a download may take before it's deferred to a second pass (`--file-time-budget <secs>`)
+    pub file_time_budget: Option<u64>,
+    /// Fetch a random range of every downloaded file again and compare it with the file on disk (`--paranoid`)
+    pub paranoid: bool,
+    /// Clone the mirror copy-on-write into the game directories instead of linking it (`--reflink`)
+    pub reflink: bool,
+    /// Where the synced files go inside of the output directory (`--layout <download|flat|custom|mirror>`)
+    pub layout: Layout,
+    /// Template of the local path of every file, inside of the layout's base directory (`--path-template <template>`)
+    pub path_template: Option<PathTemplate>,
+    /// Lowercase the local paths or their extensions (`--fold-case <paths|ext>`)
+    pub fold_case: Option<CaseFold>,
+    /// RSS or Atom feed `changes` writes (`--feed <file>`)
+    pub feed: Option<PathBuf>,
+    /// Number of downloaded files between two flushes of the manifest and the queue (`--checkpoint-every <files>`)
+    pub checkpoint_every: usize,
+    /// Flush file data and state files to the disk before they count as written (`--fsync`)
+    pub fsync: bool,
+    /// Address family of the connections (`--ip-version <auto|4|6>`)
+    pub ip_version: IpVersion,
+    /// Host -> address used instead of resolving it (`--resolve host:ip`)
+    pub resolve: Vec<(String, std::net::IpAddr)>,
+    /// Concurrent ranged requests of every file over 100 MB, written in place (`--segments <n>`)
+    pub segments: usize,
+    /// Where the downloaded files are written (`--storage <local|memory|s3://bucket/prefix>`)
+    pub storage: StorageTarget,
+    /// Paths (relative to the fastdl url) downloaded from the Wayback Machine if the fastdl no longer has them (`--wayback <path>`)
+    pub wayback: Vec<String>,
+    /// Subtrees of the fastdl the crawl is restricted to, relative to the fastdl url (`--prefix <path>`)
+    pub prefixes: Vec<String>,
+    /// Rules rewriting the links found in the listings (`--rewrite <host> <find> <replace>`)
+    pub rewrites: Rewrites,
+    /// Passwords of protected zip archives (`--archive-password <host|pattern> <password>`)
+    pub archive_passwords: ArchivePasswords,
+    /// Always crawl the listings even if the fastdl has a sitemap (`--no-sitemap`)
+    pub no_sitemap: bool,
+    /// Never fetch the checksums the fastdl publishes (`--no-remote-sums`)
+    pub no_remote_sums: bool,
+    /// Minisign public key the checksums of the fastdl must be signed with (`--sums-key <key>`)
+    pub sums_key: Option<String>,
+    /// Sources the files are discovered from, merged into one download set (`--discover <provider>`,
+    /// the sitemap or the listings if empty)
+    pub discover: Vec<ProviderSpec>,
+    /// Delete the local copies of files that vanished upstream (`--delete`)
+    pub delete: bool,
+    /// Delete more than `delete_threshold` percent of the mirror without asking (`--confirm-delete`)
+    pub confirm_delete: bool,
+    /// Percentage of the mirror `--delete` removes without confirmation (`--delete-threshold <percent>`)
+    pub delete_threshold: f64,
+    /// Send one request at a time with lenient timeouts and no console GUI, for flaky routers (`--compat`)
+    pub compat: bool,
+    /// Files downloaded at once instead of the measured default (`--download-workers <n>`)
+    pub download_workers: Option<usize>,
+    /// Files decoded at once instead of the measured default (`--decode-workers <n>`)
+    pub decode_workers: Option<usize>,
+    /// Measure the link again instead of using `fastdl_tuning.json` (`--retune`)
+    pub retune: bool,
+}
+
+impl Config {
+    /// Returns a Config parsed from the arguments of the process
+    pub fn from_args() -> Result<Self, String> {
+        Self::parse(std::env::args().skip(1))
+    }
+
+    /// Returns a Config parsed from `args`
+    ///
+    /// # Arguments
+    /// * `args`    -   Command line arguments without the program name
+    pub fn parse<I: IntoIterator<Item = String>>(args: I) -> Result<Self, String> {
+        let mut config = Self {
+            follow_interval: DEFAULT_FOLLOW_INTERVAL,
+            monitor_interval: monitor::DEFAULT_INTERVAL,
+            progress_interval: DEFAULT_PROGRESS_INTERVAL,
+            delete_threshold: deletion::DEFAULT_THRESHOLD,
+            segments: 1,
+            checkpoint_every: durability::DEFAULT_CHECKPOINT_EVERY,
+            ..Self::default()
+        };
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--since-last-run" => config.since_last_run = true,
+                "--compat" => config.compat = true,
+                "--download-workers" => {
+                    config.download_workers = Some(parse_value(&mut args, &arg)?)
+                }
+                "--decode-workers" => config.decode_workers = Some(parse_value(&mut args, &arg)?),
+                "--retune" => config.retune = true,
+                "--prefer" => config.prefer = value_of(&mut args, &arg)?.parse()?,
+                "--report" => config.report = Some(value_of(&mut args, &arg)?.into()),
+                "--map-db" => config.map_db = Some(value_of(&mut args, &arg)?),
+                "--map-browser" => config.map_browser = Some(value_of(&mut args, &arg)?.into()),
+                "--serve" => config.serve = Some(value_of(&mut args, &arg)?),
+                "--watch" => config.watch = Some(parse_value(&mut args, &arg)?),
+                "--metrics" => config.metrics = Some(value_of(&mut args, &arg)?),
+                "--maps" => config.maps = Some(value_of(&mut args, &arg)?.into()),
+                "--nominations" => config.nominations = Some(value_of(&mut args, &arg)?.into()),
+                "--follow" => config.follow = Some(value_of(&mut args, &arg)?),
+                "--follow-interval" => config.follow_interval = parse_value(&mut args, &arg)?,
+                "--alert-webhook" => config.alert_webhook = Some(value_of(&mut args, &arg)?),
+                "--monitor-interval" => config.monitor_interval = parse_value(&mut args, &arg)?,
+                "--launch" => config.launch = true,
+                "--connect" => config.connect = Some(value_of(&mut args, &arg)?),
+                "--blocklist" => config.blocklist = Some(value_of(&mut args, &arg)?.into()),
+                "--max-file-size" => {
+                    let size = value_of(&mut args, &arg)?;
+                    config.max_file_size =
+                        Some(parse_rate(&size).ok_or_else(|| format!("Invalid size: {}", size))?);
+                }
+                "--allow-ext" => config.allowed_extensions.extend(
+                    value_of(&mut args, &arg)?
+                        .split(',')
+                        .map(|ext| ext.trim().to_string())
+                        .filter(|ext| !ext.is_empty()),
+                ),
+                "--prune-keep" => config.prune_keep = Some(parse_value(&mut args, &arg)?),
+                "--skip-old-versions" => config.skip_old_versions = true,
+                "--control" => config.control = Some(value_of(&mut args, &arg)?),
+                "--follow-symlinks" => config.scan.follow_symlinks = true,
+                "--skip-hidden" => config.scan.skip_hidden = true,
+                "--crash-reports" => config.crash_reports = true,
+                "--game-dir" => config.game_dir = Some(value_of(&mut args, &arg)?.into()),
+                "--out" => config.out = Some(value_of(&mut args, &arg)?.into()),
+                "--profile" => config.profile = Some(value_of(&mut args, &arg)?),
+                "--all-profiles" => config.all_profiles = true,
+                "--events" => config.events = Some(value_of(&mut args, &arg)?.parse()?),
+                "--events-file" => config.events_file = Some(value_of(&mut args, &arg)?.into()),
+                "--progress" => config.progress = Some(value_of(&mut args, &arg)?.parse()?),
+                "--progress-interval" => config.progress_interval = parse_value(&mut args, &arg)?,
+                "--progress-endpoint" => {
+                    config.progress_endpoint = Some(value_of(&mut args, &arg)?.parse()?)
+                }
+                "--sync-window" => config
+                    .schedule
+                    .windows
+                    .push(value_of(&mut args, &arg)?.parse()?),
+                "--quiet-hours" => config
+                    .schedule
+                    .quiet_hours
+                    .push(value_of(&mut args, &arg)?.parse()?),
+                "--keep-archives" => config.keep_archives = Some(value_of(&mut args, &arg)?.into()),
+                "--archive-max-size" => {
+                    let size = value_of(&mut args, &arg)?;
+                    config.archive_max_size =
+                        Some(parse_rate(&size).ok_or_else(|| format!("Invalid size: {}", size))?);
+                }
+                "--archive-max-age" => config.archive_max_age = Some(parse_value(&mut args, &arg)?),
+                "--source" => config.source = Some(value_of(&mut args, &arg)?),
+                "--target" => config.target = Some(value_of(&mut args, &arg)?),
+                "--service" => config.service = true,
+                "--paranoid" => config.paranoid = true,
+                "--reflink" => config.reflink = true,
+                "--no-sitemap" => config.no_sitemap = true,
+                "--no-remote-sums" => config.no_remote_sums = true,
+                "--sums-key" => config.sums_key = Some(value_of(&mut args, &arg)?),
+                "--discover" => config.discover.push(value_of(&mut args, &arg)?.parse()?),
+                "--ip-version" => config.ip_version = value_of(&mut args, &arg)?.parse()?,
+                "--resolve" => config
+                    .resolve
+                    .push(net::parse_resolve(&value_of(&mut args, &arg)?)?),
+                "--checkpoint-every" => config.checkpoint_every = parse_value(&mut args, &arg)?,
+                "--fsync" => config.fsync = true,
+                "--segments" => config.segments = parse_value(&mut args, &arg)?,
+                "--drop-duplicate" => config
+                    .drop_duplicates
+                    .push(value_of(&mut args, &arg)?.parse()?),
+                "--small-file-workers" => {
+                    config.small_file_workers = Some(parse_value(&mut args, &arg)?)
+                }
+                "--wayback" => config.wayback.push(value_of(&mut args, &arg)?),
+                "--prefix" => config.prefixes.push(value_of(&mut args, &arg)?),
+                "--rewrite" => {
+                    let host = value_of(&mut args, &arg)?;
+                    let find = value_of(&mut args, &arg)?;
+                    let replace = value_of(&mut args, &arg)?;
+                    config.rewrites.insert(&host, &find, &replace)?;
+                }
+                "--archive-password" => {
+                    let pattern = value_of(&mut args, &arg)?;
+                    let password = value_of(&mut args, &arg)?;
+                    config.archive_passwords.insert(&pattern, &password);
+                }
+                "--layout" => config.layout = value_of(&mut args, &arg)?.parse()?,
+                "--storage" => config.storage = value_of(&mut args, &arg)?.parse()?,
+                "--path-template" => {
+                    config.path_template = Some(value_of(&mut args, &arg)?.parse()?)
+                }
+                "--fold-case" => config.fold_case = Some(value_of(&mut args, &arg)?.parse()?),
+                "--feed" => config.feed = Some(value_of(&mut args, &arg)?.into()),
+                "--delete" => config.delete = true,
+                "--confirm-delete" => config.confirm_delete = true,
+                "--delete-threshold" => config.delete_threshold = parse_value(&mut args, &arg)?,
+                "--file-time-budget" => {
+                    config.file_time_budget = Some(parse_value(&mut args, &arg)?)
+                }
+                "--fast-crawl" => config.fast_crawl = true,
+                "--only-missing" => config.only_missing = true,
+                "--publish-sums" => config.publish_sums = Some(value_of(&mut args, &arg)?.into()),
+                "--links" => config.links = Some(value_of(&mut args, &arg)?.into()),
+                "--profiles" => config.profiles_file = Some(value_of(&mut args, &arg)?.into()),
+                // Syncing is the default, the command only makes `sync --all-profiles` read naturally
+                "sync" if config.command == Command::Sync => {}
+                "--extract-to" => config.extract_to = Some(value_of(&mut args, &arg)?.into()),
+                "--category-dest" => config
+                    .destinations
+                    .insert_arg(&value_of(&mut args, &arg)?)?,
+                "--limit-rate" => {
+                    let rate = value_of(&mut args, &arg)?;
+                    config.limit_rate =
+                        Some(parse_rate(&rate).ok_or_else(|| format!("Invalid rate: {}", rate))?);
+                }
+                "--host-limit" => {
+                    let limit = value_of(&mut args, &arg)?;
+                    let (host, rate) = limit
+                        .split_once('=')
+                        .and_then(|(host, rate)| Some((host.to_string(), parse_rate(rate)?)))
+                        .ok_or_else(|| {
+                            format!("Invalid host limit: {} (expected host=rate)", limit)
+                        })?;
+                    config.host_limits.insert(host, rate);
+                }
+                "decode" if config.command == Command::Sync => {
+                    config.command = Command::Decode(Vec::new())
+                }
+                "crawl" if config.command == Command::Sync => config.command = Command::Crawl,
+                "download" if config.command == Command::Sync => config.command = Command::Download,
+                "verify" if config.command == Command::Sync => config.command = Command::Verify,
+                "compare" if config.command == Command::Sync => config.command = Command::Compare,
+                "install-service" if config.command == Command::Sync => {
+                    config.command = Command::InstallService
+                }
+                "uninstall-service" if config.command == Command::Sync => {
+                    config.command = Command::UninstallService
+                }
+                "link-install" if config.command == Command::Sync => {
+                    config.command = Command::LinkInstall(Vec::new())
+                }
+                "prepare" if config.command == Command::Sync => config.command = Command::Prepare,
+                "changes" if config.command == Command::Sync => config.command = Command::Changes,
+                "doctor" if config.command == Command::Sync => config.command = Command::Doctor,
+                "self-update" if config.command == Command::Sync => {
+                    config.command = Command::SelfUpdate
+                }
+                "lint" if config.command == Command::Sync => {
+                    config.command = Command::Lint(String::from("."))
+                }
+                "snapshot" if config.command == Command::Sync => {
+                    config.command = Command::Snapshot(Vec::new())
+                }
+                "stats" if config.command == Command::Sync => {
+                    config.command = Command::Stats(PathBuf::from("."))
+                }
+                _ if !arg.starts_with("--") => match &mut config.command {
+                    Command::Decode(paths) | Command::LinkInstall(paths) => paths.push(arg.into()),
+                    Command::Snapshot(args) => args.push(arg),
+                    Command::Stats(dir) => *dir = arg.into(),
+                    Command::Lint(target) => *target = arg,
+                    Command::Sync
+                    | Command::Crawl
+                    | Command::Download
+                    | Command::Verify
+                    | Command::Compare
+                    | Command::InstallService
+                    | Command::UninstallService
+                    | Command::Prepare
+                    | Command::Changes
+                    | Command::SelfUpdate
+                    | Command::Doctor => return Err(format!("Unknown command: {}", arg)),
+                },
+                _ => return Err(format!("Unknown argument: {}", arg)),
+            }
+        }
+
+        if let Command::Snapshot(args) = &config.command {
+            snapshot::Action::parse(args)?;
+        }
+
+        if config.command == Command::LinkInstall(Vec::new()) {
+            return Err(String::from(
+                "link-install needs at least one game directory",
+            ));
+        }
+
+        if config.command == Command::Compare
+            && (config.source.is_none() || config.target.is_none())
+        {
+            return Err(String::from("compare needs --source and --target"));
+        }
+
+        if config.command == Command::InstallService && config.watch.is_none() {
+            return Err(String::from(
+                "install-service needs --watch (the service runs watch mode)",
+            ));
+        }
+
+        if config.command == Command::Prepare && config.game_dir.is_none() {
+            return Err(String::from("prepare needs --game-dir"));
+        }
+
+        if config.profile.is_some() && config.all_profiles {
+            return Err(String::from(
+                "--profile and --all-profiles can't be used together",
+            ));
+        }
+
+        if config.links.is_some() && !matches!(config.command, Command::Crawl | Command::Download) {
+            return Err(String::from(
+                "--links can only be used with crawl or download",
+            ));
+        }
+
+        if (config.archive_max_size.is_some() || config.archive_max_age.is_some())
+            && config.keep_archives.is_none()
+        {
+            return Err(String::from(
+                "--archive-max-size and --archive-max-age can only be used with --keep-archives",
+            ));
+        }
+
+        if config.small_file_workers == Some(0) {
+            return Err(String::from("--small-file-workers needs at least 1 worker"));
+        }
+
+        if config.checkpoint_every == 0 {
+            return Err(String::from("--checkpoint-every needs at least 1 file"));
+        }
+
+        if config.segments == 0 {
+            return Err(String::from("--segments needs at least 1 segment"));
+        }
+
+        if !config.archive_passwords.is_empty() && !cfg!(feature = "zip") {
+            return Err(String::from(
+                "--archive-password needs a build with the zip feature",
+            ));
+        }
+
+        if config.path_template.is_some() && config.layout == Layout::Mirror {
+            return Err(String::from(
+                "--path-template can't be used with --layout mirror",
+            ));
+        }
+
+        // The files of a remote storage can't be read back, so nothing may work on them in place
+        if config.storage != StorageTarget::Local {
+            let local_only = [
+                (config.delete, "--delete"),
+                (config.paranoid, "--paranoid"),
+                (config.segments > 1, "--segments"),
+                (config.extract_to.is_some(), "--extract-to"),
+                (!config.destinations.is_empty(), "--category-dest"),
+                (config.keep_archives.is_some(), "--keep-archives"),
+                (
+                    matches!(config.command, Command::LinkInstall(_)),
+                    "link-install",
+                ),
+            ];
+            if let Some((_, option)) = local_only.iter().find(|(used, _)| *used) {
+                return Err(format!(
+                    "{} needs the local storage (the files of --storage {} can't be read back)",
+                    option,
+                    match &config.storage {
+                        StorageTarget::S3 { .. } => "s3://",
+                        _ => "memory",
+                    }
+                ));
+            }
+        }
+
+        // Compatibility mode: nothing may open several connections at once, or redraw the GUI
+        if config.compat {
+            let parallel = [
+                (config.segments > 1, "--segments"),
+                (config.small_file_workers.is_some(), "--small-file-workers"),
+                (config.download_workers.is_some(), "--download-workers"),
+                (config.decode_workers.is_some(), "--decode-workers"),
+                (
+                    config.progress == Some(ProgressMode::Fancy),
+                    "--progress fancy",
+                ),
+            ];
+            if let Some((_, option)) = parallel.iter().find(|(used, _)| *used) {
+                return Err(format!("{} can't be used with --compat", option));
+            }
+            config.progress = config.progress.or(Some(ProgressMode::Plain));
+        }
+
+        if config.feed.is_some() && config.command != Command::Changes {
+            return Err(String::from("--feed can only be used with changes"));
+        }
+
+        if config.events_file.is_some() && config.events.is_none() {
+            return Err(String::from("--events-file can only be used with --events"));
+        }
+
+        if config.follow.is_some() && config.watch.is_none() {
+            return Err(String::from("--follow can only be used with --watch"));
+        }
+
+        if config.control.is_some() && config.watch.is_none() {
+            return Err(String::from("--control can only be used with --watch"));
+        }
+
+        if config.alert_webhook.is_some() && config.watch.is_none() {
+            return Err(String::from(
+                "--alert-webhook can only be used with --watch",
+            ));
+        }
+
+        if config.download_workers == Some(0) || config.decode_workers == Some(0) {
+            return Err(String::from(
+                "--download-workers and --decode-workers must be at least 1",
+            ));
+        }
+
+        if config.monitor_interval == 0 {
+            return Err(String::from("--monitor-interval must be at least 1"));
+        }
+
+        // Keeping no version would delete every map
+        if config.prune_keep == Some(0) {
+            return Err(String::from("--prune-keep must be at least 1"));
+        }
+
+        if (!config.schedule.windows.is_empty() || !config.schedule.quiet_hours.is_empty())
+            && config.watch.is_none()
+        {
+            return Err(String::from(
+                "--sync-window and --quiet-hours can only be used with --watch",
+            ));
+        }
+
+        if (config.confirm_delete || config.delete_threshold != deletion::DEFAULT_THRESHOLD)
+            && !config.delete
+        {
+            return Err(String::from(
+                "--confirm-delete and --delete-threshold can only be used with --delete",
+            ));
+        }
+
+        // Nothing is compared, so no link needs a request while crawling
+        if config.only_missing {
+            config.fast_crawl = true;
+        }
+
+        // Quick mode skips the directories that did not change, so their files would look vanished
+        // Files found by a map list or a game server alone don't tell what vanished upstream
+        let lists_everything = config.discover.is_empty()
+            || config
+                .discover
+                .iter()
+                .any(|spec| matches!(spec, ProviderSpec::Listing | ProviderSpec::Sitemap));
+        if config.delete && !lists_everything {
+            return Err(String::from(
+                "--delete needs the listing or sitemap provider with --discover",
+            ));
+        }
+
+        if config.delete && config.since_last_run {
+            return Err(String::from("--delete can't be used with --since-last-run"));
+        }
+
+        Ok(config)
+    }
+}
+
+/// Returns the value that follows `flag`
+///
+/// # Arguments
+/// * `args`    -   Remaining command line arguments
+/// * `flag`    -   Flag the value belongs to (used for the error message)
+fn value_of<I: Iterator<Item = String>>(args: &mut I, flag: &str) -> Result<String, String> {
+    args.next()
+        .ok_or_else(|| format!("Missing value for {}", flag))
+}
+
+/// Returns the value that follows `flag` parsed as `T`
+///
+/// # Arguments
+/// * `args`    -   Remaining command line arguments
+/// * `flag`    -   Flag the value belongs to (used for the error message)
+fn parse_value<T: std::str::FromStr, I: Iterator<Item = String>>(
+    args: &mut I,
+    flag: &str,
+) -> Result<T, String> {
+    let value = value_of(args, flag)?;
+
+    value
+        .parse()
+        .map_err(|_| format!("Invalid value for {}: {}", flag, value))
+}
@@ -0,0 +1,241 @@
+use crate::{
+    meta_dir,
+    queue::DownloadQueue,
+    serve::{self, Request},
+    state_store::StateStore,
+};
+use percent_encoding::percent_decode_str;
+use std::{env, fs, io, net::TcpListener, path::Path, sync::Arc};
+
+/// Environment variable holding the token of the control interface
+pub const TOKEN_VAR: &str = "FASTDL_CONTROL_TOKEN";
+/// Name of the file the generated token is written to, for the scripts that control the sync
+const TOKEN_FILE: &str = "fastdl_control_token";
+
+/// Controlled is a profile whose download queue the control interface drives
+pub struct Controlled {
+    /// Name of the profile
+    pub name: String,
+    /// Download queue of the profile
+    pub queue: Arc<DownloadQueue>,
+    /// State store of the profile, only the links it knows can be requeued
+    pub store: Arc<dyn StateStore>,
+}
+
+/// Returns the token requests must send (`Authorization: Bearer <token>`)
+/// It's read from `FASTDL_CONTROL_TOKEN`, otherwise a random one is generated and written to
+/// `.fastdl/fastdl_control_token` of `out_dir`, readable by the owner only
+///
+/// # Arguments
+/// * `out_dir` -   Output directory of the first profile
+pub fn token(out_dir: &Path) -> io::Result<String> {
+    if let Ok(token) = env::var(TOKEN_VAR) {
+        if !token.is_empty() {
+            return Ok(token);
+        }
+    }
+
+    let mut bytes = [0u8; 32];
+    getrandom::getrandom(&mut bytes).map_err(|err| io::Error::other(err.to_string()))?;
+    let token = bytes
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+
+    let path = meta_dir::path(out_dir, TOKEN_FILE);
+    fs::create_dir_all(out_dir.join(meta_dir::META_DIR))?;
+    fs::write(&path, &token)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    println!("Control token written to {}", path.display());
+
+    Ok(token)
+}
+
+/// Returns true if `request` carries `token`, compared in constant time
+fn authorized(request: &Request, token: &str) -> bool {
+    let Some(sent) = request
+        .header("authorization")
+        .and_then(|value| value.strip_prefix("Bearer "))
+    else {
+        return false;
+    };
+
+    sent.len() == token.len()
+        && sent
+            .bytes()
+            .zip(token.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Answers the commands of the control interface on `addr` (watch mode only)
+/// `GET /status`, `POST /pause`, `POST /resume` and `POST /requeue?url=<url>[&profile=<name>]`
+/// Every request needs the token, and commands that change the queue need POST so a web page
+/// can't send them from the browser of the user
+///
+/// # Arguments
+/// * `addr`        -   Address to listen on (e.g. "127.0.0.1:9101")
+/// * `token`       -   Token every request must send
+/// * `controlled`  -   Every profile that is controlled
+pub fn listen(addr: &str, token: &str, controlled: Vec<Controlled>) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    // Connections are answered one at a time, read_request gives up on idle and endless requests
+    // and the write timeout on clients that never read the answer
+    for mut stream in listener.incoming().flatten() {
+        if stream.set_write_timeout(Some(serve::READ_TIMEOUT)).is_err() {
+            continue;
+        }
+        let request = match serve::read_request(&mut stream) {
+            Ok(request) => request,
+            Err(_) => continue,
+        };
+
+        let (status, body) = if authorized(&request, token) {
+            run_command(&controlled, &request.method, &request.target)
+        } else {
+            ("401 Unauthorized", String::from("missing or wrong token"))
+        };
+        let _ = serve::respond(&mut stream, status, "text/plain", body.as_bytes(), false);
+    }
+
+    Ok(())
+}
+
+/// Returns the status of every queue, one line per profile
+fn status(controlled: &[Controlled]) -> String {
+    controlled
+        .iter()
+        .map(|profile| format!("{}: {}", profile.name, profile.queue.status()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Runs the command of a request, returning the status line and body of the response
+/// Pause and resume apply to every profile; requeue goes to the first profile unless `profile=` is given
+///
+/// # Arguments
+/// * `controlled`  -   Every profile that is controlled
+/// * `method`      -   Method of the request
+/// * `target`      -   Request target (e.g. "/requeue?url=https%3A%2F%2F...")
+fn run_command(controlled: &[Controlled], method: &str, target: &str) -> (&'static str, String) {
+    let (command, query) = target.split_once('?').unwrap_or((target, ""));
+    let param = |key: &str| {
+        query
+            .split('&')
+            .find_map(|pair| pair.strip_prefix(key)?.strip_prefix('='))
+            .and_then(|value| percent_decode_str(value).decode_utf8().ok())
+    };
+
+    match (method, command) {
+        ("GET", "/status") => ("200 OK", status(controlled)),
+        ("POST", "/pause") => {
+            controlled.iter().for_each(|profile| profile.queue.pause());
+            ("200 OK", status(controlled))
+        }
+        ("POST", "/resume") => {
+            controlled.iter().for_each(|profile| profile.queue.resume());
+            ("200 OK", status(controlled))
+        }
+        ("POST", "/requeue") => {
+            let profile = match param("profile") {
+                Some(name) => controlled.iter().find(|profile| profile.name == name),
+                None => controlled.first(),
+            };
+
+            match (param("url"), profile) {
+                // Only links the mirror synced before, a request never picks what gets downloaded
+                (Some(url), Some(profile)) if profile.store.file(&url).is_some() => {
+                    profile.queue.requeue(&url);
+                    ("200 OK", format!("requeued {} ({})", url, profile.name))
+                }
+                (Some(_), Some(_)) => (
+                    "400 Bad Request",
+                    String::from("unknown link, only synced files can be requeued"),
+                ),
+                (None, _) => ("400 Bad Request", String::from("missing url")),
+                (_, None) => ("400 Bad Request", String::from("unknown profile")),
+            }
+        }
+        (_, "/status" | "/pause" | "/resume" | "/requeue") => (
+            "405 Method Not Allowed",
+            String::from("use GET for /status and POST for the other commands"),
+        ),
+        _ => ("404 Not Found", String::from("unknown command")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::state_store::{JsonStore, ManifestEntry};
+
+    const TOKEN: &str = "0123456789abcdef";
+
+    #[test]
+    fn needs_the_exact_token() {
+        let request = |value: &str| Request::new("GET", "/status", &[("Authorization", value)]);
+
+        assert!(authorized(&request("Bearer 0123456789abcdef"), TOKEN));
+        assert!(!authorized(&request("Bearer 0123456789abcdee"), TOKEN));
+        assert!(!authorized(&request("Bearer 0123456789abcdef0"), TOKEN));
+        assert!(!authorized(&request("Bearer 0123456789abcde"), TOKEN));
+        assert!(!authorized(&request("Bearer "), TOKEN));
+        assert!(!authorized(&request("Basic 0123456789abcdef"), TOKEN));
+        assert!(!authorized(&request("0123456789abcdef"), TOKEN));
+        assert!(!authorized(&Request::new("GET", "/status", &[]), TOKEN));
+    }
+
+    #[test]
+    fn only_changes_the_queue_on_post() {
+        let dir = std::env::temp_dir().join(format!("fastdl_control_{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        let store = JsonStore::open(&dir.join("manifest.json")).unwrap();
+        store.record_file(
+            "https://fastdl.example.com/maps/ze_a.bsp.bz2",
+            ManifestEntry::default(),
+        );
+        let controlled = [Controlled {
+            name: String::from("default"),
+            queue: Arc::new(DownloadQueue::open(&dir.join("queue.json"))),
+            store: Arc::new(store),
+        }];
+
+        assert_eq!(run_command(&controlled, "GET", "/status").0, "200 OK");
+        assert_eq!(
+            run_command(&controlled, "GET", "/pause").0,
+            "405 Method Not Allowed"
+        );
+        assert_eq!(
+            run_command(&controlled, "POST", "/status/x").0,
+            "404 Not Found"
+        );
+
+        // Only links the mirror synced before can be requeued
+        let requeue =
+            |url: &str| run_command(&controlled, "POST", &format!("/requeue?url={}", url)).0;
+        assert_eq!(
+            requeue("https%3A%2F%2Ffastdl.example.com%2Fmaps%2Fze_a.bsp.bz2"),
+            "200 OK"
+        );
+        assert_eq!(
+            requeue("https%3A%2F%2Fevil.example.com%2Fpayload.exe"),
+            "400 Bad Request"
+        );
+        assert_eq!(
+            run_command(&controlled, "POST", "/requeue").0,
+            "400 Bad Request"
+        );
+        assert_eq!(
+            run_command(&controlled, "POST", "/requeue?url=x&profile=other").0,
+            "400 Bad Request"
+        );
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,133 @@
+use crate::{
+    crawl_stats::CrawlStats,
+    events::{self, Event},
+    files::{self, RemoteFile},
+    state_store::StateStore,
+};
+use dashmap::{DashMap, DashSet};
+use parking_lot::Mutex;
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
+
+/// CrawlState owns every piece of state shared by the threads of a crawl
+/// Each method takes at most one lock, so there is no lock ordering to get wrong
+pub struct CrawlState {
+    /// Visited paths (kept in the state store so very large mirrors can use sqlite)
+    store: Arc<dyn StateStore>,
+    /// Paths waiting to be visited
+    unvisited: Mutex<VecDeque<String>>,
+    /// Files that will be downloaded
+    download_links: DashSet<RemoteFile>,
+    /// Canonical links and relative paths of the recorded files, so every file is downloaded once
+    /// no matter how many listing paths resolve to it
+    canonical: DashSet<String>,
+    /// Download link -> listing link it was redirected from, for links redirected to signed CDN urls
+    origins: Arc<DashMap<String, String>>,
+    /// Live crawl metrics
+    stats: Arc<CrawlStats>,
+}
+
+/// Returns `path` without its trailing "/" (fastdl parent directory links don't have one)
+fn without_trailing_slash(path: &str) -> &str {
+    path.strip_suffix('/').unwrap_or(path)
+}
+
+impl CrawlState {
+    /// Returns a CrawlState with an empty queue, no download links and nothing visited
+    ///
+    /// # Arguments
+    /// * `store`   -   State store that keeps the visited paths
+    /// * `stats`   -   Live crawl metrics
+    /// * `origins` -   Where the listing links of signed download links are recorded
+    pub fn new(
+        store: Arc<dyn StateStore>,
+        stats: Arc<CrawlStats>,
+        origins: Arc<DashMap<String, String>>,
+    ) -> Self {
+        store.clear_visited();
+
+        Self {
+            store,
+            unvisited: Mutex::new(VecDeque::new()),
+            download_links: DashSet::new(),
+            canonical: DashSet::new(),
+            origins,
+            stats,
+        }
+    }
+
+    /// Marks `path` (with and without its trailing "/") as visited
+    /// Returns false if it was already visited
+    pub fn mark_visited(&self, path: &str) -> bool {
+        let alt_inserted = self.store.visit(without_trailing_slash(path));
+        self.store.visit(path) || alt_inserted
+    }
+
+    /// Returns true if `path` was visited
+    pub fn is_visited(&self, path: &str) -> bool {
+        self.store.is_visited(path)
+    }
+
+    /// Returns the number of visited paths
+    pub fn visited_len(&self) -> usize {
+        self.store.visited_len()
+    }
+
+    /// Adds paths that will be visited in the next level of the crawl
+    pub fn enqueue<I: IntoIterator<Item = String>>(&self, paths: I) {
+        self.unvisited.lock().extend(paths);
+    }
+
+    /// Removes and returns every path that is waiting to be visited
+    pub fn take_level(&self) -> Vec<String> {
+        self.unvisited.lock().drain(..).collect()
+    }
+
+    /// Records a file that will be downloaded, returning false if it was already recorded
+    /// A file whose canonical link or relative path was already recorded is a duplicate and is skipped
+    pub fn record_download(&self, file: RemoteFile) -> bool {
+        let canonical = self.canonical.insert(files::canonical_url(&file.url));
+        let rel_path = self.canonical.insert(format!("path:{}", file.rel_path));
+        if !canonical || !rel_path {
+            if !self.download_links.contains(&file) {
+                self.stats.skip_duplicate();
+            }
+            return false;
+        }
+
+        let inserted = self.download_links.insert(file.clone());
+
+        if inserted {
+            self.stats.found_link();
+            events::emit(&Event::LinkDiscovered {
+                url: &file.url,
+                size: file.size,
+                mtime: file.mtime,
+                etag: file.etag.as_deref(),
+            });
+        }
+
+        inserted
+    }
+
+    /// Records the listing link a signed download link was redirected from
+    /// The signature expires, so the download re-resolves the listing link if it's refused
+    pub fn record_origin(&self, link: &str, origin: &str) {
+        self.origins.insert(link.to_string(), origin.to_string());
+    }
+
+    /// Returns the number of links that will be downloaded
+    pub fn download_len(&self) -> usize {
+        self.download_links.len()
+    }
+
+    /// Returns every file that will be downloaded
+    pub fn download_links(&self) -> HashSet<RemoteFile> {
+        self.download_links
+            .iter()
+            .map(|file| file.clone())
+            .collect()
+    }
+}
@@ -0,0 +1,160 @@
+use crate::ui;
+use std::{
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+/// Row in the console GUI where the live crawl metrics are printed
+const STATS_ROW: i32 = 6;
+/// Number of spaces used to clear the leftovers of a previous (longer) line
+const STATS_PAD: usize = 20;
+
+/// CrawlStats keeps live metrics of a crawl so a slow crawl can be told apart from a stuck one
+pub struct CrawlStats {
+    /// Time the crawl started
+    start: Instant,
+    /// Number of directories whose listing was fetched
+    pub dirs_visited: AtomicUsize,
+    /// Number of directories skipped because they were not modified since the last sync
+    pub dirs_skipped: AtomicUsize,
+    /// Number of paths waiting to be visited
+    pub queue_depth: AtomicUsize,
+    /// Number of downloadable links found
+    pub links_found: AtomicUsize,
+    /// Number of links skipped because they resolve to a file that was already found
+    pub duplicates_skipped: AtomicUsize,
+    /// Number of listings that were cut off and fetched again
+    pub listings_retried: AtomicUsize,
+    /// Number of listings that were still cut off after every attempt (their files are missing)
+    pub listings_incomplete: AtomicUsize,
+    /// Number of extra pages fetched from paginated listings
+    pub pages_followed: AtomicUsize,
+}
+
+impl CrawlStats {
+    /// Returns a CrawlStats object with every counter set to 0
+    pub fn new() -> Self {
+        Self {
+            start: Instant::now(),
+            dirs_visited: AtomicUsize::new(0),
+            dirs_skipped: AtomicUsize::new(0),
+            queue_depth: AtomicUsize::new(0),
+            links_found: AtomicUsize::new(0),
+            duplicates_skipped: AtomicUsize::new(0),
+            listings_retried: AtomicUsize::new(0),
+            listings_incomplete: AtomicUsize::new(0),
+            pages_followed: AtomicUsize::new(0),
+        }
+    }
+
+    /// Removes a directory from the queue and adds the `new_paths` it discovered
+    fn leave_queue(&self, new_paths: usize) {
+        let _ = self
+            .queue_depth
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |depth| {
+                Some(depth.saturating_sub(1) + new_paths)
+            });
+    }
+
+    /// Marks a directory as visited and replaces it in the queue with the paths it discovered
+    ///
+    /// # Arguments
+    /// * `new_paths`   -   Number of paths found inside of the visited directory
+    pub fn visit_dir(&self, new_paths: usize) {
+        self.dirs_visited.fetch_add(1, Ordering::Relaxed);
+
+        self.leave_queue(new_paths);
+    }
+
+    /// Marks a directory as skipped and removes it from the queue
+    pub fn skip_dir(&self) {
+        self.dirs_skipped.fetch_add(1, Ordering::Relaxed);
+
+        self.leave_queue(0);
+    }
+
+    /// Increments the number of listings that were cut off and fetched again
+    pub fn retry_listing(&self) {
+        self.listings_retried.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Marks a directory whose listing never arrived complete and removes it from the queue
+    pub fn incomplete_listing(&self) {
+        self.listings_incomplete.fetch_add(1, Ordering::Relaxed);
+
+        self.leave_queue(0);
+    }
+
+    /// Increments the number of extra pages fetched from paginated listings
+    pub fn follow_page(&self) {
+        self.pages_followed.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a later page of a paginated listing that never arrived complete (the directory stays queued as visited)
+    pub fn incomplete_page(&self) {
+        self.listings_incomplete.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a link that could not be probed, its file is missing from the crawl like those of a cut off listing
+    pub fn incomplete_link(&self) {
+        self.listings_incomplete.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Increments the number of downloadable links found
+    pub fn found_link(&self) {
+        self.links_found.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Increments the number of links skipped because they resolve to a file that was already found
+    pub fn skip_duplicate(&self) {
+        self.duplicates_skipped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Returns the number of directories visited per second since the crawl started
+    pub fn dirs_per_sec(&self) -> f32 {
+        let elapsed = self.start.elapsed().as_secs_f32();
+
+        if elapsed > 0.0 {
+            self.dirs_visited.load(Ordering::Relaxed) as f32 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// Prints the queue depth and discovery rate under the "Searching All Paths" section
+    /// Does nothing when the console GUI is not drawn (`--progress plain|none`)
+    pub fn print_live(&self) {
+        if !ui::fancy() {
+            return;
+        }
+
+        println!(
+            "{}Queue Depth:\t\t{}\tRate:\t{:.2} dirs/s{}",
+            term_cursor::Goto(0, STATS_ROW),
+            self.queue_depth.load(Ordering::Relaxed),
+            self.dirs_per_sec(),
+            " ".repeat(STATS_PAD),
+        );
+    }
+
+    /// Returns a one line summary of the crawl for the final report
+    pub fn summary(&self) -> String {
+        format!(
+            "{} dirs ({} unchanged, {} retried, {} incomplete, {} extra pages), {} links ({} duplicates), {:.2} dirs/s",
+            self.dirs_visited.load(Ordering::Relaxed),
+            self.dirs_skipped.load(Ordering::Relaxed),
+            self.listings_retried.load(Ordering::Relaxed),
+            self.listings_incomplete.load(Ordering::Relaxed),
+            self.pages_followed.load(Ordering::Relaxed),
+            self.links_found.load(Ordering::Relaxed),
+            self.duplicates_skipped.load(Ordering::Relaxed),
+            self.dirs_per_sec(),
+        )
+    }
+}
+
+impl Default for CrawlStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
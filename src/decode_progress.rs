@@ -0,0 +1,134 @@
+use crate::throttle::SpeedMeter;
+use crate::MB_SIZE;
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// DecodeProgress tracks the decode stage as a whole while files are decoded in parallel
+/// The remaining time is estimated from the compressed sizes, which are known up front
+pub struct DecodeProgress {
+    /// Compressed bytes of every file that is decoded
+    total: u64,
+    /// Compressed bytes decoded so far (files in progress count in proportion to their decoded bytes)
+    done: Mutex<u64>,
+    /// Smoothed decoded bytes per second
+    speed: SpeedMeter,
+    /// When the decode stage started
+    start: Instant,
+}
+
+impl DecodeProgress {
+    /// Returns a DecodeProgress for files that are `total` compressed bytes together
+    ///
+    /// # Arguments
+    /// * `total`   -   Sum of the sizes of the compressed files
+    pub fn new(total: u64) -> Self {
+        Self {
+            total,
+            done: Mutex::new(0),
+            speed: SpeedMeter::new(),
+            start: Instant::now(),
+        }
+    }
+
+    /// Records a decoded chunk
+    ///
+    /// # Arguments
+    /// * `compressed`  -   Compressed bytes the chunk accounts for
+    /// * `decoded`     -   Size of the decoded chunk
+    pub fn record(&self, compressed: u64, decoded: u64) {
+        *self.done.lock().unwrap() += compressed;
+        self.speed.record(decoded);
+    }
+
+    /// Returns the estimated time left, None until enough was decoded to tell
+    pub fn remaining(&self) -> Option<Duration> {
+        let done = (*self.done.lock().unwrap()).min(self.total);
+        if done == 0 {
+            return None;
+        }
+
+        let elapsed = self.start.elapsed().as_secs_f64();
+        Some(Duration::from_secs_f64(
+            elapsed * (self.total - done) as f64 / done as f64,
+        ))
+    }
+
+    /// Returns the throughput and time left (e.g. "35.20 MB/s, 1m 05s left")
+    pub fn summary(&self) -> String {
+        let remaining = self.remaining().map_or_else(
+            || String::from("estimating"),
+            |left| format!("{}m {:02}s left", left.as_secs() / 60, left.as_secs() % 60),
+        );
+
+        format!(
+            "{:.2} MB/s, {}",
+            self.speed.speed() / MB_SIZE as f64,
+            remaining
+        )
+    }
+}
+
+/// FileProgress converts the decoded bytes of one file into the compressed bytes they account for
+pub struct FileProgress {
+    /// Size of the compressed file
+    compressed: u64,
+    /// Estimated decoded size of the file
+    estimate: u64,
+    /// Bytes decoded so far
+    pub decoded: u64,
+    /// Compressed bytes already recorded
+    recorded: u64,
+}
+
+impl FileProgress {
+    /// Returns a FileProgress for a file that was not decoded yet
+    ///
+    /// # Arguments
+    /// * `compressed`  -   Size of the compressed file
+    /// * `estimate`    -   Estimated decoded size (see `decoder::estimate_decoded_size`)
+    pub fn new(compressed: u64, estimate: u64) -> Self {
+        Self {
+            compressed,
+            estimate: estimate.max(1),
+            decoded: 0,
+            recorded: 0,
+        }
+    }
+
+    /// Records a decoded chunk of the file in `progress`
+    ///
+    /// # Arguments
+    /// * `progress`    -   Progress of the decode stage
+    /// * `n`           -   Size of the decoded chunk
+    pub fn record(&mut self, progress: &DecodeProgress, n: u64) {
+        self.decoded += n;
+
+        // The estimate can be off, a file never accounts for more than its compressed size
+        let compressed =
+            ((self.decoded as f64 / self.estimate as f64) * self.compressed as f64) as u64;
+        let compressed = compressed.min(self.compressed);
+
+        progress.record(compressed - self.recorded, n);
+        self.recorded = compressed;
+    }
+
+    /// Records the rest of the compressed size once the file is decoded
+    ///
+    /// # Arguments
+    /// * `progress`    -   Progress of the decode stage
+    pub fn finish(&mut self, progress: &DecodeProgress) {
+        progress.record(self.compressed - self.recorded, 0);
+        self.recorded = self.compressed;
+    }
+
+    /// Returns the decoded and estimated size (e.g. "12.50 / ~40.00 MB")
+    pub fn summary(&self) -> String {
+        format!(
+            "{:.2} / ~{:.2} MB",
+            self.decoded as f32 / MB_SIZE as f32,
+            self.estimate.max(self.decoded) as f32 / MB_SIZE as f32
+        )
+    }
+}
@@ -0,0 +1,194 @@
+use crate::bz2_file::BZ2File;
+use std::{
+    error::Error,
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::Path,
+};
+
+/// Number of compressed bytes that are decoded to estimate the decoded size of a file
+const SAMPLE_SIZE: u64 = 2 * 1024 * 1024;
+/// Number of bytes that are read from a file to sniff its format
+const SNIFF_LEN: usize = 6;
+/// Number of decoded bytes that are written at once
+const DECODE_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Decoder is a compressed file that is decoded in chunks
+pub trait Decoder: Send {
+    /// Decodes the file into `output`, calling `on_chunk` with the size of every decoded chunk
+    /// Returns the number of decoded bytes
+    ///
+    /// # Arguments
+    /// * `output`      -   Where the decoded bytes are written
+    /// * `on_chunk`    -   Called after every chunk that was written (for progress)
+    fn decode_into(
+        &mut self,
+        output: &mut dyn Write,
+        on_chunk: &mut dyn FnMut(u64),
+    ) -> Result<u64, Box<dyn Error>>;
+}
+
+/// Copies the decoded bytes of `decoder` into `output` chunk by chunk, so no file is held in memory
+///
+/// # Arguments
+/// * `decoder`     -   Reader that decodes a compressed file
+/// * `output`      -   Where the decoded bytes are written
+/// * `on_chunk`    -   Called after every chunk that was written
+pub fn copy_chunks(
+    decoder: &mut dyn Read,
+    output: &mut dyn Write,
+    on_chunk: &mut dyn FnMut(u64),
+) -> Result<u64, Box<dyn Error>> {
+    let mut chunk = vec![0u8; DECODE_CHUNK_SIZE];
+    let mut decoded = 0u64;
+
+    loop {
+        let n = decoder.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+
+        output.write_all(&chunk[..n])?;
+        decoded += n as u64;
+        on_chunk(n as u64);
+    }
+
+    Ok(decoded)
+}
+
+/// Format is a compression format a fastdl serves files in
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    /// bzip2 (every fastdl)
+    Bz2,
+    /// Zstandard (some next-gen mirrors)
+    #[cfg(feature = "zstd")]
+    Zstd,
+    /// xz / LZMA2 (some next-gen mirrors)
+    #[cfg(feature = "lzma")]
+    Xz,
+}
+
+impl Format {
+    /// Every format this build can decode
+    pub const ALL: &'static [Format] = &[
+        Format::Bz2,
+        #[cfg(feature = "zstd")]
+        Format::Zstd,
+        #[cfg(feature = "lzma")]
+        Format::Xz,
+    ];
+
+    /// Returns the file extension of the format (including the dot)
+    pub fn extension(self) -> &'static str {
+        match self {
+            Format::Bz2 => ".bz2",
+            #[cfg(feature = "zstd")]
+            Format::Zstd => ".zst",
+            #[cfg(feature = "lzma")]
+            Format::Xz => ".xz",
+        }
+    }
+
+    /// Returns the magic bytes every file of the format starts with
+    fn magic(self) -> &'static [u8] {
+        match self {
+            Format::Bz2 => b"BZh",
+            #[cfg(feature = "zstd")]
+            Format::Zstd => &[0x28, 0xB5, 0x2F, 0xFD],
+            #[cfg(feature = "lzma")]
+            Format::Xz => &[0xFD, b'7', b'z', b'X', b'Z', 0x00],
+        }
+    }
+
+    /// Returns the format announced by the extension of `name`
+    ///
+    /// # Arguments
+    /// * `name`    -   File name (e.g. "ze_a.bsp.zst")
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| name.ends_with(format.extension()))
+    }
+
+    /// Returns the format of `bytes` by looking at its magic bytes
+    ///
+    /// # Arguments
+    /// * `bytes`   -   Start of the file
+    pub fn sniff(bytes: &[u8]) -> Option<Self> {
+        Self::ALL
+            .iter()
+            .copied()
+            .find(|format| bytes.starts_with(format.magic()))
+    }
+
+    /// Returns the format of the file at `path` by looking at its magic bytes
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the file that will be sniffed
+    pub fn sniff_file(path: &Path) -> io::Result<Option<Self>> {
+        let mut head = Vec::with_capacity(SNIFF_LEN);
+        File::open(path)?
+            .take(SNIFF_LEN as u64)
+            .read_to_end(&mut head)?;
+
+        Ok(Self::sniff(&head))
+    }
+
+    /// Returns a Decoder of the format that will decode `f`
+    ///
+    /// # Arguments
+    /// * `f`   -   The compressed file that would be read after you opened it
+    pub fn decoder(self, f: File) -> io::Result<Box<dyn Decoder>> {
+        Ok(match self {
+            Format::Bz2 => Box::new(BZ2File::new(f)),
+            #[cfg(feature = "zstd")]
+            Format::Zstd => Box::new(crate::zstd_file::ZstdFile::new(f)?),
+            #[cfg(feature = "lzma")]
+            Format::Xz => Box::new(crate::xz_file::XzFile::new(f)),
+        })
+    }
+
+    /// Returns a streaming reader that decodes `r`
+    ///
+    /// # Arguments
+    /// * `r`   -   Compressed bytes
+    fn stream<R: Read + 'static>(self, r: R) -> io::Result<Box<dyn Read>> {
+        Ok(match self {
+            Format::Bz2 => Box::new(bzip2::read::MultiBzDecoder::new(r)),
+            #[cfg(feature = "zstd")]
+            Format::Zstd => Box::new(zstd::stream::read::Decoder::new(r)?),
+            #[cfg(feature = "lzma")]
+            Format::Xz => Box::new(xz2::read::XzDecoder::new_multi_decoder(r)),
+        })
+    }
+}
+
+/// Estimates the decoded size of a compressed file by decoding its first blocks
+/// The ratio of the sample is extrapolated to the whole file (files smaller than the sample are exact)
+///
+/// # Arguments
+/// * `path`    -   Path of the compressed file
+/// * `format`  -   Format the file is compressed in
+pub fn estimate_decoded_size(path: &Path, format: Format) -> io::Result<u64> {
+    let len = fs::metadata(path)?.len();
+    let mut decoder = format.stream(File::open(path)?.take(SAMPLE_SIZE))?;
+    let mut buf = vec![0u8; 64 * 1024];
+    let mut decoded = 0u64;
+
+    // The sample ends in the middle of a block, which the decoder reports as an error
+    while let Ok(n) = decoder.read(&mut buf) {
+        if n == 0 {
+            break;
+        }
+        decoded += n as u64;
+    }
+
+    if len <= SAMPLE_SIZE {
+        return Ok(decoded);
+    }
+
+    // A decoded file is never smaller than the compressed file in practice
+    Ok(((decoded as f64 * len as f64 / SAMPLE_SIZE as f64) as u64).max(len))
+}
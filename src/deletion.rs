@@ -0,0 +1,188 @@
+use crate::{
+    events::{self, Event},
+    meta_dir, schema,
+    state_store::StateStore,
+    ui,
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, BufRead, IsTerminal, Write},
+    path::{Path, PathBuf},
+};
+
+/// Name of the journal of planned deletions stored in the output directory
+pub const JOURNAL_FILE: &str = "fastdl_delete_journal.json";
+/// Percentage of the mirror that is deleted without confirmation (`--delete-threshold <percent>`)
+pub const DEFAULT_THRESHOLD: f64 = 10.0;
+
+/// Journal lists the files a deletion removes (versioned by `schema::DELETE_JOURNAL`)
+/// It's written before anything is deleted, so a deletion that was interrupted is finished on the next run
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Journal {
+    /// Version of the format the journal was written in
+    #[serde(default)]
+    schema_version: u32,
+    /// Set once the deletion was confirmed, a journal that was never confirmed is discarded
+    confirmed: bool,
+    /// Download link -> local paths of every file that vanished upstream
+    planned: BTreeMap<String, Vec<PathBuf>>,
+}
+
+/// Outcome is what a deletion did
+#[derive(Debug, PartialEq, Eq)]
+pub enum Outcome {
+    /// Nothing vanished upstream
+    Nothing,
+    /// The files were deleted (number of files)
+    Deleted(usize),
+    /// More files than the threshold vanished and the deletion was not confirmed
+    Refused,
+}
+
+impl Journal {
+    /// Writes the journal to `path`, synced to disk before it replaces the previous journal
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the journal
+    fn save(&mut self, path: &Path) -> io::Result<()> {
+        self.schema_version = schema::DELETE_JOURNAL.version();
+
+        let tmp_path = path.with_extension("json.tmp");
+        let mut file = fs::File::create(&tmp_path)?;
+        file.write_all(serde_json::to_string_pretty(self)?.as_bytes())?;
+        file.sync_all()?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Deletes every planned file and removes it from the manifest, then removes the journal
+    /// Deleting is idempotent, so running it again after a crash finishes the deletion
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the journal
+    /// * `store`   -   State store whose manifest lists the files
+    fn apply(&self, path: &Path, store: &dyn StateStore) -> usize {
+        let mut deleted = 0;
+
+        for (url, paths) in &self.planned {
+            let failed = paths
+                .iter()
+                .filter(|file| match fs::remove_file(file) {
+                    Ok(_) => {
+                        events::emit(&Event::FileDeleted { url, path: file });
+                        false
+                    }
+                    Err(e) => e.kind() != io::ErrorKind::NotFound,
+                })
+                .count();
+
+            // Files that could not be deleted stay in the manifest
+            if failed == 0 {
+                store.forget_file(url);
+                deleted += 1;
+            }
+        }
+
+        // The journal goes only once the manifest no longer lists the files, otherwise the next run finishes it
+        match store.save() {
+            Ok(()) => {
+                let _ = fs::remove_file(path);
+            }
+            Err(err) => eprintln!("Failed to save the state store: {}", err),
+        }
+
+        deleted
+    }
+}
+
+/// Finishes a deletion that was interrupted, returning the number of files it deleted
+/// A journal that was never confirmed is discarded without deleting anything
+///
+/// # Arguments
+/// * `dir`     -   Output directory the journal is stored in
+/// * `store`   -   State store whose manifest lists the files
+pub fn resume(dir: &Path, store: &dyn StateStore) -> Result<usize, String> {
+    let path = meta_dir::path(dir, JOURNAL_FILE);
+    let Some(journal) = schema::DELETE_JOURNAL.load::<Journal>(&path)? else {
+        return Ok(0);
+    };
+
+    if !journal.confirmed {
+        let _ = fs::remove_file(&path);
+        return Ok(0);
+    }
+
+    Ok(journal.apply(&path, store))
+}
+
+/// Asks on the terminal whether `planned` of `total` files may be deleted
+/// Without a terminal (e.g. the background service) nobody can answer, so the answer is no
+///
+/// # Arguments
+/// * `planned` -   Number of files that would be deleted
+/// * `total`   -   Number of mirrored files
+fn ask(planned: usize, total: usize) -> bool {
+    if !io::stdin().is_terminal() {
+        return false;
+    }
+
+    print!(
+        "{}{} of {} mirrored files vanished upstream, delete them? [y/N] ",
+        ui::goto(0, 43),
+        planned,
+        total
+    );
+    let _ = io::stdout().flush();
+
+    let mut answer = String::new();
+    io::stdin().lock().read_line(&mut answer).is_ok()
+        && matches!(answer.trim().to_lowercase().as_str(), "y" | "yes")
+}
+
+/// Deletes the local copies of files that vanished upstream
+/// The deletion is journaled first, and needs confirmation (`--confirm-delete` or an answer on the terminal)
+/// if it would delete more than `threshold` percent of the mirror, so a partial listing can't wipe it
+///
+/// # Arguments
+/// * `dir`         -   Output directory the journal is stored in
+/// * `store`       -   State store whose manifest lists the files
+/// * `vanished`    -   Download link -> local paths of every file that is no longer listed
+/// * `total`       -   Number of mirrored files the listing was compared with
+/// * `threshold`   -   Percentage of `total` deleted without confirmation
+/// * `confirmed`   -   Deletions above the threshold were confirmed up front (`--confirm-delete`)
+pub fn delete_vanished(
+    dir: &Path,
+    store: &dyn StateStore,
+    vanished: BTreeMap<String, Vec<PathBuf>>,
+    total: usize,
+    threshold: f64,
+    confirmed: bool,
+) -> Result<Outcome, String> {
+    if vanished.is_empty() {
+        return Ok(Outcome::Nothing);
+    }
+
+    let path = meta_dir::path(dir, JOURNAL_FILE);
+    let mut journal = Journal {
+        planned: vanished,
+        ..Journal::default()
+    };
+    let save_err = |e: io::Error| format!("Failed to write {}: {}", path.display(), e);
+
+    // The plan is on disk before anyone is asked, so it can be inspected
+    journal.save(&path).map_err(save_err)?;
+
+    let planned = journal.planned.len();
+    let above_threshold = planned as f64 > total as f64 * threshold / 100.0;
+    if above_threshold && !confirmed && !ask(planned, total) {
+        events::emit(&Event::DeletionRefused { planned, total });
+        let _ = fs::remove_file(&path);
+        return Ok(Outcome::Refused);
+    }
+
+    journal.confirmed = true;
+    journal.save(&path).map_err(save_err)?;
+
+    Ok(Outcome::Deleted(journal.apply(&path, store)))
+}
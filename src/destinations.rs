@@ -0,0 +1,106 @@
+use crate::mirror_stats;
+use std::{
+    collections::BTreeMap,
+    path::{Component, Path, PathBuf},
+};
+
+/// Categories a destination can be set for, with the asset directory their files are in
+const CATEGORIES: &[(&str, Option<&str>)] = &[
+    ("maps", Some("maps")),
+    ("sounds", Some("sound")),
+    ("materials", Some("materials")),
+    ("models", Some("models")),
+    ("other", None),
+];
+
+/// Destinations sends the decoded files of some categories somewhere else than the other decoded files
+/// (`--category-dest maps=<game>/cstrike/maps`, or `"destinations"` in a profile)
+/// The path of a file inside of its category directory is kept: with `sounds=/archive/sound`,
+/// `sound/ze/boss.wav` is decoded to `/archive/sound/ze/boss.wav`
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Destinations {
+    /// Category -> directory its decoded files are written to
+    dirs: BTreeMap<&'static str, PathBuf>,
+}
+
+impl Destinations {
+    /// Sets the destination of `category`, replacing the previous one
+    ///
+    /// # Arguments
+    /// * `category`    -   Category of the files (maps, sounds, materials, models or other)
+    /// * `dir`         -   Directory the decoded files of the category are written to
+    pub fn insert(&mut self, category: &str, dir: PathBuf) -> Result<(), String> {
+        let (category, _) = CATEGORIES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(category.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "Unknown category: {} (expected maps, sounds, materials, models or other)",
+                    category
+                )
+            })?;
+
+        self.dirs.insert(category, dir);
+        Ok(())
+    }
+
+    /// Parses a `--category-dest` entry ("category=dir")
+    pub fn insert_arg(&mut self, entry: &str) -> Result<(), String> {
+        match entry.split_once('=') {
+            Some((category, dir)) if !dir.is_empty() => self.insert(category, dir.into()),
+            _ => Err(format!(
+                "Invalid category destination: {} (expected <category>=<dir>)",
+                entry
+            )),
+        }
+    }
+
+    /// Returns true if no category has a destination
+    pub fn is_empty(&self) -> bool {
+        self.dirs.is_empty()
+    }
+
+    /// Returns the destination of `category`, if it has one
+    ///
+    /// # Arguments
+    /// * `category`    -   Category of the files (maps, sounds, materials, models or other)
+    pub fn get(&self, category: &str) -> Option<&Path> {
+        self.dirs.get(category).map(PathBuf::as_path)
+    }
+
+    /// Returns where the decoded file `decoded` goes if its category has a destination
+    ///
+    /// # Arguments
+    /// * `decoded` -   Path the file would be decoded to otherwise
+    /// * `base`    -   Directory the compressed file was found in (the decode root)
+    pub fn resolve(&self, decoded: &Path, base: &Path) -> Option<PathBuf> {
+        let category = mirror_stats::category(decoded);
+        let dir = self.dirs.get(category)?;
+        let asset_dir = CATEGORIES
+            .iter()
+            .find(|(name, _)| *name == category)
+            .and_then(|(_, asset_dir)| *asset_dir);
+
+        // The path after the last category directory, or the path inside of the decode root
+        let components = decoded.components().collect::<Vec<_>>();
+        let inside = asset_dir
+            .and_then(|asset_dir| {
+                components.iter().rposition(|component| match component {
+                    Component::Normal(name) => name
+                        .to_str()
+                        .is_some_and(|name| name.eq_ignore_ascii_case(asset_dir)),
+                    _ => false,
+                })
+            })
+            .map(|i| components[i + 1..].iter().collect::<PathBuf>())
+            .or_else(|| {
+                decoded
+                    .strip_prefix(base)
+                    .ok()
+                    .or_else(|| decoded.file_name().map(Path::new))
+                    .map(Path::to_path_buf)
+            })?;
+
+        Some(dir.join(inside))
+    }
+}
@@ -0,0 +1,218 @@
+use crate::{
+    a2s, crawl_listings, crawl_state::CrawlState, crawl_stats::CrawlStats, files::RemoteFile,
+    prefixes::Prefixes, record_sitemap, rewrite::Rewrites, sparse,
+};
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// DiscoveryContext is what the providers of a crawl share
+pub struct DiscoveryContext<'a> {
+    /// Live crawl metrics
+    pub stats: &'a Arc<CrawlStats>,
+    /// Unix time; directories not modified after it are not examined
+    pub since: Option<u64>,
+    /// Trust the hrefs of the listings instead of sending a request per link
+    pub fast: bool,
+    /// Subtrees of the fastdl the crawl is restricted to
+    pub prefixes: &'a Prefixes,
+    /// Rules rewriting the links found in the listings
+    pub rewrites: &'a Rewrites,
+}
+
+/// DiscoveryProvider finds the files a sync downloads from one kind of source
+/// Every provider of a crawl records into the same crawl state, which drops the files another
+/// provider already found (same canonical link or path), so combined providers download every file once
+pub trait DiscoveryProvider: Send + Sync {
+    /// Name of the provider in error messages
+    fn name(&self) -> &'static str;
+
+    /// Records the files found for the fastdl url `url` in `state`
+    ///
+    /// # Arguments
+    /// * `url`     -   Fastdl url that is synced
+    /// * `state`   -   Crawl state the files are recorded in
+    /// * `ctx`     -   Options of the crawl
+    fn discover(
+        &self,
+        url: &str,
+        state: &Arc<CrawlState>,
+        ctx: &DiscoveryContext,
+    ) -> Result<(), String>;
+}
+
+/// ProviderSpec is a provider selected on the command line or in a profile (`--discover <provider>`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProviderSpec {
+    /// Crawl the HTML listings (`listing`)
+    Listing,
+    /// Take the files of the sitemap (`sitemap`)
+    Sitemap,
+    /// The current and next map of a game server (`a2s:<addr>`)
+    A2s(String),
+    /// The maps of a map list, mapcycle or mapchooser export (`maplist:<file>`)
+    MapList(PathBuf),
+}
+
+impl FromStr for ProviderSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "listing" => Ok(Self::Listing),
+            None if s == "sitemap" => Ok(Self::Sitemap),
+            Some(("a2s", addr)) if !addr.is_empty() => Ok(Self::A2s(addr.to_string())),
+            Some(("maplist", path)) if !path.is_empty() => Ok(Self::MapList(path.into())),
+            _ => Err(format!(
+                "Unknown discovery provider: {} (expected listing, sitemap, a2s:<addr> or maplist:<file>)",
+                s
+            )),
+        }
+    }
+}
+
+impl ProviderSpec {
+    /// Returns the spec with its file resolved relative to `base_dir` (the directory of the profiles file)
+    pub fn resolve(self, base_dir: &Path) -> Self {
+        match self {
+            Self::MapList(path) => Self::MapList(base_dir.join(path)),
+            spec => spec,
+        }
+    }
+
+    /// Returns the provider of this spec
+    pub fn provider(&self) -> Box<dyn DiscoveryProvider> {
+        match self {
+            Self::Listing => Box::new(Listing),
+            Self::Sitemap => Box::new(Sitemap),
+            Self::A2s(addr) => Box::new(A2s { addr: addr.clone() }),
+            Self::MapList(path) => Box::new(MapList { path: path.clone() }),
+        }
+    }
+}
+
+/// Crawls the HTML listings of the fastdl
+struct Listing;
+
+impl DiscoveryProvider for Listing {
+    fn name(&self) -> &'static str {
+        "listing"
+    }
+
+    fn discover(
+        &self,
+        url: &str,
+        state: &Arc<CrawlState>,
+        ctx: &DiscoveryContext,
+    ) -> Result<(), String> {
+        crawl_listings(
+            state,
+            url,
+            ctx.stats,
+            ctx.since,
+            ctx.fast,
+            ctx.prefixes,
+            ctx.rewrites,
+        )
+        .map_err(|err| err.to_string())
+    }
+}
+
+/// Takes the files listed by the sitemap of the fastdl
+struct Sitemap;
+
+impl DiscoveryProvider for Sitemap {
+    fn name(&self) -> &'static str {
+        "sitemap"
+    }
+
+    fn discover(
+        &self,
+        url: &str,
+        state: &Arc<CrawlState>,
+        ctx: &DiscoveryContext,
+    ) -> Result<(), String> {
+        match record_sitemap(state, url, ctx.prefixes, ctx.rewrites) {
+            Ok(true) => Ok(()),
+            Ok(false) => Err(format!("{} has no sitemap", url)),
+            Err(err) => Err(err.to_string()),
+        }
+    }
+}
+
+/// Returns the url of the maps directory of a fastdl url (the url itself if it's one)
+fn maps_url(url: &str) -> String {
+    if url.ends_with("maps/") {
+        url.to_string()
+    } else {
+        format!("{}/maps/", url.trim_end_matches('/'))
+    }
+}
+
+/// Records the compressed bsp of every map in `maps`, in the maps directory of `url`
+fn record_maps(url: &str, maps: impl IntoIterator<Item = String>, state: &CrawlState) {
+    let maps_url = maps_url(url);
+
+    for map in maps {
+        state.record_download(RemoteFile::new(
+            &format!("{}{}.bsp.bz2", maps_url, map),
+            url,
+        ));
+    }
+}
+
+/// Downloads the map a game server plays and the one it changes to
+struct A2s {
+    /// Address of the game server (e.g. "1.2.3.4:27015")
+    addr: String,
+}
+
+impl DiscoveryProvider for A2s {
+    fn name(&self) -> &'static str {
+        "a2s"
+    }
+
+    fn discover(
+        &self,
+        url: &str,
+        state: &Arc<CrawlState>,
+        _ctx: &DiscoveryContext,
+    ) -> Result<(), String> {
+        let maps = a2s::query_maps(&self.addr)
+            .map_err(|err| format!("Failed to query {}: {}", self.addr, err))?;
+
+        record_maps(
+            url,
+            [Some(maps.current), maps.next].into_iter().flatten(),
+            state,
+        );
+        Ok(())
+    }
+}
+
+/// Downloads the maps of a map list (one per line, a mapcycle or a mapchooser KeyValues export)
+struct MapList {
+    /// Path of the list
+    path: PathBuf,
+}
+
+impl DiscoveryProvider for MapList {
+    fn name(&self) -> &'static str {
+        "maplist"
+    }
+
+    fn discover(
+        &self,
+        url: &str,
+        state: &Arc<CrawlState>,
+        _ctx: &DiscoveryContext,
+    ) -> Result<(), String> {
+        let maps = sparse::load_nominations(&self.path)
+            .map_err(|err| format!("Failed to read {}: {}", self.path.display(), err))?;
+
+        record_maps(url, maps, state);
+        Ok(())
+    }
+}
@@ -0,0 +1,127 @@
+use percent_encoding::percent_decode_str;
+use reqwest::{blocking::Response, header::CONTENT_DISPOSITION};
+
+/// Characters that are not allowed in file names on Windows (and path separators everywhere)
+const FORBIDDEN_CHARS: &[char] = &['/', '\\', ':', '*', '?', '"', '<', '>', '|'];
+
+/// Returns the file name announced by the `Content-Disposition` header of `resp`, sanitized so it
+/// can't escape the directory it's written to
+///
+/// # Arguments
+/// * `resp`    -   Response of the download
+pub fn file_name(resp: &Response) -> Option<String> {
+    let header = resp.headers().get(CONTENT_DISPOSITION)?.to_str().ok()?;
+
+    parse_file_name(header).and_then(|name| sanitize(&name))
+}
+
+/// Returns the raw file name of a `Content-Disposition` value
+/// `filename*` (RFC 5987, e.g. `filename*=UTF-8''ze_a%20b.bsp.bz2`) wins over `filename`
+///
+/// # Arguments
+/// * `header`  -   Value of the `Content-Disposition` header
+fn parse_file_name(header: &str) -> Option<String> {
+    let params = header
+        .split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .map(|(key, value)| (key.trim().to_lowercase(), value.trim()))
+        .collect::<Vec<_>>();
+
+    let extended = params
+        .iter()
+        .find(|(key, _)| key == "filename*")
+        .and_then(|(_, value)| value.splitn(3, '\'').nth(2))
+        .and_then(|value| percent_decode_str(value).decode_utf8().ok())
+        .map(|value| value.into_owned());
+
+    extended.or_else(|| {
+        params
+            .iter()
+            .find(|(key, _)| key == "filename")
+            .map(|(_, value)| value.trim_matches('"').to_string())
+    })
+}
+
+/// Returns `name` without any directory part or forbidden characters, or None if nothing usable is left
+///
+/// # Arguments
+/// * `name`    -   File name sent by the server
+fn sanitize(name: &str) -> Option<String> {
+    // Only keep the last path segment so "../../x" or "C:\x" can't escape the directory
+    let name = name.rsplit(['/', '\\']).next().unwrap_or_default();
+
+    let name = name
+        .chars()
+        .filter(|c| !c.is_control() && !FORBIDDEN_CHARS.contains(c))
+        .collect::<String>();
+    let name = name.trim().trim_end_matches('.');
+
+    if name.is_empty() || name.chars().all(|c| c == '.') {
+        None
+    } else {
+        Some(name.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the file name a download with the `Content-Disposition` value `header` is written to
+    fn name_of(header: &str) -> Option<String> {
+        parse_file_name(header).and_then(|name| sanitize(&name))
+    }
+
+    #[test]
+    fn reads_the_file_name() {
+        assert_eq!(
+            name_of(r#"attachment; filename="ze_example.bsp.bz2""#).as_deref(),
+            Some("ze_example.bsp.bz2")
+        );
+        assert_eq!(
+            name_of("attachment; FILENAME=ze_example.bsp.bz2").as_deref(),
+            Some("ze_example.bsp.bz2")
+        );
+        // The extended name wins, whatever the order of the parameters
+        assert_eq!(
+            name_of(r#"attachment; filename*=UTF-8''ze_a%20b.bsp.bz2; filename="ze_a_b.bsp.bz2""#)
+                .as_deref(),
+            Some("ze_a b.bsp.bz2")
+        );
+        assert_eq!(name_of("attachment"), None);
+        assert_eq!(name_of("inline; name=upload"), None);
+    }
+
+    #[test]
+    fn sanitizes_the_file_name() {
+        assert_eq!(
+            name_of(r#"attachment; filename="../../etc/ze_x.bsp""#).as_deref(),
+            Some("ze_x.bsp")
+        );
+        assert_eq!(
+            name_of(r#"attachment; filename="C:\Windows\ze_x.bsp""#).as_deref(),
+            Some("ze_x.bsp")
+        );
+        assert_eq!(
+            name_of("attachment; filename*=UTF-8''..%2F..%2Fze_x.bsp").as_deref(),
+            Some("ze_x.bsp")
+        );
+        assert_eq!(
+            name_of("attachment; filename*=UTF-8''ze%0D%0A%3Cx%3E%3F.bsp").as_deref(),
+            Some("zex.bsp")
+        );
+        assert_eq!(
+            name_of(r#"attachment; filename="ze_x.bsp. ""#).as_deref(),
+            Some("ze_x.bsp")
+        );
+        for unusable in ["..", ".", "/", "..\\..", "***", ""] {
+            assert_eq!(
+                name_of(&format!(r#"attachment; filename="{}""#, unusable)),
+                None,
+                "{}",
+                unusable
+            );
+        }
+    }
+}
@@ -0,0 +1,286 @@
+use crate::{fd_limit, fs_limits, net, profiles::SyncProfile, tuning, MB_SIZE};
+use std::{
+    error::Error,
+    fs::{self, OpenOptions},
+    io::IsTerminal,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+/// Name of the file written to check that an output directory is writable (removed right away)
+const PROBE_NAME: &str = "fastdl_doctor.tmp";
+/// Free space below which the output directory is reported as almost full
+const LOW_SPACE: u64 = 2 * 1024 * MB_SIZE as u64;
+/// Time to the first byte above which a fastdl is reported as slow
+const SLOW_RESPONSE: Duration = Duration::from_secs(5);
+
+/// Outcome is the result of a single check
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Outcome {
+    /// Nothing to do
+    Ok,
+    /// Works, but may cause trouble
+    Warn,
+    /// Syncs fail or hang until it's fixed
+    Fail,
+}
+
+/// Doctor runs the checks and prints their results
+struct Doctor {
+    /// Number of checks that failed
+    failed: usize,
+    /// Number of checks that warned
+    warned: usize,
+}
+
+impl Doctor {
+    /// Prints the result of a check, with the fix if it did not pass
+    ///
+    /// # Arguments
+    /// * `outcome` -   Result of the check
+    /// * `what`    -   What was checked and found
+    /// * `fix`     -   What the user can do about it
+    fn report(&mut self, outcome: Outcome, what: &str, fix: &str) {
+        let label = match outcome {
+            Outcome::Ok => "ok",
+            Outcome::Warn => {
+                self.warned += 1;
+                "warn"
+            }
+            Outcome::Fail => {
+                self.failed += 1;
+                "FAIL"
+            }
+        };
+
+        println!("[{:>4}] {}", label, what);
+        if outcome != Outcome::Ok && !fix.is_empty() {
+            println!("       fix: {}", fix);
+        }
+    }
+
+    /// Checks that the fastdl url `url` answers, and that its TLS certificate is valid
+    fn check_url(&mut self, url: &str) {
+        let start = Instant::now();
+        match net::client().get(url).send() {
+            Ok(resp) if resp.status().is_success() => {
+                let elapsed = start.elapsed();
+                if elapsed > SLOW_RESPONSE {
+                    self.report(
+                        Outcome::Warn,
+                        &format!("{} answered after {:.1} s", url, elapsed.as_secs_f32()),
+                        "the fastdl or the link is slow: --compat waits longer and reuses no connections on unreliable links",
+                    );
+                } else {
+                    self.report(
+                        Outcome::Ok,
+                        &format!("{} answered in {} ms", url, elapsed.as_millis()),
+                        "",
+                    );
+                }
+            }
+            Ok(resp) => self.report(
+                Outcome::Fail,
+                &format!("{} answered HTTP {}", url, resp.status()),
+                "check the url in the browser: the fastdl may have moved, or blocks listings (try --discover sitemap)",
+            ),
+            Err(err) => {
+                let chain = error_chain(&err);
+                let lower = chain.to_lowercase();
+                let fix = if lower.contains("certificate") || lower.contains("tls") || lower.contains("ssl") {
+                    "the TLS certificate of the fastdl is invalid or expired: check the system clock, \
+                     or use the http:// url if the fastdl serves one"
+                } else if err.is_timeout() {
+                    "the fastdl does not answer in time: check a firewall or proxy, or use --compat"
+                } else if lower.contains("dns") || lower.contains("resolve") || lower.contains("lookup") {
+                    "the host name does not resolve: check the DNS settings, or pin an address with --resolve"
+                } else {
+                    "the fastdl can't be reached: check the network, a firewall or proxy, or try --ip-version 4"
+                };
+                self.report(
+                    Outcome::Fail,
+                    &format!("{} can't be reached: {}", url, chain),
+                    fix,
+                );
+            }
+        }
+    }
+
+    /// Checks that files can be written to `dir`, and that its filesystem has room for them
+    fn check_dir(&mut self, dir: &Path) {
+        let probe = dir.join(PROBE_NAME);
+        let written = fs::create_dir_all(dir).and_then(|_| {
+            OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(true)
+                .open(&probe)
+                .map(|_| ())
+        });
+        let _ = fs::remove_file(&probe);
+        match written {
+            Ok(()) => self.report(Outcome::Ok, &format!("{} is writable", dir.display()), ""),
+            Err(err) => {
+                self.report(
+                    Outcome::Fail,
+                    &format!("{} is not writable: {}", dir.display(), err),
+                    "fix the permissions or the owner of the directory, or sync elsewhere with the `out` of a profile",
+                );
+                return;
+            }
+        }
+
+        match fs2::available_space(dir) {
+            Ok(free) if free < LOW_SPACE => self.report(
+                Outcome::Warn,
+                &format!(
+                    "{:.2} MB free on the drive of {}",
+                    free as f32 / MB_SIZE as f32,
+                    dir.display()
+                ),
+                "a full ze mirror takes tens of GB: free some space, or decode elsewhere with --extract-to",
+            ),
+            Ok(free) => self.report(
+                Outcome::Ok,
+                &format!(
+                    "{:.2} GB free on the drive of {}",
+                    free as f32 / MB_SIZE as f32 / 1024.0,
+                    dir.display()
+                ),
+                "",
+            ),
+            Err(err) => self.report(
+                Outcome::Warn,
+                &format!("Free space of {} unknown: {}", dir.display(), err),
+                "",
+            ),
+        }
+
+        if let Some(free) = fs_limits::limits(dir).free_inodes {
+            if free < 10_000 {
+                self.report(
+                    Outcome::Warn,
+                    &format!("Only {} files can still be created on the drive of {}", free, dir.display()),
+                    "sound and material syncs create thousands of files: remove files or use another drive",
+                );
+            }
+        }
+    }
+
+    /// Checks that the limit of open files fits the download workers of the tuning kept in `dir`
+    fn check_fd_limit(&mut self, dir: &Path) {
+        let Some(limit) = fd_limit::soft_limit() else {
+            self.report(Outcome::Ok, "No limit of open files on this platform", "");
+            return;
+        };
+
+        let workers = fd_limit::max_workers(limit);
+        let wanted = tuning::stored(dir).download_workers;
+        if workers < wanted {
+            self.report(
+                Outcome::Warn,
+                &format!(
+                    "The limit of {} open files only fits {} workers, {} would be used",
+                    limit, workers, wanted
+                ),
+                "raise it with `ulimit -n 4096` (or in the service unit), the workers are capped until then",
+            );
+        } else {
+            self.report(
+                Outcome::Ok,
+                &format!("The limit of {} open files fits {} workers", limit, workers),
+                "",
+            );
+        }
+    }
+
+    /// Checks whether the console GUI can be drawn
+    fn check_terminal(&mut self) {
+        let term = std::env::var("TERM").unwrap_or_default();
+        if !std::io::stdout().is_terminal() {
+            self.report(
+                Outcome::Ok,
+                "The output is not a terminal: status lines are printed instead of the GUI",
+                "",
+            );
+        } else if term == "dumb" {
+            self.report(
+                Outcome::Warn,
+                "TERM=dumb: status lines are printed instead of the GUI",
+                "set TERM (e.g. xterm-256color) for the GUI, or keep --progress plain",
+            );
+        } else {
+            match terminal_size::terminal_size() {
+                Some((terminal_size::Width(columns), terminal_size::Height(rows)))
+                    if columns < crate::ui::MIN_COLUMNS || rows < crate::ui::MIN_ROWS =>
+                {
+                    self.report(
+                        Outcome::Warn,
+                        &format!(
+                            "The terminal is {}x{}, the GUI needs {}x{}",
+                            columns,
+                            rows,
+                            crate::ui::MIN_COLUMNS,
+                            crate::ui::MIN_ROWS
+                        ),
+                        "enlarge the window, or use --progress plain",
+                    );
+                }
+                Some((terminal_size::Width(columns), terminal_size::Height(rows))) => self.report(
+                    Outcome::Ok,
+                    &format!(
+                        "The terminal is {}x{}, large enough for the GUI",
+                        columns, rows
+                    ),
+                    "",
+                ),
+                None => self.report(
+                    Outcome::Warn,
+                    "The size of the terminal can't be read",
+                    "if the GUI looks garbled, use --progress plain",
+                ),
+            }
+        }
+    }
+}
+
+/// Returns `err` and every error that caused it, joined by ": "
+fn error_chain(err: &dyn Error) -> String {
+    let mut chain = err.to_string();
+    let mut source = err.source();
+    while let Some(err) = source {
+        chain.push_str(&format!(": {}", err));
+        source = err.source();
+    }
+
+    chain
+}
+
+/// Checks what makes syncs fail or hang: the fastdls of every profile (reachability, TLS), their output
+/// directories (permissions, free space and files), the limit of open files and the terminal
+/// Prints a fix for every problem, returns false if a check failed
+///
+/// # Arguments
+/// * `profiles`    -   Profiles whose fastdls and output directories are checked
+/// * `dir`         -   Directory the program was started in (where the tuning is kept)
+pub fn run(profiles: &[SyncProfile], dir: &Path) -> bool {
+    let mut doctor = Doctor {
+        failed: 0,
+        warned: 0,
+    };
+
+    for profile in profiles {
+        println!("Profile {}:", profile.name);
+        for url in &profile.urls {
+            doctor.check_url(url);
+        }
+        doctor.check_dir(&profile.out_dir);
+    }
+
+    println!("System:");
+    doctor.check_fd_limit(dir);
+    doctor.check_terminal();
+
+    println!("\n{} problems, {} warnings", doctor.failed, doctor.warned);
+    doctor.failed == 0
+}
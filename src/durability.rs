@@ -0,0 +1,104 @@
+use crate::fd_limit;
+use std::{
+    fs::{self, File},
+    io::{self, Write},
+    path::Path,
+    sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+};
+
+/// Default number of downloaded files between two checkpoints of the manifest and the queue
+pub const DEFAULT_CHECKPOINT_EVERY: usize = 100;
+
+/// Whether file data and state files are flushed to the disk before they count as written (`--fsync`)
+static FSYNC: AtomicBool = AtomicBool::new(false);
+
+/// Makes every write of this module wait for the disk (off by default: the OS flushes on its own schedule)
+pub fn set_fsync(fsync: bool) {
+    FSYNC.store(fsync, Ordering::Relaxed);
+}
+
+/// Returns true if writes wait for the disk (`--fsync`)
+pub fn fsync() -> bool {
+    FSYNC.load(Ordering::Relaxed)
+}
+
+/// Flushes `file` to the disk if `--fsync` is on
+pub fn sync_file(file: &File) -> io::Result<()> {
+    if fsync() {
+        file.sync_all()?;
+    }
+
+    Ok(())
+}
+
+/// Creates the file at `path` as a new file: an existing file is removed instead of truncated,
+/// so a copy of it hardlinked into a snapshot keeps its content
+///
+/// # Arguments
+/// * `path`    -   Path of the file
+pub fn create(path: &Path) -> io::Result<File> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+        _ => {}
+    }
+
+    fd_limit::retry(|| File::create(path))
+}
+
+/// Writes a downloaded file, flushed to the disk if `--fsync` is on
+///
+/// # Arguments
+/// * `path`    -   Path of the file
+/// * `bytes`   -   Content of the file
+pub fn write_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut file = create(path)?;
+    file.write_all(bytes)?;
+    sync_file(&file)
+}
+
+/// Replaces the state file at `path` with `bytes` through a temporary file, so a crash never leaves it half-written
+/// With `--fsync` the data and the rename are on the disk before this returns, so a power loss keeps either version
+///
+/// # Arguments
+/// * `path`    -   Path of the state file
+/// * `bytes`   -   New content of the state file
+pub fn write_atomic(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let tmp_path = path.with_extension("json.tmp");
+    write_file(&tmp_path, bytes)?;
+    fs::rename(&tmp_path, path)?;
+
+    // The rename is only durable once the directory entry is flushed (directories can't be opened on Windows)
+    #[cfg(unix)]
+    if fsync() {
+        if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+            File::open(dir)?.sync_all()?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Checkpoint decides when the state of a running sync is flushed (`--checkpoint-every <files>`)
+/// A sync interrupted between two checkpoints downloads at most that many files again
+#[derive(Debug)]
+pub struct Checkpoint {
+    /// Number of downloaded files between two checkpoints
+    every: usize,
+    /// Number of files downloaded since the sync started
+    done: AtomicUsize,
+}
+
+impl Checkpoint {
+    /// Returns a Checkpoint every `every` files (at least 1)
+    pub fn new(every: usize) -> Self {
+        Self {
+            every: every.max(1),
+            done: AtomicUsize::new(0),
+        }
+    }
+
+    /// Counts a downloaded file, returning true if the state should be flushed now
+    pub fn tick(&self) -> bool {
+        (self.done.fetch_add(1, Ordering::Relaxed) + 1).is_multiple_of(self.every)
+    }
+}
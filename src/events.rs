@@ -0,0 +1,156 @@
+use crate::{overlay, state_store::unix_now};
+use serde::Serialize;
+use std::{
+    fs::File,
+    io::{self, LineWriter, Write},
+    path::Path,
+    str::FromStr,
+    sync::{Mutex, OnceLock},
+};
+
+/// EventFormat is how events are written (`--events <format>`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EventFormat {
+    /// One JSON object per line
+    Ndjson,
+}
+
+impl FromStr for EventFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(Self::Ndjson),
+            _ => Err(format!("Unknown event format: {} (expected ndjson)", s)),
+        }
+    }
+}
+
+/// Event is something that happened during a sync that other programs may want to track
+#[derive(Debug, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event<'a> {
+    /// The crawl found a file that will be downloaded (size, modification time and entity tag are
+    /// only known if the crawl probed the file)
+    LinkDiscovered {
+        url: &'a str,
+        size: Option<u64>,
+        mtime: Option<u64>,
+        etag: Option<&'a str>,
+    },
+    /// A part of a split archive (`.bz2.001`, `.bz2.002`, ...) is not served, so the archive can't be joined
+    SplitPartMissing { url: &'a str },
+    /// A download started
+    DownloadStarted { url: &'a str },
+    /// A download was written to disk
+    DownloadFinished {
+        url: &'a str,
+        path: &'a Path,
+        bytes: u64,
+    },
+    /// A download was dropped because its content is blocked
+    DownloadBlocked { url: &'a str },
+    /// A download was dropped because the link is missing or serves an HTML page
+    DownloadRejected { url: &'a str },
+    /// A file was skipped because it breaks `--max-file-size` or `--allow-ext`
+    PolicyViolation { url: &'a str, reason: &'a str },
+    /// A `--wayback` path missing from the fastdl will be downloaded from its archived copy
+    ArchiveResolved { url: &'a str, snapshot: &'a str },
+    /// A `--wayback` path missing from the fastdl was never archived
+    ArchiveMissing { url: &'a str },
+    /// A download ended before the `Content-Length` it announced and is downloaded again
+    DownloadTruncated { url: &'a str },
+    /// A host answered 429 or 503, every request to it is paused for `pause_secs` and then ramped back up
+    RateLimited {
+        host: &'a str,
+        status: u16,
+        pause_secs: u64,
+    },
+    /// A download took longer than its time budget and was moved to the second pass
+    DownloadDeferred { url: &'a str },
+    /// A random range of a download differed from a second fetch, so the file is downloaded again
+    SampleMismatch { url: &'a str },
+    /// A download did not match the checksum the fastdl publishes, so it's downloaded again
+    ChecksumMismatch { url: &'a str },
+    /// Every download of a file differed from the checksum the fastdl publishes, it was discarded
+    ChecksumFailed { url: &'a str },
+    /// A download or a decoded file could not be written to disk (the link stays queued, the compressed file is kept)
+    WriteFailed { path: &'a Path, reason: &'a str },
+    /// A compressed file was decoded
+    DecodeFinished {
+        path: &'a str,
+        output: &'a Path,
+        bytes: u64,
+    },
+    /// A file ending with a compressed extension was not compressed and was renamed instead
+    DecodeRenamed { path: &'a str, output: &'a Path },
+    /// A compressed file failed to decode
+    DecodeFailed { path: &'a str },
+    /// A compressed file left next to its decoded copy by an interrupted run was removed (the copy was verified)
+    OrphanRemoved { path: &'a str, output: &'a Path },
+    /// The decoded copy next to a compressed file left by an interrupted run was invalid and is decoded again
+    OrphanRedecoded { path: &'a str, output: &'a Path },
+    /// A file renamed upstream (same chunk checksums) was renamed in the mirror instead of downloaded again
+    FileRenamed { from: &'a str, to: &'a str },
+    /// The local copy of a file that vanished upstream was deleted (`--delete`)
+    FileDeleted { url: &'a str, path: &'a Path },
+    /// More files vanished upstream than `--delete-threshold` allows and the deletion was not confirmed
+    DeletionRefused { planned: usize, total: usize },
+    /// A fastdl root stopped answering (or answers with an error) in watch mode, syncs are paused
+    UpstreamDown { url: &'a str, reason: &'a str },
+    /// A fastdl root that was down answers again after `down_secs`, syncs resume
+    UpstreamRecovered { url: &'a str, down_secs: u64 },
+}
+
+/// Line is an event with the time it happened
+#[derive(Serialize)]
+struct Line<'a> {
+    /// Unix time of the event
+    time: u64,
+    #[serde(flatten)]
+    event: &'a Event<'a>,
+}
+
+/// Where the events are written, only set when events are enabled (`--events ndjson`)
+static SINK: OnceLock<Mutex<Box<dyn Write + Send>>> = OnceLock::new();
+
+/// Enables the events, writing them to `path` (or stdout if None)
+///
+/// # Arguments
+/// * `format`  -   Format of the events
+/// * `path`    -   File the events are written to (`--events-file <path>`)
+pub fn init(format: EventFormat, path: Option<&Path>) -> io::Result<()> {
+    let EventFormat::Ndjson = format;
+
+    // Line buffered so every event reaches the reader as soon as it happens
+    let sink: Box<dyn Write + Send> = match path {
+        Some(path) => Box::new(LineWriter::new(File::create(path)?)),
+        None => Box::new(io::stdout()),
+    };
+
+    let _ = SINK.set(Mutex::new(sink));
+    Ok(())
+}
+
+/// Writes `event` as one JSON line (does nothing if events are disabled)
+///
+/// # Arguments
+/// * `event`   -   Event that happened
+pub fn emit(event: &Event) {
+    overlay::observe(event);
+
+    let Some(sink) = SINK.get() else {
+        return;
+    };
+
+    let line = Line {
+        time: unix_now(),
+        event,
+    };
+
+    if let Ok(json) = serde_json::to_string(&line) {
+        let mut sink = sink.lock().unwrap();
+        let _ = writeln!(sink, "{}", json);
+        let _ = sink.flush();
+    }
+}
@@ -0,0 +1,103 @@
+use std::{io, sync::OnceLock, thread, time::Duration};
+
+/// Descriptors kept free for the standard streams, state files, the run lock, the metrics and control sockets
+const RESERVED: u64 = 64;
+/// Descriptors a worker holds at most at once (connection, compressed file, output file, temporary file)
+const PER_WORKER: u64 = 4;
+/// Highest soft limit asked for when it's raised (macOS refuses more than `OPEN_MAX` per process)
+const RAISE_TO: u64 = 10240;
+/// Attempts at opening a file while the process is out of descriptors
+const ATTEMPTS: u32 = 8;
+/// Pause after the first failed attempt, doubled after every other one
+const FIRST_PAUSE: Duration = Duration::from_millis(100);
+
+/// Returns the soft limit of open files of the process, raised towards the hard limit first
+/// (macOS starts processes with 256, which a few dozen download and decode workers exhaust)
+/// Returns None where there is no such limit (Windows)
+#[cfg(unix)]
+pub fn soft_limit() -> Option<u64> {
+    let mut limit = libc::rlimit {
+        rlim_cur: 0,
+        rlim_max: 0,
+    };
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, &mut limit) } != 0 {
+        return None;
+    }
+
+    let wanted = limit.rlim_max.min(RAISE_TO);
+    if limit.rlim_cur < wanted {
+        let raised = libc::rlimit {
+            rlim_cur: wanted as libc::rlim_t,
+            rlim_max: limit.rlim_max,
+        };
+        if unsafe { libc::setrlimit(libc::RLIMIT_NOFILE, &raised) } == 0 {
+            limit.rlim_cur = raised.rlim_cur;
+        }
+    }
+
+    Some(limit.rlim_cur)
+}
+
+/// Returns the soft limit of open files of the process, raised towards the hard limit first
+/// Returns None where there is no such limit (Windows)
+#[cfg(not(unix))]
+pub fn soft_limit() -> Option<u64> {
+    None
+}
+
+/// Returns the number of workers that fit in `limit` open files (at least 1)
+///
+/// # Arguments
+/// * `limit`   -   Soft limit of open files
+pub fn max_workers(limit: u64) -> usize {
+    (limit.saturating_sub(RESERVED) / PER_WORKER).max(1) as usize
+}
+
+/// Number of workers that fit in the limit of open files, set once at startup
+static WORKERS: OnceLock<usize> = OnceLock::new();
+
+/// Detects the limit of open files and returns the number of workers that fit in it (None without a limit)
+pub fn init() -> Option<usize> {
+    let workers = soft_limit().map(max_workers);
+    let _ = WORKERS.set(workers.unwrap_or(usize::MAX));
+    workers
+}
+
+/// Returns the number of workers that fit in the limit of open files (unbounded before `init`)
+pub fn workers() -> usize {
+    WORKERS.get().copied().unwrap_or(usize::MAX)
+}
+
+/// Returns true if `err` means the process or the system ran out of file descriptors
+pub fn is_exhausted(err: &io::Error) -> bool {
+    // EMFILE and ENFILE on unix, ERROR_TOO_MANY_OPEN_FILES on Windows
+    #[cfg(unix)]
+    let codes = [libc::EMFILE, libc::ENFILE];
+    #[cfg(windows)]
+    let codes = [4];
+    #[cfg(not(any(unix, windows)))]
+    let codes: [i32; 0] = [];
+
+    err.raw_os_error().is_some_and(|code| codes.contains(&code))
+}
+
+/// Runs `open` again with a growing pause while it fails because the process is out of descriptors,
+/// so a burst of workers waits for the others to close their files instead of failing mid-file
+///
+/// # Arguments
+/// * `open`    -   Opens or creates a file
+pub fn retry<T>(mut open: impl FnMut() -> io::Result<T>) -> io::Result<T> {
+    let mut pause = FIRST_PAUSE;
+
+    for _ in 1..ATTEMPTS {
+        match open() {
+            Err(err) if is_exhausted(&err) => {
+                thread::sleep(pause);
+                pause *= 2;
+            }
+            result => return result,
+        }
+    }
+
+    open()
+}
@@ -0,0 +1,213 @@
+use crate::blocklist::sha256_hex;
+use crate::probe;
+use crate::MB_SIZE;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::{
+    blocking::Response,
+    header::{HeaderName, ETAG, LAST_MODIFIED},
+};
+use std::{
+    borrow::Borrow,
+    fmt, fs,
+    hash::{Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Characters that are percent-encoded in canonical paths (everything else is kept as is)
+const PATH_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Returns the canonical form of a link, which is the same for every spelling of the same file:
+/// lowercase scheme and host, no default port, query or fragment, no empty or dot segments
+/// and one percent-encoding of the path ("%5F" and "_" are the same character)
+///
+/// # Arguments
+/// * `url`     -   Link of the file
+pub fn canonical_url(url: &str) -> String {
+    let Ok(parsed) = url::Url::parse(url) else {
+        return url.to_string();
+    };
+
+    let path = parsed
+        .path_segments()
+        .into_iter()
+        .flatten()
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            let decoded = percent_decode_str(segment).decode_utf8_lossy();
+            utf8_percent_encode(&decoded, PATH_SET).to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("/");
+
+    format!(
+        "{}://{}{}/{}",
+        parsed.scheme(),
+        parsed.host_str().unwrap_or_default(),
+        parsed
+            .port()
+            .map(|port| format!(":{}", port))
+            .unwrap_or_default(),
+        path
+    )
+}
+
+/// RemoteFile is a file found on a fastdl
+/// Files are the same if their urls are, so a set of RemoteFiles can be looked up by url
+#[derive(Clone, Debug, Default)]
+pub struct RemoteFile {
+    /// Download link of the file
+    pub url: String,
+    /// Percent-decoded path of the file relative to the url the crawl started at, separated by "/"
+    pub rel_path: String,
+    /// Size in bytes (None if the listing was trusted without a request, see `--fast-crawl`)
+    pub size: Option<u64>,
+    /// Unix time the file was last modified on the server
+    pub mtime: Option<u64>,
+    /// Entity tag the server sent for the file
+    pub etag: Option<String>,
+}
+
+impl RemoteFile {
+    /// Returns a RemoteFile that nothing but its url is known about
+    ///
+    /// # Arguments
+    /// * `url`         -   Download link of the file
+    /// * `root_url`    -   Url the crawl started at (the relative path starts after it)
+    pub fn new(url: &str, root_url: &str) -> Self {
+        let rel_path = match url.strip_prefix(root_url) {
+            Some(rel_path) => rel_path,
+            // Files redirected to another host keep their whole path
+            None => url.splitn(4, '/').nth(3).unwrap_or(url),
+        };
+
+        Self {
+            url: url.to_string(),
+            rel_path: percent_decode_str(rel_path)
+                .decode_utf8_lossy()
+                .into_owned(),
+            ..Self::default()
+        }
+    }
+
+    /// Returns a RemoteFile with the size, modification time and entity tag of a probe response
+    ///
+    /// # Arguments
+    /// * `url`         -   Download link of the file
+    /// * `root_url`    -   Url the crawl started at
+    /// * `resp`        -   Response of the HEAD (or 1 byte ranged GET) request of the file
+    pub fn from_probe(url: &str, root_url: &str, resp: &Response) -> Self {
+        let header = |name: HeaderName| resp.headers().get(name).and_then(|v| v.to_str().ok());
+
+        Self {
+            size: probe::remote_size(resp),
+            mtime: header(LAST_MODIFIED)
+                .and_then(|v| httpdate::parse_http_date(v).ok())
+                .and_then(|t| t.duration_since(UNIX_EPOCH).ok())
+                .map(|t| t.as_secs()),
+            etag: header(ETAG).map(String::from),
+            ..Self::new(url, root_url)
+        }
+    }
+
+    /// Returns the file name (the last segment of the relative path)
+    pub fn file_name(&self) -> &str {
+        self.rel_path.rsplit('/').next().unwrap_or(&self.rel_path)
+    }
+}
+
+impl PartialEq for RemoteFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.url == other.url
+    }
+}
+
+impl Eq for RemoteFile {}
+
+impl Hash for RemoteFile {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Must hash like the url alone for `Borrow<str>`
+        self.url.as_str().hash(state);
+    }
+}
+
+impl Borrow<str> for RemoteFile {
+    fn borrow(&self) -> &str {
+        &self.url
+    }
+}
+
+impl fmt::Display for RemoteFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.url)?;
+        if let Some(size) = self.size {
+            write!(f, " ({:.2} MB)", size as f32 / MB_SIZE as f32)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// LocalFile is a file of the local mirror
+#[derive(Clone, Debug)]
+pub struct LocalFile {
+    /// Path of the file
+    pub path: PathBuf,
+    /// Size in bytes
+    pub size: u64,
+    /// sha256 of the content (None until `hash` computed it)
+    pub hash: Option<String>,
+}
+
+impl LocalFile {
+    /// Returns the LocalFile at `path` (its content is not hashed yet)
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the file
+    pub fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            path: path.to_path_buf(),
+            size: fs::metadata(path)?.len(),
+            hash: None,
+        })
+    }
+
+    /// Returns the sha256 of the content, reading the file the first time
+    pub fn hash(&mut self) -> io::Result<&str> {
+        if self.hash.is_none() {
+            self.hash = Some(sha256_hex(&fs::read(&self.path)?));
+        }
+
+        Ok(self.hash.as_deref().unwrap_or_default())
+    }
+
+    /// Returns the file name
+    pub fn file_name(&self) -> &str {
+        self.path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .unwrap_or_default()
+    }
+}
+
+impl fmt::Display for LocalFile {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} ({:.2} MB)",
+            self.path.display(),
+            self.size as f32 / MB_SIZE as f32
+        )
+    }
+}
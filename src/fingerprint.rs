@@ -0,0 +1,202 @@
+use crate::{
+    changes::{self, CrawlSnapshot},
+    meta_dir, state_store,
+};
+use select::{document::Document, node::Node, predicate::Name};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, io, path::Path, sync::Mutex};
+
+/// ListingStyle is the software that generated the directory listings of a host
+/// The entries of a listing are told apart from its sort and parent links by it
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ListingStyle {
+    /// Apache `mod_autoindex`
+    Apache,
+    /// nginx `autoindex` and `fancyindex`
+    Nginx,
+    /// IIS directory browsing
+    Iis,
+    /// lighttpd `mod_dirlisting`
+    Lighttpd,
+    /// Caddy `file_server browse`
+    Caddy,
+    /// Anything else: every link is an entry
+    Generic,
+}
+
+impl ListingStyle {
+    /// Returns the style of a listing from the `Server` header and markers in its body
+    ///
+    /// # Arguments
+    /// * `server`  -   `Server` header of the listing response
+    /// * `body`    -   Body of the listing
+    pub fn detect(server: Option<&str>, body: &str) -> Self {
+        let server = server.unwrap_or_default().to_lowercase();
+
+        if server.contains("microsoft-iis") || body.contains("[To Parent Directory]") {
+            Self::Iis
+        } else if server.starts_with("apache") || body.contains("?C=N;O=D") {
+            Self::Apache
+        } else if server.contains("caddy") || body.contains("?sort=name") {
+            Self::Caddy
+        } else if server.contains("lighttpd") {
+            Self::Lighttpd
+        } else if server.contains("nginx") || server.contains("openresty") {
+            Self::Nginx
+        } else {
+            Self::Generic
+        }
+    }
+
+    /// Returns the label of the link to the parent directory
+    fn parent_label(self) -> Option<&'static str> {
+        match self {
+            Self::Apache | Self::Lighttpd => Some("Parent Directory"),
+            Self::Iis => Some("[To Parent Directory]"),
+            Self::Caddy => Some("Go up"),
+            Self::Nginx => Some("../"),
+            Self::Generic => None,
+        }
+    }
+
+    /// Returns true if the anchor `node` is an entry of the directory
+    /// Sort links (`?C=N;O=D`, `?sort=name`) and parent links are left out, they would only be probed for nothing
+    fn is_entry(self, node: &Node) -> bool {
+        if self == Self::Generic {
+            return true;
+        }
+
+        let href = node.attr("href").unwrap_or_default().trim();
+        let label = node.text();
+        !href.starts_with('?')
+            && href != ".."
+            && href != "../"
+            && self.parent_label() != Some(label.trim())
+    }
+
+    /// Returns the hrefs of the entries of a listing page
+    ///
+    /// # Arguments
+    /// * `doc`     -   Page of the listing
+    /// * `skip`    -   Href left out (the link to the next page)
+    pub fn hrefs(self, doc: &Document, skip: Option<&str>) -> Vec<String> {
+        doc.find(Name("a"))
+            .filter(|node| self.is_entry(node))
+            .filter_map(|node| node.attr("href"))
+            .filter(|href| Some(*href) != skip)
+            .map(str::to_string)
+            .collect()
+    }
+}
+
+/// HostFingerprint is what the previous runs learned about a host, so the next crawl doesn't probe it again
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct HostFingerprint {
+    /// `Server` header of its listings
+    pub server: Option<String>,
+    /// Style of its listings (None until a listing was fetched)
+    pub style: Option<ListingStyle>,
+    /// Whether it answers HEAD requests (None until a file was probed)
+    pub head: Option<bool>,
+    /// Whether it answers ranged requests with the range (None until one was sent)
+    pub ranges: Option<bool>,
+    /// Unix time of the last time any of it was learned
+    pub seen_at: u64,
+}
+
+/// Host -> fingerprint of every host seen by this run or stored in an output directory
+static HOSTS: Mutex<BTreeMap<String, HostFingerprint>> = Mutex::new(BTreeMap::new());
+
+/// Updates the fingerprint of `host` (the host of a url, compared case-insensitively)
+fn update(host: &str, change: impl FnOnce(&mut HostFingerprint)) {
+    if host.is_empty() {
+        return;
+    }
+
+    let mut hosts = HOSTS.lock().unwrap();
+    let fingerprint = hosts.entry(host.to_lowercase()).or_default();
+    change(fingerprint);
+    fingerprint.seen_at = state_store::unix_now();
+}
+
+/// Returns the fingerprint of `host` learned so far
+fn get(host: &str) -> Option<HostFingerprint> {
+    HOSTS.lock().unwrap().get(&host.to_lowercase()).cloned()
+}
+
+/// Returns every known fingerprint (stored in `fastdl_crawl.json`)
+pub fn all() -> BTreeMap<String, HostFingerprint> {
+    HOSTS.lock().unwrap().clone()
+}
+
+/// Adds the fingerprints stored in an output directory, the most recent one wins for hosts that are known twice
+///
+/// # Arguments
+/// * `out_dir` -   Output directory whose `fastdl_crawl.json` is read
+pub fn load(out_dir: &Path) -> Result<(), String> {
+    let snapshot = CrawlSnapshot::load(&meta_dir::path(out_dir, changes::CRAWL_FILE))?;
+
+    let mut hosts = HOSTS.lock().unwrap();
+    for (host, fingerprint) in snapshot.hosts {
+        let newer = hosts
+            .get(&host)
+            .is_none_or(|known| fingerprint.seen_at > known.seen_at);
+        if newer {
+            hosts.insert(host, fingerprint);
+        }
+    }
+
+    Ok(())
+}
+
+/// Writes every known fingerprint to the `fastdl_crawl.json` of an output directory
+///
+/// # Arguments
+/// * `out_dir` -   Output directory the fingerprints are stored in
+pub fn save(out_dir: &Path) -> io::Result<()> {
+    let path = meta_dir::path(out_dir, changes::CRAWL_FILE);
+    let mut snapshot = CrawlSnapshot::load(&path)
+        .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+    snapshot.save(&path)
+}
+
+/// Returns the listing style stored for `host` if its `Server` header did not change since
+///
+/// # Arguments
+/// * `host`    -   Host of the listing
+/// * `server`  -   `Server` header of its current listing
+pub fn listing_style(host: &str, server: Option<&str>) -> Option<ListingStyle> {
+    get(host)
+        .filter(|fingerprint| fingerprint.server.as_deref() == server)
+        .and_then(|fingerprint| fingerprint.style)
+}
+
+/// Records the software that serves the listings of `host`
+pub fn record_listing(host: &str, server: Option<&str>, style: ListingStyle) {
+    update(host, |fingerprint| {
+        fingerprint.server = server.map(str::to_string);
+        fingerprint.style = Some(style);
+    });
+}
+
+/// Returns whether `host` answered HEAD requests the last time it was probed
+pub fn head_allowed(host: &str) -> Option<bool> {
+    get(host).and_then(|fingerprint| fingerprint.head)
+}
+
+/// Records whether `host` answers HEAD requests
+pub fn record_head(host: &str, allowed: bool) {
+    update(host, |fingerprint| fingerprint.head = Some(allowed));
+}
+
+/// Returns whether `host` answered the last ranged request with the range
+pub fn ranges(host: &str) -> Option<bool> {
+    get(host).and_then(|fingerprint| fingerprint.ranges)
+}
+
+/// Records whether `host` answers ranged requests with the range
+pub fn record_ranges(host: &str, supported: bool) {
+    update(host, |fingerprint| fingerprint.ranges = Some(supported));
+}
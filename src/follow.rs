@@ -0,0 +1,74 @@
+use crate::{a2s, decode_files, download_files, files::RemoteFile, ratios, scan, SyncContext};
+use std::{
+    collections::HashSet,
+    sync::{Arc, Mutex, RwLock},
+};
+
+/// Returns true if `map` was already downloaded and extracted somewhere the decode stage writes maps to
+/// (the output directory, `--extract-to` and the destination of the maps)
+///
+/// # Arguments
+/// * `map`     -   Map name without any extension
+/// * `ctx`     -   State shared by every stage of a sync
+fn map_present(map: &str, ctx: &SyncContext) -> bool {
+    let bsp_name = format!("{}.bsp", map);
+
+    [
+        Some(ctx.out_dir.as_path()),
+        ctx.extract_to.as_deref(),
+        ctx.destinations.get("maps"),
+    ]
+    .into_iter()
+    .flatten()
+    .any(|dir| {
+        !scan::find_files(dir, ctx.scan, |name| name.eq_ignore_ascii_case(&bsp_name)).is_empty()
+    })
+}
+
+/// Polls the game server at `addr` and immediately downloads and extracts its current and next map
+/// if they are not present locally, so the player never sits through the in-game downloader
+///
+/// # Arguments
+/// * `addr`            -   Address of the game server (e.g. "1.2.3.4:27015")
+/// * `maps_url`        -   Fastdl url of the maps directory (e.g. "https://fastdl.example.com/cstrike/maps/")
+/// * `ctx`             -   State shared by every stage of a sync
+/// * `corrupt_files`   -   Files that failed to decode
+pub fn fetch_missing_maps(
+    addr: &str,
+    maps_url: &str,
+    ctx: &SyncContext,
+    corrupt_files: &Mutex<HashSet<String>>,
+) {
+    let maps = match a2s::query_maps(addr) {
+        Ok(maps) => maps,
+        Err(e) => {
+            println!("Failed to query {}: {}", addr, e);
+            return;
+        }
+    };
+
+    let missing = [Some(maps.current), maps.next]
+        .into_iter()
+        .flatten()
+        .filter(|map| !map_present(map, ctx))
+        .map(|map| RemoteFile::new(&format!("{}{}.bsp.bz2", maps_url, map), maps_url))
+        .collect::<HashSet<_>>();
+
+    if missing.is_empty() {
+        return;
+    }
+
+    download_files(&Arc::new(RwLock::new(missing)), ctx);
+    let decoded = decode_files(
+        &[ctx.out_dir.join(ctx.local_path(maps_url))],
+        ctx.scan,
+        corrupt_files,
+        ctx.extract_to.as_deref(),
+        &ctx.destinations,
+        ctx.keep_archives
+            .as_ref()
+            .map(|dir| dir.join(ctx.local_path(maps_url)))
+            .as_deref(),
+    );
+    ratios::record_in_manifest(ctx.store.as_ref(), &decoded);
+}
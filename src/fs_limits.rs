@@ -0,0 +1,80 @@
+use std::path::Path;
+
+/// FsLimits are the limits of a filesystem besides its free space
+#[derive(Debug, Default)]
+pub struct FsLimits {
+    /// Number of files that can still be created (None if the filesystem has no fixed inode table)
+    pub free_inodes: Option<u64>,
+    /// Longest file name the filesystem accepts
+    pub max_name_len: Option<u64>,
+}
+
+/// Returns the closest directory of `path` that exists (the target may not be created yet)
+fn existing_ancestor(path: &Path) -> Option<&Path> {
+    path.ancestors().find(|p| p.is_dir())
+}
+
+/// Returns the limits of the filesystem `dir` is on
+///
+/// # Arguments
+/// * `dir` -   Directory the files will be written to (or one of its descendants)
+#[cfg(unix)]
+pub fn limits(dir: &Path) -> FsLimits {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let Some(path) =
+        existing_ancestor(dir).and_then(|dir| CString::new(dir.as_os_str().as_bytes()).ok())
+    else {
+        return FsLimits::default();
+    };
+
+    let mut stat = std::mem::MaybeUninit::<libc::statvfs>::uninit();
+    // SAFETY: `path` is a valid C string and `stat` is only read if the call succeeded
+    if unsafe { libc::statvfs(path.as_ptr(), stat.as_mut_ptr()) } != 0 {
+        return FsLimits::default();
+    }
+    let stat = unsafe { stat.assume_init() };
+
+    FsLimits {
+        // btrfs, ZFS and most network filesystems report 0 inodes because they allocate them on demand
+        free_inodes: (stat.f_files > 0).then_some(stat.f_favail),
+        max_name_len: (stat.f_namemax > 0).then_some(stat.f_namemax),
+    }
+}
+
+/// Returns the limits of the filesystem `dir` is on
+/// NTFS has no inode table and allows 255 character names
+///
+/// # Arguments
+/// * `dir` -   Directory the files will be written to (or one of its descendants)
+#[cfg(not(unix))]
+pub fn limits(dir: &Path) -> FsLimits {
+    FsLimits {
+        free_inodes: None,
+        max_name_len: existing_ancestor(dir).map(|_| 255),
+    }
+}
+
+/// Returns a warning if the filesystem `dir` is on can't hold `files` new files
+/// or a name of `longest_name` characters
+///
+/// # Arguments
+/// * `dir`             -   Directory the files will be written to
+/// * `files`           -   Number of files that will be created
+/// * `longest_name`    -   Length of the longest file name that will be created
+pub fn preflight(dir: &Path, files: u64, longest_name: usize) -> Option<String> {
+    let limits = limits(dir);
+    let mut warnings = Vec::new();
+
+    if let Some(free) = limits.free_inodes.filter(|free| *free < files) {
+        warnings.push(format!("only {} free inodes for {} files!", free, files));
+    }
+    if let Some(max) = limits.max_name_len.filter(|max| *max < longest_name as u64) {
+        warnings.push(format!(
+            "names up to {} chars, the filesystem allows {}!",
+            longest_name, max
+        ));
+    }
+
+    (!warnings.is_empty()).then(|| warnings.join(", "))
+}
@@ -0,0 +1,148 @@
+use url::Url;
+
+/// Replaces the HTML entities that show up in listing hrefs (`&amp;`, `&#39;`, `&#x27;`, ...)
+///
+/// # Arguments
+/// * `href`    -   Raw href of a link
+pub fn decode_entities(href: &str) -> String {
+    let mut decoded = String::with_capacity(href.len());
+    let mut rest = href;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let entity = rest
+            .find(';')
+            .map(|end| (&rest[1..end], end))
+            .and_then(|(name, end)| decode_entity(name).map(|c| (c, end)));
+
+        match entity {
+            Some((c, end)) => {
+                decoded.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                // Not an entity, keep the "&" as is
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    decoded.push_str(rest);
+    decoded
+}
+
+/// Returns the character of an entity name (without the "&" and ";")
+fn decode_entity(name: &str) -> Option<char> {
+    match name {
+        "amp" => Some('&'),
+        "lt" => Some('<'),
+        "gt" => Some('>'),
+        "quot" => Some('"'),
+        "apos" => Some('\''),
+        _ => {
+            let code = name.strip_prefix('#')?;
+            let code = match code.strip_prefix(['x', 'X']) {
+                Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                None => code.parse().ok()?,
+            };
+
+            char::from_u32(code)
+        }
+    }
+}
+
+/// Resolves an href of a listing against the url of the listing
+/// Returns None if the href leaves the crawl root (other host, parent directories, ...)
+///
+/// # Arguments
+/// * `dir_url`     -   Url of the directory listing the href was found in
+/// * `root_url`    -   Url the crawl started at
+/// * `href`        -   Raw href of the link
+/// * `allowed`     -   Path fragment that may live outside of the crawl root (the fastdl redirect directory)
+pub fn resolve_href(dir_url: &Url, root_url: &Url, href: &str, allowed: &str) -> Option<Url> {
+    // Url::join normalizes "./" and "../" segments
+    let mut url = dir_url.join(decode_entities(href).trim()).ok()?;
+    url.set_fragment(None);
+    url.set_query(None);
+
+    let same_origin = url.scheme() == root_url.scheme()
+        && url.host_str() == root_url.host_str()
+        && url.port_or_known_default() == root_url.port_or_known_default();
+    let in_root = url.path().starts_with(root_url.path()) || url.path().contains(allowed);
+
+    (same_origin && in_root).then_some(url)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(dir: &str, href: &str) -> Option<String> {
+        let root_url = Url::parse("https://fastdl.example.com/cstrike/").unwrap();
+        let dir_url = Url::parse(dir).unwrap();
+        resolve_href(&dir_url, &root_url, href, "/redirect/").map(|url| url.to_string())
+    }
+
+    #[test]
+    fn decodes_entities() {
+        assert_eq!(decode_entities("ze_a&amp;b.bsp.bz2"), "ze_a&b.bsp.bz2");
+        assert_eq!(decode_entities("ze_&#39;x&#x27;.bsp"), "ze_'x'.bsp");
+        assert_eq!(decode_entities("a&b&unknown;c&"), "a&b&unknown;c&");
+    }
+
+    #[test]
+    fn resolves_entities() {
+        assert_eq!(
+            resolve(
+                "https://fastdl.example.com/cstrike/maps/",
+                "ze_a&amp;b.bsp.bz2"
+            )
+            .as_deref(),
+            Some("https://fastdl.example.com/cstrike/maps/ze_a&b.bsp.bz2")
+        );
+    }
+
+    #[test]
+    fn resolves_dot_segments() {
+        assert_eq!(
+            resolve("https://fastdl.example.com/cstrike/maps/", "./ze_x.bsp.bz2").as_deref(),
+            Some("https://fastdl.example.com/cstrike/maps/ze_x.bsp.bz2")
+        );
+        assert_eq!(
+            resolve("https://fastdl.example.com/cstrike/maps/", "../sound/").as_deref(),
+            Some("https://fastdl.example.com/cstrike/sound/")
+        );
+    }
+
+    #[test]
+    fn rejects_links_out_of_the_root() {
+        // Parent link of the crawl root
+        assert_eq!(resolve("https://fastdl.example.com/cstrike/", "../"), None);
+        assert_eq!(
+            resolve("https://fastdl.example.com/cstrike/maps/", "../../csgo/"),
+            None
+        );
+        assert_eq!(
+            resolve(
+                "https://fastdl.example.com/cstrike/maps/",
+                "https://other.example.com/cstrike/x"
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn drops_fragment_and_query() {
+        assert_eq!(
+            resolve(
+                "https://fastdl.example.com/cstrike/maps/",
+                "ze_x.bsp.bz2?C=M;O=A#top"
+            )
+            .as_deref(),
+            Some("https://fastdl.example.com/cstrike/maps/ze_x.bsp.bz2")
+        );
+    }
+}
@@ -0,0 +1,450 @@
+use crate::{
+    meta_dir, net,
+    scan::{self, ScanPolicy},
+    schema,
+    state_store::unix_now,
+    storage::Storage,
+};
+use percent_encoding::percent_decode_str;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    env,
+    fs::{self, File},
+    io::{self, Cursor},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// Name of the published checksum manifest, at the top of the mirror (`sha256sum` format)
+pub const SUMS_FILE: &str = "SHA256SUMS";
+/// Name of the minisign signature of the checksum manifest, next to it
+pub const SIGNATURE_FILE: &str = "SHA256SUMS.minisig";
+/// Names of the checksum manifests fastdls publish, looked up in this order
+const PUBLISHED_NAMES: &[&str] = &[SUMS_FILE, "sha256sums.txt", "SHA256SUMS.txt"];
+/// Name of the checksums kept between runs, so unchanged files are not hashed again
+const CACHE_FILE: &str = "fastdl_sums.json";
+/// Environment variable holding the password of an encrypted signing key
+pub const PASSWORD_VAR: &str = "FASTDL_SIGN_PASSWORD";
+
+/// Sum is the checksum of a published file, with what tells whether the file changed since
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Sum {
+    /// Hex sha256 of the file
+    sha256: String,
+    /// Size of the file in bytes
+    size: u64,
+    /// Unix time the file was last modified (0 for uploads, which are hashed as they're written)
+    mtime: u64,
+}
+
+/// Sums are the checksums of every file the mirror serves (versioned by `schema::SUMS`)
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Sums {
+    /// Version of the format the checksums were written in
+    #[serde(default)]
+    schema_version: u32,
+    /// Path relative to the output directory (separated by "/") -> checksum
+    files: BTreeMap<String, Sum>,
+}
+
+/// Returns `path` relative to `root`, separated by "/" (None if it's not inside of `root`)
+fn relative(root: &Path, path: &Path) -> Option<String> {
+    let rel = path.strip_prefix(root).ok()?;
+    Some(
+        rel.components()
+            .map(|component| component.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/"),
+    )
+}
+
+/// Returns the size and the modification time of the file at `path`
+fn stat(path: &Path) -> io::Result<(u64, u64)> {
+    let meta = fs::metadata(path)?;
+    let mtime = meta
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+
+    Ok((meta.len(), mtime))
+}
+
+/// Returns the hex sha256 of the file at `path`, read as a stream
+fn hash_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+impl Sums {
+    /// Reads the checksums kept in the output directory `out_dir` (empty if there are none)
+    pub fn load(out_dir: &Path) -> Result<Self, String> {
+        Ok(schema::SUMS
+            .load(&meta_dir::path(out_dir, CACHE_FILE))?
+            .unwrap_or_default())
+    }
+
+    /// Writes the checksums to the output directory `out_dir`
+    pub fn save(&mut self, out_dir: &Path) -> io::Result<()> {
+        self.schema_version = schema::SUMS.version();
+
+        let path = meta_dir::path(out_dir, CACHE_FILE);
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Records the checksum of a file uploaded to a storage that is not the output directory
+    ///
+    /// # Arguments
+    /// * `out_dir` -   Output directory
+    /// * `path`    -   Local path of the file (inside of `out_dir`)
+    /// * `sha256`  -   Hex sha256 of the file
+    /// * `size`    -   Size of the file
+    pub fn record(&mut self, out_dir: &Path, path: &Path, sha256: &str, size: u64) {
+        if let Some(rel) = relative(out_dir, path) {
+            self.files.insert(
+                rel,
+                Sum {
+                    sha256: sha256.to_string(),
+                    size,
+                    mtime: 0,
+                },
+            );
+        }
+    }
+
+    /// Keeps only the uploads whose local path is in `paths` (the files the manifest still has)
+    ///
+    /// # Arguments
+    /// * `out_dir` -   Output directory
+    /// * `paths`   -   Local paths of the files the mirror serves
+    pub fn retain_uploads(&mut self, out_dir: &Path, paths: &[PathBuf]) {
+        let kept = paths
+            .iter()
+            .filter_map(|path| relative(out_dir, path))
+            .collect::<HashSet<_>>();
+        self.files.retain(|rel, _| kept.contains(rel));
+    }
+
+    /// Replaces the checksums with those of the files below `out_dir`
+    /// Files whose size and modification time did not change keep their checksum, the others are hashed
+    ///
+    /// # Arguments
+    /// * `out_dir` -   Output directory
+    /// * `scan`    -   Which symlinks and hidden directories are walked into
+    pub fn refresh(&mut self, out_dir: &Path, scan: ScanPolicy) {
+        let published = scan::find_files(out_dir, scan, |name| {
+            name != SUMS_FILE && name != SIGNATURE_FILE
+        });
+
+        let previous = std::mem::take(&mut self.files);
+        self.files = published
+            .par_iter()
+            .filter_map(|path| {
+                let rel = relative(out_dir, path)?;
+                let (size, mtime) = stat(path).ok()?;
+                let sum = match previous.get(&rel) {
+                    Some(sum) if sum.size == size && sum.mtime == mtime => sum.clone(),
+                    _ => Sum {
+                        sha256: hash_file(path).ok()?,
+                        size,
+                        mtime,
+                    },
+                };
+
+                Some((rel, sum))
+            })
+            .collect();
+    }
+
+    /// Returns the checksum manifest, one "<sha256>  <path>" line per file like `sha256sum` writes
+    pub fn render(&self) -> String {
+        self.files
+            .iter()
+            .map(|(rel, sum)| format!("{}  {}\n", sum.sha256, rel))
+            .collect()
+    }
+}
+
+/// Returns the minisign signature of `sums`, signed with the secret key at `key`
+///
+/// # Arguments
+/// * `sums`        -   Checksum manifest
+/// * `key`         -   Minisign secret key (`minisign -G`)
+/// * `password`    -   Password of an encrypted key (empty for an unencrypted one)
+fn sign(sums: &str, key: &Path, password: String) -> Result<String, String> {
+    let key_box = fs::read_to_string(key)
+        .map_err(|e| format!("Failed to read the signing key {}: {}", key.display(), e))
+        .and_then(|text| {
+            minisign::SecretKeyBox::from_string(&text)
+                .map_err(|e| format!("{} is not a minisign secret key: {}", key.display(), e))
+        })?;
+    let secret_key = key_box.into_secret_key(Some(password)).map_err(|e| {
+        format!(
+            "Failed to open the signing key {} (set {} for an encrypted key): {}",
+            key.display(),
+            PASSWORD_VAR,
+            e
+        )
+    })?;
+
+    let trusted_comment = format!("timestamp:{}\tfile:{}", unix_now(), SUMS_FILE);
+    minisign::sign(
+        None,
+        &secret_key,
+        Cursor::new(sums.as_bytes()),
+        Some(&trusted_comment),
+        Some("signature of the checksums of a css-downloader mirror"),
+    )
+    .map(|signature| signature.into_string())
+    .map_err(|e| format!("Failed to sign the checksums: {}", e))
+}
+
+/// Writes the checksum manifest of `sums` and its signature to the top of the mirror through `storage`,
+/// so mirrors syncing from this one can verify every file they download
+/// An encrypted key is opened with the password in `FASTDL_SIGN_PASSWORD`
+/// Returns the number of files listed
+///
+/// # Arguments
+/// * `out_dir` -   Output directory
+/// * `storage` -   Backend the mirror is written to (the output directory or a bucket)
+/// * `sums`    -   Checksums of every file the mirror serves
+/// * `key`     -   Minisign secret key the checksums are signed with
+pub fn publish(
+    out_dir: &Path,
+    storage: &dyn Storage,
+    sums: &mut Sums,
+    key: &Path,
+) -> Result<usize, String> {
+    let manifest = sums.render();
+    let signature = sign(&manifest, key, env::var(PASSWORD_VAR).unwrap_or_default())?;
+
+    storage
+        .write(&out_dir.join(SUMS_FILE), manifest.as_bytes())
+        .and_then(|_| storage.write(&out_dir.join(SIGNATURE_FILE), signature.as_bytes()))
+        .map_err(|e| format!("Failed to publish the checksums: {}", e))?;
+    sums.save(out_dir)
+        .map_err(|e| format!("Failed to save the checksums: {}", e))?;
+
+    Ok(sums.files.len())
+}
+
+/// Returns the checksums of a checksum manifest: path -> hex sha256
+/// Reads the `sha256sum` format ("<hex>  <path>", "<hex> *<path>") and the BSD one ("SHA256 (<path>) = <hex>")
+///
+/// # Arguments
+/// * `text`    -   Checksum manifest
+fn parse(text: &str) -> HashMap<String, String> {
+    let is_sha256 = |hash: &str| hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit());
+
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim_end();
+            let (path, hash) = match line.strip_prefix("SHA256 (") {
+                Some(rest) => rest.rsplit_once(") = ")?,
+                None => {
+                    let (hash, path) = line.split_once(char::is_whitespace)?;
+                    (path.trim_start().trim_start_matches('*'), hash)
+                }
+            };
+            let path = path.trim_start_matches("./");
+
+            (is_sha256(hash) && !path.is_empty()).then(|| (path.to_string(), hash.to_lowercase()))
+        })
+        .collect()
+}
+
+/// Returns the minisign public key `key`: a key file (`minisign -G`) or the key itself in base64
+fn public_key(key: &str) -> Result<minisign::PublicKey, String> {
+    match fs::read_to_string(key) {
+        Ok(text) => minisign::PublicKeyBox::from_string(&text)
+            .and_then(|key_box| key_box.into_public_key())
+            .map_err(|e| format!("{} is not a minisign public key: {}", key, e)),
+        Err(_) => minisign::PublicKey::from_base64(key).map_err(|e| {
+            format!(
+                "{} is neither a key file nor a minisign public key: {}",
+                key, e
+            )
+        }),
+    }
+}
+
+/// Returns an error if the signature at `signature_url` of the checksum manifest `text` does not match `key`
+///
+/// # Arguments
+/// * `text`            -   Checksum manifest as it was downloaded
+/// * `signature_url`   -   Url of its minisign signature
+/// * `key`             -   Public key of the mirror that publishes it
+fn verify(text: &str, signature_url: &str, key: &str) -> Result<(), String> {
+    let public_key = public_key(key)?;
+    let signature = net::client()
+        .get(signature_url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.text())
+        .map_err(|e| format!("Failed to download {}: {}", signature_url, e))?;
+    let signature = minisign::SignatureBox::from_string(&signature)
+        .map_err(|e| format!("{} is not a minisign signature: {}", signature_url, e))?;
+
+    minisign::verify(
+        &public_key,
+        &signature,
+        Cursor::new(text.as_bytes()),
+        true,
+        false,
+        false,
+    )
+    .map_err(|e| format!("The signature {} does not match: {}", signature_url, e))
+}
+
+/// Returns the checksums the fastdl at `url` publishes, keyed by the path relative to `url` (percent-decoded
+/// and separated by "/" like `RemoteFile::rel_path`), or None if it publishes none
+/// The checksum manifest is looked up in `url`, then in every parent directory up to the root of the host:
+/// a mirror published by `--publish-sums` lists the paths of its whole output directory
+/// With `key`, a manifest whose minisign signature (`<name>.minisig`) does not match is ignored
+///
+/// # Arguments
+/// * `url`     -   Fastdl url that is crawled
+/// * `key`     -   Public key the checksums must be signed with (`--sums-key`)
+pub fn fetch_published(url: &str, key: Option<&str>) -> Option<HashMap<String, String>> {
+    let url = url::Url::parse(url).ok()?;
+    let path = url.path().to_string();
+
+    // Deepest directory first: "/a/b/" -> "/a/b/", "/a/", "/"
+    let mut dirs = vec![path.clone()];
+    let mut dir = path.as_str();
+    while let Some(pos) = dir.trim_end_matches('/').rfind('/') {
+        dir = &dir[..=pos];
+        dirs.push(dir.to_string());
+    }
+
+    for dir in dirs {
+        // The crawled directory inside of the one the manifest lists
+        let prefix = percent_decode_str(&path[dir.len()..])
+            .decode_utf8_lossy()
+            .into_owned();
+
+        for name in PUBLISHED_NAMES {
+            let mut sums_url = url.clone();
+            sums_url.set_path(&format!("{}{}", dir, name));
+            sums_url.set_query(None);
+
+            let Ok(text) = net::client()
+                .get(sums_url.as_str())
+                .send()
+                .and_then(|resp| resp.error_for_status())
+                .and_then(|resp| resp.text())
+            else {
+                continue;
+            };
+
+            if let Some(key) = key {
+                if let Err(err) = verify(&text, &format!("{}.minisig", sums_url), key) {
+                    eprintln!("Ignored the checksums of {}: {}", sums_url, err);
+                    return None;
+                }
+            }
+
+            let sums = parse(&text)
+                .into_iter()
+                .filter_map(|(path, hash)| Some((path.strip_prefix(&prefix)?.to_string(), hash)))
+                .collect::<HashMap<_, _>>();
+            // A page served for any path (e.g. a soft 404) is not a checksum manifest
+            if !sums.is_empty() {
+                return Some(sums);
+            }
+        }
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HASH: &str = "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08";
+
+    /// Returns an empty directory for the test `name`
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = env::temp_dir().join(format!("fastdl_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn parses_checksum_manifests() {
+        let sums = parse(&format!(
+            "{hash}  maps/ze_a.bsp.bz2\n\
+             {upper} *./sound/ze boss.wav\n\
+             SHA256 (materials/x.vmt) = {hash}\n\
+             # comment\n\
+             abc  maps/short.bsp\n\
+             {hash}\n",
+            hash = HASH,
+            upper = HASH.to_uppercase()
+        ));
+
+        assert_eq!(sums.len(), 3);
+        assert_eq!(sums["maps/ze_a.bsp.bz2"], HASH);
+        assert_eq!(sums["sound/ze boss.wav"], HASH);
+        assert_eq!(sums["materials/x.vmt"], HASH);
+    }
+
+    #[test]
+    fn lists_the_served_files() {
+        let dir = test_dir("integrity_refresh");
+        fs::create_dir_all(dir.join("maps")).unwrap();
+        fs::create_dir_all(dir.join(meta_dir::META_DIR)).unwrap();
+        fs::write(dir.join("maps").join("ze_a.bsp"), b"test").unwrap();
+        fs::write(dir.join(SUMS_FILE), b"old").unwrap();
+        fs::write(dir.join(meta_dir::META_DIR).join("state"), b"private").unwrap();
+
+        let mut sums = Sums::default();
+        sums.refresh(&dir, ScanPolicy::default());
+        assert_eq!(sums.render(), format!("{}  maps/ze_a.bsp\n", HASH));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn signatures_verify_with_the_public_key() {
+        // Unencrypted key pair made from a fixed seed, `minisign -G -W` writes the same format
+        const SECRET_KEY: &str = "untrusted comment: minisign secret key (test)\n\
+            RWQAAEIyAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAQIDBAUGBwgAAQIDBAUG\
+            BwgJCgsMDQ4PEBESExQVFhcYGRobHB0eHwOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4UuhoZ0oj6XehkwAx\
+            qgcDfOhmwCrPXeYwQwAwVY5NB98=\n";
+        const PUBLIC_KEY: &str = "RWQBAgMEBQYHCAOhB7/zzhC+HXDdGOdLwJln5NYwm6UNXx3chmQSVTG4";
+
+        let dir = test_dir("integrity_sign");
+        let key = dir.join("mirror.key");
+        fs::write(&key, SECRET_KEY).unwrap();
+
+        let manifest = format!("{}  maps/ze_a.bsp\n", HASH);
+        let signature = sign(&manifest, &key, String::new()).unwrap();
+        let signature = minisign::SignatureBox::from_string(&signature).unwrap();
+        let public_key = public_key(PUBLIC_KEY).unwrap();
+        let verify = |text: &str| {
+            minisign::verify(
+                &public_key,
+                &signature,
+                Cursor::new(text.as_bytes()),
+                true,
+                false,
+                false,
+            )
+        };
+
+        assert!(verify(&manifest).is_ok());
+        assert!(verify(&manifest.replace("ze_a", "ze_b")).is_err());
+        assert!(sign(&manifest, &dir.join("missing.key"), String::new()).is_err());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
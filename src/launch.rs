@@ -0,0 +1,37 @@
+use std::{io, process::Command};
+
+/// Steam app id of Counter-Strike: Source
+const CSS_APP_ID: u32 = 240;
+
+/// Returns the steam protocol url that starts CS:S (and connects to `server` if given)
+///
+/// # Arguments
+/// * `server`  -   Game server to connect to (e.g. "1.2.3.4:27015")
+pub fn steam_url(server: Option<&str>) -> String {
+    match server {
+        Some(server) => format!("steam://rungameid/{}//+connect%20{}", CSS_APP_ID, server),
+        None => format!("steam://rungameid/{}", CSS_APP_ID),
+    }
+}
+
+/// Starts CS:S through Steam by opening the steam protocol url with the OS handler
+///
+/// # Arguments
+/// * `server`  -   Game server to connect to once the game started
+pub fn launch_css(server: Option<&str>) -> io::Result<()> {
+    let url = steam_url(server);
+
+    let mut command = if cfg!(target_os = "windows") {
+        let mut command = Command::new("cmd");
+        command.args(["/C", "start", ""]);
+        command
+    } else if cfg!(target_os = "macos") {
+        Command::new("open")
+    } else {
+        Command::new("xdg-open")
+    };
+
+    command.arg(url).spawn()?;
+
+    Ok(())
+}
@@ -0,0 +1,91 @@
+use crate::prepare::ASSET_DIRS;
+use std::{path::PathBuf, str::FromStr};
+use url::Url;
+
+/// Directory the `custom` layout writes into, inside of the `custom` folder of the game
+const CUSTOM_NAME: &str = "fastdl";
+
+/// Layout decides where the synced files go inside of the output directory (`--layout <layout>`)
+/// Every layout but `mirror` starts the local path at the first asset directory of the link
+/// ("https://host/fastdl/cstrike/maps/ze_x.bsp.bz2" -> "maps/ze_x.bsp.bz2")
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Layout {
+    /// `download/maps/...`, where the client looks for custom content (run from the game directory)
+    #[default]
+    Download,
+    /// `maps/...`, next to the stock content (e.g. for a server's game directory)
+    Flat,
+    /// `custom/fastdl/maps/...`, a custom content folder the game mounts on its own
+    Custom,
+    /// The whole path of the link ("fastdl/cstrike/maps/..."), the layout of older releases
+    Mirror,
+}
+
+impl FromStr for Layout {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "download" => Ok(Self::Download),
+            "flat" => Ok(Self::Flat),
+            "custom" => Ok(Self::Custom),
+            "mirror" => Ok(Self::Mirror),
+            _ => Err(format!(
+                "Unknown layout: {} (expected download, flat, custom or mirror)",
+                s
+            )),
+        }
+    }
+}
+
+impl Layout {
+    /// Returns the directory (relative to the output directory) every path of the layout starts with
+    pub fn base(&self) -> PathBuf {
+        let mut path = PathBuf::from(".");
+        match self {
+            Self::Download => path.push("download"),
+            Self::Flat | Self::Mirror => {}
+            Self::Custom => path.extend(["custom", CUSTOM_NAME]),
+        }
+
+        path
+    }
+
+    /// Returns the path (relative to the output directory) a link or fastdl url is synced to
+    /// Paths without an asset directory keep only what follows the fastdl root's game directory,
+    /// so a fastdl url like "https://host/fastdl/cstrike/" maps to the layout's base directory
+    ///
+    /// # Arguments
+    /// * `url`     -   Download link or fastdl url
+    pub fn local_path(&self, url: &str) -> PathBuf {
+        let segments = Url::parse(url)
+            .ok()
+            .map(|url| {
+                url.path_segments()
+                    .into_iter()
+                    .flatten()
+                    .filter(|segment| !segment.is_empty())
+                    .map(String::from)
+                    .collect::<Vec<_>>()
+            })
+            .unwrap_or_default();
+
+        let mut path = self.base();
+        if *self == Self::Mirror {
+            path.extend(&segments);
+            return path;
+        }
+
+        let start = segments
+            .iter()
+            .position(|segment| ASSET_DIRS.contains(&segment.to_lowercase().as_str()));
+        match start {
+            Some(start) => path.extend(&segments[start..]),
+            // Only files keep their name, directories above the asset directories are dropped
+            None if !url.ends_with('/') => path.extend(segments.last()),
+            None => {}
+        }
+
+        path
+    }
+}
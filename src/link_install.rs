@@ -0,0 +1,168 @@
+use crate::prepare::ASSET_DIRS;
+use crate::reflink;
+use crate::scan::{self, ScanPolicy};
+#[cfg(windows)]
+use std::process::Command;
+use std::{
+    fs, io,
+    path::{Component, Path, PathBuf},
+};
+
+/// LinkSummary counts the links created in a game directory
+#[derive(Debug, Default)]
+pub struct LinkSummary {
+    /// Asset directories linked as a whole (new files show up without relinking)
+    pub dir_links: usize,
+    /// Files linked one by one into asset directories that already existed
+    pub file_links: usize,
+    /// Files cloned copy-on-write instead of linked (`--reflink`)
+    pub clones: usize,
+    /// Files or directories that could not be linked
+    pub failed: Vec<PathBuf>,
+}
+
+/// Returns the path of a mirror directory relative to the `download` folder of a game install
+/// The path starts at the first asset directory ("./gflfastdlv2/cstrike/maps" becomes "maps")
+///
+/// # Arguments
+/// * `output_root` -   Directory a fastdl url downloads into (see `Layout::local_path`)
+pub fn install_path(output_root: &Path) -> Option<PathBuf> {
+    let components = output_root
+        .components()
+        .filter_map(|c| match c {
+            Component::Normal(name) => name.to_str(),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    let start = components
+        .iter()
+        .position(|name| ASSET_DIRS.contains(&name.to_lowercase().as_str()))?;
+
+    Some(components[start..].iter().collect())
+}
+
+/// Links the directory `dst` to `src` (an NTFS junction on Windows, which needs no privileges)
+fn link_dir(src: &Path, dst: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    {
+        let status = Command::new("cmd")
+            .args(["/C", "mklink", "/J"])
+            .arg(dst)
+            .arg(src)
+            .output()?
+            .status;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(io::Error::new(io::ErrorKind::Other, "mklink /J failed"))
+        }
+    }
+
+    #[cfg(not(windows))]
+    std::os::unix::fs::symlink(src, dst)
+}
+
+/// Links the file `dst` to `src`
+/// Windows only allows symlinks with developer mode or as admin, so a hard link is made instead if it fails
+fn link_file(src: &Path, dst: &Path) -> io::Result<()> {
+    #[cfg(windows)]
+    let linked = std::os::windows::fs::symlink_file(src, dst);
+    #[cfg(not(windows))]
+    let linked = std::os::unix::fs::symlink(src, dst);
+
+    linked.or_else(|_| fs::hard_link(src, dst))
+}
+
+/// Returns true if something (even a dangling link) is at `path`
+fn is_taken(path: &Path) -> bool {
+    fs::symlink_metadata(path).is_ok()
+}
+
+/// Returns true if `path` is a link whose target no longer exists
+fn is_dangling(path: &Path) -> bool {
+    is_taken(path) && !path.exists()
+}
+
+/// Links the content of the mirror directory `src` into `<game_dir>/download/<install_path>`
+/// Missing asset directories are linked as a whole; existing ones get a link for every file they lack
+/// Running it again only adds the links of the files that arrived since the last run
+/// With `reflink` every file is cloned copy-on-write instead, so the game directory holds real files
+/// that share their blocks with the mirror; files the filesystem can't clone are linked
+///
+/// # Arguments
+/// * `src`             -   Absolute mirror directory with the decoded files
+/// * `install_path`    -   Path of the directory relative to the `download` folder (e.g. "maps")
+/// * `game_dir`        -   Game directory of an install (e.g. ".../cstrike")
+/// * `policy`          -   Which symlinks and hidden directories are walked into
+/// * `reflink`         -   Clone the files instead of linking them
+/// * `summary`         -   Counts of the links that were created
+pub fn link_install(
+    src: &Path,
+    install_path: &Path,
+    game_dir: &Path,
+    policy: ScanPolicy,
+    reflink: bool,
+    summary: &mut LinkSummary,
+) {
+    let dst = game_dir.join("download").join(install_path);
+    if !src.is_dir() {
+        return;
+    }
+
+    // Links whose mirror directory moved are replaced
+    if is_dangling(&dst) {
+        let _ = fs::remove_file(&dst).or_else(|_| fs::remove_dir(&dst));
+    }
+
+    if !reflink && !is_taken(&dst) {
+        let linked = dst
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| link_dir(src, &dst));
+
+        match linked {
+            Ok(_) => summary.dir_links += 1,
+            Err(_) => summary.failed.push(dst),
+        }
+        return;
+    }
+
+    // Already linked to the mirror, new files show up on their own
+    if fs::canonicalize(&dst).ok() == fs::canonicalize(src).ok() {
+        return;
+    }
+
+    for file in scan::find_files(src, policy, |_| true) {
+        let Ok(relative) = file.strip_prefix(src) else {
+            continue;
+        };
+        let dst_file = dst.join(relative);
+
+        // Clones of files that got a new version are cloned again
+        if is_dangling(&dst_file) || (reflink && reflink::is_stale(&file, &dst_file)) {
+            let _ = fs::remove_file(&dst_file);
+        }
+        if is_taken(&dst_file) {
+            continue;
+        }
+
+        if dst_file
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .is_err()
+        {
+            summary.failed.push(dst_file);
+            continue;
+        }
+        if reflink && reflink::clone_file(&file, &dst_file).is_ok() {
+            summary.clones += 1;
+            continue;
+        }
+
+        match link_file(&file, &dst_file) {
+            Ok(_) => summary.file_links += 1,
+            Err(_) => summary.failed.push(dst_file),
+        }
+    }
+}
@@ -0,0 +1,91 @@
+use crate::files::RemoteFile;
+use std::{
+    collections::HashSet,
+    fs,
+    io::{self, Read, Write},
+    path::Path,
+};
+
+/// Name of the link list written by `crawl` and read by `download` when `--links` is not given
+pub const LINKS_FILE: &str = "links.txt";
+
+/// Returns true if `path` means stdout/stdin instead of a file
+///
+/// # Arguments
+/// * `path`    -   Path passed with `--links`
+pub fn is_stdio(path: &Path) -> bool {
+    path == Path::new("-")
+}
+
+/// Writes `links` to `path` (or stdout if it's "-"), one link per line, sorted
+///
+/// # Arguments
+/// * `path`    -   Path of the link list
+/// * `links`   -   Files found by the crawl
+pub fn write(path: &Path, links: &HashSet<RemoteFile>) -> io::Result<()> {
+    let mut links = links
+        .iter()
+        .map(|file| file.url.as_str())
+        .collect::<Vec<_>>();
+    links.sort_unstable();
+
+    let mut text = links.join("\n");
+    text.push('\n');
+
+    if is_stdio(path) {
+        io::stdout().write_all(text.as_bytes())
+    } else {
+        fs::write(path, text)
+    }
+}
+
+/// Returns the links of the link list at `path` (or stdin if it's "-")
+/// Empty lines and lines starting with "#" are ignored so the list can be edited by hand
+///
+/// # Arguments
+/// * `path`    -   Path of the link list
+pub fn read(path: &Path) -> io::Result<HashSet<String>> {
+    let text = if is_stdio(path) {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        text
+    } else {
+        fs::read_to_string(path)?
+    };
+
+    let links = text
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect();
+
+    Ok(links)
+}
+
+/// Splits `links` between the profiles whose urls they start with
+/// Links that match no profile go to the first one
+///
+/// # Arguments
+/// * `links`           -   Download links of the link list
+/// * `profile_urls`    -   Fastdl urls of every selected profile
+pub fn split_by_profile(
+    links: HashSet<String>,
+    profile_urls: &[&[String]],
+) -> Vec<HashSet<RemoteFile>> {
+    let mut split = vec![HashSet::new(); profile_urls.len()];
+
+    for link in links {
+        let (idx, root_url) = profile_urls
+            .iter()
+            .enumerate()
+            .find_map(|(idx, urls)| {
+                let url = urls.iter().find(|url| link.starts_with(url.as_str()))?;
+                Some((idx, url.as_str()))
+            })
+            .unwrap_or((0, ""));
+        split[idx].insert(RemoteFile::new(&link, root_url));
+    }
+
+    split
+}
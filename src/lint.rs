@@ -0,0 +1,267 @@
+use crate::collisions;
+use crate::files::RemoteFile;
+use crate::net;
+use crate::probe::{self, HostProbe};
+use crate::scan::{self, ScanPolicy};
+use crate::MB_SIZE;
+use rayon::prelude::*;
+use reqwest::Url;
+use std::{
+    collections::{BTreeMap, HashSet},
+    fmt, fs,
+    path::Path,
+};
+
+/// Longest relative path the engine accepts (MAX_PATH minus the terminator)
+const MAX_PATH_LEN: usize = 259;
+/// Largest file clients download without a bz2 copy
+const MAX_RAW_SIZE: u64 = 150 * MB_SIZE as u64;
+/// Characters the downloader rejects in a path
+const FORBIDDEN_CHARS: &[char] = &[':', '*', '?', '"', '<', '>', '|', '\\'];
+/// Extensions the engine never downloads, no matter what the server lists
+pub const BLOCKED_EXTENSIONS: &[&str] = &[
+    "bat", "cfg", "com", "dll", "exe", "gcf", "ini", "log", "lst", "lua", "nut", "smx", "sys",
+    "vbs", "vdf",
+];
+
+/// Entry is a file of the linted tree
+#[derive(Debug)]
+pub struct Entry {
+    /// Path relative to the root of the tree, separated by "/"
+    pub path: String,
+    /// Size in bytes (None if it's unknown)
+    pub size: Option<u64>,
+}
+
+/// Severity of an issue
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    /// Clients fail to download the file
+    Error,
+    /// Clients download the file, but not the way the operator probably wants
+    Warning,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Severity::Error => write!(f, "error"),
+            Severity::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+/// Issue is a file that would break (or degrade) the in-game download
+#[derive(Debug)]
+pub struct Issue {
+    /// How bad the issue is
+    pub severity: Severity,
+    /// Path of the file
+    pub path: String,
+    /// What is wrong with it
+    pub message: String,
+}
+
+/// Returns the files below `root`
+///
+/// # Arguments
+/// * `root`    -   Directory of the tree
+/// * `policy`  -   Which symlinks and hidden directories are walked into
+pub fn local_entries(root: &Path, policy: ScanPolicy) -> Vec<Entry> {
+    scan::find_files(root, policy, |_| true)
+        .into_iter()
+        .map(|path| Entry {
+            size: fs::metadata(&path).ok().map(|m| m.len()),
+            path: path
+                .strip_prefix(root)
+                .unwrap_or(&path)
+                .components()
+                .map(|c| c.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/"),
+        })
+        .collect()
+}
+
+/// Returns the files of a crawled fastdl (sizes are only known if the crawl probed the files)
+///
+/// # Arguments
+/// * `links`   -   Files found by the crawl
+pub fn remote_entries(links: &HashSet<RemoteFile>) -> Vec<Entry> {
+    links
+        .iter()
+        .map(|file| Entry {
+            path: file.rel_path.clone(),
+            size: file.size,
+        })
+        .collect()
+}
+
+/// Fetches the size of the remote raw files that have no bz2 copy (the only sizes the lint needs)
+/// Files whose size the crawl already probed are skipped
+///
+/// # Arguments
+/// * `entries`     -   Files returned by `remote_entries`
+/// * `root_url`    -   Url the crawl started at
+pub fn probe_sizes(entries: &mut [Entry], root_url: &str) {
+    let names = entries
+        .iter()
+        .map(|e| e.path.clone())
+        .collect::<HashSet<_>>();
+    let client = net::client();
+    let host_probe = HostProbe::new();
+
+    entries
+        .par_iter_mut()
+        .filter(|e| {
+            e.size.is_none()
+                && !e.path.ends_with(".bz2")
+                && !names.contains(&format!("{}.bz2", e.path))
+        })
+        .for_each(|entry| {
+            entry.size = Url::parse(root_url)
+                .and_then(|root| root.join(&entry.path))
+                .ok()
+                .and_then(|url| host_probe.send(&client, url).ok())
+                .and_then(|resp| probe::remote_size(&resp));
+        });
+}
+
+/// Returns the issues of a single path (name rules only)
+///
+/// # Arguments
+/// * `path`    -   Path without the ".bz2" extension
+fn lint_path(path: &str) -> Vec<(Severity, String)> {
+    let mut issues = Vec::new();
+    let ext = path
+        .rsplit_once('.')
+        .map_or("", |(_, ext)| ext)
+        .to_lowercase();
+
+    if path.len() > MAX_PATH_LEN {
+        issues.push((
+            Severity::Error,
+            format!(
+                "path is {} characters long (max {})",
+                path.len(),
+                MAX_PATH_LEN
+            ),
+        ));
+    }
+    if path
+        .chars()
+        .any(|c| !c.is_ascii() || c.is_ascii_control() || FORBIDDEN_CHARS.contains(&c))
+    {
+        issues.push((
+            Severity::Error,
+            String::from("contains characters the downloader rejects"),
+        ));
+    }
+    if path.split('/').any(|segment| segment == "..") {
+        issues.push((Severity::Error, String::from("contains \"..\"")));
+    }
+    if BLOCKED_EXTENSIONS.contains(&ext.as_str()) {
+        issues.push((
+            Severity::Error,
+            format!(".{} files are never downloaded by clients", ext),
+        ));
+    }
+    if path.chars().any(|c| c.is_ascii_uppercase()) {
+        issues.push((
+            Severity::Warning,
+            String::from("contains uppercase letters (Linux servers are case-sensitive)"),
+        ));
+    }
+
+    issues
+}
+
+/// Returns every issue of the tree, errors first
+///
+/// # Arguments
+/// * `entries` -   Files of the tree
+pub fn lint(entries: &[Entry]) -> Vec<Issue> {
+    let names = entries
+        .iter()
+        .map(|e| e.path.clone())
+        .collect::<HashSet<_>>();
+    // A raw file and its .bz2 copy share their name issues, so they're reported once
+    let raw_paths = entries
+        .iter()
+        .map(|e| e.path.strip_suffix(".bz2").unwrap_or(&e.path))
+        .collect::<HashSet<_>>();
+
+    let mut issues = raw_paths
+        .iter()
+        .flat_map(|path| {
+            lint_path(path)
+                .into_iter()
+                .map(|(severity, message)| Issue {
+                    severity,
+                    path: path.to_string(),
+                    message,
+                })
+        })
+        .collect::<Vec<_>>();
+
+    // Files that overwrite each other on Windows clients
+    for group in collisions::find_collisions(names.iter().map(String::as_str)) {
+        issues.push(Issue {
+            severity: Severity::Error,
+            path: group[0].clone(),
+            message: format!(
+                "collides with {:?} on case-insensitive clients",
+                &group[1..]
+            ),
+        });
+    }
+
+    // Raw files without a bz2 copy
+    for entry in entries
+        .iter()
+        .filter(|e| !e.path.ends_with(".bz2") && !names.contains(&format!("{}.bz2", e.path)))
+    {
+        let (severity, message) = match entry.size {
+            Some(size) if size > MAX_RAW_SIZE => (
+                Severity::Error,
+                format!(
+                    "is {:.2} MB without a .bz2 copy (clients refuse files over {} MB)",
+                    size as f32 / MB_SIZE as f32,
+                    MAX_RAW_SIZE / MB_SIZE as u64
+                ),
+            ),
+            _ => (
+                Severity::Warning,
+                String::from("has no .bz2 copy (slower downloads)"),
+            ),
+        };
+
+        issues.push(Issue {
+            severity,
+            path: entry.path.clone(),
+            message,
+        });
+    }
+
+    issues.sort_by(|a, b| (a.severity, &a.path).cmp(&(b.severity, &b.path)));
+    issues
+}
+
+/// Prints every issue and a summary per severity
+///
+/// # Arguments
+/// * `issues`  -   Issues returned by `lint`
+pub fn print(issues: &[Issue]) {
+    let mut counts = BTreeMap::<Severity, usize>::new();
+
+    for issue in issues {
+        println!("{}:\t{}\t{}", issue.severity, issue.path, issue.message);
+        *counts.entry(issue.severity).or_default() += 1;
+    }
+
+    println!(
+        "\n{} error(s), {} warning(s)",
+        counts.get(&Severity::Error).unwrap_or(&0),
+        counts.get(&Severity::Warning).unwrap_or(&0),
+    );
+}
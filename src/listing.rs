@@ -0,0 +1,154 @@
+use crate::{fingerprint::ListingStyle, net};
+use reqwest::blocking::Response;
+use select::{
+    document::Document,
+    node::Node,
+    predicate::{Name, Or},
+};
+use std::{collections::HashSet, io::Read, thread, time::Duration};
+use url::Url;
+
+/// Attempts at fetching a directory listing before the crawl gives up on it
+pub const MAX_ATTEMPTS: u32 = 3;
+/// Pages of a paginated listing followed at most (a "next" link pointing back to an earlier page ends it sooner)
+const MAX_PAGES: usize = 1000;
+/// Labels of the anchors that lead to the next page of a listing (compared without spaces, lowercased)
+const NEXT_LABELS: [&str; 9] = [
+    "next", "nextpage", "next»", "next›", "next>", "»", "›", ">", ">>",
+];
+/// Pause between two attempts
+const RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Reads the whole body of a directory listing
+/// Fails if the body was cut off: the connection dropped mid-body or fewer bytes arrived than
+/// the `Content-Length` announced (the links of the missing part would be silently skipped)
+///
+/// # Arguments
+/// * `resp`    -   Response of the listing request
+pub fn read_complete(mut resp: Response) -> Result<String, String> {
+    let expected = resp.content_length();
+    let mut body = Vec::new();
+
+    resp.read_to_end(&mut body)
+        .map_err(|e| format!("listing cut off after {} bytes: {}", body.len(), e))?;
+
+    match expected {
+        Some(expected) if (body.len() as u64) < expected => Err(format!(
+            "listing cut off after {} of {} bytes",
+            body.len(),
+            expected
+        )),
+        _ => Ok(String::from_utf8_lossy(&body).into_owned()),
+    }
+}
+
+/// Fetches the directory listing at `url` again until it arrives complete
+/// Returns the last error if every attempt was cut off
+///
+/// # Arguments
+/// * `url`         -   Url of the listing
+/// * `attempts`    -   Number of requests sent at most
+pub fn refetch(url: &str, attempts: u32) -> Result<String, String> {
+    let mut last_err = String::from("listing was never fetched");
+
+    for _ in 0..attempts {
+        thread::sleep(RETRY_DELAY);
+
+        match net::get(url) {
+            Ok(resp) => match read_complete(resp) {
+                Ok(body) => return Ok(body),
+                Err(e) => last_err = e,
+            },
+            Err(e) => last_err = e.to_string(),
+        }
+    }
+
+    Err(last_err)
+}
+
+/// Returns the href of the link to the next page of a paginated listing (nginx fancyindex themes, h5ai, ...)
+/// A `rel="next"` link wins over an anchor labelled "Next", "»", ...
+///
+/// # Arguments
+/// * `doc`     -   Page of the listing
+fn next_page_href(doc: &Document) -> Option<String> {
+    let href = |node: Node| node.attr("href").map(str::to_string);
+
+    let rel_next = doc
+        .find(Or(Name("a"), Name("link")))
+        .filter(|node| {
+            node.attr("rel").is_some_and(|rel| {
+                rel.split_whitespace()
+                    .any(|r| r.eq_ignore_ascii_case("next"))
+            })
+        })
+        .find_map(href);
+
+    rel_next.or_else(|| {
+        doc.find(Name("a"))
+            .filter(|node| {
+                let label = node
+                    .text()
+                    .split_whitespace()
+                    .collect::<String>()
+                    .to_lowercase();
+                NEXT_LABELS.contains(&label.as_str())
+            })
+            .find_map(href)
+    })
+}
+
+/// Returns the hrefs of every page of a directory listing, following its "next page" links
+/// Pages must stay on the host and inside of the directory, a later page that never arrives complete
+/// is reported as an error along with the hrefs of the pages before it
+/// The "next page" links themselves are left out (they're not entries of the directory)
+///
+/// # Arguments
+/// * `url`     -   Url of the first page
+/// * `body`    -   Body of the first page
+/// * `style`   -   Software that generated the listing, which tells its entries apart from its other links
+/// * `pages`   -   Called once for every page fetched after the first one
+pub fn all_pages(
+    url: &Url,
+    body: String,
+    style: ListingStyle,
+    pages: &mut dyn FnMut(),
+) -> (Vec<String>, Result<(), String>) {
+    let mut hrefs = Vec::new();
+    let mut seen = HashSet::from([url.clone()]);
+    let mut page_url = url.clone();
+    let mut body = body;
+
+    loop {
+        let doc = Document::from(body.as_str());
+        let next = next_page_href(&doc);
+        hrefs.extend(style.hrefs(&doc, next.as_deref()));
+
+        let next_url = next
+            .and_then(|href| page_url.join(href.trim()).ok())
+            .map(|mut next_url| {
+                next_url.set_fragment(None);
+                next_url
+            })
+            .filter(|next_url| {
+                next_url.host_str() == url.host_str() && next_url.path().starts_with(url.path())
+            });
+        let Some(next_url) = next_url else {
+            return (hrefs, Ok(()));
+        };
+        if seen.len() >= MAX_PAGES || !seen.insert(next_url.clone()) {
+            return (hrefs, Ok(()));
+        }
+
+        pages();
+        body = match net::get(next_url.as_str())
+            .map_err(|e| e.to_string())
+            .and_then(read_complete)
+            .or_else(|_| refetch(next_url.as_str(), MAX_ATTEMPTS - 1))
+        {
+            Ok(body) => body,
+            Err(e) => return (hrefs, Err(e)),
+        };
+        page_url = next_url;
+    }
+}
@@ -1,17 +1,144 @@
+pub mod a2s;
+pub mod archive_cache;
+pub mod backoff;
+pub mod bandwidth;
+pub mod blocklist;
+pub mod bsp;
 pub mod bz2_file;
+pub mod case_sense;
+pub mod changes;
+pub mod classify;
+pub mod collisions;
+pub mod compare;
+pub mod config;
+pub mod control;
+pub mod crash;
+pub mod crawl_state;
+pub mod crawl_stats;
+pub mod decode_progress;
+pub mod decoder;
+pub mod deletion;
+pub mod destinations;
+pub mod discovery;
+pub mod disposition;
+pub mod doctor;
+pub mod durability;
+pub mod events;
+pub mod fd_limit;
+pub mod files;
+pub mod fingerprint;
+pub mod follow;
+pub mod fs_limits;
+pub mod href;
+pub mod integrity;
+pub mod launch;
+pub mod layout;
+pub mod link_install;
+pub mod link_list;
+pub mod lint;
+pub mod listing;
+pub mod map_browser;
+pub mod mapname;
+pub mod meta_dir;
+pub mod metrics;
+pub mod mirror_stats;
+pub mod monitor;
+pub mod mover;
+pub mod net;
+pub mod orphans;
+pub mod overlay;
+pub mod paranoid;
+pub mod path_template;
+pub mod policy;
+pub mod prefixes;
+pub mod prepare;
+pub mod probe;
+pub mod profiles;
+pub mod queue;
+pub mod ratios;
+pub mod reflink;
+pub mod renames;
+pub mod repair;
+pub mod report;
+pub mod rewrite;
+pub mod run_lock;
+pub mod scan;
+pub mod schedule;
+pub mod schema;
+pub mod segmented;
+pub mod self_update;
+pub mod serve;
+pub mod service;
+pub mod signed_url;
+pub mod sitemap;
+pub mod snapshot;
+pub mod sound;
+pub mod sparse;
+pub mod split;
+pub mod state_store;
+pub mod storage;
+pub mod throttle;
+pub mod transport;
+pub mod tuning;
+pub mod ui;
+pub mod variants;
+pub mod versions;
+pub mod wayback;
+#[cfg(feature = "lzma")]
+pub mod xz_file;
+pub mod zip_archive;
+#[cfg(feature = "zstd")]
+pub mod zstd_file;
+use bandwidth::Bandwidth;
+use blocklist::Blocklist;
+use case_sense::CaseFold;
+use classify::FileKind;
+use config::{Command, Config};
+use crawl_state::CrawlState;
+use crawl_stats::CrawlStats;
+use dashmap::DashMap;
+use decode_progress::{DecodeProgress, FileProgress};
+use decoder::Format;
+use destinations::Destinations;
+use discovery::DiscoveryContext;
+use durability::Checkpoint;
 use error_chain::error_chain;
+use events::Event;
+use files::{LocalFile, RemoteFile};
+use fingerprint::ListingStyle;
+use integrity::Sums;
+use layout::Layout;
+use link_install::LinkSummary;
+use metrics::{Metrics, METRICS};
+use monitor::Monitor;
+use mover::Mover;
+use paranoid::Sample;
+use path_template::PathTemplate;
+use policy::Policy;
+use prefixes::Prefixes;
+use probe::HostProbe;
+use queue::DownloadQueue;
 use rayon::iter::*;
-use regex::Regex;
+use rewrite::Rewrites;
+use run_lock::RunLock;
+use scan::ScanPolicy;
+use segmented::Digest;
 use select::{document::Document, predicate::Name};
+use state_store::{ManifestEntry, StateStore};
+use storage::{Storage, StorageTarget};
+use throttle::{SpeedMeter, Throttle};
+use transport::Transport;
 use url::{Position, Url};
-use walkdir::{DirEntry, WalkDir};
 
 use std::{
-    collections::{HashSet, VecDeque},
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
     fs::{self, File},
     io::{self, stdin, Read, Write},
     path::{Path, PathBuf},
-    sync::{Arc, Mutex, RwLock},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex, RwLock,
+    },
     time::{Duration, Instant},
 };
 
@@ -20,6 +147,7 @@ const MB_SIZE: usize = KB_SIZE * KB_SIZE;
 const SEP_LEN: usize = 50;
 const POST_MSG_REPLACE: usize = 70;
 const REDIRECT_LINK: &str = "gflfastdlv2";
+const CHUNK_SIZE: usize = 64 * KB_SIZE;
 
 error_chain! {
     foreign_links {
@@ -30,476 +158,2905 @@ error_chain! {
 }
 
 fn get_base_url(url: &Url, doc: &Document) -> Result<Url> {
-    let base_tag_href = doc.find(Name("base")).filter_map(|n| n.attr("href")).nth(0);
+    let base_tag_href = doc.find(Name("base")).filter_map(|n| n.attr("href")).next();
     let base_url =
         base_tag_href.map_or_else(|| Url::parse(&url[..Position::BeforePath]), Url::parse)?;
 
     Ok(base_url)
 }
 
+/// Returns true if the `Last-Modified` header of `resp` is newer than `since`
+/// Responses without a (valid) header are treated as modified
+///
+/// # Arguments
+/// * `resp`        Response of the directory listing
+/// * `since`       Unix time to compare against (None means everything is modified)
+fn modified_since(resp: &reqwest::blocking::Response, since: Option<u64>) -> bool {
+    let since = match since {
+        Some(since) => since,
+        None => return true,
+    };
+
+    resp.headers()
+        .get(reqwest::header::LAST_MODIFIED)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| httpdate::parse_http_date(v).ok())
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .is_none_or(|t| t.as_secs() > since)
+}
+
 /// Peform BFS on the `dl_url` that was provided
 ///
 /// # Arguments
 /// * `dl_url`      A &str which is the fastdl url
-fn scrape_web(dl_url: &str) -> Result<Arc<RwLock<HashSet<String>>>> {
-    // println!("{}{}\n", term_cursor::Goto(0, 1), "=".repeat(SEP_LEN));
-    // println!("{}{}\n", term_cursor::Goto(0, 7), "=".repeat(SEP_LEN));
+/// * `stats`       Live crawl metrics that are updated while traversing
+/// * `store`       State store that keeps the visited paths
+/// * `since`       Unix time; directories not modified after it are not examined
+/// * `fast`        Trust the hrefs of the listings instead of sending a request per link
+/// * `use_sitemap` Take the files from the sitemap of the fastdl instead of crawling if it has one
+/// * `prefixes`    Subtrees of the fastdl the crawl is restricted to (everything if empty)
+/// * `origins`     Where the listing links of links redirected to signed CDN urls are recorded
+/// * `rewrites`    Rules rewriting the links found in the listings
+#[allow(clippy::too_many_arguments)]
+fn scrape_web(
+    dl_url: &str,
+    stats: &Arc<CrawlStats>,
+    store: &Arc<dyn StateStore>,
+    since: Option<u64>,
+    fast: bool,
+    use_sitemap: bool,
+    prefixes: &Prefixes,
+    origins: &Arc<DashMap<String, String>>,
+    rewrites: &Rewrites,
+) -> Result<Arc<RwLock<HashSet<RemoteFile>>>> {
+    // Visited paths, unvisited paths and download links shared by every thread
+    let state = Arc::new(CrawlState::new(
+        Arc::clone(store),
+        Arc::clone(stats),
+        Arc::clone(origins),
+    ));
+
+    // A sitemap lists every file, so there is nothing to crawl
+    if !(use_sitemap && record_sitemap(&state, dl_url, prefixes, rewrites)?) {
+        crawl_listings(&state, dl_url, stats, since, fast, prefixes, rewrites)?;
+    }
+
+    Ok(Arc::new(RwLock::new(state.download_links())))
+}
+
+/// Records the files of the sitemap of `dl_url` in `state`
+/// Returns false (recording nothing) if the fastdl has no sitemap
+///
+/// # Arguments
+/// * `state`       Crawl state the files are recorded in
+/// * `dl_url`      A &str which is the fastdl url
+/// * `prefixes`    Subtrees of the fastdl the files are restricted to (everything if empty)
+/// * `rewrites`    Rules rewriting the links of the sitemap
+fn record_sitemap(
+    state: &CrawlState,
+    dl_url: &str,
+    prefixes: &Prefixes,
+    rewrites: &Rewrites,
+) -> Result<bool> {
+    let Some(files) = sitemap::discover(dl_url) else {
+        return Ok(false);
+    };
+
+    let root_url = Url::parse(dl_url)?;
+    for mut file in files {
+        let Some(url) = Url::parse(&file.url)
+            .ok()
+            .filter(|url| prefixes.allows(url, &root_url))
+        else {
+            continue;
+        };
+        if !rewrites.is_empty() {
+            file.url = rewrites.apply(url).to_string();
+        }
+        state.record_download(file);
+    }
+
+    Ok(true)
+}
 
-    // Store the links that will be downloaded
-    let download_links = Arc::new(RwLock::new(HashSet::<String>::new()));
-    // Stores the links that were visited
-    let visited_paths = Arc::new(Mutex::new(HashSet::<String>::new()));
-    // Stores the paths that were not visited
-    let unvisited_paths = Mutex::new(VecDeque::<String>::new());
+/// Peform BFS on the listings of `dl_url`, recording the downloadable links in `state`
+///
+/// # Arguments
+/// * `state`       Crawl state with the visited paths, queue and download links
+/// * `dl_url`      A &str which is the fastdl url
+/// * `stats`       Live crawl metrics that are updated while traversing
+/// * `since`       Unix time; directories not modified after it are not examined
+/// * `fast`        Trust the hrefs of the listings instead of sending a request per link
+/// * `prefixes`    Subtrees of the fastdl the crawl is restricted to (everything if empty)
+/// * `rewrites`    Rules rewriting the links found in the listings
+fn crawl_listings(
+    state: &Arc<CrawlState>,
+    dl_url: &str,
+    stats: &Arc<CrawlStats>,
+    since: Option<u64>,
+    fast: bool,
+    prefixes: &Prefixes,
+    rewrites: &Rewrites,
+) -> Result<()> {
+    // Remembers which hosts block HEAD requests
+    let host_probe = Arc::new(HostProbe::new());
 
     // Parent directory of `dl_url`
-    let parent_dir_url_1 = Url::parse(format!("{}{}", dl_url, "..").as_str())?
+    let parent_dir_url = Url::parse(format!("{}{}", dl_url, "..").as_str())?
         .path()
         .to_string();
-    // fastdl parent directory link results in no suffix "/" character
-    // Use this to go from "/cstrike/" -> "/cstrike"
-    let parent_dir_url_2 = {
-        let mut temp_chars = parent_dir_url_1.chars();
-        temp_chars.next_back();
-        temp_chars.as_str().to_string()
-    };
 
     // Visited links should include the parent directory and the `base_url`
-    visited_paths.lock().unwrap().insert(String::from("/"));
-    visited_paths.lock().unwrap().insert(parent_dir_url_1);
-    visited_paths.lock().unwrap().insert(parent_dir_url_2);
+    state.mark_visited("/");
+    state.mark_visited(&parent_dir_url);
 
     // Get the `base_url` of `dl_url`
-    let temp_req = reqwest::blocking::get(dl_url)?.text()?;
+    let temp_resp = net::get(dl_url)?;
+    let server = temp_resp
+        .headers()
+        .get(reqwest::header::SERVER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let temp_req = temp_resp.text()?;
     let temp_doc = Document::from(temp_req.as_str());
     let dl_url = Url::parse(dl_url)?;
+    let root_path = dl_url.path().to_string();
+
+    // The listing style a previous run detected is reused as long as the host runs the same server
+    let host = dl_url.host_str().unwrap_or_default();
+    let style = fingerprint::listing_style(host, server.as_deref())
+        .unwrap_or_else(|| ListingStyle::detect(server.as_deref(), &temp_req));
+    fingerprint::record_listing(host, server.as_deref(), style);
+
+    // Get the `base_url` of `dl_url`
+    let base_url = get_base_url(&dl_url, &temp_doc)?;
+    // `head` is used to perform HEADER req
+    let head = net::client_builder().timeout(None).build()?;
 
     // Store the path we will first visit
-    unvisited_paths
-        .lock()
-        .unwrap()
-        .push_front(dl_url.path().to_string());
+    state.enqueue([root_path.clone()]);
+
+    // Lists one directory, recording its files and queueing its subdirectories for the next level
+    let visit_dir = |curr_path: String| {
+        // Paths found in this directory, queued once the directory is done
+        let new_paths = parking_lot::Mutex::new(Vec::new());
+
+        // Create a url out of the `dl_url` &str
+        let Ok(url) = base_url.join(curr_path.as_str()) else {
+            stats.incomplete_listing();
+            stats.print_live();
+            return;
+        };
+
+        // GET Request containing all the links to recursively traverse
+        // A listing that can't be fetched is retried, and counted as incomplete if it never arrives
+        let resp = net::get(url.as_str()).ok();
+
+        // Quick mode: skip directories that were not modified since the last sync
+        // The starting directory is always examined
+        if let Some(resp) = &resp {
+            if curr_path != root_path && !modified_since(resp, since) {
+                stats.skip_dir();
+                stats.print_live();
+                return;
+            }
+        }
+
+        // A listing cut off mid-body would silently miss files, so it's fetched again
+        let req = match resp
+            .ok_or_else(|| String::from("listing was never fetched"))
+            .and_then(listing::read_complete)
+            .or_else(|_| {
+                stats.retry_listing();
+                listing::refetch(url.as_str(), listing::MAX_ATTEMPTS - 1)
+            }) {
+            Ok(req) => req,
+            Err(_) => {
+                stats.incomplete_listing();
+                stats.print_live();
+                return;
+            }
+        };
+
+        // Iterate through the list of websites in `url`, parsing only the links (dir/files)
+        // Paginated listings are read to their last page, a large directory is never cut to its first page
+        let (curr_path_links, pages) =
+            listing::all_pages(&url, req, style, &mut || stats.follow_page());
+        if pages.is_err() {
+            stats.incomplete_page();
+        }
+
+        // Iterate through all the url links and add the list to a checkable path if it was not seen
+        // If the url link is a downloadable link, the url link will be added to the download links
+        curr_path_links.par_iter().for_each(|x| {
+            // Hrefs that escape the crawl root (parent links, other hosts) are skipped
+            let new_url = match href::resolve_href(&url, &dl_url, x, REDIRECT_LINK) {
+                Some(new_url) => new_url,
+                None => return,
+            };
+
+            // Subtrees outside of `--prefix` are never requested
+            if !prefixes.allows(&new_url, &dl_url) {
+                return;
+            }
+
+            // Mirrors whose listings don't point at the downloads get their links rewritten
+            let new_url = rewrites.apply(new_url);
+
+            let (next_url, kind, origin, header) = if fast {
+                // Fast crawl: hrefs ending with "/" are listings, everything else is a file
+                // Broken links are caught when they're downloaded instead of with a request per link
+                let kind = if new_url.path().ends_with('/') {
+                    FileKind::Html
+                } else {
+                    FileKind::Unknown
+                };
+                (new_url, kind, None, None)
+            } else {
+                // Send HEADER requests (faster than GET, falls back to a ranged GET if the host blocks HEAD)
+                let origin = new_url.to_string();
+                // A link that can't be probed is missing from the crawl, like the files of a cut off listing
+                let Ok(header) = host_probe.send(&head, new_url) else {
+                    stats.incomplete_link();
+                    return;
+                };
+                // Signed CDN urls expire, the listing link is kept to re-resolve them at download time
+                let origin = signed_url::is_signed(header.url()).then_some(origin);
+                // HTML responses are listings or error pages, never downloadable files
+                let kind = header
+                    .headers()
+                    .get(reqwest::header::CONTENT_TYPE)
+                    .and_then(|v| v.to_str().ok())
+                    .map_or(FileKind::Unknown, classify::from_content_type);
+                (header.url().clone(), kind, origin, Some(header))
+            };
+
+            // Parse in the format: {scheme}://{domain}/{path}
+            // Note: `path` includes a prepended / in the assignment of`next_site`
+            let scheme = next_url.scheme();
+            let Some(domain) = next_url.host_str() else {
+                return;
+            };
+            let path = next_url.path();
+            let next_site = format!("{scheme}://{domain}{path}");
+            if let Some(origin) = &origin {
+                state.record_origin(&next_site, origin);
+            }
+
+            // Append the paths we have not visited
+            // Conditions:
+            //  1. Set contains a visited path
+            //  2. String contains "index.html"
+            //  3. String contains ".tmp"
+            //  4. String contains ".ztmp"
+            if !state.is_visited(path)
+                && !path.contains("index.html")
+                && !path.contains(".tmp")
+                && !path.contains(".ztmp")
+            {
+                // Without the HEAD requests, files are not redirected to "fastdlv2" so they're
+                // told apart from directories by their href alone
+                let is_file = !path.ends_with("/");
+
+                if !(path.contains(REDIRECT_LINK) || path.contains("maps/") || (fast && is_file)) {
+                    // Do not add "fastdlv2" links - We don't want to recurse through fastdlv2
+                    new_paths.lock().push(path.to_string());
+                } else if (((path.contains(REDIRECT_LINK) || fast)
+                    && is_file
+                    && !path.contains("maps/"))
+                    || (path.contains("maps/") && path.contains("ze_")))
+                    && kind != FileKind::Html
+                {
+                    // Only add "fastdlv2" in our download links
+                    // Second case ensures that the fastdlv2 directories are not being recursed as well
+                    // I'm not sure why there are fastdlv2 directory links
+                    if ui::fancy() {
+                        print!(
+                            "{}{}{}",
+                            term_cursor::Goto(0, 5),
+                            next_site,
+                            " ".repeat(POST_MSG_REPLACE)
+                        );
+                    }
+
+                    // The probe already told the size and modification time of the file
+                    let file = match &header {
+                        Some(header) => RemoteFile::from_probe(&next_site, dl_url.as_str(), header),
+                        None => RemoteFile::new(&next_site, dl_url.as_str()),
+                    };
+                    state.record_download(file);
+
+                    if ui::fancy() {
+                        println!(
+                            "{}Downloadable Links:\t{}",
+                            term_cursor::Goto(0, 4),
+                            state.download_len()
+                        );
+                    }
+                }
+            }
+        });
+
+        // Replace this directory in the queue with the paths it discovered
+        let new_paths = new_paths.into_inner();
+        stats.visit_dir(new_paths.len());
+        stats.print_live();
+
+        state.enqueue(new_paths);
+    };
 
     // Iterate through every directory
     loop {
+        let level = state.take_level();
+
         // Base case: All paths/links have been visited
-        if unvisited_paths.lock().unwrap().is_empty() {
+        if level.is_empty() {
             break;
         }
 
-        // Thread handler which will join all threads (synchronize)
-        let mut handler = Vec::new();
-        // Iterate through every item
-        //      Length is obtained because we don't want to deadlock
-        //      and it's possible to get a runtime error
-        let unvisited_len = unvisited_paths.lock().unwrap().len();
+        // Every path in this level is waiting to be visited
+        stats.queue_depth.store(level.len(), Ordering::Relaxed);
+        stats.print_live();
+
+        // The crawl workers take the paths of the level one at a time (compatibility mode lists one
+        // directory at a time), the paths they discover are queued for the next level
+        let workers = if net::compat() {
+            1
+        } else {
+            tuning::crawl_workers().clamp(1, level.len())
+        };
+        let level = Mutex::new(level.into_iter());
+        std::thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let Some(curr_path) = level.lock().unwrap().next() else {
+                        break;
+                    };
+
+                    // Move to the next path if the link was already visited
+                    if !state.mark_visited(&curr_path) {
+                        continue;
+                    }
 
-        // Iterate through every item in the directory
-        for _ in 0..unvisited_len {
-            // Pop the last visited object in the path
-            let curr_path = unvisited_paths.lock().unwrap().pop_back().unwrap();
-            // let curr_path = String::from(curr_path);
+                    if ui::fancy() {
+                        println!(
+                            "{}Visited Paths:\t\t{}",
+                            term_cursor::Goto(0, 3),
+                            state.visited_len()
+                        );
+                    }
 
-            // Move to the next path if the link was visited was already visited
-            if visited_paths.lock().unwrap().contains(curr_path.as_str()) {
-                continue;
+                    visit_dir(curr_path);
+                });
             }
+        });
+    }
 
-            // Clone the `visited_paths` and `download_links` for parallel storing of paths/links
-            let visited_paths_clone = Arc::clone(&visited_paths);
-            let download_links_clone = Arc::clone(&download_links);
-
-            // Get the `base_url` of `dl_url`
-            let base_url = get_base_url(&dl_url, &temp_doc)?;
-            // `head` is used to perform HEADER req
-            let head = reqwest::blocking::Client::builder()
-                .timeout(None)
-                .build()
-                .unwrap();
-
-            // Create a thread for each path (file/dir) to visit
-            let t = std::thread::spawn(move || {
-                // Used to join the threads together to prevent race conditions with the function terminating too early
-                let new_paths = Arc::new(Mutex::new(VecDeque::new()));
-
-                // fastdl parent directory link results in no suffix "/" character
-                // Adding the `curr_path` without the suffix "/" is the same reasoning as above
-                let curr_path_alt = {
-                    let mut temp_chars = curr_path.chars();
-                    temp_chars.next_back();
-                    temp_chars.as_str().to_string()
-                };
+    // Clear the list of files/paths that were checked
+    if ui::fancy() {
+        println!("{}{}", term_cursor::Goto(0, 5), " ".repeat(170));
+    }
 
-                // Add `curr_path` as a visited link
-                visited_paths_clone
-                    .lock()
-                    .unwrap()
-                    .insert(curr_path.clone());
-                visited_paths_clone.lock().unwrap().insert(curr_path_alt);
+    Ok(())
+}
 
-                println!(
-                    "{}Visited Paths:\t\t{}",
-                    term_cursor::Goto(0, 3),
-                    visited_paths_clone.lock().unwrap().len()
-                );
+/// SyncContext stores what every stage of a sync needs besides the links
+pub struct SyncContext {
+    /// State store whose manifest records every downloaded file
+    pub store: Arc<dyn StateStore>,
+    /// Files whose hash is blocked are not written
+    pub blocklist: Option<Blocklist>,
+    /// Size and extension rules every file is checked against before it's downloaded
+    pub policy: Policy,
+    /// Global and per-host speed caps (shared by every profile)
+    pub throttle: Arc<Throttle>,
+    /// Persistent prioritized queue of the links that are not downloaded yet
+    pub queue: Arc<DownloadQueue>,
+    /// Directory the decoded files are written to (next to the bz2 files if None)
+    pub extract_to: Option<PathBuf>,
+    /// Directories the decoded files of some categories are written to (instead of `extract_to`)
+    pub destinations: Destinations,
+    /// Directory the compressed files are moved to once decoded (deleted if None)
+    pub keep_archives: Option<PathBuf>,
+    /// Download link -> listing link it was redirected from, for links redirected to signed CDN urls
+    pub origins: Arc<DashMap<String, String>>,
+    /// Download link -> Wayback Machine copy it's downloaded from, for files the fastdl no longer has (`--wayback`)
+    pub archived: DashMap<String, String>,
+    /// Which symlinks and hidden directories the local scans walk into
+    pub scan: ScanPolicy,
+    /// Sends the downloads and counts the HTTP version of every transfer (shared by every profile)
+    pub transport: Arc<Transport>,
+    /// Absolute directory the files are synced into
+    pub out_dir: PathBuf,
+    /// Time a download may take before it's deferred to a second pass (no limit if None)
+    pub file_time_budget: Option<Duration>,
+    /// Fetch a random range of every downloaded file again and compare it with the file on disk
+    pub paranoid: bool,
+    /// Where the synced files go inside of `out_dir`
+    pub layout: Layout,
+    /// Template of the local path of every file inside of the layout's base directory (the layout's path if None)
+    pub path_template: Option<PathTemplate>,
+    /// Concurrent ranged requests of a large file written in place (1 downloads everything in one request)
+    pub segments: usize,
+    /// Pool small files are downloaded by next to the other downloads (the shared pool if None)
+    pub small_file_pool: Option<rayon::ThreadPool>,
+    /// When the manifest and the queue are flushed during the downloads
+    pub checkpoint: Checkpoint,
+    /// Where the downloaded files are written (the output directory unless `--storage` says otherwise)
+    pub storage: Arc<dyn Storage>,
+    /// Urls of the parts missing from split archives found during the crawls
+    pub missing_parts: Mutex<Vec<String>>,
+    /// The filesystem of `out_dir` treats names that only differ by case as the same file
+    pub case_insensitive: bool,
+    /// How the local paths are normalized (`--fold-case`), kept as served if None
+    pub fold_case: Option<CaseFold>,
+    /// What this run downloaded and saved (the state store keeps what every run did)
+    pub bandwidth: Mutex<Bandwidth>,
+    /// Download link -> hex sha256 the fastdl publishes for it (`SHA256SUMS`)
+    pub published_sums: DashMap<String, String>,
+    /// Checksums of the files uploaded to a storage that is not the output directory (`--publish-sums`)
+    pub uploaded_sums: Option<Mutex<Sums>>,
+}
 
-                // Create a url out of the `dl_url` &str
-                let url = base_url.join(curr_path.as_str()).unwrap();
+impl SyncContext {
+    /// Returns the local path of the download link or fastdl url `url` inside of the output directory,
+    /// folded by `--fold-case`
+    ///
+    /// # Arguments
+    /// * `url`     -   Download link or fastdl url
+    pub fn local_path(&self, url: &str) -> PathBuf {
+        fold(self.layout.local_path(url), self.fold_case)
+    }
 
-                // GET Request containing all the links to recursively traverse
-                let req = reqwest::blocking::get(url.as_str())
-                    .unwrap()
-                    .text()
-                    .unwrap();
-
-                // Iterate through the list of websites in `url`, parsing only the links (dir/files)
-                let curr_path_links = Document::from(req.as_str())
-                    .find(Name("a"))
-                    .filter_map(|n| n.attr("href"))
-                    .map(|x| x.to_string())
-                    .collect::<Vec<_>>();
-
-                let curr_path_links_clone = curr_path_links.clone();
-                let new_paths_clone = Arc::clone(&new_paths);
-
-                // Iterate through all the url links and add the list to a checkable path if it was not seen
-                // If the url link is a downloadable link, the url link will be added to `download_links`
-                curr_path_links_clone.par_iter().for_each(|x| {
-                    // Send HEADER requests (faster than GET) and parse in the format:
-                    // {scheme}://{domain}/{path}
-                    // Note: `path` includes a prepended / in the assignment of`next_site`
-                    let new_url = url.join(x).unwrap();
-                    let header = head.post(new_url).send().unwrap();
-                    let scheme = header.url().scheme();
-                    let domain = header.url().host_str().unwrap();
-                    let path = header.url().path();
-                    let next_site = format!("{scheme}://{domain}{path}");
-
-                    // Append the paths we have not visited
-                    // Conditions:
-                    //  1. Set contains a visited path
-                    //  2. String contains "index.html"
-                    //  3. String contains ".tmp"
-                    //  4. String contains ".ztmp"
-                    if !visited_paths_clone.lock().unwrap().contains(path)
-                        && !path.contains("index.html")
-                        && !path.contains(".tmp")
-                        && !path.contains(".ztmp")
-                    {
-                        if !path.contains(REDIRECT_LINK) && !path.contains("maps/") {
-                            // Do not add "fastdlv2" links - We don't want to recurse through fastdlv2
-                            new_paths_clone.lock().unwrap().push_front(path.to_string());
-                        } else if (path.contains(REDIRECT_LINK)
-                            && !path.ends_with("/")
-                            && !path.contains("maps/"))
-                            || (path.contains("maps/") && path.contains("ze_"))
-                        {
-                            // Only add "fastdlv2" in our `download_links` Vec
-                            // Second case ensures that the fastdlv2 directories are not being recursed as well
-                            // I'm not sure why there are fastdlv2 directory links
-                            print!(
-                                "{}{}{}",
-                                term_cursor::Goto(0, 5),
-                                next_site,
-                                " ".repeat(POST_MSG_REPLACE)
-                            );
+    /// Returns where the file of the download link `dl_url` is written: its local path (or the
+    /// `--path-template` rendering) inside of the output directory
+    ///
+    /// # Arguments
+    /// * `dl_url`  -   Download link of the file
+    pub fn file_path(&self, dl_url: &str) -> PathBuf {
+        let local_path = match &self.path_template {
+            Some(template) => fold(template.render(dl_url, self.layout), self.fold_case),
+            None => self.local_path(dl_url),
+        };
+        self.out_dir.join(local_path)
+    }
 
-                            download_links_clone.write().unwrap().insert(next_site);
+    /// Records `bandwidth` in the counters of this run and of the mirror
+    fn add_bandwidth(&self, bandwidth: Bandwidth) {
+        *self.bandwidth.lock().unwrap() += bandwidth;
+        self.store.add_bandwidth(bandwidth);
+    }
+}
 
-                            println!(
-                                "{}Downloadable Links:\t{}",
-                                term_cursor::Goto(0, 4),
-                                download_links_clone.write().unwrap().len()
-                            );
-                        }
-                    }
-                });
+/// Returns what the syncs of this run downloaded and saved, over every profile
+///
+/// # Arguments
+/// `runs`          Profiles that are synced with their state
+fn run_bandwidth(runs: &[(&profiles::SyncProfile, SyncContext)]) -> Bandwidth {
+    let mut bandwidth = Bandwidth::default();
+    for (_, ctx) in runs {
+        bandwidth += *ctx.bandwidth.lock().unwrap();
+    }
 
-                // Each thread will return a Vec of all the links to its directory
-                return new_paths;
-            });
+    bandwidth
+}
+
+/// Returns what every run downloaded and saved, over every profile
+///
+/// # Arguments
+/// `runs`          Profiles that are synced with their state
+fn total_bandwidth(runs: &[(&profiles::SyncProfile, SyncContext)]) -> Bandwidth {
+    let mut bandwidth = Bandwidth::default();
+    for (_, ctx) in runs {
+        bandwidth += ctx.store.bandwidth();
+    }
+
+    bandwidth
+}
+
+/// Returns `path` folded by `fold_case` (unchanged if None)
+///
+/// # Arguments
+/// `path`          Local path of a file or directory
+/// `fold_case`     How the path is normalized (`--fold-case`)
+fn fold(path: PathBuf, fold_case: Option<CaseFold>) -> PathBuf {
+    match fold_case {
+        Some(fold_case) => fold_case.apply(&path),
+        None => path,
+    }
+}
 
-            // Append all threads that are traversing the directory
-            handler.push(t);
+/// Reads the body of `response` in chunks so the speed caps apply and the smoothed speed can be displayed
+/// Fails with `TimedOut` once `deadline` passed, and with `UnexpectedEof` if fewer bytes arrived than the
+/// `Content-Length` announced (a dropped connection can end the body early without an error)
+///
+/// # Arguments
+/// `response`      Response of the file that is downloaded
+/// `throttle`      Global and per-host speed caps
+/// `deadline`      When the download is given up (no limit if None)
+fn read_body(
+    mut response: reqwest::blocking::Response,
+    throttle: &Throttle,
+    deadline: Option<Instant>,
+) -> io::Result<Vec<u8>> {
+    let host = response.url().host_str().unwrap_or_default().to_string();
+    let expected = response.content_length();
+    let file_speed = SpeedMeter::new();
+    let mut body = Vec::new();
+    let mut chunk = vec![0u8; CHUNK_SIZE];
+
+    loop {
+        let n = response.read(&mut chunk)?;
+        if n == 0 {
+            break;
         }
 
-        // Join all threads, then append all the vectors into the vectors (thread-safety)
-        for t in handler {
-            let mut unvisited_vec_thread = t.join().unwrap().lock().unwrap().to_owned();
+        body.extend_from_slice(&chunk[..n]);
+        file_speed.record(n as u64);
+        throttle.consume(&host, n as u64);
 
-            // Append new links to the unvisited path
-            unvisited_paths
-                .lock()
-                .unwrap()
-                .append(&mut unvisited_vec_thread);
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Download took longer than its time budget",
+            ));
         }
-    }
 
-    // Clear the list of files/paths that were checked
-    println!("{}{}", term_cursor::Goto(0, 5), " ".repeat(170));
-    // println!("{}", term_cursor::Goto(0, 8));
+        if ui::fancy() {
+            print!(
+                "{}Speed:\t\t\t{:.2} MB/s (total {:.2} MB/s){}",
+                term_cursor::Goto(0, 14),
+                file_speed.speed() / MB_SIZE as f64,
+                throttle.aggregate.speed() / MB_SIZE as f64,
+                " ".repeat(POST_MSG_REPLACE),
+            );
+        }
+    }
 
-    Ok(download_links)
+    match expected {
+        Some(expected) if body.len() as u64 != expected => Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            format!("Body cut off after {} of {} bytes", body.len(), expected),
+        )),
+        _ => Ok(body),
+    }
 }
 
 /// Downloads all the files in `dl_links`
 /// Create directories inside of the current directory for the path of the file if it does not exist
 ///
 /// # Arguments
-/// `dl_links`      HashSet that contains all the download links that will be downloaded and stored
-fn download_files(dl_links: &Arc<RwLock<HashSet<String>>>) {
-    let idx = Mutex::new(0);
-    let curr_path = std::env::current_dir().unwrap();
+/// `dl_links`      HashSet that contains all the files that will be downloaded and stored
+/// `ctx`           State shared by every stage of a sync
+fn download_files(dl_links: &Arc<RwLock<HashSet<RemoteFile>>>, ctx: &SyncContext) {
+    let idx = AtomicUsize::new(0);
+    let curr_path = &ctx.out_dir;
 
-    // Use regex to obtain the directory path and file name
+    // The layout (or the path template) maps the path of the link to the directory of the file
     let dl_url_paths = |dl_url: &str| -> (PathBuf, PathBuf) {
-        let re = Regex::new("(.+?)//(.+?)/(.*+)/(.*+)").unwrap();
-        let captures = re.captures(dl_url).unwrap();
+        let file_path = ctx.file_path(dl_url);
+        let dir_path = file_path.parent().unwrap_or(curr_path).to_path_buf();
+
+        (dir_path, file_path)
+    };
+
+    // Queue every link; links left over from an interrupted sync are still in the queue
+    ctx.queue
+        .push_all(dl_links.read().unwrap().iter().map(|file| &file.url));
+
+    // Large sound/material syncs can run out of inodes long before they run out of space
+    let longest_name = dl_links
+        .read()
+        .unwrap()
+        .iter()
+        .map(|file| file.file_name().len())
+        .max()
+        .unwrap_or(0);
+    let files = dl_links.read().unwrap().len() as u64;
+    let mut warnings = Vec::new();
+
+    // The compression ratios of earlier downloads tell how much space the files take once decoded
+    if ctx.storage.is_local() {
+        let target = ctx.extract_to.as_deref().unwrap_or(curr_path);
+        let needed = ratios::forecast(&ctx.store.files(), dl_links.read().unwrap().iter());
+        let available = fs2::available_space(target).unwrap_or(u64::MAX);
+        if needed > available {
+            warnings.push(format!(
+                "about {:.2} MB once decoded, only {:.2} MB free!",
+                needed as f32 / MB_SIZE as f32,
+                available as f32 / MB_SIZE as f32
+            ));
+        }
+    }
+    warnings.extend(fs_limits::preflight(curr_path, files, longest_name));
 
-        let dir = &captures[3].replace("/", "\\");
-        let file = &captures[4];
+    if !warnings.is_empty() {
+        if ui::fancy() {
+            print!(
+                "{}Preflight:\t\t{}{}",
+                term_cursor::Goto(0, 9),
+                warnings.join(", "),
+                " ".repeat(POST_MSG_REPLACE)
+            );
+        } else {
+            println!("Preflight: {}", warnings.join(", "));
+        }
+    }
 
-        let dir_path_str = format!("{}\\{}", curr_path.to_str().unwrap(), dir);
-        let dir_path = Path::new(dir_path_str.as_str());
-        let file_path_str = format!("{}\\{}", dir_path_str, file);
-        let file_path = Path::new(file_path_str.as_str());
+    // Files that ran out of time are downloaded after every other file, without a budget
+    let deferred = Mutex::new(Vec::new());
+
+    // Small files are latency-bound, so they get their own workers that keep more requests in flight
+    let small_files = match &ctx.small_file_pool {
+        Some(_) => dl_links
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|file| sound::is_small(file))
+            .map(|file| file.url.clone())
+            .collect(),
+        None => HashSet::new(),
+    };
 
-        (dir_path.to_path_buf(), file_path.to_path_buf())
+    // A disk that refuses a write is reported and the link stays queued for the next sync
+    let write_failed = |path: &Path, err: io::Error| -> bool {
+        let reason = err.to_string();
+        eprintln!("Failed to write {}: {}", path.display(), reason);
+        events::emit(&Event::WriteFailed {
+            path,
+            reason: &reason,
+        });
+        false
     };
 
-    // Iterate and get all the paths that are visited
-    dl_links.read().unwrap().par_iter().for_each(|dl_url| {
+    // Downloads `dl_url` (one of the `total` files of its pass)
+    // Returns false if it took longer than `budget` or could not be written
+    let download = |dl_url: &String, total: usize, budget: Option<Duration>| -> bool {
+        // Downloads that did not start yet wait while the queue is paused
+        ctx.queue.wait_while_paused();
+
         // Get PathBufs of the file and its directory
         let (dir_path, file_path) = dl_url_paths(dl_url);
+        crash::set_current(dl_url);
+        events::emit(&Event::DownloadStarted { url: dl_url });
 
-        // Track our item status and info (You can disable and it may improve runtime)
-        *idx.lock().unwrap() += 1;
+        // Track our item status and info, the position is taken once so no worker waits on another's print
+        let position = idx.fetch_add(1, Ordering::Relaxed) + 1;
 
-        print!(
-            "
+        if ui::fancy() {
+            print!(
+                "
 {}[ {} / {} ]
 {}Link:\t\t\t{}{}
 {}File:\t\t\t{}{}
 {}Dir:\t\t\t{}{}",
-            // Total Left Params
-            term_cursor::Goto(0, 10),
-            idx.lock().unwrap(),
-            dl_links.read().unwrap().len(),
-            // Link Params
-            term_cursor::Goto(0, 11),
-            dl_url,
-            " ".repeat(POST_MSG_REPLACE),
-            // Capture Params
-            term_cursor::Goto(0, 12),
-            file_path.to_str().unwrap(),
-            " ".repeat(POST_MSG_REPLACE),
-            // Dir Params
-            term_cursor::Goto(0, 13),
-            dir_path.to_str().unwrap(),
-            " ".repeat(POST_MSG_REPLACE),
-        );
+                // Total Left Params
+                term_cursor::Goto(0, 10),
+                position,
+                total,
+                // Link Params
+                term_cursor::Goto(0, 11),
+                dl_url,
+                " ".repeat(POST_MSG_REPLACE),
+                // Capture Params
+                term_cursor::Goto(0, 12),
+                file_path.to_str().unwrap(),
+                " ".repeat(POST_MSG_REPLACE),
+                // Dir Params
+                term_cursor::Goto(0, 13),
+                dir_path.to_str().unwrap(),
+                " ".repeat(POST_MSG_REPLACE),
+            );
+        }
 
         // Recursively create directories to the folders we want to search
-        std::fs::create_dir_all(dir_path).unwrap();
+        if let Err(err) = fd_limit::retry(|| ctx.storage.create_dir_all(&dir_path)) {
+            return write_failed(&dir_path, err);
+        }
+
+        // Signed CDN urls expire, so a refused link is fetched again through the listing link it came from
+        let origin = ctx.origins.get(dl_url).map(|origin| origin.clone());
+        // Files the fastdl no longer has are fetched from their archived copy
+        let archived_from = ctx.archived.get(dl_url).map(|snapshot| snapshot.clone());
+        let mut fetch_url = archived_from.clone().unwrap_or_else(|| dl_url.to_string());
+        let deadline = budget.map(|budget| Instant::now() + budget);
+        let mut refetches = 0;
 
         // Get request the file link and store it in the directory path
         loop {
             // If the request times out, send another request
-            if let Ok(response) = reqwest::blocking::get(dl_url) {
-                if let Ok(file_bytes) = response.bytes() {
-                    File::create(file_path)
-                        .unwrap()
-                        .write_all(&file_bytes)
-                        .unwrap();
+            if let Ok(response) = ctx.transport.get(&fetch_url) {
+                if signed_url::is_expired(response.status()) {
+                    if let Some(origin) = origin.as_ref().filter(|origin| **origin != fetch_url) {
+                        fetch_url = origin.clone();
+                        continue;
+                    }
+                }
+
+                // Links are only checked here after a fast crawl, so missing files are dropped
+                // Server errors are retried like timeouts, rate-limited requests once the transport's backoff is over
+                if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+                    continue;
+                }
+                if response.status().is_client_error() {
+                    events::emit(&Event::DownloadRejected { url: dl_url });
                     break;
                 }
-            }
+                if response.status().is_server_error() {
+                    std::thread::sleep(Duration::from_secs(1));
+                    continue;
+                }
 
-            std::thread::sleep(Duration::from_secs(1));
-        }
-    });
-}
+                // Sizes the crawl did not learn (e.g. with --fast-crawl) are only known now
+                if let Some(violation) = ctx.policy.check(dl_url, probe::remote_size(&response)) {
+                    ctx.policy.skip(dl_url, &violation);
+                    events::emit(&Event::PolicyViolation {
+                        url: dl_url,
+                        reason: &violation.to_string(),
+                    });
+                    break;
+                }
 
-/// Decodes all bz2 files in the current directory by recursively searching through all the paths
-/// After all paths are decoded, the original bz2 files are deleted
-fn decode_files(corrupt_files: &Mutex<HashSet<String>>) {
-    // Recursively collect files ending with .bz2
-    let dirs = WalkDir::new(".")
-        .into_iter()
-        .flatten()
-        .filter(|dir| dir.file_name().to_str().unwrap().trim().ends_with(".bz2"))
-        .collect::<Vec<DirEntry>>();
+                // Opaque CDN urls send the real file name in Content-Disposition
+                let file_path = disposition::file_name(&response)
+                    .map_or_else(|| file_path.clone(), |name| dir_path.join(name));
+
+                // Large files are fetched in ranged segments written in place instead of through this response
+                let segmented_size = segmented::segmented_size(&response, ctx.segments)
+                    .filter(|_| ctx.storage.is_local());
+                let body = match segmented_size {
+                    Some(size) => {
+                        drop(response);
+                        segmented::download(
+                            &ctx.transport,
+                            &ctx.throttle,
+                            &fetch_url,
+                            &file_path,
+                            size,
+                            ctx.segments,
+                            deadline,
+                        )
+                        // Hashed while it's read back, a segmented file can be larger than the memory
+                        .and_then(|_| Digest::of_file(&file_path))
+                        .map(|digest| (None, digest))
+                    }
+                    None => read_body(response, &ctx.throttle, deadline).map(|bytes| {
+                        let digest = Digest::of(&bytes);
+                        (Some(bytes), digest)
+                    }),
+                };
 
-    let cmp_dir_size = Mutex::<usize>::new(0);
+                match body {
+                    // The bytes of segmented downloads are already written and only their digest is kept
+                    Ok((file_bytes, digest)) => {
+                        let written = file_bytes.is_none();
 
-    // Print all the bz2 files that will be decoded
-    // dirs.par_iter()
-    // .for_each(|f| println!("{}", f.file_name().to_str().unwrap().trim()));
+                        // Known-bad files are dropped instead of being written
+                        if ctx
+                            .blocklist
+                            .as_ref()
+                            .is_some_and(|b| b.blocks_sha256(&digest.sha256))
+                        {
+                            if written {
+                                let _ = ctx.storage.remove(&file_path);
+                            }
+                            events::emit(&Event::DownloadBlocked { url: dl_url });
+                            break;
+                        }
 
-    // File print separator
-    // println!("\n{}\n{}\n", "=".repeat(SEP_LEN), "=".repeat(SEP_LEN));
+                        // Listings and error pages served with a 200 are not files
+                        let kind = classify::sniff(&digest.head);
+                        if kind == FileKind::Html {
+                            if written {
+                                let _ = ctx.storage.remove(&file_path);
+                            }
+                            events::emit(&Event::DownloadRejected { url: dl_url });
+                            break;
+                        }
 
-    // Iterate through every file and decode it
-    dirs.par_iter().for_each(|dir| {
-        // Grab the {bz2/bsp} file name and path
-        let file_name = dir
-            .file_name()
-            .to_str()
-            .expect("Failed to convert &OSStr to &str");
-        let file_name_path = dir.path().to_str().unwrap();
-
-        let output_name_path = file_name_path.replace(".bz2", "");
-
-        // Open the file and check if it's a bz2 file
-        if let Ok(f) = File::open(dir.path()) {
-            // Create the decoder (converts bz2 to bsp)
-            let mut decoder = bz2_file::BZ2File::new(f);
-
-            match decoder.decode_block() {
-                Ok(_) => {}
-                _ => {
-                    corrupt_files.lock().unwrap().insert(file_name.to_string());
-                    return;
+                        // A file that does not match the checksum the fastdl publishes was corrupted on the way
+                        if ctx
+                            .published_sums
+                            .get(dl_url)
+                            .is_some_and(|published| *published != digest.sha256)
+                        {
+                            if written {
+                                let _ = ctx.storage.remove(&file_path);
+                            }
+                            // A file that never matches is not written or recorded, the next sync tries again
+                            if refetches == paranoid::MAX_REFETCHES {
+                                events::emit(&Event::ChecksumFailed { url: dl_url });
+                                break;
+                            }
+                            events::emit(&Event::ChecksumMismatch { url: dl_url });
+                            refetches += 1;
+                            continue;
+                        }
+
+                        // Trust the content over the extension (some mirrors serve plain BSPs named .bz2)
+                        let written_path = file_path;
+                        let file_path = classify::path_for_kind(&written_path, kind);
+
+                        if let Some(file_bytes) = &file_bytes {
+                            if let Err(err) =
+                                fd_limit::retry(|| ctx.storage.write(&file_path, file_bytes))
+                            {
+                                // Uploads fail like downloads and are retried, a local disk that refuses a write won't recover
+                                if ctx.storage.is_local() {
+                                    return write_failed(&file_path, err);
+                                }
+                                std::thread::sleep(Duration::from_secs(1));
+                                continue;
+                            }
+                        } else if file_path != written_path {
+                            if let Err(err) = fs::rename(&written_path, &file_path) {
+                                let _ = fs::remove_file(&written_path);
+                                return write_failed(&file_path, err);
+                            }
+                        }
+
+                        // Flaky connections can corrupt a file without failing the transfer
+                        if ctx.paranoid
+                            && ctx.storage.is_local()
+                            && refetches < paranoid::MAX_REFETCHES
+                            && paranoid::sample(&ctx.transport, &fetch_url, &file_path, digest.size)
+                                == Sample::Mismatch
+                        {
+                            events::emit(&Event::SampleMismatch { url: dl_url });
+                            refetches += 1;
+                            continue;
+                        }
+
+                        events::emit(&Event::DownloadFinished {
+                            url: dl_url,
+                            path: &file_path,
+                            bytes: digest.size,
+                        });
+                        Metrics::add(&METRICS.files_downloaded, 1);
+                        Metrics::add(&METRICS.bytes_downloaded, digest.size);
+                        ctx.add_bandwidth(Bandwidth {
+                            downloaded: digest.size,
+                            saved: 0,
+                        });
+
+                        if let Some(sums) = &ctx.uploaded_sums {
+                            sums.lock().unwrap().record(
+                                &ctx.out_dir,
+                                &file_path,
+                                &digest.sha256,
+                                digest.size,
+                            );
+                        }
+                        ctx.store.record_file(
+                            dl_url,
+                            ManifestEntry {
+                                size: digest.size,
+                                synced_at: state_store::unix_now(),
+                                path: Some(file_path),
+                                chunks: digest.chunks,
+                                archived_from: archived_from.clone(),
+                                decoded_size: None,
+                                sha256: Some(digest.sha256),
+                            },
+                        );
+                        break;
+                    }
+                    // Slow files give their worker back and are downloaded again in the second pass
+                    Err(err) if err.kind() == io::ErrorKind::TimedOut => {
+                        events::emit(&Event::DownloadDeferred { url: dl_url });
+                        return false;
+                    }
+                    // Truncated files are never written, they're downloaded again like timeouts
+                    Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => {
+                        events::emit(&Event::DownloadTruncated { url: dl_url });
+                    }
+                    // Other read errors are retried like timeouts
+                    Err(_) => {}
                 }
             }
 
-            // Increment the compared value (for status checking)
-            *cmp_dir_size.lock().unwrap() += 1;
+            std::thread::sleep(Duration::from_secs(1));
+        }
 
-            // Print the file information
-            print!(
-                "
-                {}File:\t\t\t{}{}
-                {}Directory:\t\t{}{}
-                {}Size:\t\t\t{} MB{}
-                {}Finished Decoding:\t{} / {}{}
-                ",
-                // File Params
-                term_cursor::Goto(0, 18),
-                file_name,
-                " ".repeat(POST_MSG_REPLACE),
-                // Directory Params
-                term_cursor::Goto(0, 19),
-                file_name_path.replace(file_name, ""),
-                " ".repeat(POST_MSG_REPLACE),
-                // Size Params
-                term_cursor::Goto(0, 20),
-                decoder.decoded_block.get_mut().len() as f32 / MB_SIZE as f32,
-                " ".repeat(POST_MSG_REPLACE),
-                // Finished Decoding Params
-                term_cursor::Goto(0, 21),
-                cmp_dir_size.lock().unwrap(),
-                dirs.len(),
-                " ".repeat(POST_MSG_REPLACE),
-            );
+        true
+    };
 
-            // Decoding completion separator
-            // println!("{}{}\n", "=".repeat(SEP_LEN));
+    // Links requeued while a batch is downloading are picked up by the next batch
+    loop {
+        let batch = ctx.queue.take_batch();
+        if batch.is_empty() {
+            break;
+        }
+        idx.store(0, Ordering::Relaxed);
+
+        let download_batch = |links: &[String]| {
+            links.par_iter().for_each(|dl_url| {
+                if download(dl_url, batch.len(), ctx.file_time_budget) {
+                    ctx.queue.complete(dl_url);
+                    checkpoint(ctx);
+                } else {
+                    // The link stays queued so an interrupted sync still picks it up
+                    deferred.lock().unwrap().push(dl_url.clone());
+                }
+            })
+        };
+
+        match &ctx.small_file_pool {
+            Some(pool) => {
+                let (small, large): (Vec<_>, Vec<_>) = batch
+                    .iter()
+                    .cloned()
+                    .partition(|dl_url| small_files.contains(dl_url));
+                rayon::join(
+                    || pool.install(|| download_batch(&small)),
+                    || tuning::download_pool().install(|| download_batch(&large)),
+                );
+            }
+            None => tuning::download_pool().install(|| download_batch(&batch)),
+        }
+    }
 
-            // Create the bsp file
-            let mut output = File::create(output_name_path).unwrap();
+    let deferred = deferred.into_inner().unwrap();
+    idx.store(0, Ordering::Relaxed);
 
-            if let Err(_) = output.write_all(&decoder.decoded_block.get_mut()) {
-                corrupt_files
-                    .lock()
-                    .unwrap()
-                    .insert(format!("{}", file_name_path.to_string(),));
+    tuning::download_pool().install(|| {
+        deferred.par_iter().for_each(|dl_url| {
+            // Without a budget only a failed write returns false, the link stays queued
+            if download(dl_url, deferred.len(), None) {
+                ctx.queue.complete(dl_url);
+                checkpoint(ctx);
             }
-
-            // Delete the bz2 file
-            fs::remove_file(file_name_path).unwrap();
-        }
+        })
     });
-}
 
-fn print_console_gui() {
-    print!("{}", term_cursor::Clear);
+    // Whatever finished since the last checkpoint
+    flush_state(ctx);
+}
 
-    print!(
-        "
-        {}Searching All Paths
-        {}{}
-        {}Downloading Files
-        {}{}
-        {}Decoding All Files (bz2 -> original file)
-        {}{}
-        ",
-        // Checking All Paths
-        term_cursor::Goto((SEP_LEN / 8) as i32, 0),
-        term_cursor::Goto(0, 1),
-        "=".repeat(SEP_LEN),
-        // Downloading Files
-        term_cursor::Goto((SEP_LEN / 8) as i32, 7),
-        term_cursor::Goto(0, 8),
-        "=".repeat(SEP_LEN),
-        // Decoding All Files
-        term_cursor::Goto((SEP_LEN / 8) as i32, 15),
-        term_cursor::Goto(0, 16),
-        "=".repeat(SEP_LEN),
-    );
+/// Flushes the manifest, then the queue, so the queue never forgets a file the manifest does not have yet
+/// A manifest that can't be written (full disk, read-only metadata directory) is reported and written
+/// again by the next checkpoint, the files it misses are only downloaded again
+///
+/// # Arguments
+/// `ctx`           State shared by every stage of a sync
+fn flush_state(ctx: &SyncContext) {
+    if let Err(err) = ctx.store.save() {
+        eprintln!("Failed to save the state store: {}", err);
+    }
+    ctx.queue.persist();
 }
 
-fn main() -> Result<()> {
-    // TIMER START
-    let timer = Instant::now();
-    let corrupt_files = Mutex::new(HashSet::<String>::new());
+/// Counts a downloaded file, flushing the state every `--checkpoint-every` files
+///
+/// # Arguments
+/// `ctx`           State shared by every stage of a sync
+fn checkpoint(ctx: &SyncContext) {
+    if ctx.checkpoint.tick() {
+        flush_state(ctx);
+    }
+}
 
-    // Prints a real-time readable console output
-    print_console_gui();
+/// Returns where the decoded copy of `compressed_path` is written
+/// The destination of its category wins, otherwise the path of the compressed file relative to `base`
+/// is kept inside of `extract_to`
+///
+/// # Arguments
+/// `compressed_path`   Path of the compressed file (.bz2, .zst, .xz)
+/// `base`              Directory the compressed file was found in (the decode root)
+/// `extract_to`        Directory the decoded files are written to (next to the compressed file if None)
+/// `destinations`      Directories the decoded files of some categories are written to
+fn output_path(
+    compressed_path: &Path,
+    base: &Path,
+    extract_to: Option<&Path>,
+    destinations: &Destinations,
+) -> PathBuf {
+    let name = compressed_path.to_str().unwrap();
+    let decoded = PathBuf::from(
+        Format::from_name(name)
+            .and_then(|format| name.strip_suffix(format.extension()))
+            .unwrap_or(name),
+    );
 
-    // TODO: Add support for ze_* maps
-    // CS:S
-    let mut fastdl_urls = Vec::<&str>::new();
-    // let fastdl_urls = Vec::with_capacity(5);
-    fastdl_urls.push("https://fastdl.gflclan.com/cstrike/maps/");
-    // fastdl_urls.push("https://fastdl.gflclan.com/cstrike/materials/");
-    // fastdl_urls.push("https://fastdl.gflclan.com/cstrike/models/");
-    // fastdl_urls.push("https://fastdl.gflclan.com/cstrike/resource/");
-    // fastdl_urls.push("https://fastdl.gflclan.com/cstrike/sound/");
-    // fastdl_urls.push("https://fastdl.gflclan.com/cstrike/");
-
-    for url in fastdl_urls.to_owned() {
-        let dl_links = scrape_web(url).unwrap();
+    if let Some(destination) = destinations.resolve(&decoded, base) {
+        return destination;
+    }
 
-        // Create directories for the files, then download and store them in their respective directories
-        download_files(&dl_links);
+    match extract_to {
+        Some(target) => target.join(decoded.strip_prefix(base).unwrap_or(&decoded)),
+        None => decoded,
+    }
+}
 
-        // Grabs all the bz2 files and decodes them, making bsp files
-        // Then, the bz2 files are deleted, keeping only the bsp files
-        decode_files(&corrupt_files);
+/// Joins the downloaded parts of the split archives in `roots` so they're decoded like any other archive
+/// Archives with missing parts can't be decoded and are counted as corrupt
+///
+/// # Arguments
+/// `roots`             Directories that are searched
+/// `scan`              Which symlinks and hidden directories are walked into
+/// `corrupt_files`     Files that failed to decode
+fn join_split_archives(
+    roots: &[PathBuf],
+    scan: ScanPolicy,
+    corrupt_files: &Mutex<HashSet<String>>,
+) {
+    match split::join_parts(roots, scan) {
+        Ok(incomplete) => corrupt_files.lock().unwrap().extend(
+            incomplete
+                .iter()
+                .map(|archive| format!("{} (missing parts)", archive.display())),
+        ),
+        Err(err) => println!("Failed to join the split archives: {}", err),
     }
+}
 
-    println!(
-        "{}{}
-        {}URL:\t{:#?}
-        {}Time:\t{}
-        {}{}",
-        // Separator Params
-        term_cursor::Goto(0, 23),
-        "=".repeat(25),
-        // URL
-        term_cursor::Goto(0, 24),
-        fastdl_urls,
-        // Time
-        term_cursor::Goto(0, 25),
-        timer.elapsed().as_secs_f32(),
+/// Extracts the zip archives in `roots` with the password `--archive-password` gives their download link
+/// Archives that can't be extracted (no password matches, wrong password) are counted as corrupt with the reason
+///
+/// # Arguments
+/// `roots`             Directories that are searched
+/// `config`            Command line options
+/// `ctx`               State shared by every stage of a sync
+/// `corrupt_files`     Files that failed to decode
+#[cfg(feature = "zip")]
+fn extract_zips(
+    roots: &[PathBuf],
+    config: &Config,
+    ctx: &SyncContext,
+    corrupt_files: &Mutex<HashSet<String>>,
+) {
+    let links = ctx
+        .store
+        .files()
+        .into_iter()
+        .filter_map(|(link, entry)| Some((entry.path?, link)))
+        .collect::<HashMap<_, _>>();
+
+    let failed = zip_archive::extract_all(roots, ctx.scan, |path| {
+        links
+            .get(path)
+            .and_then(|link| config.archive_passwords.lookup(link))
+            .map(String::from)
+    });
+    corrupt_files.lock().unwrap().extend(
+        failed
+            .iter()
+            .map(|(archive, reason)| format!("{} ({})", archive.display(), reason)),
+    );
+}
+
+/// Cleans up after a run that was killed between decoding a file and removing its compressed file:
+/// when both are there, the decoded copy is checked against the compressed file, then the compressed
+/// file is removed (or archived), otherwise the decoded copy is removed and the decode stage decodes it again
+///
+/// # Arguments
+/// `roots`             Directories or files that are searched
+/// `scan`              Which symlinks and hidden directories are walked into
+/// `extract_to`        Directory the decoded files are written to (next to the bz2 files if None)
+/// `destinations`      Directories the decoded files of some categories are written to
+/// `keep_archives`     Directory the compressed files are moved to once decoded (deleted if None)
+fn reconcile_orphans(
+    roots: &[PathBuf],
+    scan: ScanPolicy,
+    extract_to: Option<&Path>,
+    destinations: &Destinations,
+    keep_archives: Option<&Path>,
+) {
+    let pairs = collect_compressed_files(roots, scan)
+        .into_iter()
+        .map(|(file, base)| {
+            let output = output_path(&file.path, &base, extract_to, destinations);
+            (file.path, base, output)
+        })
+        .filter(|(_, _, output)| output.is_file())
+        .collect::<Vec<_>>();
+
+    pairs.par_iter().for_each(|(archive, base, output)| {
+        let path = archive.to_str().unwrap();
+
+        if orphans::is_decoded(archive, output).unwrap_or(false) {
+            let removed = match keep_archives {
+                Some(archive_dir) => archive_cache::archive(archive, base, archive_dir),
+                None => fs::remove_file(archive),
+            };
+            match removed {
+                Ok(()) => events::emit(&Event::OrphanRemoved { path, output }),
+                Err(err) => println!("Failed to remove {}: {}", path, err),
+            }
+        } else {
+            match fs::remove_file(output) {
+                Ok(()) => events::emit(&Event::OrphanRedecoded { path, output }),
+                Err(err) => println!("Failed to remove {}: {}", output.display(), err),
+            }
+        }
+    });
+}
+
+/// Recursively collects the compressed files of every root (a root can be a directory or a single file)
+/// Returns every compressed file along with the directory its relative path starts from
+///
+/// # Arguments
+/// `roots`     Directories or files that are searched
+/// `scan`      Which symlinks and hidden directories are walked into
+fn collect_compressed_files(roots: &[PathBuf], scan: ScanPolicy) -> Vec<(LocalFile, PathBuf)> {
+    roots
+        .iter()
+        .flat_map(|root| {
+            let base = if root.is_file() {
+                root.parent().map(Path::to_path_buf).unwrap_or_default()
+            } else {
+                root.clone()
+            };
+
+            scan::find_files(root, scan, |name| Format::from_name(name).is_some())
+                .into_iter()
+                .filter_map(move |path| Some((LocalFile::read(&path).ok()?, base.clone())))
+        })
+        .collect()
+}
+
+/// Prints the decode progress of a file and of the whole decode stage (does nothing with `--progress plain|none`)
+///
+/// # Arguments
+/// `file_name`         Name of the compressed file
+/// `file_name_path`    Path of the compressed file
+/// `file_progress`     Decoded bytes of the file
+/// `progress`          Throughput and time left of the decode stage
+fn print_decode_progress(
+    file_name: &str,
+    file_name_path: &str,
+    file_progress: &FileProgress,
+    progress: &DecodeProgress,
+) {
+    if !ui::fancy() {
+        return;
+    }
+
+    print!(
+        "
+                {}File:\t\t\t{}{}
+                {}Directory:\t\t{}{}
+                {}Size:\t\t\t{}{}
+                {}Throughput:\t\t{}{}
+                ",
+        // File Params
+        term_cursor::Goto(0, 18),
+        file_name,
+        " ".repeat(POST_MSG_REPLACE),
+        // Directory Params
+        term_cursor::Goto(0, 19),
+        file_name_path.replace(file_name, ""),
+        " ".repeat(POST_MSG_REPLACE),
+        // Size Params
+        term_cursor::Goto(0, 20),
+        file_progress.summary(),
+        " ".repeat(POST_MSG_REPLACE),
+        // Throughput Params
+        term_cursor::Goto(0, 22),
+        progress.summary(),
+        " ".repeat(POST_MSG_REPLACE),
+    );
+}
+
+/// Why the decode stage moves a file
+enum DecodeMove {
+    /// A file ending with a compressed extension was not compressed, it's moved to its output path
+    Renamed,
+    /// A decoded compressed file is moved to the archive directory (`--keep-archives`)
+    Archived,
+}
+
+/// Decodes all compressed files (bz2, and zst/xz when those features are enabled) in `roots` by
+/// recursively searching through all the paths
+/// After all paths are decoded, the original compressed files are deleted (or moved to `keep_archives`)
+/// Moves run on their own workers next to the decodes, they copy when the target is on another drive
+///
+/// # Arguments
+/// `roots`             Directories or files that are decoded
+/// `scan`              Which symlinks and hidden directories are walked into
+/// `corrupt_files`     Files that failed to decode
+/// `extract_to`        Directory the decoded files are written to (next to the bz2 files if None)
+/// `destinations`      Directories the decoded files of some categories are written to
+/// `keep_archives`     Directory the compressed files are moved to once decoded (deleted if None)
+///
+/// Returns the path of every compressed file that was decoded with its decoded size
+fn decode_files(
+    roots: &[PathBuf],
+    scan: ScanPolicy,
+    corrupt_files: &Mutex<HashSet<String>>,
+    extract_to: Option<&Path>,
+    destinations: &Destinations,
+    keep_archives: Option<&Path>,
+) -> Vec<(PathBuf, u64)> {
+    // Recursively collect files ending with .bz2 (or another supported extension)
+    let dirs = collect_compressed_files(roots, scan);
+
+    // Estimate the decoded sizes up front, then decode the largest files first
+    let mut estimates = dirs
+        .into_par_iter()
+        .map(|(file, base)| {
+            let format = Format::sniff_file(&file.path)
+                .ok()
+                .flatten()
+                .unwrap_or(Format::Bz2);
+            let estimate = decoder::estimate_decoded_size(&file.path, format).unwrap_or(0);
+            (estimate, (file, base))
+        })
+        .collect::<Vec<_>>();
+    estimates.sort_by_key(|(estimate, _)| std::cmp::Reverse(*estimate));
+
+    let estimated_total = estimates.iter().map(|(estimate, _)| estimate).sum::<u64>();
+    let target = extract_to.unwrap_or(Path::new("."));
+    let available = fs2::available_space(target).unwrap_or(u64::MAX);
+
+    // Every decoded file is a new file on the target filesystem (the compressed file is only freed afterwards)
+    let longest_name = estimates
+        .iter()
+        .filter_map(|(_, (file, base))| {
+            output_path(&file.path, base, extract_to, destinations)
+                .file_name()
+                .map(|n| n.len())
+        })
+        .max()
+        .unwrap_or(0);
+    let limits_warning = fs_limits::preflight(target, estimates.len() as u64, longest_name);
+
+    let mut warnings = Vec::new();
+    if estimated_total > available {
+        warnings.push(format!(
+            "only {:.2} MB free!",
+            available as f32 / MB_SIZE as f32
+        ));
+    }
+    warnings.extend(limits_warning);
+
+    if ui::fancy() {
+        print!(
+            "{}Estimated Output:\t{:.2} MB{}{}",
+            term_cursor::Goto(0, 17),
+            estimated_total as f32 / MB_SIZE as f32,
+            if warnings.is_empty() {
+                String::new()
+            } else {
+                format!(" ({})", warnings.join(", "))
+            },
+            " ".repeat(POST_MSG_REPLACE),
+        );
+    } else if !warnings.is_empty() {
+        println!("Preflight: {}", warnings.join(", "));
+    }
+
+    // Remaining time is estimated from the compressed sizes, the decoded sizes are only estimates
+    let dirs = estimates
+        .into_iter()
+        .map(|(estimate, (file, base))| (file.path, base, file.size, estimate))
+        .collect::<Vec<_>>();
+    let progress = DecodeProgress::new(dirs.iter().map(|(_, _, size, _)| size).sum());
+
+    let cmp_dir_size = AtomicUsize::new(0);
+    let mover = Mover::<DecodeMove>::start();
+    let decoded_sizes = Mutex::new(Vec::new());
+
+    // Print all the bz2 files that will be decoded
+    // dirs.par_iter()
+    // .for_each(|f| println!("{}", f.file_name().to_str().unwrap().trim()));
+
+    // File print separator
+    // println!("\n{}\n{}\n", "=".repeat(SEP_LEN), "=".repeat(SEP_LEN));
+
+    // Iterate through every file and decode it on the decode workers
+    tuning::decode_pool().install(|| {
+        dirs.par_iter().for_each(|(dir, base, size, estimate)| {
+            // Grab the {bz2/bsp} file name and path
+            let file_name = dir
+                .file_name()
+                .and_then(|name| name.to_str())
+                .expect("Failed to convert &OSStr to &str");
+            let file_name_path = dir.to_str().unwrap();
+            crash::set_current(file_name_path);
+
+            // The compressed file is kept when the output can't be written, the next run decodes it again
+            let write_failed = |path: &Path, err: io::Error| {
+                let reason = err.to_string();
+                eprintln!("Failed to write {}: {}", path.display(), reason);
+                events::emit(&Event::WriteFailed {
+                    path,
+                    reason: &reason,
+                });
+            };
+
+            let output_name_path = output_path(dir, base, extract_to, destinations);
+            if let Some(parent) = output_name_path.parent() {
+                if let Err(err) = fd_limit::retry(|| fs::create_dir_all(parent)) {
+                    return write_failed(parent, err);
+                }
+            }
+
+            // The magic bytes pick the decoder; misnamed files (e.g. plain BSPs ending with .bz2) only need to be renamed
+            let format = match Format::sniff_file(dir) {
+                Ok(Some(format)) => format,
+                Ok(None) => {
+                    mover.submit(DecodeMove::Renamed, dir.clone(), output_name_path);
+                    return;
+                }
+                Err(_) => Format::Bz2,
+            };
+
+            // Open the file and check if it's a compressed file
+            if let Ok(f) = fd_limit::retry(|| File::open(dir)) {
+                // Create the decoder (converts bz2 to bsp)
+                let mut decoder = match format.decoder(f) {
+                    Ok(decoder) => decoder,
+                    Err(_) => {
+                        corrupt_files.lock().unwrap().insert(file_name.to_string());
+                        events::emit(&Event::DecodeFailed {
+                            path: file_name_path,
+                        });
+                        Metrics::add(&METRICS.decode_failures, 1);
+                        return;
+                    }
+                };
+
+                // Decode straight into the output file, so large maps are never held in memory
+                let mut output = match durability::create(&output_name_path) {
+                    Ok(output) => output,
+                    Err(err) => return write_failed(&output_name_path, err),
+                };
+                let mut file_progress = FileProgress::new(*size, *estimate);
+
+                let decoded = decoder
+                    .decode_into(&mut output, &mut |n| {
+                        file_progress.record(&progress, n);
+                        print_decode_progress(file_name, file_name_path, &file_progress, &progress);
+                    })
+                    // The compressed file is removed next, so the decoded copy must reach the disk first
+                    .and_then(|bytes| {
+                        durability::sync_file(&output)?;
+                        Ok(bytes)
+                    });
+                file_progress.finish(&progress);
+
+                let bytes = match decoded {
+                    Ok(bytes) => bytes,
+                    Err(_) => {
+                        // Keep the compressed file, the partly written output is useless
+                        drop(output);
+                        let _ = fs::remove_file(&output_name_path);
+
+                        corrupt_files.lock().unwrap().insert(file_name.to_string());
+                        events::emit(&Event::DecodeFailed {
+                            path: file_name_path,
+                        });
+                        Metrics::add(&METRICS.decode_failures, 1);
+                        return;
+                    }
+                };
+
+                // Increment the compared value (for status checking)
+                let decoded = cmp_dir_size.fetch_add(1, Ordering::Relaxed) + 1;
+
+                if ui::fancy() {
+                    print!(
+                        "{}Finished Decoding:\t{} / {}{}",
+                        term_cursor::Goto(0, 21),
+                        decoded,
+                        dirs.len(),
+                        " ".repeat(POST_MSG_REPLACE),
+                    );
+                }
+
+                events::emit(&Event::DecodeFinished {
+                    path: file_name_path,
+                    output: &output_name_path,
+                    bytes,
+                });
+                ratios::record(&output_name_path, *size, bytes);
+                decoded_sizes.lock().unwrap().push((dir.clone(), bytes));
+
+                // Delete the compressed file, or keep it so it doesn't have to be downloaded again
+                match keep_archives {
+                    Some(archive_dir) => mover.submit(
+                        DecodeMove::Archived,
+                        dir.clone(),
+                        archive_cache::target(dir, base, archive_dir),
+                    ),
+                    // A compressed file left next to its decoded copy is cleaned up by the next run
+                    None => {
+                        if let Err(err) = fs::remove_file(file_name_path) {
+                            eprintln!("Failed to remove {}: {}", file_name_path, err);
+                        }
+                    }
+                }
+            }
+        })
+    });
+
+    for (reason, src, dst, result) in mover.finish() {
+        match (reason, result) {
+            (DecodeMove::Renamed, Ok(())) => events::emit(&Event::DecodeRenamed {
+                path: src.to_str().unwrap(),
+                output: &dst,
+            }),
+            (DecodeMove::Archived, Ok(())) => {}
+            // The source is still there, the next decode moves it again
+            (_, Err(err)) => println!(
+                "Failed to move {} to {}: {}",
+                src.display(),
+                dst.display(),
+                err
+            ),
+        }
+    }
+
+    decoded_sizes.into_inner().unwrap()
+}
+
+/// Draws the headers of the console GUI (does nothing with `--progress plain|none`)
+fn print_console_gui() {
+    if !ui::fancy() {
+        return;
+    }
+
+    print!("{}", term_cursor::Clear);
+
+    print!(
+        "
+        {}Searching All Paths
+        {}{}
+        {}Downloading Files
+        {}{}
+        {}Decoding All Files (bz2 -> original file)
+        {}{}
+        ",
+        // Checking All Paths
+        term_cursor::Goto((SEP_LEN / 8) as i32, 0),
+        term_cursor::Goto(0, 1),
+        "=".repeat(SEP_LEN),
+        // Downloading Files
+        term_cursor::Goto((SEP_LEN / 8) as i32, 7),
+        term_cursor::Goto(0, 8),
+        "=".repeat(SEP_LEN),
+        // Decoding All Files
+        term_cursor::Goto((SEP_LEN / 8) as i32, 15),
+        term_cursor::Goto(0, 16),
+        "=".repeat(SEP_LEN),
+    );
+}
+
+/// Links that will be downloaded and every link the listings had (before any filter)
+type Crawled = (Arc<RwLock<HashSet<RemoteFile>>>, HashSet<String>);
+
+/// Crawls `url` and returns the links that should be downloaded
+/// Links that collide, are not in the map list, are blocked, are old versions or duplicate variants are dropped
+///
+/// # Arguments
+/// * `config`              Command line options
+/// * `url`                 Fastdl url that is crawled
+/// * `ctx`                 State shared by every stage of a sync
+/// * `crawl_stats`         Live crawl metrics
+/// * `case_collisions`     Links skipped because of case-insensitive collisions
+///
+/// Returns the links that will be downloaded and every link the listings had (before any filter),
+/// or the error that stopped the crawl (e.g. the fastdl is offline)
+fn crawl(
+    config: &Config,
+    url: &str,
+    ctx: &SyncContext,
+    crawl_stats: &Arc<CrawlStats>,
+    case_collisions: &Mutex<Vec<RemoteFile>>,
+) -> Result<Crawled> {
+    let store = &ctx.store;
+    // Quick mode only makes sense if there was a previous successful sync
+    let since = Some(store.last_sync()).filter(|t| config.since_last_run && *t > 0);
+    // Sparse checkout: the list is read on every crawl so it can be edited while watching
+    let list = config
+        .maps
+        .as_ref()
+        .map(|path| sparse::load_map_list(path).expect("Failed to read the map list"));
+    let nominations = config
+        .nominations
+        .as_ref()
+        .map(|path| sparse::load_nominations(path).expect("Failed to read the nominations list"));
+    let maps = match (list, nominations) {
+        (Some(list), Some(nominations)) => Some(list.into_iter().chain(nominations).collect()),
+        (list, nominations) => list.or(nominations),
+    };
+
+    crash::set_stage("crawl");
+    ui::set_stage("crawl");
+    crash::set_current(url);
+    let dl_links = if config.discover.is_empty() {
+        scrape_web(
+            url,
+            crawl_stats,
+            store,
+            since,
+            config.fast_crawl,
+            !config.no_sitemap,
+            &Prefixes::new(&config.prefixes),
+            &ctx.origins,
+            &config.rewrites,
+        )?
+    } else {
+        discover(config, url, ctx, crawl_stats, since)
+    };
+    let listed = dl_links
+        .read()
+        .unwrap()
+        .iter()
+        .map(|file| file.url.clone())
+        .collect();
+
+    // Split archives with a gap can't be joined, the missing parts are reported before anything is downloaded
+    for part in split::missing_parts(&dl_links.read().unwrap()) {
+        events::emit(&Event::SplitPartMissing { url: &part });
+        ctx.missing_parts.lock().unwrap().push(part);
+    }
+
+    // Drop links that would overwrite each other on case-insensitive filesystems
+    case_collisions
+        .lock()
+        .unwrap()
+        .append(&mut collisions::resolve_collisions(
+            &mut dl_links.write().unwrap(),
+        ));
+
+    // Only download the maps the user asked for
+    if let Some(maps) = &maps {
+        sparse::retain_maps(&mut dl_links.write().unwrap(), maps);
+    }
+
+    // Skip files known to be corrupt or removed-for-cause
+    if let Some(blocklist) = &ctx.blocklist {
+        blocklist.retain_links(&mut dl_links.write().unwrap());
+    }
+
+    // Skip executables and oversized files before anything lands on disk
+    for (file, violation) in ctx.policy.retain_links(&mut dl_links.write().unwrap()) {
+        events::emit(&Event::PolicyViolation {
+            url: &file.url,
+            reason: &violation.to_string(),
+        });
+    }
+
+    // Don't download map versions that would be pruned right away
+    if config.skip_old_versions {
+        versions::retain_newest_links(
+            &mut dl_links.write().unwrap(),
+            config.prune_keep.unwrap_or(1),
+        );
+    }
+
+    // Skip e.g. the .mp3 copies of sounds that are also served as .wav
+    sound::drop_duplicates(&mut dl_links.write().unwrap(), &config.drop_duplicates);
+
+    // Only download one copy of files that are served both raw and as .bz2
+    variants::drop_duplicate_variants(&mut dl_links.write().unwrap(), config.prefer);
+
+    // Checksums the fastdl publishes tell changed files apart better than sizes, and verify the downloads
+    if !config.no_remote_sums {
+        if let Some(sums) = integrity::fetch_published(url, config.sums_key.as_deref()) {
+            for file in dl_links.read().unwrap().iter() {
+                if let Some(sha256) = sums.get(&file.rel_path) {
+                    ctx.published_sums.insert(file.url.clone(), sha256.clone());
+                }
+            }
+        }
+    }
+
+    // Skip the files the mirror already has (matched the way the filesystem matches names)
+    if config.only_missing {
+        drop_present(url, &mut dl_links.write().unwrap(), ctx);
+    } else {
+        drop_unchanged(url, &mut dl_links.write().unwrap(), ctx);
+        // Files renamed upstream are renamed in the mirror instead of downloaded again
+        if ctx.storage.is_local() {
+            apply_renames(url, &mut dl_links.write().unwrap(), &listed, ctx);
+        }
+    }
+
+    // What the crawl learned about the hosts spares the next crawl from probing them again
+    if let Err(err) = fingerprint::save(&ctx.out_dir) {
+        eprintln!("Failed to save the host fingerprints: {}", err);
+    }
+
+    Ok((dl_links, listed))
+}
+
+/// Merges the files every `--discover` provider finds for `url` into one download set
+/// A provider that fails (e.g. an offline game server) is reported and the others are still used
+///
+/// # Arguments
+/// * `config`          Command line options
+/// * `url`             Fastdl url that is crawled
+/// * `ctx`             State shared by every stage of a sync
+/// * `crawl_stats`     Live crawl metrics
+/// * `since`           Unix time; directories not modified after it are not examined
+fn discover(
+    config: &Config,
+    url: &str,
+    ctx: &SyncContext,
+    crawl_stats: &Arc<CrawlStats>,
+    since: Option<u64>,
+) -> Arc<RwLock<HashSet<RemoteFile>>> {
+    // One crawl state for every provider, a file found twice is recorded once
+    let state = Arc::new(CrawlState::new(
+        Arc::clone(&ctx.store),
+        Arc::clone(crawl_stats),
+        Arc::clone(&ctx.origins),
+    ));
+    let prefixes = Prefixes::new(&config.prefixes);
+    let discovery_ctx = DiscoveryContext {
+        stats: crawl_stats,
+        since,
+        fast: config.fast_crawl,
+        prefixes: &prefixes,
+        rewrites: &config.rewrites,
+    };
+
+    for spec in &config.discover {
+        let provider = spec.provider();
+        if let Err(err) = provider.discover(url, &state, &discovery_ctx) {
+            eprintln!("The {} provider found nothing: {}", provider.name(), err);
+        }
+    }
+
+    Arc::new(RwLock::new(state.download_links()))
+}
+
+/// Removes the links whose file is already in the mirror: the manifest has it with the size the crawl
+/// probed and its download (or what it was decoded to) is still on disk, or in the storage when the
+/// files are not written to the output directory
+/// On a case-insensitive filesystem, a link whose case changed upstream matches the file synced before
+/// and the manifest entry moves to the new link instead of the file being downloaded again
+///
+/// # Arguments
+/// * `url`         Fastdl url that was crawled
+/// * `links`       Files that will be downloaded
+/// * `ctx`         State shared by every stage of a sync
+fn drop_unchanged(url: &str, links: &mut HashSet<RemoteFile>, ctx: &SyncContext) {
+    let base = ctx.out_dir.join(ctx.local_path(url));
+    let mirrored = ctx
+        .store
+        .files()
+        .into_iter()
+        .filter(|(link, entry)| link.starts_with(url) && entry.path.is_some())
+        .map(|(link, entry)| {
+            (
+                case_sense::key(&link, ctx.case_insensitive).into_owned(),
+                (link, entry),
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    links.retain(|file| {
+        let Some((link, entry)) =
+            mirrored.get(case_sense::key(&file.url, ctx.case_insensitive).as_ref())
+        else {
+            return true;
+        };
+
+        // The checksum the fastdl publishes beats the probed size: it also catches changes that keep the size
+        // Without either, a changed file can't be told apart from an unchanged one
+        let published = ctx.published_sums.get(&file.url);
+        let unchanged = match (published.as_deref(), &entry.sha256) {
+            (Some(published), Some(sha256)) => published == sha256,
+            _ => file.size == Some(entry.size),
+        };
+
+        let path = entry.path.as_deref().unwrap();
+        let present = if ctx.storage.is_local() {
+            path.exists()
+                || output_path(path, &base, ctx.extract_to.as_deref(), &ctx.destinations).exists()
+        } else {
+            ctx.storage.exists(path).unwrap_or_else(|err| {
+                eprintln!("Failed to check {} in the storage: {}", path.display(), err);
+                false
+            })
+        };
+        if !unchanged || !present {
+            return true;
+        }
+
+        if *link != file.url {
+            ctx.store.forget_file(link);
+            ctx.store.record_file(&file.url, entry.clone());
+        }
+        ctx.add_bandwidth(Bandwidth {
+            downloaded: 0,
+            saved: entry.size,
+        });
+        false
+    });
+}
+
+/// Removes the links that have a local copy, whatever its size or date (`--only-missing`)
+/// A link counts as present if its download or what it was decoded to is on disk, or, with a storage
+/// that is not the output directory, if the manifest has it
+///
+/// # Arguments
+/// * `url`         Fastdl url that was crawled
+/// * `links`       Files that will be downloaded
+/// * `ctx`         State shared by every stage of a sync
+fn drop_present(url: &str, links: &mut HashSet<RemoteFile>, ctx: &SyncContext) {
+    let base = ctx.out_dir.join(ctx.local_path(url));
+    let on_disk = |path: &Path| {
+        path.exists()
+            || output_path(path, &base, ctx.extract_to.as_deref(), &ctx.destinations).exists()
+    };
+    let mirrored = ctx
+        .store
+        .files()
+        .into_iter()
+        .filter(|(link, _)| link.starts_with(url))
+        .map(|(link, entry)| {
+            (
+                case_sense::key(&link, ctx.case_insensitive).into_owned(),
+                entry,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+
+    links.retain(|file| {
+        let entry = mirrored.get(case_sense::key(&file.url, ctx.case_insensitive).as_ref());
+        let present = if ctx.storage.is_local() {
+            on_disk(&ctx.file_path(&file.url))
+                || entry
+                    .and_then(|entry| entry.path.as_deref())
+                    .is_some_and(on_disk)
+        } else {
+            entry.is_some()
+        };
+        if let (true, Some(entry)) = (present, entry) {
+            ctx.add_bandwidth(Bandwidth {
+                downloaded: 0,
+                saved: entry.size,
+            });
+        }
+        !present
+    });
+}
+
+/// Renames the local copies of the files `renames::detect` finds under a new link, so a map renamed
+/// upstream (e.g. ze_foo_v3.bsp.bz2 -> ze_foo_v3_fix.bsp.bz2) is neither downloaded again nor deleted
+/// The download and what it was decoded to are both moved, and the manifest entry follows the new link
+///
+/// # Arguments
+/// * `url`         Fastdl url that was crawled
+/// * `links`       Files that will be downloaded
+/// * `listed`      Every link the listings of `url` had
+/// * `ctx`         State shared by every stage of a sync
+fn apply_renames(
+    url: &str,
+    links: &mut HashSet<RemoteFile>,
+    listed: &HashSet<String>,
+    ctx: &SyncContext,
+) {
+    let base = ctx.out_dir.join(ctx.local_path(url));
+    let decoded =
+        |path: &Path| output_path(path, &base, ctx.extract_to.as_deref(), &ctx.destinations);
+    let mirrored = ctx
+        .store
+        .files()
+        .into_iter()
+        .filter(|(link, entry)| {
+            link.starts_with(url)
+                && entry
+                    .path
+                    .as_deref()
+                    .is_some_and(|path| path.exists() || decoded(path).exists())
+        })
+        .collect::<Vec<_>>();
+
+    for rename in renames::detect(links, listed, mirrored, &ctx.transport) {
+        let old_path = rename.entry.path.clone().unwrap();
+        let new_path = ctx.file_path(&rename.to);
+        let moves = [
+            (old_path.clone(), new_path.clone()),
+            (decoded(&old_path), decoded(&new_path)),
+        ];
+
+        let mut moved = false;
+        for (from, to) in moves
+            .iter()
+            .filter(|(from, to)| from != to && from.exists())
+        {
+            let result = to
+                .parent()
+                .map_or(Ok(()), fs::create_dir_all)
+                .and_then(|_| fs::rename(from, to));
+            match result {
+                Ok(()) => moved = true,
+                Err(err) => eprintln!(
+                    "Failed to rename {} to {}: {}",
+                    from.display(),
+                    to.display(),
+                    err
+                ),
+            }
+        }
+        if !moved {
+            continue;
+        }
+
+        events::emit(&Event::FileRenamed {
+            from: &rename.from,
+            to: &rename.to,
+        });
+        ctx.store.forget_file(&rename.from);
+        ctx.store.record_file(
+            &rename.to,
+            ManifestEntry {
+                path: Some(new_path),
+                ..rename.entry.clone()
+            },
+        );
+        ctx.add_bandwidth(Bandwidth {
+            downloaded: 0,
+            saved: rename.entry.size,
+        });
+        links.retain(|file| file.url != rename.to);
+    }
+}
+
+/// Publishes the signed checksums of every file the mirror serves (`--publish-sums`)
+/// The files of the output directory are hashed (unchanged ones keep their checksum), the uploads to a
+/// remote storage were hashed as they were written and are listed as long as the manifest has them
+///
+/// # Arguments
+/// * `key`         Minisign secret key the checksums are signed with
+/// * `ctx`         State shared by every stage of a sync
+fn publish_sums(key: &Path, ctx: &SyncContext) {
+    let publish =
+        |sums: &mut Sums| match integrity::publish(&ctx.out_dir, ctx.storage.as_ref(), sums, key) {
+            Ok(files) => println!(
+                "{}Published the signed checksums of {} file(s)",
+                ui::goto(0, 45),
+                files
+            ),
+            Err(err) => eprintln!("{}", err),
+        };
+
+    match &ctx.uploaded_sums {
+        Some(sums) => {
+            let paths = ctx
+                .store
+                .files()
+                .into_iter()
+                .filter_map(|(_, entry)| entry.path)
+                .collect::<Vec<_>>();
+            let mut sums = sums.lock().unwrap();
+            sums.retain_uploads(&ctx.out_dir, &paths);
+            publish(&mut sums);
+        }
+        None => {
+            let mut sums = Sums::load(&ctx.out_dir).unwrap_or_default();
+            sums.refresh(&ctx.out_dir, ctx.scan);
+            publish(&mut sums);
+        }
+    }
+}
+
+/// Deletes the local copies of the files of `url` that are no longer listed (`--delete`)
+///
+/// # Arguments
+/// * `config`              Command line options
+/// * `url`                 Fastdl url that was crawled
+/// * `listed`              Every link the listings of `url` had
+/// * `ctx`                 State shared by every stage of a sync
+fn delete_vanished(config: &Config, url: &str, listed: &HashSet<String>, ctx: &SyncContext) {
+    let base = ctx.out_dir.join(ctx.local_path(url));
+    // Files outside of the `--prefix` subtrees were not crawled, so they can't have vanished
+    let prefixes = Prefixes::new(&config.prefixes);
+    let root_url = Url::parse(url).expect("Failed to parse the fastdl url");
+    let mirrored = ctx
+        .store
+        .files()
+        .into_iter()
+        // Archived copies were never listed upstream, they're kept
+        .filter(|(link, entry)| link.starts_with(url) && entry.archived_from.is_none())
+        .filter(|(link, _)| Url::parse(link).is_ok_and(|link| prefixes.allows(&link, &root_url)))
+        .collect::<Vec<_>>();
+
+    // A link whose case changed upstream is the same file on a case-insensitive filesystem, it did not vanish
+    let listed_keys = listed
+        .iter()
+        .map(|link| case_sense::key(link, ctx.case_insensitive))
+        .collect::<HashSet<_>>();
+
+    // The download and what it was decoded to
+    let vanished = mirrored
+        .iter()
+        .filter(|(link, _)| !listed_keys.contains(&case_sense::key(link, ctx.case_insensitive)))
+        .map(|(link, entry)| {
+            let mut paths = entry
+                .path
+                .iter()
+                .flat_map(|path| {
+                    [
+                        path.clone(),
+                        output_path(path, &base, ctx.extract_to.as_deref(), &ctx.destinations),
+                    ]
+                })
+                .collect::<Vec<_>>();
+            paths.dedup();
+            (link.clone(), paths)
+        })
+        .collect::<BTreeMap<_, _>>();
+    let planned = vanished.len();
+
+    let outcome = deletion::delete_vanished(
+        &ctx.out_dir,
+        ctx.store.as_ref(),
+        vanished,
+        mirrored.len(),
+        config.delete_threshold,
+        config.confirm_delete,
+    )
+    .expect("Failed to delete the files that vanished upstream");
+
+    match outcome {
+        deletion::Outcome::Nothing => {}
+        deletion::Outcome::Deleted(deleted) => println!(
+            "{}Deleted {} file(s) that vanished from {}",
+            ui::goto(0, 44),
+            deleted,
+            url
+        ),
+        deletion::Outcome::Refused => println!(
+            "{}Kept {} of {} file(s) that vanished from {} (more than {}% of the mirror, pass --confirm-delete if the listing is right)",
+            ui::goto(0, 44),
+            planned,
+            mirrored.len(),
+            url,
+            config.delete_threshold
+        ),
+    }
+}
+
+/// Adds the `--wayback` paths the crawl of `url` did not find to `dl_links`, downloaded from their newest
+/// Wayback Machine copy (paths already synced, from the fastdl or the archive, are not looked up again)
+///
+/// # Arguments
+/// * `config`              Command line options
+/// * `url`                 Fastdl url that was crawled
+/// * `dl_links`            Files that will be downloaded
+/// * `listed`              Every link the listings of `url` had
+/// * `ctx`                 State shared by every stage of a sync
+fn resolve_archived(
+    config: &Config,
+    url: &str,
+    dl_links: &Arc<RwLock<HashSet<RemoteFile>>>,
+    listed: &HashSet<String>,
+    ctx: &SyncContext,
+) {
+    let client = net::client();
+
+    for path in &config.wayback {
+        let dl_url = format!("{}{}", url, path.trim_start_matches('/'));
+        if listed.contains(&dl_url) || ctx.store.file(&dl_url).is_some() {
+            continue;
+        }
+
+        match wayback::snapshot(&client, &dl_url) {
+            Some(snapshot) => {
+                events::emit(&Event::ArchiveResolved {
+                    url: &dl_url,
+                    snapshot: &snapshot,
+                });
+                ctx.archived.insert(dl_url.clone(), snapshot);
+                dl_links
+                    .write()
+                    .unwrap()
+                    .insert(RemoteFile::new(&dl_url, url));
+            }
+            None => events::emit(&Event::ArchiveMissing { url: &dl_url }),
+        }
+    }
+}
+
+/// Syncs every fastdl url once: crawl, download, then decode
+///
+/// # Arguments
+/// * `config`              Command line options
+/// * `fastdl_urls`         Fastdl urls that are synced
+/// * `ctx`                 State shared by every stage of a sync
+/// * `crawl_stats`         Live crawl metrics
+/// * `corrupt_files`       Files that failed to decode
+/// * `case_collisions`     Links skipped because of case-insensitive collisions
+fn sync(
+    config: &Config,
+    fastdl_urls: &[&str],
+    ctx: &SyncContext,
+    crawl_stats: &Arc<CrawlStats>,
+    corrupt_files: &Mutex<HashSet<String>>,
+    case_collisions: &Mutex<Vec<RemoteFile>>,
+) {
+    // A fastdl that could not be crawled is synced in full by the next run, even in quick mode
+    let mut crawled_all = true;
+
+    for url in fastdl_urls.iter().copied() {
+        // A previous run may have been killed in the middle of its decode stage
+        if ctx.storage.is_local() {
+            reconcile_orphans(
+                &[ctx.out_dir.join(ctx.local_path(url))],
+                ctx.scan,
+                ctx.extract_to.as_deref(),
+                &ctx.destinations,
+                ctx.keep_archives
+                    .as_ref()
+                    .map(|dir| dir.join(ctx.local_path(url)))
+                    .as_deref(),
+            );
+        }
+
+        let incomplete = crawl_stats.listings_incomplete.load(Ordering::Relaxed);
+        let (dl_links, listed) = match crawl(config, url, ctx, crawl_stats, case_collisions) {
+            Ok(crawled) => crawled,
+            Err(err) => {
+                eprintln!("Failed to crawl {}: {}", url, err);
+                crawled_all = false;
+                continue;
+            }
+        };
+        let complete = crawl_stats.listings_incomplete.load(Ordering::Relaxed) == incomplete;
+
+        // Paths the fastdl no longer has are downloaded from the Wayback Machine
+        if !config.wayback.is_empty() {
+            resolve_archived(config, url, &dl_links, &listed, ctx);
+        }
+
+        // Create directories for the files, then download and store them in their respective directories
+        crash::set_stage("download");
+        ui::set_stage("download");
+        download_files(&dl_links, ctx);
+
+        // Grabs all the bz2 files and decodes them, making bsp files
+        // Then, the bz2 files are deleted, keeping only the bsp files
+        // Only the directory this url downloads into is scanned, not everything in the CWD
+        // Remote storages keep the files as served: a cloud-hosted mirror serves the bz2 files itself
+        if ctx.storage.is_local() {
+            crash::set_stage("decode");
+            ui::set_stage("decode");
+            join_split_archives(
+                &[ctx.out_dir.join(ctx.local_path(url))],
+                ctx.scan,
+                corrupt_files,
+            );
+            let decoded = decode_files(
+                &[ctx.out_dir.join(ctx.local_path(url))],
+                ctx.scan,
+                corrupt_files,
+                ctx.extract_to.as_deref(),
+                &ctx.destinations,
+                ctx.keep_archives
+                    .as_ref()
+                    .map(|dir| dir.join(ctx.local_path(url)))
+                    .as_deref(),
+            );
+            // The compression ratios of the downloads forecast the space the next syncs need
+            ratios::record_in_manifest(ctx.store.as_ref(), &decoded);
+            #[cfg(feature = "zip")]
+            extract_zips(
+                &[ctx.out_dir.join(ctx.local_path(url))],
+                config,
+                ctx,
+                corrupt_files,
+            );
+        }
+
+        // Mirror mode: files that vanished upstream are deleted locally too
+        // The files of a listing that never arrived complete only look vanished
+        if config.delete && complete {
+            delete_vanished(config, url, &listed, ctx);
+        } else if config.delete {
+            println!(
+                "{}Kept the files that vanished from {} (some listings were cut off)",
+                ui::goto(0, 44),
+                url
+            );
+        }
+    }
+
+    // Keep the archive directory from growing without bounds
+    if let Some(archive_dir) = &ctx.keep_archives {
+        archive_cache::evict(
+            archive_dir,
+            config.archive_max_size,
+            config.archive_max_age,
+            ctx.scan,
+        );
+    }
+
+    // Remove old versions of every map family
+    if let Some(keep) = config.prune_keep {
+        versions::prune_local(&ctx.out_dir, keep, ctx.scan);
+    }
+
+    // Mirror source: mirrors syncing from this one verify their downloads against the signed checksums
+    if let Some(key) = &config.publish_sums {
+        publish_sums(key, ctx);
+    }
+
+    // Record the sync so the next run knows when the mirror was last updated
+    let saved = if crawled_all {
+        ctx.store.finish_sync()
+    } else {
+        ctx.store.save()
+    };
+    if let Err(err) = saved {
+        eprintln!("Failed to save the state store: {}", err);
+    }
+
+    Metrics::add(&METRICS.syncs, 1);
+    METRICS
+        .last_sync
+        .store(state_store::unix_now(), Ordering::Relaxed);
+}
+
+/// Returns the fastdl urls `profile` syncs
+///
+/// # Arguments
+/// `profile`       Profile whose urls are returned
+fn fastdl_urls(profile: &profiles::SyncProfile) -> Vec<&str> {
+    profile.urls.iter().map(String::as_str).collect()
+}
+
+fn main() -> Result<()> {
+    let config = Config::from_args()?;
+
+    // Crash reports are opt-in and only ever written locally
+    if config.crash_reports {
+        crash::install(&config);
+    }
+
+    // Must be set before any state store is opened
+    durability::set_fsync(config.fsync);
+
+    // Must be set before any client is built
+    net::init(net::NetOptions {
+        ip_version: config.ip_version,
+        resolve: config.resolve.clone(),
+        compat: config.compat,
+    });
+
+    // Every parallel loop (probes, downloads, decodes) gets no more workers than the open files limit allows,
+    // and a single one in compatibility mode
+    let fd_workers = fd_limit::init();
+    let default_workers = std::thread::available_parallelism().map_or(1, |n| n.get());
+    let workers = if config.compat {
+        Some(1)
+    } else {
+        fd_workers.filter(|workers| *workers < default_workers)
+    };
+    if let Some(workers) = workers {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .build_global()
+            .expect("Failed to start the worker thread pool");
+    }
+
+    // Structured events for external dashboards and scripts
+    if let Some(format) = config.events {
+        events::init(format, config.events_file.as_deref())?;
+    }
+
+    // Cursor-addressed GUI on terminals, plain status lines in CI logs and pipes
+    // `crawl --links -` writes the link list to stdout, so nothing else may be printed there
+    let links_to_stdout = config.command == Command::Crawl
+        && config.links.as_deref().is_some_and(link_list::is_stdio);
+    // The service logs a line per sync instead of showing progress
+    ui::init(
+        config
+            .progress
+            .or(config.service.then_some(ui::ProgressMode::None)),
+        (config.events.is_some() && config.events_file.is_none()) || links_to_stdout,
+    );
+
+    run(config).inspect_err(|e| {
+        crash::report_error(e);
+    })
+}
+
+/// Runs the command selected on the command line
+///
+/// # Arguments
+/// * `config`  -   Command line options
+fn run(config: Config) -> Result<()> {
+    // The executable a previous update on Windows moved aside can only be removed once it stopped running
+    self_update::cleanup();
+
+    // Self-update mode: replace this binary with the latest release instead of syncing
+    if config.command == Command::SelfUpdate {
+        match self_update::self_update()? {
+            self_update::Update::UpToDate(version) => println!("{} is the latest release", version),
+            self_update::Update::Updated(old, new) => println!("Updated from {} to {}", old, new),
+        }
+        return Ok(());
+    }
+
+    // Doctor mode: diagnose what makes syncs fail or hang instead of syncing (exits with 1 on problems)
+    if config.command == Command::Doctor {
+        let dir = std::env::current_dir()?;
+        if !doctor::run(&profiles::select(&config, &dir)?, &dir) {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Decode mode: recover existing folders (e.g. from the in-game downloader) without syncing
+    // Without paths, the directories the fastdl urls download into are decoded (the output of `download`)
+    if let Command::Decode(paths) = &config.command {
+        let paths = if paths.is_empty() {
+            profiles::select(&config, &std::env::current_dir()?)?
+                .iter()
+                .flat_map(|profile| {
+                    profile.urls.iter().map(|url| {
+                        profile.out_dir.join(fold(
+                            profile.config.layout.local_path(url),
+                            profile.config.fold_case,
+                        ))
+                    })
+                })
+                .filter(|path| path.exists())
+                .collect()
+        } else {
+            paths.clone()
+        };
+
+        // Decoding only needs the decode workers, the link is not measured
+        tuning::init(&config, &std::env::current_dir()?, None)?;
+
+        let corrupt_files = Mutex::new(HashSet::<String>::new());
+        reconcile_orphans(
+            &paths,
+            config.scan,
+            config.extract_to.as_deref(),
+            &config.destinations,
+            config.keep_archives.as_deref(),
+        );
+        join_split_archives(&paths, config.scan, &corrupt_files);
+        decode_files(
+            &paths,
+            config.scan,
+            &corrupt_files,
+            config.extract_to.as_deref(),
+            &config.destinations,
+            config.keep_archives.as_deref(),
+        );
+
+        if let Some(archive_dir) = &config.keep_archives {
+            archive_cache::evict(
+                archive_dir,
+                config.archive_max_size,
+                config.archive_max_age,
+                config.scan,
+            );
+        }
+
+        println!(
+            "\nCompression: {}\nFiles that failed to decompress correctly: {:#?}",
+            ratios::summary(),
+            corrupt_files.lock().unwrap()
+        );
+        return Ok(());
+    }
+
+    // Snapshot mode: hardlinked copies of the mirror to roll back a bad upstream change (e.g. after --delete)
+    if let Command::Snapshot(args) = &config.command {
+        let action = snapshot::Action::parse(args)?;
+
+        for profile in profiles::select(&config, &std::env::current_dir()?)? {
+            let out_dir = &profile.out_dir;
+            let _lock = RunLock::acquire(out_dir)?;
+
+            match &action {
+                snapshot::Action::Create(name) => println!(
+                    "Snapshot {} of {} created ({} files)",
+                    name,
+                    out_dir.display(),
+                    snapshot::create(out_dir, name)?
+                ),
+                snapshot::Action::Restore(name) => println!(
+                    "{} restored from snapshot {} (the previous state is kept as snapshot {})",
+                    out_dir.display(),
+                    name,
+                    snapshot::restore(out_dir, name)?
+                ),
+                snapshot::Action::Delete(name) => {
+                    snapshot::delete(out_dir, name)?;
+                    println!("Snapshot {} of {} deleted", name, out_dir.display());
+                }
+                snapshot::Action::List => snapshot::list(out_dir)?,
+            }
+        }
+        return Ok(());
+    }
+
+    // Stats mode: show what takes up space so the user can decide what to prune
+    if let Command::Stats(dir) = &config.command {
+        mirror_stats::print(&mirror_stats::collect(dir, config.scan));
+        return Ok(());
+    }
+
+    // Verify mode: check the mirror against the manifest and re-download only the damaged chunks
+    if config.command == Command::Verify {
+        let _lock = RunLock::acquire(&std::env::current_dir()?)?;
+        let store = state_store::open_store(&std::env::current_dir()?);
+        repair::verify_mirror(
+            &store,
+            &Throttle::new(config.limit_rate, &config.host_limits),
+        );
+        return Ok(());
+    }
+
+    // Lint mode: report files that would break the in-game downloader (exits with 1 on errors)
+    if let Command::Lint(target) = &config.command {
+        let entries = if target.starts_with("http://") || target.starts_with("https://") {
+            let store = state_store::open_store(&std::env::current_dir()?);
+            let links = scrape_web(
+                target,
+                &Arc::new(CrawlStats::new()),
+                &store,
+                None,
+                config.fast_crawl,
+                !config.no_sitemap,
+                &Prefixes::new(&config.prefixes),
+                &Arc::new(DashMap::new()),
+                &config.rewrites,
+            )?;
+            let mut entries = lint::remote_entries(&links.read().unwrap());
+            lint::probe_sizes(&mut entries, target);
+            if ui::fancy() {
+                print!("{}", term_cursor::Clear);
+            }
+            entries
+        } else {
+            lint::local_entries(Path::new(target), config.scan)
+        };
+
+        let issues = lint::lint(&entries);
+        lint::print(&issues);
+
+        if issues
+            .iter()
+            .any(|issue| issue.severity == lint::Severity::Error)
+        {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Compare mode: report what a secondary mirror lacks compared to the primary (exits with 1 on differences)
+    if config.command == Command::Compare {
+        let store = state_store::open_store(&std::env::current_dir()?);
+        let crawl_entries = |url: &str| -> Result<Vec<lint::Entry>> {
+            let links = scrape_web(
+                url,
+                &Arc::new(CrawlStats::new()),
+                &store,
+                None,
+                config.fast_crawl,
+                !config.no_sitemap,
+                &Prefixes::new(&config.prefixes),
+                &Arc::new(DashMap::new()),
+                &config.rewrites,
+            )?;
+            let entries = lint::remote_entries(&links.read().unwrap());
+            Ok(entries)
+        };
+
+        let source_url = config.source.as_deref().unwrap();
+        let target_url = config.target.as_deref().unwrap();
+        let source = crawl_entries(source_url)?;
+        let target = crawl_entries(target_url)?;
+        let diff = compare::compare(source, source_url, target, target_url);
+        if ui::fancy() {
+            print!("{}", term_cursor::Clear);
+        }
+        compare::print(&diff);
+
+        if !diff.missing.is_empty() || !diff.different.is_empty() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    // Service mode: run watch mode in the background with the same arguments, from this directory
+    if config.command == Command::InstallService {
+        let args = std::env::args()
+            .skip(1)
+            .filter(|arg| arg != "install-service")
+            .collect::<Vec<_>>();
+        let installed = service::install(&args, &std::env::current_dir()?)?;
+        println!("Installed {}", installed);
+        return Ok(());
+    }
+    if config.command == Command::UninstallService {
+        service::uninstall()?;
+        println!("Removed {}", service::SERVICE_NAME);
+        return Ok(());
+    }
+
+    // Prepare mode: build the bz2 tree a server operator uploads to their FastDL
+    if config.command == Command::Prepare {
+        let game_dir = config.game_dir.as_deref().unwrap();
+        let out = config
+            .out
+            .clone()
+            .unwrap_or_else(|| PathBuf::from("fastdl"));
+
+        prepare::print(&prepare::prepare(game_dir, &out, config.scan), &out);
+        return Ok(());
+    }
+
+    // FastDL server mode: serve what was already synced instead of syncing
+    if let Some(addr) = &config.serve {
+        let root = std::env::current_dir()?;
+        if let Some(key) = &config.publish_sums {
+            let mut sums = Sums::load(&root)?;
+            sums.refresh(&root, config.scan);
+            let files =
+                integrity::publish(&root, &storage::LocalStorage::default(), &mut sums, key)?;
+            println!("Published the signed checksums of {} file(s)", files);
+        }
+        serve::serve(addr, &root)?;
+        return Ok(());
+    }
+
+    // TIMER START
+    let timer = Instant::now();
+    let corrupt_files = Mutex::new(HashSet::<String>::new());
+    let case_collisions = Mutex::new(Vec::<RemoteFile>::new());
+    let crawl_stats = Arc::new(CrawlStats::new());
+
+    // Every profile shares the speed caps and the connections (the download pool)
+    let throttle = Arc::new(Throttle::new(config.limit_rate, &config.host_limits));
+    let transport = Arc::new(Transport::new());
+    let profiles = profiles::select(&config, &std::env::current_dir()?)?;
+
+    // Download and decode parallelism, measured on the first fastdl url by the first run
+    tuning::init(
+        &config,
+        &std::env::current_dir()?,
+        profiles
+            .first()
+            .and_then(|profile| profile.urls.first())
+            .map(String::as_str),
+    )?;
+
+    // Overlapping runs (e.g. a cron job while watch mode syncs) would corrupt each other's files and manifest
+    // Profiles can share an output directory, which is only locked once
+    let _locks = profiles
+        .iter()
+        .map(|profile| &profile.out_dir)
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .map(|dir| RunLock::acquire(dir))
+        .collect::<io::Result<Vec<_>>>()?;
+
+    let runs =
+        profiles
+            .iter()
+            .map(|profile| {
+                fs::create_dir_all(&profile.out_dir)?;
+                fingerprint::load(&profile.out_dir)
+                    .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+
+                Ok((
+                    profile,
+                    SyncContext {
+                        store: state_store::open_store(&profile.out_dir),
+                        blocklist: profile.config.blocklist.as_ref().map(|path| {
+                            Blocklist::load(path).expect("Failed to read the blocklist")
+                        }),
+                        policy: Policy::new(
+                            profile.config.max_file_size,
+                            &profile.config.allowed_extensions,
+                        ),
+                        throttle: Arc::clone(&throttle),
+                        queue: Arc::new(DownloadQueue::open(&meta_dir::path(
+                            &profile.out_dir,
+                            queue::QUEUE_FILE,
+                        ))),
+                        extract_to: profile.config.extract_to.clone(),
+                        destinations: profile.config.destinations.clone(),
+                        keep_archives: profile.config.keep_archives.clone(),
+                        origins: Arc::new(DashMap::new()),
+                        archived: DashMap::new(),
+                        scan: profile.config.scan,
+                        transport: Arc::clone(&transport),
+                        out_dir: profile.out_dir.clone(),
+                        file_time_budget: profile.config.file_time_budget.map(Duration::from_secs),
+                        paranoid: profile.config.paranoid,
+                        layout: profile.config.layout,
+                        path_template: profile.config.path_template.clone(),
+                        segments: profile.config.segments,
+                        checkpoint: Checkpoint::new(profile.config.checkpoint_every),
+                        missing_parts: Mutex::new(Vec::new()),
+                        case_insensitive: profile.config.storage == StorageTarget::Local
+                            && case_sense::is_case_insensitive(&profile.out_dir),
+                        fold_case: profile.config.fold_case,
+                        bandwidth: Mutex::new(Bandwidth::default()),
+                        published_sums: DashMap::new(),
+                        uploaded_sums: match &profile.config.publish_sums {
+                            Some(_) if profile.config.storage != StorageTarget::Local => {
+                                Some(Mutex::new(Sums::load(&profile.out_dir).map_err(|err| {
+                                    io::Error::new(io::ErrorKind::InvalidData, err)
+                                })?))
+                            }
+                            _ => None,
+                        },
+                        storage: profile
+                            .config
+                            .storage
+                            .open(&profile.out_dir)
+                            .map_err(|err| io::Error::new(io::ErrorKind::InvalidInput, err))?,
+                        small_file_pool: profile.config.small_file_workers.map(|workers| {
+                            rayon::ThreadPoolBuilder::new()
+                                .num_threads(workers.min(fd_limit::workers()))
+                                .build()
+                                .expect("Failed to start the small-file workers")
+                        }),
+                    },
+                ))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+
+    // Finish the deletions a previous run was interrupted in
+    for (_, ctx) in &runs {
+        deletion::resume(&ctx.out_dir, ctx.store.as_ref())?;
+    }
+
+    // Prints a real-time readable console output
+    print_console_gui();
+    ui::spawn_status(config.progress_interval, Arc::clone(&crawl_stats));
+    if let Some(endpoint) = &config.progress_endpoint {
+        overlay::spawn(endpoint.clone());
+    }
+
+    // TODO: Add support for ze_* maps
+    let links_path = config
+        .links
+        .clone()
+        .unwrap_or_else(|| PathBuf::from(link_list::LINKS_FILE));
+
+    // Crawl mode: only write the links that a sync would download, so they can be inspected or edited
+    if config.command == Command::Crawl {
+        let mut dl_links = HashSet::new();
+        for (profile, ctx) in &runs {
+            for url in fastdl_urls(profile) {
+                let (links, _) = crawl(&profile.config, url, ctx, &crawl_stats, &case_collisions)?;
+                dl_links.extend(links.read().unwrap().iter().cloned());
+            }
+        }
+
+        link_list::write(&links_path, &dl_links)?;
+        eprintln!(
+            "{}Crawl:\t{}\nWrote {} link(s) to {}",
+            ui::goto(0, 23),
+            crawl_stats.summary(),
+            dl_links.len(),
+            links_path.display()
+        );
+        return Ok(());
+    }
+
+    // Changes mode: report what changed upstream since the previous run, without downloading anything
+    if config.command == Command::Changes {
+        let mut history = Vec::new();
+        let mut changed = 0;
+
+        for (profile, ctx) in &runs {
+            let mut files = HashSet::new();
+            for url in fastdl_urls(profile) {
+                let (links, _) = crawl(&profile.config, url, ctx, &crawl_stats, &case_collisions)?;
+                files.extend(links.read().unwrap().iter().cloned());
+            }
+
+            let path = meta_dir::path(&ctx.out_dir, changes::CRAWL_FILE);
+            let mut snapshot = changes::CrawlSnapshot::load(&path)?;
+            let first_run = snapshot.crawled_at == 0;
+            let found = snapshot.update(&files, state_store::unix_now());
+            snapshot.save(&path)?;
+
+            if ui::fancy() {
+                print!("{}", term_cursor::Clear);
+            }
+            if first_run {
+                println!(
+                    "Recorded {} file(s) of {}, changes are reported from the next run on",
+                    files.len(),
+                    profile.urls.join(", ")
+                );
+            }
+            changes::print(&found);
+            changed += found.len();
+            history.append(&mut snapshot.history);
+        }
+
+        if let Some(feed) = &config.feed {
+            history.sort_by_key(|change| change.time);
+            let sources = runs
+                .iter()
+                .flat_map(|(profile, _)| profile.urls.iter().map(String::as_str))
+                .collect::<Vec<_>>()
+                .join(", ");
+            changes::write_feed(feed, &sources, &history)?;
+        }
+
+        eprintln!("{} change(s) since the last run", changed);
+        return Ok(());
+    }
+
+    // Download mode: download the links of a link list without crawling or decoding
+    if config.command == Command::Download {
+        let profile_urls = runs
+            .iter()
+            .map(|(profile, _)| profile.urls.as_slice())
+            .collect::<Vec<_>>();
+        let split = link_list::split_by_profile(link_list::read(&links_path)?, &profile_urls);
+
+        crash::set_stage("download");
+        ui::set_stage("download");
+        for ((_, ctx), dl_links) in runs.iter().zip(split) {
+            download_files(&Arc::new(RwLock::new(dl_links)), ctx);
+            ctx.store
+                .save()
+                .map_err(|err| format!("Failed to save the state store: {}", err))?;
+        }
+
+        println!(
+            "{}Downloaded {} file(s) in {:.2}s",
+            ui::goto(0, 23),
+            METRICS.files_downloaded.load(Ordering::Relaxed),
+            timer.elapsed().as_secs_f32()
+        );
+        return Ok(());
+    }
+
+    // Syncs every selected profile back-to-back
+    let sync_all = || {
+        for (profile, ctx) in &runs {
+            sync(
+                &profile.config,
+                &fastdl_urls(profile),
+                ctx,
+                &crawl_stats,
+                &corrupt_files,
+                &case_collisions,
+            );
+        }
+    };
+
+    // Link install mode: every game install shares the mirror instead of keeping its own copy
+    // Runs after every sync so the files that arrived get linked too
+    let linked_installs = Mutex::new(Vec::<(PathBuf, LinkSummary)>::new());
+    let link_all = || {
+        let Command::LinkInstall(game_dirs) = &config.command else {
+            return;
+        };
+        crash::set_stage("link");
+        ui::set_stage("link");
+
+        let mut linked_installs = linked_installs.lock().unwrap();
+        linked_installs.clear();
+        for game_dir in game_dirs {
+            let mut summary = LinkSummary::default();
+            for (profile, ctx) in &runs {
+                for url in &profile.urls {
+                    let output_root = ctx.out_dir.join(ctx.local_path(url));
+                    let Some(install_path) = link_install::install_path(&output_root) else {
+                        continue;
+                    };
+                    let src = ctx.extract_to.as_deref().unwrap_or(&output_root);
+
+                    link_install::link_install(
+                        src,
+                        &install_path,
+                        game_dir,
+                        ctx.scan,
+                        config.reflink,
+                        &mut summary,
+                    );
+                }
+            }
+            linked_installs.push((game_dir.clone(), summary));
+        }
+    };
+
+    // Watch mode: keep the mirror in sync forever
+    if let Some(interval) = config.watch {
+        // Maps of the followed game server are fetched straight from the maps directory of the first profile
+        let (first_profile, first_ctx) = &runs[0];
+        let first_urls = fastdl_urls(first_profile);
+        let maps_url = first_urls
+            .iter()
+            .find(|url| url.ends_with("maps/"))
+            .unwrap_or(&first_urls[0]);
+
+        if let Some(addr) = config.metrics.clone() {
+            std::thread::spawn(move || {
+                metrics::listen(&addr).expect("Failed to serve the metrics")
+            });
+        }
+
+        // Control interface: pause, resume, status and requeue while syncing
+        if let Some(addr) = config.control.clone() {
+            let token =
+                control::token(&first_ctx.out_dir).expect("Failed to create the control token");
+            let controlled = runs
+                .iter()
+                .map(|(profile, ctx)| control::Controlled {
+                    name: profile.name.clone(),
+                    queue: Arc::clone(&ctx.queue),
+                    store: Arc::clone(&ctx.store),
+                })
+                .collect();
+            std::thread::spawn(move || {
+                control::listen(&addr, &token, controlled)
+                    .expect("Failed to serve the control interface")
+            });
+        }
+
+        // Every fastdl root is probed before a sync, an unreachable one pauses the syncs until it recovers
+        let mut monitor = Monitor::new(config.alert_webhook.clone(), config.service);
+        let all_urls = runs
+            .iter()
+            .flat_map(|(profile, _)| fastdl_urls(profile))
+            .collect::<Vec<_>>();
+
+        loop {
+            // Outside of the sync windows (or during quiet hours) the schedule is checked again every minute
+            let wait = if !monitor.check(&all_urls) {
+                ui::set_stage("upstream down");
+                config.monitor_interval
+            } else if config.schedule.allows(state_store::unix_now()) {
+                print_console_gui();
+                sync_all();
+                link_all();
+
+                if config.service {
+                    service::log(&format!(
+                        "Sync finished: {}, {} files downloaded, {} decode failures, {}",
+                        crawl_stats.summary(),
+                        METRICS.files_downloaded.load(Ordering::Relaxed),
+                        METRICS.decode_failures.load(Ordering::Relaxed),
+                        bandwidth::summary(run_bandwidth(&runs), total_bandwidth(&runs)),
+                    ));
+                }
+                interval
+            } else {
+                ui::set_stage("waiting");
+                60
+            };
+
+            // Poll the followed game server until the next full sync is due
+            let next_sync = Instant::now() + Duration::from_secs(wait);
+            while let Some(remaining) = next_sync.checked_duration_since(Instant::now()) {
+                if let Some(addr) = config.follow.as_ref().filter(|_| monitor.is_up()) {
+                    follow::fetch_missing_maps(addr, maps_url, first_ctx, &corrupt_files);
+                }
+
+                std::thread::sleep(remaining.min(Duration::from_secs(config.follow_interval * 60)));
+            }
+        }
+    }
+
+    sync_all();
+    link_all();
+
+    // Human-readable report of every synced map
+    if let Some(report_path) = &config.report {
+        let files = runs
+            .iter()
+            .flat_map(|(_, ctx)| ctx.store.files())
+            .collect::<Vec<_>>();
+        let rows = report::collect_rows(&files, config.map_db.as_deref());
+        report::write_report(report_path, &rows).expect("Failed to write the map report");
+    }
+
+    // Static page of every synced map with its overview image and description, for community sites
+    if let Some(browser_dir) = &config.map_browser {
+        let files = runs
+            .iter()
+            .flat_map(|(_, ctx)| ctx.store.files())
+            .collect::<Vec<_>>();
+        let maps = map_browser::build(browser_dir, &files, config.map_db.as_deref())
+            .expect("Failed to write the map browser");
+        println!(
+            "Map browser of {} maps written to {}",
+            maps,
+            browser_dir.join("index.html").display()
+        );
+    }
+
+    // "Update maps and join" in one click
+    if config.launch {
+        launch::launch_css(config.connect.as_deref()).expect("Failed to launch CS:S");
+    }
+
+    println!(
+        "{}{}
+        {}URL:\t{:#?}
+        {}Crawl:\t{}
+        {}Protocols:\t{}
+        {}Time:\t{}
+        {}Compression:\t{}
+        {}Bandwidth:\t{}
+        {}{}",
+        // Separator Params
+        ui::goto(0, 23),
+        "=".repeat(25),
+        // URL
+        ui::goto(0, 24),
+        profiles.iter().flat_map(|p| &p.urls).collect::<Vec<_>>(),
+        // Crawl
+        ui::goto(0, 25),
+        crawl_stats.summary(),
+        // Protocols
+        ui::goto(0, 26),
+        transport.summary(),
+        // Time
+        ui::goto(0, 27),
+        timer.elapsed().as_secs_f32(),
+        // Compression
+        ui::goto(0, 28),
+        ratios::summary(),
+        // Bandwidth
+        ui::goto(0, 29),
+        bandwidth::summary(run_bandwidth(&runs), total_bandwidth(&runs)),
         // Separator
-        term_cursor::Goto(0, 26),
+        ui::goto(0, 30),
         "=".repeat(25)
     );
 
+    // Flag blocked files that were already present before the blocklist was configured
+    let blocked_files = runs
+        .iter()
+        .filter_map(|(_, ctx)| Some(ctx.blocklist.as_ref()?.scan_local(&ctx.out_dir, ctx.scan)))
+        .flatten()
+        .map(|file| file.to_string())
+        .collect::<Vec<_>>();
+
+    let policy_skipped = runs
+        .iter()
+        .flat_map(|(_, ctx)| ctx.policy.skipped())
+        .collect::<Vec<_>>();
+    let missing_parts = runs
+        .iter()
+        .flat_map(|(_, ctx)| ctx.missing_parts.lock().unwrap().clone())
+        .collect::<Vec<_>>();
+
     print!(
-        "{}Files that failed to decompress correctly: {:#?}{}",
-        term_cursor::Goto(0, 28),
+        "{}Files that failed to decompress correctly: {:#?}
+Files skipped due to case-insensitive collisions: {:#?}
+Blocked files present locally: {:#?}
+Files skipped by the size and extension rules: {:#?}
+Missing parts of split archives: {:#?}
+Linked game directories: {:#?}{}",
+        ui::goto(0, 31),
         corrupt_files.lock().unwrap(),
-        term_cursor::Goto(0, 35),
+        case_collisions
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|file| file.to_string())
+            .collect::<Vec<_>>(),
+        blocked_files,
+        policy_skipped,
+        missing_parts,
+        linked_installs.lock().unwrap(),
+        ui::goto(0, 39),
     );
 
-    // User Input to confirm that all maps are downloaded/extracted
-    print!("{}Press Enter to exit...", term_cursor::Goto(0, 42));
-    Write::flush(&mut io::stdout()).expect("Failed to flush the ");
+    // User Input to confirm that all maps are downloaded/extracted (nobody is watching a CI log)
+    if ui::fancy() {
+        print!("{}Press Enter to exit...", term_cursor::Goto(0, 43));
+        Write::flush(&mut io::stdout()).expect("Failed to flush the ");
 
-    stdin().read(&mut [0]).unwrap();
+        let _ = stdin().read(&mut [0]);
+    }
 
     // for corr_f in corrupt_files.lock().unwrap().iter() {
     // println!("{}", corr_f);
@@ -0,0 +1,223 @@
+use crate::{
+    changes::escape,
+    classify::{self, FileKind},
+    net, report,
+    state_store::ManifestEntry,
+    MB_SIZE,
+};
+use rayon::prelude::*;
+use select::{
+    document::Document,
+    predicate::{Attr, Name, Predicate},
+};
+use std::{fs, io, path::Path};
+
+/// Directory of the browser the thumbnails are copied to
+const THUMBS_DIR: &str = "thumbs";
+/// Extensions of the overview images fastdls serve next to the maps
+const IMAGE_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+/// Characters of a description shown on a card
+const MAX_DESCRIPTION_LEN: usize = 600;
+
+/// MapCard is a single map in the browser
+struct MapCard {
+    /// Map name without any extension (e.g. "ze_example_v1")
+    name: String,
+    /// Downloaded size in bytes
+    size: u64,
+    /// Source of the overview image, relative to the browser or absolute (from a map database)
+    image: Option<String>,
+    /// Description of the map
+    description: Option<String>,
+}
+
+/// Returns true if `bytes` is a JPEG or PNG image (error pages are often served with a 200)
+fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xff, 0xd8, 0xff]) || bytes.starts_with(b"\x89PNG")
+}
+
+/// Returns `bytes` fetched from `url`, or None if the request failed or was not answered with a 200
+fn fetch(url: &str) -> Option<Vec<u8>> {
+    let resp = net::get(url).ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+
+    resp.bytes().ok().map(|bytes| bytes.to_vec())
+}
+
+/// Returns the overview image of `map`: the local copy next to the map, otherwise the one the fastdl serves
+/// The image is copied to the thumbnail directory of the browser, the returned source is relative to it
+///
+/// # Arguments
+/// * `map`         -   Map name
+/// * `local_dir`   -   Directory the map was downloaded to
+/// * `dir_url`     -   Url of the directory the map is served from
+/// * `thumbs_dir`  -   Thumbnail directory of the browser
+fn find_image(
+    map: &str,
+    local_dir: Option<&Path>,
+    dir_url: &str,
+    thumbs_dir: &Path,
+) -> Option<String> {
+    for ext in IMAGE_EXTENSIONS {
+        let file_name = format!("{}.{}", map, ext);
+        let bytes = local_dir
+            .and_then(|dir| fs::read(dir.join(&file_name)).ok())
+            .filter(|bytes| is_image(bytes))
+            .or_else(|| {
+                fetch(&format!("{}{}", dir_url, file_name)).filter(|bytes| is_image(bytes))
+            });
+
+        if let Some(bytes) = bytes {
+            fs::write(thumbs_dir.join(&file_name), bytes).ok()?;
+            return Some(format!("{}/{}", THUMBS_DIR, file_name));
+        }
+    }
+
+    None
+}
+
+/// Returns the description of `map` from the `.txt` next to it, locally or on the fastdl
+///
+/// # Arguments
+/// * `map`         -   Map name
+/// * `local_dir`   -   Directory the map was downloaded to
+/// * `dir_url`     -   Url of the directory the map is served from
+fn find_description(map: &str, local_dir: Option<&Path>, dir_url: &str) -> Option<String> {
+    let file_name = format!("{}.txt", map);
+    let bytes = local_dir
+        .and_then(|dir| fs::read(dir.join(&file_name)).ok())
+        .or_else(|| fetch(&format!("{}{}", dir_url, file_name)))
+        .filter(|bytes| classify::sniff(bytes) != FileKind::Html)?;
+
+    let text = String::from_utf8_lossy(&bytes).trim().to_string();
+    (!text.is_empty()).then_some(text)
+}
+
+/// Returns the `og:image` and `og:description` of the map database page of `map`
+///
+/// # Arguments
+/// * `map_db`  -   Url template of the page (`{map}` is replaced by the map name)
+/// * `map`     -   Map name
+fn scrape_map_db(map_db: &str, map: &str) -> (Option<String>, Option<String>) {
+    let Some(page) = net::get(&map_db.replace("{map}", map))
+        .ok()
+        .filter(|resp| resp.status().is_success())
+        .and_then(|resp| resp.text().ok())
+    else {
+        return (None, None);
+    };
+
+    let document = Document::from(page.as_str());
+    let meta = |property: &str| {
+        document
+            .find(Name("meta").and(Attr("property", property)))
+            .find_map(|node| node.attr("content"))
+            .map(|content| content.trim().to_string())
+            .filter(|content| !content.is_empty())
+    };
+
+    (meta("og:image"), meta("og:description"))
+}
+
+/// Returns the card of the map downloaded from `url`, or None if the file is not a map
+///
+/// # Arguments
+/// * `url`         -   Download link of the file
+/// * `entry`       -   Manifest entry of the file
+/// * `map_db`      -   Url template of a community map database page
+/// * `thumbs_dir`  -   Thumbnail directory of the browser
+fn card(
+    url: &str,
+    entry: &ManifestEntry,
+    map_db: Option<&str>,
+    thumbs_dir: &Path,
+) -> Option<MapCard> {
+    let name = report::map_name(url)?;
+    let dir_url = &url[..url.rfind('/')? + 1];
+    let local_dir = entry.path.as_deref().and_then(Path::parent);
+
+    let mut image = find_image(&name, local_dir, dir_url, thumbs_dir);
+    let mut description = find_description(&name, local_dir, dir_url);
+
+    // The map database only fills in what the fastdl does not have
+    if let Some(map_db) = map_db.filter(|_| image.is_none() || description.is_none()) {
+        let (db_image, db_description) = scrape_map_db(map_db, &name);
+        image = image.or(db_image);
+        description = description.or(db_description);
+    }
+
+    Some(MapCard {
+        name,
+        size: entry.size,
+        image,
+        description: description.map(|text| text.chars().take(MAX_DESCRIPTION_LEN).collect()),
+    })
+}
+
+/// Builds a static HTML map browser of every synced map in `dir` (`index.html` and the thumbnails)
+/// Returns the number of maps in the browser
+///
+/// # Arguments
+/// * `dir`     -   Directory of the browser
+/// * `files`   -   Every downloaded file in the manifests
+/// * `map_db`  -   Url template of a community map database page (`{map}` is replaced by the map name)
+pub fn build(
+    dir: &Path,
+    files: &[(String, ManifestEntry)],
+    map_db: Option<&str>,
+) -> io::Result<usize> {
+    let thumbs_dir = dir.join(THUMBS_DIR);
+    fs::create_dir_all(&thumbs_dir)?;
+
+    let mut cards = files
+        .par_iter()
+        .filter_map(|(url, entry)| card(url, entry, map_db, &thumbs_dir))
+        .collect::<Vec<_>>();
+    cards.sort_by(|a, b| a.name.cmp(&b.name));
+    cards.dedup_by(|a, b| a.name == b.name);
+
+    let mut html = String::from(
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Map Browser</title>\n<style>\
+         body{font-family:sans-serif;background:#16181d;color:#ddd;margin:2em}\
+         #search{padding:.5em;width:20em;margin-bottom:1em}\
+         .maps{display:grid;grid-template-columns:repeat(auto-fill,minmax(260px,1fr));gap:1em}\
+         .map{background:#22252c;border-radius:6px;overflow:hidden}\
+         .map img{width:100%;aspect-ratio:16/9;object-fit:cover;background:#000}\
+         .map h2{font-size:1em;margin:.5em}.map p{font-size:.85em;margin:.5em;color:#aaa}\
+         </style></head><body>\n<h1>Map Browser</h1>\n\
+         <input id=\"search\" placeholder=\"Search maps\" oninput=\"for(const m of document.querySelectorAll('.map'))\
+         m.hidden=!m.dataset.name.includes(this.value.toLowerCase())\">\n<div class=\"maps\">\n",
+    );
+
+    for card in &cards {
+        html.push_str(&format!(
+            "<div class=\"map\" data-name=\"{}\">",
+            escape(&card.name.to_lowercase())
+        ));
+        if let Some(image) = &card.image {
+            html.push_str(&format!(
+                "<img src=\"{}\" alt=\"\" loading=\"lazy\">",
+                escape(image)
+            ));
+        }
+        html.push_str(&format!(
+            "<h2>{}</h2><p>{:.2} MB</p>",
+            escape(&card.name),
+            card.size as f32 / MB_SIZE as f32
+        ));
+        if let Some(description) = &card.description {
+            html.push_str(&format!("<p>{}</p>", escape(description)));
+        }
+        html.push_str("</div>\n");
+    }
+
+    html.push_str(&format!(
+        "</div>\n<p>{} maps</p>\n</body></html>\n",
+        cards.len()
+    ));
+    fs::write(dir.join("index.html"), html)?;
+
+    Ok(cards.len())
+}
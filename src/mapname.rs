@@ -0,0 +1,302 @@
+//! Heuristic parser for Source map names
+//!
+//! A map name is split into its family, its version and a suffix, e.g.
+//!
+//! | Map                                  | Family                      | Version  | Suffix  |
+//! | ------------------------------------ | --------------------------- | -------- | ------- |
+//! | `ze_ffvii_mako_reactor_v5_3`         | `ze_ffvii_mako_reactor`     | `5.3`    |         |
+//! | `ze_paranoid_rezurrection_v11_9`     | `ze_paranoid_rezurrection`  | `11.9`   |         |
+//! | `ze_lotr_minas_tirith_v3_5`          | `ze_lotr_minas_tirith`      | `3.5`    |         |
+//! | `ze_tilex_ultimate_v2_13s`           | `ze_tilex_ultimate`         | `2.13s`  |         |
+//! | `ze_pirates_port_royal_v3_6`         | `ze_pirates_port_royal`     | `3.6`    |         |
+//! | `ze_predator_ultimate_v3`            | `ze_predator_ultimate`      | `3`      |         |
+//! | `ze_mist_v1_3_fix`                   | `ze_mist`                   | `1.3`    | `fix`   |
+//! | `ze_sandstorm_f1`                    | `ze_sandstorm_f1`           |          |         |
+//! | `ze_shroomforest2`                   | `ze_shroomforest2`          |          |         |
+//! | `ze_dark_souls_ptd_v0_4_b2`          | `ze_dark_souls_ptd`         | `0.4`    | `b2`    |
+//! | `ze_frostdrake_tower_v1s`            | `ze_frostdrake_tower`       | `1s`     |         |
+//! | `ze_minecraft_universe_v2_1_final`   | `ze_minecraft_universe`     | `2.1`    | `final` |
+//!
+//! The prefix and at least one word of the name always stay in the family, so `ze_2012_v2`
+//! is version `2` of `ze_2012` rather than a map called `ze`
+
+use regex::Regex;
+use std::{fmt, sync::OnceLock};
+
+/// Suffix words that mark a new release of the same map (e.g. "ze_foo_v3_fix")
+pub const VERSION_WORDS: &[&str] = &["fix", "fixed", "final", "test", "beta", "patch", "hotfix"];
+
+/// Version numbers ("v5", "3", "13s"), compiled once
+static VERSION_NUMBER: OnceLock<Regex> = OnceLock::new();
+/// Release tags ("b2", "p1", "t3", "rc1"), compiled once
+static RELEASE_TAG: OnceLock<Regex> = OnceLock::new();
+/// Digit runs of a version, compiled once
+static DIGITS: OnceLock<Regex> = OnceLock::new();
+
+/// MapName is a map name split into its family, version and suffix
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MapName {
+    /// Lowercased name shared by every version of the map (e.g. "ze_ffvii_mako_reactor")
+    pub family: String,
+    /// Version numbers separated by "." (e.g. "5.3"), None if the name has no version
+    pub version: Option<String>,
+    /// Release words and tags after the version, separated by "_" (e.g. "fix", "b2")
+    pub suffix: Option<String>,
+}
+
+/// Returns true if `token` is one of the version words (optionally followed by a number, e.g. "fix2")
+fn is_version_word(token: &str) -> bool {
+    VERSION_WORDS.iter().any(|word| {
+        token
+            .strip_prefix(word)
+            .is_some_and(|n| n.chars().all(|c| c.is_ascii_digit()))
+    })
+}
+
+/// Returns true if `token` is a version number ("v5", "3", "13s")
+fn is_version_number(token: &str) -> bool {
+    VERSION_NUMBER
+        .get_or_init(|| Regex::new(r"^v?\d+[a-z]?$").unwrap())
+        .is_match(token)
+}
+
+/// Returns true if `token` is a release tag ("b2", "p1", "t3", "rc1")
+fn is_release_tag(token: &str) -> bool {
+    RELEASE_TAG
+        .get_or_init(|| Regex::new(r"^([bpt]\d+|rc\d*)$").unwrap())
+        .is_match(token)
+}
+
+/// Returns true if `token` of a lowercased map name looks like part of a version suffix
+fn is_version_token(token: &str) -> bool {
+    is_version_number(token) || is_release_tag(token) || is_version_word(token)
+}
+
+/// Parses a map name (extensions are optional)
+///
+/// # Arguments
+/// * `map`     -   Map name or file name (e.g. "ze_ffvii_mako_reactor_v5_3.bsp.bz2")
+pub fn parse(map: &str) -> MapName {
+    let map = map.to_lowercase();
+    let map = map.strip_suffix(".bz2").unwrap_or(&map);
+    let map = map.strip_suffix(".bsp").unwrap_or(map);
+    let tokens = map.split('_').collect::<Vec<_>>();
+
+    // The version is the run of version tokens at the end, in a single pass from the back.
+    // Keep the prefix ("ze") and at least one word of the name in the family
+    let trailing = tokens
+        .iter()
+        .rev()
+        .take_while(|t| is_version_token(t))
+        .count();
+    let start = (tokens.len() - trailing).max(tokens.len().min(2));
+
+    let (numbers, suffix): (Vec<&str>, Vec<&str>) =
+        tokens[start..].iter().partition(|t| is_version_number(t));
+    let join = |parts: Vec<&str>, sep| (!parts.is_empty()).then(|| parts.join(sep));
+
+    MapName {
+        family: tokens[..start].join("_"),
+        version: join(
+            numbers
+                .into_iter()
+                .map(|n| n.strip_prefix('v').unwrap_or(n))
+                .collect(),
+            ".",
+        ),
+        suffix: join(suffix, "_"),
+    }
+}
+
+impl MapName {
+    /// Returns every number of the version and the release tags, in order (e.g. "3.2" + "b1" -> [3, 2, 1])
+    pub fn numbers(&self) -> Vec<u32> {
+        let digits = DIGITS.get_or_init(|| Regex::new(r"\d+").unwrap());
+        let tags = self
+            .suffix
+            .iter()
+            .flat_map(|suffix| suffix.split('_'))
+            .filter(|t| is_release_tag(t));
+
+        self.version
+            .iter()
+            .flat_map(|version| version.split('.'))
+            .chain(tags)
+            .flat_map(|part| {
+                digits
+                    .find_iter(part)
+                    .filter_map(|d| d.as_str().parse().ok())
+                    .collect::<Vec<_>>()
+            })
+            .collect()
+    }
+
+    /// Returns the number of release words of the suffix (a "_fix" comes after the release it fixes)
+    pub fn words(&self) -> usize {
+        self.suffix
+            .iter()
+            .flat_map(|suffix| suffix.split('_'))
+            .filter(|t| is_version_word(t))
+            .count()
+    }
+}
+
+impl fmt::Display for MapName {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.family)?;
+        if let Some(version) = &self.version {
+            write!(f, " v{}", version)?;
+        }
+        if let Some(suffix) = &self.suffix {
+            write!(f, " ({})", suffix)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Map names of ZE servers with their family, version and suffix
+    const CORPUS: &[(&str, &str, Option<&str>, Option<&str>)] = &[
+        (
+            "ze_ffvii_mako_reactor_v5_3",
+            "ze_ffvii_mako_reactor",
+            Some("5.3"),
+            None,
+        ),
+        (
+            "ze_paranoid_rezurrection_v11_9",
+            "ze_paranoid_rezurrection",
+            Some("11.9"),
+            None,
+        ),
+        (
+            "ze_lotr_minas_tirith_v3_5",
+            "ze_lotr_minas_tirith",
+            Some("3.5"),
+            None,
+        ),
+        (
+            "ze_tilex_ultimate_v2_13s",
+            "ze_tilex_ultimate",
+            Some("2.13s"),
+            None,
+        ),
+        (
+            "ze_pirates_port_royal_v3_6",
+            "ze_pirates_port_royal",
+            Some("3.6"),
+            None,
+        ),
+        (
+            "ze_predator_ultimate_v3",
+            "ze_predator_ultimate",
+            Some("3"),
+            None,
+        ),
+        ("ze_mist_v1_3_fix", "ze_mist", Some("1.3"), Some("fix")),
+        ("ze_sandstorm_f1", "ze_sandstorm_f1", None, None),
+        ("ze_shroomforest2", "ze_shroomforest2", None, None),
+        (
+            "ze_dark_souls_ptd_v0_4_b2",
+            "ze_dark_souls_ptd",
+            Some("0.4"),
+            Some("b2"),
+        ),
+        (
+            "ze_frostdrake_tower_v1s",
+            "ze_frostdrake_tower",
+            Some("1s"),
+            None,
+        ),
+        (
+            "ze_minecraft_universe_v2_1_final",
+            "ze_minecraft_universe",
+            Some("2.1"),
+            Some("final"),
+        ),
+        ("ze_2012_v2", "ze_2012", Some("2"), None),
+        ("ze_grau_s2", "ze_grau_s2", None, None),
+        ("ze_atix_panic_b7", "ze_atix_panic", None, Some("b7")),
+        (
+            "ze_mountain_escape_v5_zy",
+            "ze_mountain_escape_v5_zy",
+            None,
+            None,
+        ),
+        ("ze_gods_wrath_v3_8b", "ze_gods_wrath", Some("3.8b"), None),
+        ("ze_journey_v1_2", "ze_journey", Some("1.2"), None),
+        ("ze_hsc_a4_5", "ze_hsc_a4", Some("5"), None),
+        (
+            "ze_halloween_house_b4s",
+            "ze_halloween_house_b4s",
+            None,
+            None,
+        ),
+        (
+            "ze_sorrento_escape_v5",
+            "ze_sorrento_escape",
+            Some("5"),
+            None,
+        ),
+        ("ze_doom3_v1", "ze_doom3", Some("1"), None),
+        ("ze_rizomata_s1_3", "ze_rizomata_s1", Some("3"), None),
+        ("ze_italy_town_v3", "ze_italy_town", Some("3"), None),
+        ("ze_bioshock_v6_3", "ze_bioshock", Some("6.3"), None),
+        ("ze_v0u0v_b4_p2", "ze_v0u0v", None, Some("b4_p2")),
+        (
+            "ze_ffxii_westersand_v8zeta1",
+            "ze_ffxii_westersand_v8zeta1",
+            None,
+            None,
+        ),
+        (
+            "ze_ashen_keep_v0_3_hotfix2",
+            "ze_ashen_keep",
+            Some("0.3"),
+            Some("hotfix2"),
+        ),
+        ("ze_diddle_v3", "ze_diddle", Some("3"), None),
+        ("ze", "ze", None, None),
+        ("ze_v1", "ze_v1", None, None),
+    ];
+
+    #[test]
+    fn parses_the_corpus() {
+        for &(map, family, version, suffix) in CORPUS {
+            let expected = MapName {
+                family: family.to_string(),
+                version: version.map(str::to_string),
+                suffix: suffix.map(str::to_string),
+            };
+            assert_eq!(parse(map), expected, "{}", map);
+        }
+    }
+
+    #[test]
+    fn ignores_case_and_extensions() {
+        assert_eq!(
+            parse("ZE_FFVII_Mako_Reactor_V5_3.bsp.bz2"),
+            parse("ze_ffvii_mako_reactor_v5_3")
+        );
+    }
+
+    #[test]
+    fn orders_releases() {
+        assert_eq!(parse("ze_dark_souls_ptd_v0_4_b2").numbers(), vec![0, 4, 2]);
+        assert_eq!(parse("ze_mist_v1_3_fix").words(), 1);
+        assert_eq!(parse("ze_mist_v1_3").words(), 0);
+    }
+
+    #[test]
+    fn long_names_are_linear() {
+        // Used to be quadratic in the number of tokens
+        let map = format!("ze_{}", vec!["v1"; 100_000].join("_"));
+        let name = parse(&map);
+        assert_eq!(name.family, "ze_v1");
+        assert_eq!(name.version.map(|v| v.len()), Some(2 * 99_999 - 1));
+    }
+}
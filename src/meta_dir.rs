@@ -0,0 +1,46 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Hidden directory of an output directory holding every state file (manifest, queue, crawl, journal, lock, snapshots)
+/// Keeping them together makes a mirror self-describing: moving the output directory moves its state along
+pub const META_DIR: &str = ".fastdl";
+
+/// Returns the path of the state file or directory `name` of the output directory `out_dir`
+/// The metadata directory is created if needed, and a file of that name left at the top of `out_dir`
+/// by an older release is moved into it, so existing mirrors keep their state
+///
+/// # Arguments
+/// * `out_dir` -   Output directory
+/// * `name`    -   Name of the state file or directory (e.g. "fastdl_manifest.json")
+pub fn path(out_dir: &Path, name: &str) -> PathBuf {
+    let meta_dir = out_dir.join(META_DIR);
+    let path = meta_dir.join(name);
+
+    let legacy = out_dir.join(name);
+    if !path.exists() && legacy.exists() {
+        let moved = fs::create_dir_all(&meta_dir).and_then(|_| fs::rename(&legacy, &path));
+        if let Err(err) = moved {
+            eprintln!(
+                "Failed to move {} into {}: {}",
+                legacy.display(),
+                meta_dir.display(),
+                err
+            );
+            return legacy;
+        }
+
+        // The sqlite journal files belong to the database they were written next to
+        for suffix in ["-wal", "-shm"] {
+            let journal = out_dir.join(format!("{}{}", name, suffix));
+            if journal.exists() {
+                let _ = fs::rename(&journal, meta_dir.join(format!("{}{}", name, suffix)));
+            }
+        }
+    } else {
+        let _ = fs::create_dir_all(&meta_dir);
+    }
+
+    path
+}
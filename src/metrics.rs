@@ -0,0 +1,142 @@
+use crate::serve;
+use std::{
+    io,
+    net::TcpListener,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+/// Metrics keeps the counters exposed on the Prometheus `/metrics` endpoint
+pub struct Metrics {
+    /// Number of syncs that finished
+    pub syncs: AtomicU64,
+    /// Unix time of the last sync that finished
+    pub last_sync: AtomicU64,
+    /// Number of files downloaded
+    pub files_downloaded: AtomicU64,
+    /// Number of bytes downloaded
+    pub bytes_downloaded: AtomicU64,
+    /// Number of files that failed to decode
+    pub decode_failures: AtomicU64,
+    /// Number of HTTP requests answered in serve mode
+    pub requests_served: AtomicU64,
+    /// Number of file bytes sent in serve mode
+    pub bytes_served: AtomicU64,
+}
+
+/// Counters shared by every stage of the program
+pub static METRICS: Metrics = Metrics::new();
+
+impl Metrics {
+    /// Returns a Metrics object with every counter set to 0
+    pub const fn new() -> Self {
+        Self {
+            syncs: AtomicU64::new(0),
+            last_sync: AtomicU64::new(0),
+            files_downloaded: AtomicU64::new(0),
+            bytes_downloaded: AtomicU64::new(0),
+            decode_failures: AtomicU64::new(0),
+            requests_served: AtomicU64::new(0),
+            bytes_served: AtomicU64::new(0),
+        }
+    }
+
+    /// Adds `n` to `counter`
+    ///
+    /// # Arguments
+    /// * `counter` -   Counter that is incremented
+    /// * `n`       -   Amount added to the counter
+    pub fn add(counter: &AtomicU64, n: u64) {
+        counter.fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Returns every counter in the Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let counters = [
+            (
+                "fastdl_syncs_total",
+                "counter",
+                "Number of syncs that finished",
+                &self.syncs,
+            ),
+            (
+                "fastdl_last_sync_timestamp_seconds",
+                "gauge",
+                "Unix time of the last sync that finished",
+                &self.last_sync,
+            ),
+            (
+                "fastdl_files_downloaded_total",
+                "counter",
+                "Number of files downloaded",
+                &self.files_downloaded,
+            ),
+            (
+                "fastdl_bytes_downloaded_total",
+                "counter",
+                "Number of bytes downloaded",
+                &self.bytes_downloaded,
+            ),
+            (
+                "fastdl_decode_failures_total",
+                "counter",
+                "Number of files that failed to decode",
+                &self.decode_failures,
+            ),
+            (
+                "fastdl_http_requests_total",
+                "counter",
+                "Number of HTTP requests answered in serve mode",
+                &self.requests_served,
+            ),
+            (
+                "fastdl_http_bytes_served_total",
+                "counter",
+                "Number of file bytes sent in serve mode",
+                &self.bytes_served,
+            ),
+        ];
+
+        counters
+            .iter()
+            .map(|(name, kind, help, value)| {
+                format!(
+                    "# HELP {name} {help}\n# TYPE {name} {kind}\n{name} {}\n",
+                    value.load(Ordering::Relaxed)
+                )
+            })
+            .collect()
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Answers every request on `addr` with the metrics (used in watch mode)
+///
+/// # Arguments
+/// * `addr`    -   Address to listen on (e.g. "127.0.0.1:9100")
+pub fn listen(addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    // Connections are answered one at a time, read_request gives up on idle and endless requests
+    // and the write timeout on clients that never read the answer
+    for mut stream in listener.incoming().flatten() {
+        // Scrapers only ever ask for the metrics, so the request itself does not matter
+        if stream.set_write_timeout(Some(serve::READ_TIMEOUT)).is_ok()
+            && serve::read_request(&mut stream).is_ok()
+        {
+            let _ = serve::respond(
+                &mut stream,
+                "200 OK",
+                "text/plain; version=0.0.4",
+                METRICS.render().as_bytes(),
+                false,
+            );
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+use crate::blocklist::sha256_hex;
+use crate::scan::{self, ScanPolicy};
+use crate::MB_SIZE;
+use rayon::prelude::*;
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Number of files listed in the largest files section
+const LARGEST_LEN: usize = 10;
+
+/// Totals of one category or extension
+#[derive(Debug, Default)]
+pub struct Totals {
+    /// Number of files
+    pub count: usize,
+    /// Size of all the files in bytes
+    pub bytes: u64,
+}
+
+impl Totals {
+    /// Counts one more file of `size` bytes
+    fn add(&mut self, size: u64) {
+        self.count += 1;
+        self.bytes += size;
+    }
+}
+
+/// MirrorStats is a breakdown of the files of a local mirror
+#[derive(Debug, Default)]
+pub struct MirrorStats {
+    /// Category (maps, sounds, ...) -> totals
+    pub categories: BTreeMap<&'static str, Totals>,
+    /// Extension -> totals
+    pub extensions: BTreeMap<String, Totals>,
+    /// Largest files with their size, largest first
+    pub largest: Vec<(PathBuf, u64)>,
+    /// Groups of files with identical content
+    pub duplicates: Vec<Vec<PathBuf>>,
+}
+
+/// Returns the category of a file from the directory it's in, or from its extension if the directory says nothing
+///
+/// # Arguments
+/// * `path`    -   Path of the file
+pub fn category(path: &Path) -> &'static str {
+    let in_dir = |name: &str| {
+        path.components().any(|c| {
+            c.as_os_str()
+                .to_str()
+                .is_some_and(|s| s.eq_ignore_ascii_case(name))
+        })
+    };
+
+    if in_dir("maps") {
+        return "maps";
+    } else if in_dir("sound") {
+        return "sounds";
+    } else if in_dir("materials") {
+        return "materials";
+    } else if in_dir("models") {
+        return "models";
+    }
+
+    match extension(path).as_str() {
+        "bsp" | "nav" | "ain" => "maps",
+        "wav" | "mp3" => "sounds",
+        "vmt" | "vtf" => "materials",
+        "mdl" | "vvd" | "vtx" | "phy" => "models",
+        _ => "other",
+    }
+}
+
+/// Returns the lowercase extension of a file, ignoring a trailing ".bz2" (e.g. "bsp" for "ze_a.bsp.bz2")
+///
+/// # Arguments
+/// * `path`    -   Path of the file
+fn extension(path: &Path) -> String {
+    let name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let name = name.strip_suffix(".bz2").unwrap_or(&name);
+
+    Path::new(name)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_string()
+}
+
+/// Returns the statistics of every file below `root`
+/// Only files that share their size are hashed to find the duplicates
+///
+/// # Arguments
+/// * `root`    -   Directory of the local mirror
+/// * `policy`  -   Which symlinks and hidden directories are walked into
+pub fn collect(root: &Path, policy: ScanPolicy) -> MirrorStats {
+    let files = scan::find_files(root, policy, |_| true)
+        .into_par_iter()
+        .filter_map(|path| fs::metadata(&path).ok().map(|m| (path, m.len())))
+        .collect::<Vec<_>>();
+
+    let mut stats = MirrorStats::default();
+    let mut by_size = HashMap::<u64, Vec<PathBuf>>::new();
+
+    for (path, size) in &files {
+        stats
+            .categories
+            .entry(category(path))
+            .or_default()
+            .add(*size);
+        stats
+            .extensions
+            .entry(extension(path))
+            .or_default()
+            .add(*size);
+        by_size.entry(*size).or_default().push(path.clone());
+    }
+
+    stats.largest = files;
+    stats
+        .largest
+        .sort_by_key(|(_, size)| std::cmp::Reverse(*size));
+    stats.largest.truncate(LARGEST_LEN);
+
+    // Empty files are all "identical", which says nothing useful
+    let mut by_hash = by_size
+        .into_iter()
+        .filter(|(size, paths)| *size > 0 && paths.len() > 1)
+        .flat_map(|(_, paths)| paths)
+        .par_bridge()
+        .filter_map(|path| fs::read(&path).ok().map(|bytes| (sha256_hex(&bytes), path)))
+        .fold(
+            HashMap::<String, Vec<PathBuf>>::new,
+            |mut groups, (hash, path)| {
+                groups.entry(hash).or_default().push(path);
+                groups
+            },
+        )
+        .reduce(HashMap::new, |mut a, b| {
+            b.into_iter()
+                .for_each(|(hash, paths)| a.entry(hash).or_default().extend(paths));
+            a
+        });
+
+    stats.duplicates = by_hash
+        .drain()
+        .map(|(_, mut paths)| {
+            paths.sort();
+            paths
+        })
+        .filter(|paths| paths.len() > 1)
+        .collect();
+    stats.duplicates.sort();
+
+    stats
+}
+
+/// Prints the statistics as plain tables
+///
+/// # Arguments
+/// * `stats`   -   Statistics returned by `collect`
+pub fn print(stats: &MirrorStats) {
+    let mb = |bytes: u64| bytes as f32 / MB_SIZE as f32;
+
+    println!("Category\tFiles\tSize");
+    for (name, totals) in &stats.categories {
+        println!("{}\t{}\t{:.2} MB", name, totals.count, mb(totals.bytes));
+    }
+
+    println!("\nExtension\tFiles\tSize");
+    for (ext, totals) in &stats.extensions {
+        let ext = if ext.is_empty() { "(none)" } else { ext };
+        println!("{}\t\t{}\t{:.2} MB", ext, totals.count, mb(totals.bytes));
+    }
+
+    println!("\nLargest Files");
+    for (path, size) in &stats.largest {
+        println!("{:.2} MB\t{}", mb(*size), path.display());
+    }
+
+    println!("\nDuplicate Files");
+    for paths in &stats.duplicates {
+        let size = fs::metadata(&paths[0]).map_or(0, |m| m.len());
+        let wasted = size * (paths.len() as u64 - 1);
+
+        println!("{} copies, {:.2} MB wasted", paths.len(), mb(wasted));
+        paths
+            .iter()
+            .for_each(|path| println!("\t{}", path.display()));
+    }
+}
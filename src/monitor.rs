@@ -0,0 +1,117 @@
+use crate::{
+    events::{self, Event},
+    net, service,
+    state_store::unix_now,
+};
+use reqwest::header::CONTENT_TYPE;
+use serde_json::json;
+use std::collections::HashMap;
+
+/// Default seconds between two probes of an unreachable fastdl in watch mode
+pub const DEFAULT_INTERVAL: u64 = 60;
+
+/// Returns an error describing why the fastdl root at `url` is unavailable
+/// Server errors and refused requests count as unavailable, like a connection that fails
+///
+/// # Arguments
+/// * `url`     -   Fastdl url that is probed
+fn probe(url: &str) -> Result<(), String> {
+    let resp = net::client()
+        .get(url)
+        .send()
+        .map_err(|err| err.to_string())?;
+
+    if resp.status().is_success() {
+        Ok(())
+    } else {
+        Err(format!("HTTP {}", resp.status()))
+    }
+}
+
+/// Monitor probes the fastdl roots between two watch mode syncs and alerts once when one goes down
+/// and once when it's back, so an outage pauses the syncs instead of flooding the logs with failed downloads
+pub struct Monitor {
+    /// Url alerts are posted to as JSON (`--alert-webhook <url>`)
+    webhook: Option<String>,
+    /// Alerts also go to the native log of the platform (running as a service)
+    service: bool,
+    /// Fastdl url -> unix time it went down
+    down: HashMap<String, u64>,
+}
+
+impl Monitor {
+    /// Returns a Monitor that considers every fastdl available
+    ///
+    /// # Arguments
+    /// * `webhook` -   Url alerts are posted to (`--alert-webhook <url>`)
+    /// * `service` -   Alerts also go to the native log of the platform
+    pub fn new(webhook: Option<String>, service: bool) -> Self {
+        Self {
+            webhook,
+            service,
+            down: HashMap::new(),
+        }
+    }
+
+    /// Probes every fastdl root, alerting about the ones that went down or came back
+    /// Returns true if every one of them is available
+    ///
+    /// # Arguments
+    /// * `urls`    -   Fastdl urls that are synced
+    pub fn check(&mut self, urls: &[&str]) -> bool {
+        for url in urls.iter().copied() {
+            match (probe(url), self.down.get(url).copied()) {
+                (Err(reason), None) => {
+                    self.down.insert(url.to_string(), unix_now());
+                    events::emit(&Event::UpstreamDown {
+                        url,
+                        reason: &reason,
+                    });
+                    self.alert(&format!(
+                        "{} is unavailable ({}), syncs are paused until it recovers",
+                        url, reason
+                    ));
+                }
+                (Ok(()), Some(since)) => {
+                    self.down.remove(url);
+                    let down_secs = unix_now().saturating_sub(since);
+                    events::emit(&Event::UpstreamRecovered { url, down_secs });
+                    self.alert(&format!(
+                        "{} is available again after {} s, syncs resume",
+                        url, down_secs
+                    ));
+                }
+                _ => {}
+            }
+        }
+
+        self.down.is_empty()
+    }
+
+    /// Returns true if no fastdl was down at the last check
+    pub fn is_up(&self) -> bool {
+        self.down.is_empty()
+    }
+
+    /// Logs `message` and posts it to the webhook
+    /// The body has both `text` (Slack, Mattermost) and `content` (Discord)
+    fn alert(&self, message: &str) {
+        if self.service {
+            service::log(message);
+        } else {
+            eprintln!("{}", message);
+        }
+
+        if let Some(webhook) = &self.webhook {
+            let body = json!({ "text": message, "content": message });
+            let sent = net::client()
+                .post(webhook)
+                .header(CONTENT_TYPE, "application/json")
+                .body(body.to_string())
+                .send();
+            if let Err(err) = sent.and_then(|resp| resp.error_for_status()) {
+                eprintln!("Failed to post the alert to {}: {}", webhook, err);
+            }
+        }
+    }
+}
@@ -0,0 +1,184 @@
+use crate::{fd_limit, ui, MB_SIZE};
+use std::{
+    fs::{self, File},
+    io::{self, Read, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Sender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+/// Number of threads that move files while the decode workers keep decoding
+const MOVE_WORKERS: usize = 2;
+/// Size of the chunks a file is copied in when it's moved to another drive
+const COPY_CHUNK_SIZE: usize = MB_SIZE;
+
+/// Returns true if `err` is a rename that failed because the target is on another drive
+fn is_cross_device(err: &io::Error) -> bool {
+    // EXDEV on unix, ERROR_NOT_SAME_DEVICE on Windows
+    #[cfg(unix)]
+    let code = 18;
+    #[cfg(windows)]
+    let code = 17;
+    #[cfg(not(any(unix, windows)))]
+    let code = -1;
+
+    err.raw_os_error() == Some(code)
+}
+
+/// Moves the file `src` to `dst`, copying it when they're on different drives
+/// A copy goes through a temporary file next to `dst` and reaches the disk before `src` is removed,
+/// so an interruption leaves either the source or the whole moved file
+///
+/// # Arguments
+/// * `src`         -   File that is moved
+/// * `dst`         -   Path it's moved to (its directory is created)
+/// * `on_chunk`    -   Called with the size of every copied chunk (and once with the whole size after a rename)
+pub fn move_file(src: &Path, dst: &Path, on_chunk: &mut dyn FnMut(u64)) -> io::Result<()> {
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    match fs::rename(src, dst) {
+        Ok(()) => {
+            on_chunk(fs::metadata(dst).map_or(0, |meta| meta.len()));
+            return Ok(());
+        }
+        Err(err) if !is_cross_device(&err) => return Err(err),
+        Err(_) => {}
+    }
+
+    let mut tmp_name = dst.file_name().unwrap_or_default().to_os_string();
+    tmp_name.push(".move.tmp");
+    let tmp_path = dst.with_file_name(tmp_name);
+
+    let copied = (|| {
+        let mut input = fd_limit::retry(|| File::open(src))?;
+        let mut output = fd_limit::retry(|| File::create(&tmp_path))?;
+        let mut chunk = vec![0u8; COPY_CHUNK_SIZE];
+        loop {
+            let n = input.read(&mut chunk)?;
+            if n == 0 {
+                break;
+            }
+            output.write_all(&chunk[..n])?;
+            on_chunk(n as u64);
+        }
+
+        // The source is removed next, the copy is the only one left: it's flushed even without --fsync
+        output.sync_all()?;
+        fs::rename(&tmp_path, dst)
+    })();
+    if let Err(err) = copied {
+        let _ = fs::remove_file(&tmp_path);
+        return Err(err);
+    }
+
+    fs::remove_file(src)
+}
+
+/// MoveProgress counts the bytes queued and moved by a Mover
+#[derive(Debug, Default)]
+struct MoveProgress {
+    /// Bytes of every file submitted
+    queued: AtomicU64,
+    /// Bytes moved so far
+    moved: AtomicU64,
+}
+
+impl MoveProgress {
+    /// Prints the moved bytes (does nothing with `--progress plain|none`)
+    fn print(&self) {
+        if !ui::fancy() {
+            return;
+        }
+
+        print!(
+            "{}Moving:\t\t\t{:.2} / {:.2} MB{}",
+            term_cursor::Goto(0, 23),
+            self.moved.load(Ordering::Relaxed) as f32 / MB_SIZE as f32,
+            self.queued.load(Ordering::Relaxed) as f32 / MB_SIZE as f32,
+            " ".repeat(crate::POST_MSG_REPLACE),
+        );
+    }
+}
+
+/// Outcome of a move: the tag it was submitted with, its source, its target and whether it succeeded
+pub type Moved<T> = (T, PathBuf, PathBuf, io::Result<()>);
+
+/// Mover moves files on its own threads, so a slow copy to another drive never holds up a decode worker
+pub struct Mover<T: Send + 'static> {
+    /// Moves waiting for a worker (None once the mover is finishing)
+    jobs: Mutex<Option<Sender<(T, PathBuf, PathBuf)>>>,
+    /// Workers, each returning the outcome of the moves it made
+    workers: Vec<JoinHandle<Vec<Moved<T>>>>,
+    /// Bytes queued and moved
+    progress: Arc<MoveProgress>,
+}
+
+impl<T: Send + 'static> Mover<T> {
+    /// Starts the move workers
+    pub fn start() -> Self {
+        let (sender, receiver) = mpsc::channel::<(T, PathBuf, PathBuf)>();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let progress = Arc::new(MoveProgress::default());
+
+        let workers = (0..MOVE_WORKERS)
+            .map(|_| {
+                let receiver = Arc::clone(&receiver);
+                let progress = Arc::clone(&progress);
+                thread::spawn(move || {
+                    let mut outcomes = Vec::new();
+                    loop {
+                        // The lock is only held while waiting for the next move
+                        let job = receiver.lock().unwrap().recv();
+                        let Ok((tag, src, dst)) = job else {
+                            break;
+                        };
+                        let result = move_file(&src, &dst, &mut |n| {
+                            progress.moved.fetch_add(n, Ordering::Relaxed);
+                            progress.print();
+                        });
+                        outcomes.push((tag, src, dst, result));
+                    }
+                    outcomes
+                })
+            })
+            .collect();
+
+        Self {
+            jobs: Mutex::new(Some(sender)),
+            workers,
+            progress,
+        }
+    }
+
+    /// Queues the move of `src` to `dst`
+    ///
+    /// # Arguments
+    /// * `tag`     -   Returned with the outcome of the move by `finish`
+    /// * `src`     -   File that is moved
+    /// * `dst`     -   Path it's moved to
+    pub fn submit(&self, tag: T, src: PathBuf, dst: PathBuf) {
+        let size = fs::metadata(&src).map_or(0, |meta| meta.len());
+        self.progress.queued.fetch_add(size, Ordering::Relaxed);
+
+        if let Some(jobs) = &*self.jobs.lock().unwrap() {
+            jobs.send((tag, src, dst))
+                .expect("The move workers stopped");
+        }
+    }
+
+    /// Waits for every queued move and returns their outcomes
+    pub fn finish(mut self) -> Vec<Moved<T>> {
+        self.jobs.lock().unwrap().take();
+
+        self.workers
+            .drain(..)
+            .flat_map(|worker| worker.join().expect("A move worker panicked"))
+            .collect()
+    }
+}
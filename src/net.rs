@@ -0,0 +1,146 @@
+use reqwest::blocking::{Client, ClientBuilder, Response};
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::OnceLock,
+    time::Duration,
+};
+
+/// Time a connection may take to open in compatibility mode
+const COMPAT_CONNECT_TIMEOUT: Duration = Duration::from_secs(60);
+/// Time a whole request may take in compatibility mode (instead of the default 30 seconds)
+const COMPAT_REQUEST_TIMEOUT: Duration = Duration::from_secs(600);
+
+/// IpVersion is which address family connections use (`--ip-version <auto|4|6>`)
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum IpVersion {
+    /// Happy eyeballs: the first address family the resolver returns is tried first,
+    /// the other one is raced in after a short delay
+    #[default]
+    Auto,
+    /// Only IPv4 addresses
+    V4,
+    /// Only IPv6 addresses (IPv6-only networks, or a CDN whose IPv4 route is slow)
+    V6,
+}
+
+impl FromStr for IpVersion {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "auto" => Ok(Self::Auto),
+            "4" => Ok(Self::V4),
+            "6" => Ok(Self::V6),
+            _ => Err(format!("Unknown IP version: {} (expected auto, 4 or 6)", s)),
+        }
+    }
+}
+
+impl IpVersion {
+    /// Returns the address connections are bound to, which only leaves the addresses of its family
+    /// to connect to (None lets every address family through)
+    fn local_address(&self) -> Option<IpAddr> {
+        match self {
+            Self::Auto => None,
+            Self::V4 => Some(IpAddr::V4(Ipv4Addr::UNSPECIFIED)),
+            Self::V6 => Some(IpAddr::V6(Ipv6Addr::UNSPECIFIED)),
+        }
+    }
+}
+
+/// NetOptions decides how every client of the program connects
+#[derive(Clone, Debug, Default)]
+pub struct NetOptions {
+    /// Address family of the connections
+    pub ip_version: IpVersion,
+    /// Host -> address used instead of resolving it (`--resolve host:ip`)
+    pub resolve: Vec<(String, IpAddr)>,
+    /// One request at a time over fresh HTTP/1.1 connections with lenient timeouts (`--compat`)
+    pub compat: bool,
+}
+
+/// Options of every client, set once at startup (the defaults if never set)
+static OPTIONS: OnceLock<NetOptions> = OnceLock::new();
+
+/// Sets how every client built afterwards connects
+pub fn init(options: NetOptions) {
+    let _ = OPTIONS.set(options);
+}
+
+/// Returns true if requests are sent one at a time (`--compat`)
+pub fn compat() -> bool {
+    OPTIONS.get().is_some_and(|options| options.compat)
+}
+
+/// Returns a client builder with the connection options applied, for clients that need more settings
+pub fn client_builder() -> ClientBuilder {
+    let options = OPTIONS.get_or_init(NetOptions::default);
+    let mut builder = Client::builder();
+
+    if let Some(addr) = options.ip_version.local_address() {
+        builder = builder.local_address(addr);
+    }
+
+    // Overrides skip the resolver, the port of the url is kept
+    for (host, ip) in &options.resolve {
+        builder = builder.resolve(host, SocketAddr::new(*ip, 0));
+    }
+
+    // Routers that drop idle or multiplexed connections get a new HTTP/1.1 connection per request,
+    // and slow links get time to answer instead of being cut off by the default timeout
+    if options.compat {
+        builder = builder
+            .http1_only()
+            .pool_max_idle_per_host(0)
+            .connect_timeout(COMPAT_CONNECT_TIMEOUT)
+            .timeout(COMPAT_REQUEST_TIMEOUT);
+    }
+
+    builder
+}
+
+/// Returns an HTTP/3 client with the connection options applied
+/// The blocking client can't speak QUIC, so it's the async client, driven by the runtime of the transport
+#[cfg(feature = "http3")]
+pub fn h3_client() -> reqwest::Result<reqwest::Client> {
+    let options = OPTIONS.get_or_init(NetOptions::default);
+    let mut builder = reqwest::Client::builder().http3_prior_knowledge();
+
+    if let Some(addr) = options.ip_version.local_address() {
+        builder = builder.local_address(addr);
+    }
+    for (host, ip) in &options.resolve {
+        builder = builder.resolve(host, SocketAddr::new(*ip, 0));
+    }
+
+    builder.build()
+}
+
+/// Returns a client with the connection options applied
+pub fn client() -> Client {
+    client_builder()
+        .build()
+        .expect("Failed to build the HTTP client")
+}
+
+/// Sends a GET request to `url` with a client that has the connection options applied
+/// (like `reqwest::blocking::get`)
+///
+/// # Arguments
+/// * `url`     -   Url that is requested
+pub fn get(url: &str) -> reqwest::Result<Response> {
+    client().get(url).send()
+}
+
+/// Parses a `--resolve` entry ("host:ip", the ip may be IPv6)
+pub fn parse_resolve(entry: &str) -> Result<(String, IpAddr), String> {
+    entry
+        .split_once(':')
+        .and_then(|(host, ip)| {
+            let ip = ip.trim_start_matches('[').trim_end_matches(']');
+            Some((host.to_lowercase(), ip.parse().ok()?))
+        })
+        .filter(|(host, _)| !host.is_empty())
+        .ok_or_else(|| format!("Invalid resolve entry: {} (expected host:ip)", entry))
+}
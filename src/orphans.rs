@@ -0,0 +1,67 @@
+use crate::decoder::Format;
+use std::{
+    fs::File,
+    io::{self, BufReader, Read, Write},
+    path::Path,
+};
+
+/// Matcher compares the bytes written to it with the bytes of a file, stopping at the first difference
+struct Matcher {
+    /// File the decoded bytes are compared with
+    file: BufReader<File>,
+    /// Buffer the bytes of the file are read into
+    buf: Vec<u8>,
+}
+
+impl Write for Matcher {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.buf.resize(bytes.len(), 0);
+        self.file.read_exact(&mut self.buf)?;
+
+        if self.buf != bytes {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "The decoded file differs from the archive",
+            ));
+        }
+
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Returns true if `output` holds exactly the decoded content of `archive`
+/// This is the state a run leaves behind when it's killed between decoding a file and removing its archive
+/// The archive is decoded again but nothing is written, so a large map is checked without doubling its size on disk
+///
+/// # Arguments
+/// * `archive` -   Compressed file that was left behind
+/// * `output`  -   Decoded file next to it (or where it's decoded to)
+pub fn is_decoded(archive: &Path, output: &Path) -> io::Result<bool> {
+    let Some(format) = Format::sniff_file(archive)? else {
+        // A misnamed plain file is renamed, it's never decoded next to an output
+        return Ok(false);
+    };
+
+    let mut matcher = Matcher {
+        file: BufReader::new(File::open(output)?),
+        buf: Vec::new(),
+    };
+    let decoder = format.decoder(File::open(archive)?);
+    if decoder
+        .and_then(|mut decoder| {
+            decoder
+                .decode_into(&mut matcher, &mut |_| {})
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err.to_string()))
+        })
+        .is_err()
+    {
+        return Ok(false);
+    }
+
+    // A longer output is not the decoded archive either
+    Ok(matcher.file.read(&mut [0u8; 1])? == 0)
+}
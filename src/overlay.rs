@@ -0,0 +1,203 @@
+use crate::{durability, events::Event, metrics::METRICS, serve, ui, MB_SIZE};
+use serde::Serialize;
+use std::{
+    io::{self, Write},
+    net::TcpListener,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{atomic::Ordering, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Seconds between two progress updates written to a file or a pipe
+const UPDATE_INTERVAL: u64 = 1;
+
+/// ProgressEndpoint is where stream overlays read the progress from (`--progress-endpoint <addr|path>`)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ProgressEndpoint {
+    /// Local HTTP endpoint ("127.0.0.1:9200"): JSON at `/`, one line of text at `/text`
+    Http(String),
+    /// File replaced every second (JSON if it ends with `.json`, one line of text otherwise),
+    /// or a named pipe (FIFO) that gets one JSON line per second while a reader has it open
+    File(PathBuf),
+}
+
+impl FromStr for ProgressEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.is_empty() {
+            return Err(String::from(
+                "--progress-endpoint needs an address or a path",
+            ));
+        }
+
+        // "host:port" is an address, anything else (including "C:\overlay.txt") is a path
+        match s.rsplit_once(':') {
+            Some((host, port))
+                if !host.is_empty()
+                    && !host.contains(['/', '\\'])
+                    && port.parse::<u16>().is_ok() =>
+            {
+                Ok(Self::Http(s.to_string()))
+            }
+            _ => Ok(Self::File(s.into())),
+        }
+    }
+}
+
+/// Progress is what an overlay shows
+#[derive(Debug, Default, Serialize)]
+struct Progress {
+    /// Stage of the sync (crawl, download, decode, ...)
+    stage: &'static str,
+    /// File name of the latest download that started
+    file: Option<String>,
+    files_downloaded: u64,
+    bytes_downloaded: u64,
+    /// Bytes of the downloads that finished during the last second
+    bytes_per_second: u64,
+    /// Seconds since the sync started
+    elapsed: u64,
+}
+
+impl Progress {
+    /// Returns the progress as one line of text (for text sources of OBS)
+    fn text(&self) -> String {
+        format!(
+            "{}{} | {} files ({:.2} MB) | {:.2} MB/s",
+            self.stage,
+            self.file
+                .as_ref()
+                .map_or_else(String::new, |file| format!(": {}", file)),
+            self.files_downloaded,
+            self.bytes_downloaded as f32 / MB_SIZE as f32,
+            self.bytes_per_second as f32 / MB_SIZE as f32,
+        )
+    }
+}
+
+/// File name of the latest download that started
+static CURRENT_FILE: Mutex<Option<String>> = Mutex::new(None);
+/// Latest progress, refreshed every second
+static PROGRESS: Mutex<Option<Progress>> = Mutex::new(None);
+
+/// Tracks the file that is downloaded (called for every event, whether events are enabled or not)
+///
+/// # Arguments
+/// * `event`   -   Event that happened
+pub fn observe(event: &Event) {
+    if let Event::DownloadStarted { url } = event {
+        let name = url.rsplit('/').next().unwrap_or(url);
+        let name = percent_encoding::percent_decode_str(name).decode_utf8_lossy();
+        *CURRENT_FILE.lock().unwrap() = Some(name.into_owned());
+    }
+}
+
+/// Returns the latest progress as one line of text if `text`, as JSON otherwise
+fn render(text: bool) -> String {
+    let progress = PROGRESS.lock().unwrap();
+    let default = Progress::default();
+    let progress = progress.as_ref().unwrap_or(&default);
+
+    if text {
+        progress.text()
+    } else {
+        serde_json::to_string(progress).unwrap_or_default()
+    }
+}
+
+/// Writes the progress to a file (replaced, so an overlay never reads half of it) or a named pipe
+///
+/// # Arguments
+/// * `path`    -   File or named pipe
+fn write_to(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::{
+            fs::OpenOptions,
+            os::unix::fs::{FileTypeExt, OpenOptionsExt},
+        };
+
+        if path.metadata().is_ok_and(|meta| meta.file_type().is_fifo()) {
+            // Opening a pipe nobody reads fails instead of blocking the updates
+            let mut pipe = OpenOptions::new()
+                .write(true)
+                .custom_flags(libc::O_NONBLOCK)
+                .open(path)?;
+            return writeln!(pipe, "{}", render(false));
+        }
+    }
+
+    let json = path.extension().is_some_and(|ext| ext == "json");
+    durability::write_atomic(path, render(!json).as_bytes())
+}
+
+/// Answers every request on `addr` with the progress
+/// CORS is allowed so browser sources can poll it from a local HTML overlay
+///
+/// # Arguments
+/// * `addr`    -   Address to listen on (e.g. "127.0.0.1:9200")
+fn listen(addr: &str) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+
+    for mut stream in listener.incoming().flatten() {
+        let Ok(request) = serve::read_request(&mut stream) else {
+            continue;
+        };
+        let (content_type, body) = match request.target.split('?').next() {
+            Some("/text") => ("text/plain; charset=utf-8", render(true)),
+            _ => ("application/json", render(false)),
+        };
+
+        let _ = write!(
+            stream,
+            "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nAccess-Control-Allow-Origin: *\r\n\
+             Cache-Control: no-store\r\nConnection: close\r\n\r\n{}",
+            content_type,
+            body.len(),
+            body
+        );
+    }
+
+    Ok(())
+}
+
+/// Starts publishing the progress to `endpoint`
+///
+/// # Arguments
+/// * `endpoint`    -   Where overlays read the progress from (`--progress-endpoint`)
+pub fn spawn(endpoint: ProgressEndpoint) {
+    let start = Instant::now();
+
+    if let ProgressEndpoint::Http(addr) = &endpoint {
+        let addr = addr.clone();
+        std::thread::spawn(move || {
+            listen(&addr).expect("Failed to serve the progress endpoint");
+        });
+    }
+
+    std::thread::spawn(move || {
+        let mut last_bytes = METRICS.bytes_downloaded.load(Ordering::Relaxed);
+
+        loop {
+            let bytes_downloaded = METRICS.bytes_downloaded.load(Ordering::Relaxed);
+            *PROGRESS.lock().unwrap() = Some(Progress {
+                stage: ui::stage(),
+                file: CURRENT_FILE.lock().unwrap().clone(),
+                files_downloaded: METRICS.files_downloaded.load(Ordering::Relaxed),
+                bytes_downloaded,
+                bytes_per_second: (bytes_downloaded - last_bytes) / UPDATE_INTERVAL,
+                elapsed: start.elapsed().as_secs(),
+            });
+            last_bytes = bytes_downloaded;
+
+            // An overlay that went away is no reason to stop the sync
+            if let ProgressEndpoint::File(path) = &endpoint {
+                let _ = write_to(path);
+            }
+
+            std::thread::sleep(Duration::from_secs(UPDATE_INTERVAL));
+        }
+    });
+}
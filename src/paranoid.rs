@@ -0,0 +1,90 @@
+use crate::{fingerprint, transport::Transport};
+use reqwest::StatusCode;
+use std::{
+    collections::hash_map::RandomState,
+    fs::File,
+    hash::{BuildHasher, Hasher},
+    io::{Read, Seek, SeekFrom},
+    path::Path,
+};
+
+/// Number of bytes fetched a second time per file
+const SAMPLE_LEN: u64 = 64 * 1024;
+/// Number of times a file whose sample differs is downloaded again before it's kept as is
+pub const MAX_REFETCHES: usize = 3;
+
+/// Outcome of comparing a random range of a downloaded file with a second fetch
+#[derive(Debug, PartialEq, Eq)]
+pub enum Sample {
+    /// The range on disk matches the server
+    Match,
+    /// The range on disk differs from the server, the file needs a full download
+    Mismatch,
+    /// The range could not be compared (no ranges on the server, request or read failed)
+    Unchecked,
+}
+
+/// Returns a random offset a range of `SAMPLE_LEN` bytes of a `size` byte file starts at
+fn random_start(size: u64) -> u64 {
+    // Every RandomState is seeded differently, which is random enough to pick a range
+    let random = RandomState::new().build_hasher().finish();
+
+    random % size.saturating_sub(SAMPLE_LEN).max(1)
+}
+
+/// Reads `len` bytes at `start` of the file at `path`
+fn read_range(path: &Path, start: u64, len: u64) -> std::io::Result<Vec<u8>> {
+    let mut file = File::open(path)?;
+    let mut bytes = vec![0; len as usize];
+
+    file.seek(SeekFrom::Start(start))?;
+    file.read_exact(&mut bytes)?;
+
+    Ok(bytes)
+}
+
+/// Fetches a random byte range of a downloaded file again and compares it with what's on disk
+///
+/// # Arguments
+/// * `transport`   -   Sends the ranged request
+/// * `url`         -   Link the file was downloaded from
+/// * `path`        -   Where the file was written
+/// * `size`        -   Size of the file
+pub fn sample(transport: &Transport, url: &str, path: &Path, size: u64) -> Sample {
+    if size == 0 {
+        return Sample::Match;
+    }
+
+    // Hosts that ignore ranges would send the whole file again for every sample
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    if fingerprint::ranges(&host) == Some(false) {
+        return Sample::Unchecked;
+    }
+
+    let start = random_start(size);
+    let len = SAMPLE_LEN.min(size - start);
+
+    let Ok(on_disk) = read_range(path, start, len) else {
+        return Sample::Unchecked;
+    };
+    let Ok(mut resp) = transport.get_range(url, start, start + len - 1) else {
+        return Sample::Unchecked;
+    };
+    if resp.status() != StatusCode::PARTIAL_CONTENT {
+        return Sample::Unchecked;
+    }
+
+    let mut remote = Vec::with_capacity(len as usize);
+    if resp.read_to_end(&mut remote).is_err() {
+        return Sample::Unchecked;
+    }
+
+    if remote == on_disk {
+        Sample::Match
+    } else {
+        Sample::Mismatch
+    }
+}
@@ -0,0 +1,180 @@
+use crate::layout::Layout;
+use std::{
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+use url::Url;
+
+/// Placeholders a template may use
+const PLACEHOLDERS: &[&str] = &["host", "path", "dir", "category", "filename", "prefix"];
+
+/// PathTemplate derives the local path of a file from its link (`--path-template <template>`)
+/// The rendered path is relative to the base directory of the layout (e.g. `download/`):
+/// * `{host}`      -   Host of the link ("fastdl.gflclan.com")
+/// * `{path}`      -   Path the layout would use ("maps/ze_x.bsp.bz2"), the default template
+/// * `{dir}`       -   Directory of `{path}` ("maps", "." for files at the top)
+/// * `{category}`  -   First directory of `{path}` ("maps", "materials", "other" for files at the top)
+/// * `{filename}`  -   Name of the file ("ze_x.bsp.bz2")
+/// * `{prefix}`    -   Map prefix of the file name ("ze", "other" without one)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathTemplate {
+    template: String,
+}
+
+impl FromStr for PathTemplate {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut rest = s;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .ok_or_else(|| format!("Unclosed placeholder in path template: {}", s))?;
+            let name = &rest[start + 1..start + end];
+            if !PLACEHOLDERS.contains(&name) {
+                return Err(format!(
+                    "Unknown placeholder {{{}}} in path template (expected {{{}}})",
+                    name,
+                    PLACEHOLDERS.join("}, {")
+                ));
+            }
+            rest = &rest[start + end + 1..];
+        }
+
+        // The rendered path must stay inside of the output directory
+        if Path::new(s)
+            .components()
+            .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir))
+        {
+            return Err(format!(
+                "Path template must be a relative path without \"..\": {}",
+                s
+            ));
+        }
+
+        Ok(Self {
+            template: s.to_string(),
+        })
+    }
+}
+
+impl PathTemplate {
+    /// Returns the path (relative to the output directory) the file at `url` is synced to
+    ///
+    /// # Arguments
+    /// * `url`     -   Download link of the file
+    /// * `layout`  -   Layout whose base directory and path the template starts from
+    pub fn render(&self, url: &str, layout: Layout) -> PathBuf {
+        let path = layout.local_path(url);
+        let path = path.strip_prefix(layout.base()).unwrap_or(&path);
+        let path_str = path.to_string_lossy().replace('\\', "/");
+
+        let filename = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let dir = path
+            .parent()
+            .map(|dir| dir.to_string_lossy().replace('\\', "/"))
+            .filter(|dir| !dir.is_empty())
+            .unwrap_or_else(|| String::from("."));
+        let category = match path_str.split_once('/') {
+            Some((category, _)) => category.to_string(),
+            None => String::from("other"),
+        };
+        let prefix = match filename.split_once('_') {
+            Some((prefix, _)) if !prefix.is_empty() => prefix.to_lowercase(),
+            _ => String::from("other"),
+        };
+        let host = Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+
+        let rendered = self
+            .template
+            .replace("{host}", &host)
+            .replace("{path}", &path_str)
+            .replace("{dir}", &dir)
+            .replace("{category}", &category)
+            .replace("{filename}", &filename)
+            .replace("{prefix}", &prefix);
+
+        // Values come from the link, so only plain components are kept
+        let mut local_path = layout.base();
+        local_path.extend(
+            Path::new(&rendered)
+                .components()
+                .filter(|c| matches!(c, Component::Normal(_))),
+        );
+        local_path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const URL: &str = "https://fastdl.gflclan.com/cstrike/maps/ze_example_v2.bsp.bz2";
+
+    /// Returns the path `template` renders the file at `url` to with the download layout
+    fn render(template: &str, url: &str) -> PathBuf {
+        template
+            .parse::<PathTemplate>()
+            .unwrap()
+            .render(url, Layout::Download)
+    }
+
+    #[test]
+    fn rejects_invalid_templates() {
+        assert!("{category}/{nope}".parse::<PathTemplate>().is_err());
+        assert!("{category/{filename}".parse::<PathTemplate>().is_err());
+        assert!("../{path}".parse::<PathTemplate>().is_err());
+        assert!("{dir}/../../{filename}".parse::<PathTemplate>().is_err());
+        assert!("/srv/{path}".parse::<PathTemplate>().is_err());
+        assert!("./{host}/{path}".parse::<PathTemplate>().is_ok());
+    }
+
+    #[test]
+    fn renders_the_placeholders() {
+        assert_eq!(render("{path}", URL), Layout::Download.local_path(URL));
+        assert_eq!(
+            render("{host}/{category}/{prefix}/{filename}", URL),
+            Path::new("./download/fastdl.gflclan.com/maps/ze/ze_example_v2.bsp.bz2")
+        );
+        assert_eq!(
+            render(
+                "{dir}/{filename}",
+                "https://fastdl.gflclan.com/cstrike/sound/ze/boss.wav"
+            ),
+            Path::new("./download/sound/ze/boss.wav")
+        );
+        // Files at the top have no category, directory or prefix
+        assert_eq!(
+            render(
+                "{category}/{prefix}/{dir}/{filename}",
+                "https://fastdl.gflclan.com/cstrike/readme.txt"
+            ),
+            Path::new("./download/other/other/readme.txt")
+        );
+    }
+
+    #[test]
+    fn keeps_rendered_paths_inside_of_the_output_directory() {
+        for url in [
+            "https://fastdl.gflclan.com/cstrike/maps/%2e%2e/%2e%2e/ze_x.bsp",
+            "https://fastdl.gflclan.com/cstrike/maps/..%2F..%2Fze_x.bsp",
+            "https://fastdl.gflclan.com/cstrike/maps/..%5C..%5Cze_x.bsp",
+        ] {
+            let rendered = render("{category}/{dir}/{filename}", url);
+            assert!(rendered.starts_with("./download"), "{}", rendered.display());
+            assert!(
+                rendered
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_) | Component::CurDir)),
+                "{}",
+                rendered.display()
+            );
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use crate::{files::RemoteFile, MB_SIZE};
+use std::{
+    collections::HashSet,
+    fmt::{self, Display},
+    sync::Mutex,
+};
+
+/// Compressed wrappers that are looked through when the extension of a file is checked
+const COMPRESSED_EXTENSIONS: &[&str] = &["bz2", "zst", "xz"];
+
+/// Violation is why a file breaks the policy
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Violation {
+    /// The file is larger than `--max-file-size` (its size and the limit in bytes)
+    TooLarge(u64, u64),
+    /// The extension of the file is not in `--allow-ext` (the extension, empty for files without one)
+    Extension(String),
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::TooLarge(size, max) => write!(
+                f,
+                "{:.2} MB is over the {:.2} MB limit",
+                *size as f32 / MB_SIZE as f32,
+                *max as f32 / MB_SIZE as f32
+            ),
+            Self::Extension(ext) if ext.is_empty() => write!(f, "no extension"),
+            Self::Extension(ext) => write!(f, ".{} is not an allowed extension", ext),
+        }
+    }
+}
+
+/// Policy decides which files may land on disk, checked before they're downloaded
+/// Operators mirroring third-party FastDLs use it to keep out executables and absurdly large files
+#[derive(Debug, Default)]
+pub struct Policy {
+    /// Largest remote size of a file in bytes (`--max-file-size <size>`)
+    max_size: Option<u64>,
+    /// Lowercased extensions that are downloaded, every extension if None (`--allow-ext <ext,...>`)
+    extensions: Option<HashSet<String>>,
+    /// Files skipped so far and why ("url (reason)")
+    skipped: Mutex<Vec<String>>,
+}
+
+/// Returns the lowercased extension of a link, looking through compressed wrappers ("ze_x.bsp.bz2" -> "bsp")
+fn extension(url: &str) -> String {
+    let mut name = url.rsplit('/').next().unwrap_or(url).to_lowercase();
+
+    loop {
+        match name.rsplit_once('.') {
+            Some((stem, ext)) if COMPRESSED_EXTENSIONS.contains(&ext) => name = stem.to_string(),
+            Some((_, ext)) => return ext.to_string(),
+            None => return String::new(),
+        }
+    }
+}
+
+impl Policy {
+    /// Returns the Policy of the given limits (no limits allow everything)
+    ///
+    /// # Arguments
+    /// * `max_size`    -   Largest remote size of a file in bytes
+    /// * `extensions`  -   Extensions that are downloaded (with or without the leading ".")
+    pub fn new(max_size: Option<u64>, extensions: &[String]) -> Self {
+        Self {
+            max_size,
+            extensions: (!extensions.is_empty()).then(|| {
+                extensions
+                    .iter()
+                    .map(|ext| ext.trim_start_matches('.').to_lowercase())
+                    .collect()
+            }),
+            skipped: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns why the file breaks the policy, None if it may be downloaded
+    ///
+    /// # Arguments
+    /// * `url`     -   Download link of the file
+    /// * `size`    -   Remote size of the file (the size limit is not checked if None)
+    pub fn check(&self, url: &str, size: Option<u64>) -> Option<Violation> {
+        if let Some(extensions) = &self.extensions {
+            let ext = extension(url);
+            if !extensions.contains(&ext) {
+                return Some(Violation::Extension(ext));
+            }
+        }
+
+        match (size, self.max_size) {
+            (Some(size), Some(max)) if size > max => Some(Violation::TooLarge(size, max)),
+            _ => None,
+        }
+    }
+
+    /// Records that the file at `url` was skipped for `violation`
+    pub fn skip(&self, url: &str, violation: &Violation) {
+        self.skipped
+            .lock()
+            .unwrap()
+            .push(format!("{} ({})", url, violation));
+    }
+
+    /// Removes (and records) every file of `links` that breaks the policy
+    /// Files whose size the crawl did not learn are checked again once their download starts
+    ///
+    /// # Arguments
+    /// * `links`   -   Files found during the crawl
+    pub fn retain_links(&self, links: &mut HashSet<RemoteFile>) -> Vec<(RemoteFile, Violation)> {
+        let violations = links
+            .iter()
+            .filter_map(|file| Some((file.clone(), self.check(&file.url, file.size)?)))
+            .collect::<Vec<_>>();
+
+        for (file, violation) in &violations {
+            links.remove(file);
+            self.skip(&file.url, violation);
+        }
+
+        violations
+    }
+
+    /// Returns every file skipped so far and why
+    pub fn skipped(&self) -> Vec<String> {
+        self.skipped.lock().unwrap().clone()
+    }
+}
@@ -0,0 +1,72 @@
+use percent_encoding::percent_decode_str;
+use url::Url;
+
+/// Prefixes are the subtrees of a fastdl the crawl is restricted to (`--prefix <path>`)
+/// Paths are compared segment by segment and case-insensitively, like the game does
+#[derive(Clone, Debug, Default)]
+pub struct Prefixes {
+    /// Lowercased segments of every prefix, relative to the fastdl url (e.g. ["cstrike", "maps"])
+    prefixes: Vec<Vec<String>>,
+}
+
+/// Returns the lowercased, percent-decoded, non-empty segments of `path`
+fn segments(path: &str) -> Vec<String> {
+    path.split('/')
+        .filter(|segment| !segment.is_empty())
+        .map(|segment| {
+            percent_decode_str(segment)
+                .decode_utf8_lossy()
+                .to_lowercase()
+        })
+        .collect()
+}
+
+/// Returns true if `path` starts with every segment of `prefix`
+fn starts_with(path: &[String], prefix: &[String]) -> bool {
+    path.len() >= prefix.len() && path.iter().zip(prefix).all(|(a, b)| a == b)
+}
+
+impl Prefixes {
+    /// Returns the Prefixes of the given paths (no paths allow everything)
+    ///
+    /// # Arguments
+    /// * `paths`   -   Paths relative to the fastdl url (e.g. "cstrike/maps")
+    pub fn new(paths: &[String]) -> Self {
+        Self {
+            prefixes: paths
+                .iter()
+                .map(|path| segments(path))
+                .filter(|segments| !segments.is_empty())
+                .collect(),
+        }
+    }
+
+    /// Returns true if the crawl may visit (a directory) or record (a file) `url`
+    /// Directories above a prefix are visited to get to it, but only files inside of a prefix are recorded
+    /// Links outside of the crawl root (the fastdl redirect directory) are allowed if a prefix appears in their path
+    ///
+    /// # Arguments
+    /// * `url`         -   Resolved link found in a listing
+    /// * `root_url`    -   Url the crawl started at
+    pub fn allows(&self, url: &Url, root_url: &Url) -> bool {
+        if self.prefixes.is_empty() {
+            return true;
+        }
+
+        let is_dir = url.path().ends_with('/');
+        match url.path().strip_prefix(root_url.path()) {
+            Some(rel_path) => {
+                let path = segments(rel_path);
+                self.prefixes.iter().any(|prefix| {
+                    starts_with(&path, prefix) || (is_dir && starts_with(prefix, &path))
+                })
+            }
+            None => {
+                let path = segments(url.path());
+                self.prefixes
+                    .iter()
+                    .any(|prefix| (0..path.len()).any(|start| starts_with(&path[start..], prefix)))
+            }
+        }
+    }
+}
@@ -0,0 +1,148 @@
+use crate::lint::BLOCKED_EXTENSIONS;
+use crate::scan::{self, ScanPolicy};
+use crate::MB_SIZE;
+use bzip2::{write::BzEncoder, Compression};
+use rayon::prelude::*;
+use std::{
+    fs::{self, File},
+    io::{self, BufReader, BufWriter},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Directories of the game directory whose files clients download
+pub const ASSET_DIRS: &[&str] = &[
+    "maps",
+    "materials",
+    "models",
+    "particles",
+    "resource",
+    "sound",
+];
+
+/// PrepareSummary counts what `prepare` did
+#[derive(Debug, Default)]
+pub struct PrepareSummary {
+    /// Files that were compressed
+    pub compressed: usize,
+    /// Files whose .bz2 was already newer than the file
+    pub up_to_date: usize,
+    /// Files that failed to compress
+    pub failed: Vec<PathBuf>,
+    /// Size of the compressed files in bytes
+    pub raw_bytes: u64,
+    /// Size of the .bz2 files in bytes
+    pub bz2_bytes: u64,
+}
+
+/// Returns true if clients download `path` (its extension is not refused by the engine)
+///
+/// # Arguments
+/// * `path`    -   Path of the file relative to the game directory
+fn is_downloadable(path: &Path) -> bool {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+
+    !ext.is_empty() && ext != "bz2" && !BLOCKED_EXTENSIONS.contains(&ext.as_str())
+}
+
+/// Returns true if `bz2_path` was written after `path` was last modified
+fn is_up_to_date(path: &Path, bz2_path: &Path) -> bool {
+    let modified = |p: &Path| fs::metadata(p).and_then(|m| m.modified()).ok();
+
+    match (modified(path), modified(bz2_path)) {
+        (Some(src), Some(dst)) => dst >= src,
+        _ => false,
+    }
+}
+
+/// Compresses `path` into `bz2_path` with the best bz2 compression
+/// The .bz2 is written to a temporary file first so an interrupted run never leaves a truncated copy
+fn compress(path: &Path, bz2_path: &Path) -> io::Result<u64> {
+    let tmp_path = bz2_path.with_extension("bz2.tmp");
+
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut encoder = BzEncoder::new(
+        BufWriter::new(File::create(&tmp_path)?),
+        Compression::best(),
+    );
+    io::copy(&mut reader, &mut encoder)?;
+    encoder.finish()?;
+
+    fs::rename(&tmp_path, bz2_path)?;
+    Ok(fs::metadata(bz2_path)?.len())
+}
+
+/// Compresses the downloadable assets of a game server into a FastDL tree ready to upload
+/// `<game_dir>/maps/ze_a.bsp` becomes `<out>/maps/ze_a.bsp.bz2`; files that did not change are skipped
+///
+/// # Arguments
+/// * `game_dir`    -   Game directory of the server (e.g. ".../cstrike")
+/// * `out`         -   Directory the FastDL tree is written to (the `sv_downloadurl` root)
+/// * `policy`      -   Which symlinks and hidden directories are walked into
+pub fn prepare(game_dir: &Path, out: &Path, policy: ScanPolicy) -> PrepareSummary {
+    let files = ASSET_DIRS
+        .iter()
+        .map(|dir| game_dir.join(dir))
+        .filter(|dir| dir.is_dir())
+        .flat_map(|dir| scan::find_files(&dir, policy, |_| true))
+        .filter_map(|path| {
+            let relative = path.strip_prefix(game_dir).ok()?.to_path_buf();
+            is_downloadable(&relative).then_some((path, relative))
+        })
+        .collect::<Vec<_>>();
+
+    let summary = Mutex::new(PrepareSummary::default());
+
+    files.par_iter().for_each(|(path, relative)| {
+        let bz2_path = PathBuf::from(format!("{}.bz2", out.join(relative).display()));
+
+        if is_up_to_date(path, &bz2_path) {
+            summary.lock().unwrap().up_to_date += 1;
+            return;
+        }
+
+        let result = bz2_path
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| compress(path, &bz2_path));
+
+        let mut summary = summary.lock().unwrap();
+        match result {
+            Ok(bz2_len) => {
+                summary.compressed += 1;
+                summary.raw_bytes += fs::metadata(path).map_or(0, |m| m.len());
+                summary.bz2_bytes += bz2_len;
+            }
+            Err(_) => summary.failed.push(path.clone()),
+        }
+    });
+
+    summary.into_inner().unwrap()
+}
+
+/// Prints what `prepare` did
+///
+/// # Arguments
+/// * `summary` -   Summary returned by `prepare`
+/// * `out`     -   Directory the FastDL tree was written to
+pub fn print(summary: &PrepareSummary, out: &Path) {
+    let mb = |bytes: u64| bytes as f32 / MB_SIZE as f32;
+
+    println!(
+        "Compressed {} file(s) ({:.2} MB -> {:.2} MB), {} already up to date",
+        summary.compressed,
+        mb(summary.raw_bytes),
+        mb(summary.bz2_bytes),
+        summary.up_to_date,
+    );
+
+    if !summary.failed.is_empty() {
+        println!("Failed to compress: {:#?}", summary.failed);
+    }
+
+    println!("Upload {} and point sv_downloadurl at it", out.display());
+}
@@ -0,0 +1,89 @@
+use crate::fingerprint;
+use reqwest::{
+    blocking::{Client, Response},
+    header::{HeaderName, CONTENT_LENGTH, CONTENT_RANGE, RANGE},
+    StatusCode, Url,
+};
+use std::{collections::HashMap, sync::Mutex};
+
+/// HostProbe remembers which hosts accept HEAD requests
+/// Some hosts 403 HEAD requests but allow GET, so those are probed with a 1 byte ranged GET instead
+/// Hosts a previous run found blocking HEAD requests are probed with the ranged GET right away
+pub struct HostProbe {
+    /// Host -> whether HEAD requests work on it
+    head_allowed: Mutex<HashMap<String, bool>>,
+}
+
+impl HostProbe {
+    /// Returns a HostProbe that does not know about any host yet
+    pub fn new() -> Self {
+        Self {
+            head_allowed: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Sends a cheap request to `url` to obtain its final url (after redirects), size and type
+    /// HEAD is tried first for unknown hosts; if it's blocked, `GET` with `Range: bytes=0-0` is used from then on
+    ///
+    /// # Arguments
+    /// * `client`  -   Client used to send the request
+    /// * `url`     -   Url that will be probed
+    pub fn send(&self, client: &Client, url: Url) -> reqwest::Result<Response> {
+        let host = url.host_str().unwrap_or_default().to_string();
+        let head_allowed = self.head_allowed.lock().unwrap().get(&host).copied();
+
+        if head_allowed.or_else(|| fingerprint::head_allowed(&host)) != Some(false) {
+            let resp = client.head(url.clone()).send()?;
+
+            // Only trust the answer the first time we hear from the host
+            if head_allowed.is_none() {
+                let blocked = is_head_blocked(resp.status());
+                self.head_allowed
+                    .lock()
+                    .unwrap()
+                    .insert(host.clone(), !blocked);
+                fingerprint::record_head(&host, !blocked);
+
+                if !blocked {
+                    return Ok(resp);
+                }
+            } else {
+                return Ok(resp);
+            }
+        }
+
+        let resp = client.get(url).header(RANGE, "bytes=0-0").send()?;
+        if resp.status().is_success() {
+            fingerprint::record_ranges(&host, resp.status() == StatusCode::PARTIAL_CONTENT);
+        }
+        Ok(resp)
+    }
+}
+
+impl Default for HostProbe {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Returns true if `status` means that the host refuses HEAD requests
+fn is_head_blocked(status: StatusCode) -> bool {
+    status == StatusCode::FORBIDDEN
+        || status == StatusCode::METHOD_NOT_ALLOWED
+        || status == StatusCode::NOT_IMPLEMENTED
+}
+
+/// Returns the size of the remote file from a probe response
+/// Ranged responses store the full size after the "/" of `Content-Range` (e.g. "bytes 0-0/12345")
+///
+/// # Arguments
+/// * `resp`    -   Response returned by `HostProbe::send`
+pub fn remote_size(resp: &Response) -> Option<u64> {
+    let header = |name: HeaderName| resp.headers().get(name).and_then(|v| v.to_str().ok());
+
+    if let Some(range) = header(CONTENT_RANGE) {
+        return range.rsplit('/').next().and_then(|s| s.parse().ok());
+    }
+
+    header(CONTENT_LENGTH).and_then(|s| s.parse().ok())
+}
@@ -0,0 +1,173 @@
+use crate::config::Config;
+use crate::discovery::ProviderSpec;
+use crate::rewrite::RuleSpec;
+use crate::variants::Variant;
+use serde::Deserialize;
+use std::{
+    collections::BTreeMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// Name of the profiles file read from the current directory when `--profiles` is not given
+pub const PROFILES_FILE: &str = "fastdl_profiles.json";
+/// Fastdl urls synced when no profile is selected
+pub const DEFAULT_FASTDL_URLS: &[&str] = &[
+    "https://fastdl.gflclan.com/cstrike/maps/",
+    // "https://fastdl.gflclan.com/cstrike/materials/",
+    // "https://fastdl.gflclan.com/cstrike/models/",
+    // "https://fastdl.gflclan.com/cstrike/resource/",
+    // "https://fastdl.gflclan.com/cstrike/sound/",
+];
+
+/// Profile is a named sync setup in the profiles file
+/// Options that are left out fall back to the command line
+#[derive(Debug, Deserialize)]
+pub struct Profile {
+    /// Fastdl urls that are synced
+    pub urls: Vec<String>,
+    /// Directory the files are synced into (relative to the profiles file)
+    pub out: Option<PathBuf>,
+    /// File listing the only map names that are synced
+    pub maps: Option<PathBuf>,
+    /// Nominations or rotation list exported by SourceMod's mapchooser plugins
+    pub nominations: Option<PathBuf>,
+    /// File of sha256 hashes or file names that are never synced
+    pub blocklist: Option<PathBuf>,
+    /// Copy that is downloaded when a file is served both raw and as `.bz2` ("bz2" or "raw")
+    pub prefer: Option<String>,
+    /// Keep only the N newest versions of every map family locally
+    pub prune_keep: Option<usize>,
+    /// Don't download map versions that would be pruned
+    pub skip_old_versions: Option<bool>,
+    /// Category -> directory its decoded files are written to (relative to the profiles file),
+    /// added to the `--category-dest` entries
+    pub destinations: Option<BTreeMap<String, PathBuf>>,
+    /// Rules rewriting the links found in the listings, applied after the `--rewrite` rules
+    pub rewrites: Option<Vec<RuleSpec>>,
+    /// Sources the files are discovered from (replaces the `--discover` providers)
+    pub discover: Option<Vec<String>>,
+}
+
+/// SyncProfile is a profile resolved against the command line, ready to be synced
+#[derive(Debug)]
+pub struct SyncProfile {
+    /// Name of the profile ("default" when no profile is selected)
+    pub name: String,
+    /// Fastdl urls that are synced
+    pub urls: Vec<String>,
+    /// Command line options with the options of the profile applied
+    pub config: Config,
+    /// Absolute directory the files are synced into
+    pub out_dir: PathBuf,
+}
+
+/// Returns the profiles of the profiles file at `path` (sorted by name)
+///
+/// # Arguments
+/// * `path`    -   Path of the profiles file
+pub fn load(path: &Path) -> Result<BTreeMap<String, Profile>, String> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+
+    serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid profiles file {}: {}", path.display(), e))
+}
+
+impl Profile {
+    /// Returns the SyncProfile of this profile, with its paths resolved relative to `base_dir`
+    ///
+    /// # Arguments
+    /// * `name`        -   Name of the profile
+    /// * `config`      -   Command line options the profile overrides
+    /// * `base_dir`    -   Directory of the profiles file
+    fn resolve(&self, name: &str, config: &Config, base_dir: &Path) -> Result<SyncProfile, String> {
+        let mut config = config.clone();
+
+        if let Some(maps) = &self.maps {
+            config.maps = Some(base_dir.join(maps));
+        }
+        if let Some(nominations) = &self.nominations {
+            config.nominations = Some(base_dir.join(nominations));
+        }
+        if let Some(blocklist) = &self.blocklist {
+            config.blocklist = Some(base_dir.join(blocklist));
+        }
+        if let Some(prefer) = &self.prefer {
+            config.prefer = prefer.parse::<Variant>()?;
+        }
+        if let Some(keep) = self.prune_keep {
+            if keep == 0 {
+                return Err(format!("prune_keep of profile {} must be at least 1", name));
+            }
+            config.prune_keep = Some(keep);
+        }
+        if let Some(skip) = self.skip_old_versions {
+            config.skip_old_versions = skip;
+        }
+        for (category, dir) in self.destinations.iter().flatten() {
+            config.destinations.insert(category, base_dir.join(dir))?;
+        }
+        for rule in self.rewrites.iter().flatten() {
+            config.rewrites.insert_spec(rule)?;
+        }
+        if let Some(discover) = &self.discover {
+            config.discover = discover
+                .iter()
+                .map(|spec| Ok(spec.parse::<ProviderSpec>()?.resolve(base_dir)))
+                .collect::<Result<_, String>>()?;
+        }
+
+        Ok(SyncProfile {
+            name: name.to_string(),
+            urls: self.urls.clone(),
+            config,
+            out_dir: base_dir.join(self.out.as_deref().unwrap_or(Path::new(name))),
+        })
+    }
+}
+
+/// Returns the profiles selected on the command line (`--profile <name>` or `--all-profiles`)
+/// Without either, a single "default" profile syncs the built-in urls into `base_dir`
+///
+/// # Arguments
+/// * `config`      -   Command line options
+/// * `base_dir`    -   Directory the program was started in
+pub fn select(config: &Config, base_dir: &Path) -> Result<Vec<SyncProfile>, String> {
+    if config.profile.is_none() && !config.all_profiles {
+        return Ok(vec![SyncProfile {
+            name: String::from("default"),
+            urls: DEFAULT_FASTDL_URLS
+                .iter()
+                .map(|url| url.to_string())
+                .collect(),
+            config: config.clone(),
+            out_dir: base_dir.to_path_buf(),
+        }]);
+    }
+
+    let path = base_dir.join(
+        config
+            .profiles_file
+            .as_deref()
+            .unwrap_or(Path::new(PROFILES_FILE)),
+    );
+    let profiles_dir = path.parent().unwrap_or(base_dir);
+    let profiles = load(&path)?;
+    if profiles.is_empty() {
+        return Err(format!("No profiles in {}", path.display()));
+    }
+
+    match &config.profile {
+        Some(name) => {
+            let profile = profiles
+                .get(name)
+                .ok_or_else(|| format!("Unknown profile: {}", name))?;
+            Ok(vec![profile.resolve(name, config, profiles_dir)?])
+        }
+        None => profiles
+            .iter()
+            .map(|(name, profile)| profile.resolve(name, config, profiles_dir))
+            .collect(),
+    }
+}
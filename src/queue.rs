@@ -0,0 +1,178 @@
+use crate::{durability, schema};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::{Path, PathBuf},
+    sync::{Condvar, Mutex},
+};
+
+/// Name of the persisted queue stored in the output directory
+pub const QUEUE_FILE: &str = "fastdl_queue.json";
+/// Priority of maps (players wait on them in-game)
+pub const PRIORITY_MAP: u8 = 2;
+/// Priority of every other file
+pub const PRIORITY_DEFAULT: u8 = 1;
+/// Priority of links requeued through the control interface
+pub const PRIORITY_REQUEUE: u8 = 3;
+
+/// Returns the priority of a download link
+pub fn priority_of(url: &str) -> u8 {
+    if url.contains(".bsp") {
+        PRIORITY_MAP
+    } else {
+        PRIORITY_DEFAULT
+    }
+}
+
+/// QueueFile is the on-disk format of the queue (versioned by `schema::QUEUE`)
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct QueueFile {
+    /// Version of the format the queue was written in
+    #[serde(default)]
+    schema_version: u32,
+    /// Download link -> priority
+    pending: BTreeMap<String, u8>,
+}
+
+/// DownloadQueue is a persistent prioritized queue of download links that can be paused
+/// Links stay in the queue (and on disk) until they're downloaded, so an interrupted sync resumes where it stopped
+pub struct DownloadQueue {
+    /// Path of the persisted queue
+    path: PathBuf,
+    /// Download link -> priority of every link that was not downloaded yet
+    pending: Mutex<BTreeMap<String, u8>>,
+    /// Links that are being downloaded
+    in_flight: Mutex<HashSet<String>>,
+    /// Whether downloads are paused
+    paused: Mutex<bool>,
+    /// Wakes up the downloads waiting on a pause
+    resumed: Condvar,
+}
+
+impl DownloadQueue {
+    /// Returns the queue persisted at `path` (or an empty one if it does not exist)
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the persisted queue
+    pub fn open(path: &Path) -> Self {
+        // The queue only holds resume information, a queue that can't be read starts empty
+        let file: QueueFile = schema::QUEUE.load(path).ok().flatten().unwrap_or_default();
+
+        Self {
+            path: path.to_path_buf(),
+            pending: Mutex::new(file.pending),
+            in_flight: Mutex::new(HashSet::new()),
+            paused: Mutex::new(false),
+            resumed: Condvar::new(),
+        }
+    }
+
+    /// Adds a link to the queue (a link that is already queued keeps the highest priority)
+    ///
+    /// # Arguments
+    /// * `url`         -   Download link
+    /// * `priority`    -   Higher priorities are downloaded first
+    pub fn push(&self, url: &str, priority: u8) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            let entry = pending.entry(url.to_string()).or_insert(priority);
+            *entry = (*entry).max(priority);
+        }
+
+        self.persist();
+    }
+
+    /// Adds every link of `urls` to the queue with the priority returned by `priority_of`
+    pub fn push_all<'a, I: IntoIterator<Item = &'a String>>(&self, urls: I) {
+        {
+            let mut pending = self.pending.lock().unwrap();
+            for url in urls {
+                let priority = priority_of(url);
+                let entry = pending.entry(url.clone()).or_insert(priority);
+                *entry = (*entry).max(priority);
+            }
+        }
+
+        self.persist();
+    }
+
+    /// Adds a link to the front of the queue
+    pub fn requeue(&self, url: &str) {
+        self.push(url, PRIORITY_REQUEUE);
+    }
+
+    /// Returns every queued link that is not being downloaded, highest priority first
+    /// The links are marked as being downloaded
+    pub fn take_batch(&self) -> Vec<String> {
+        let pending = self.pending.lock().unwrap();
+        let mut in_flight = self.in_flight.lock().unwrap();
+
+        let mut batch = pending
+            .iter()
+            .filter(|(url, _)| !in_flight.contains(*url))
+            .map(|(url, priority)| (*priority, url.clone()))
+            .collect::<Vec<_>>();
+        batch.sort_by(|a, b| b.0.cmp(&a.0).then_with(|| a.1.cmp(&b.1)));
+
+        batch
+            .into_iter()
+            .map(|(_, url)| {
+                in_flight.insert(url.clone());
+                url
+            })
+            .collect()
+    }
+
+    /// Removes a downloaded link from the queue
+    /// The removal reaches the disk with the next `persist` (a checkpoint), after the manifest recorded the file
+    pub fn complete(&self, url: &str) {
+        self.pending.lock().unwrap().remove(url);
+        self.in_flight.lock().unwrap().remove(url);
+    }
+
+    /// Pauses the downloads (the ones in progress finish first)
+    pub fn pause(&self) {
+        *self.paused.lock().unwrap() = true;
+    }
+
+    /// Resumes the downloads
+    pub fn resume(&self) {
+        *self.paused.lock().unwrap() = false;
+        self.resumed.notify_all();
+    }
+
+    /// Blocks while the downloads are paused
+    pub fn wait_while_paused(&self) {
+        let mut paused = self.paused.lock().unwrap();
+
+        while *paused {
+            paused = self.resumed.wait(paused).unwrap();
+        }
+    }
+
+    /// Returns a one line status of the queue
+    pub fn status(&self) -> String {
+        format!(
+            "{}: {} queued, {} downloading",
+            if *self.paused.lock().unwrap() {
+                "paused"
+            } else {
+                "running"
+            },
+            self.pending.lock().unwrap().len(),
+            self.in_flight.lock().unwrap().len(),
+        )
+    }
+
+    /// Writes the queue to disk (a failed write only costs the resume information)
+    pub fn persist(&self) {
+        let file = QueueFile {
+            schema_version: schema::QUEUE.version(),
+            pending: self.pending.lock().unwrap().clone(),
+        };
+
+        if let Ok(json) = serde_json::to_string(&file) {
+            let _ = durability::write_atomic(&self.path, json.as_bytes());
+        }
+    }
+}
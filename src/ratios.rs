@@ -0,0 +1,141 @@
+use crate::{
+    decoder::Format,
+    files::RemoteFile,
+    mirror_stats,
+    state_store::{ManifestEntry, StateStore},
+    MB_SIZE,
+};
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+/// Sizes of the files decoded so far
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Ratio {
+    /// Number of files
+    pub files: usize,
+    /// Size of the compressed files in bytes
+    pub compressed: u64,
+    /// Size of the decoded files in bytes
+    pub decoded: u64,
+}
+
+impl Ratio {
+    /// Returns how many times larger the decoded files are than the compressed files
+    pub fn ratio(&self) -> f64 {
+        self.decoded as f64 / self.compressed.max(1) as f64
+    }
+}
+
+/// Category (maps, sounds, ...) -> sizes of every file decoded since the program started
+static RATIOS: Mutex<BTreeMap<&'static str, Ratio>> = Mutex::new(BTreeMap::new());
+
+/// Counts a decoded file
+///
+/// # Arguments
+/// * `output`      -   Path the file was decoded to
+/// * `compressed`  -   Size of the compressed file in bytes
+/// * `decoded`     -   Size of the decoded file in bytes
+pub fn record(output: &Path, compressed: u64, decoded: u64) {
+    let mut ratios = RATIOS.lock().unwrap();
+    let ratio = ratios.entry(mirror_stats::category(output)).or_default();
+    ratio.files += 1;
+    ratio.compressed += compressed;
+    ratio.decoded += decoded;
+}
+
+/// Returns the total compressed and decoded sizes with the ratio of every category
+/// e.g. "412.00 MB -> 1350.20 MB (3.28x): maps 3.51x, sounds 1.12x"
+pub fn summary() -> String {
+    let ratios = RATIOS.lock().unwrap();
+    if ratios.is_empty() {
+        return String::from("nothing decoded");
+    }
+
+    let total = ratios
+        .values()
+        .fold(Ratio::default(), |total, ratio| Ratio {
+            files: total.files + ratio.files,
+            compressed: total.compressed + ratio.compressed,
+            decoded: total.decoded + ratio.decoded,
+        });
+    let categories = ratios
+        .iter()
+        .map(|(category, ratio)| format!("{} {:.2}x", category, ratio.ratio()))
+        .collect::<Vec<_>>();
+
+    format!(
+        "{:.2} MB -> {:.2} MB ({:.2}x): {}",
+        total.compressed as f32 / MB_SIZE as f32,
+        total.decoded as f32 / MB_SIZE as f32,
+        total.ratio(),
+        categories.join(", ")
+    )
+}
+
+/// Records the decoded size of every decoded download in the manifest, so its ratio is known later
+///
+/// # Arguments
+/// * `store`   -   State store whose manifest has the downloads
+/// * `decoded` -   Path of every compressed file that was decoded with its decoded size
+pub fn record_in_manifest(store: &dyn StateStore, decoded: &[(PathBuf, u64)]) {
+    if decoded.is_empty() {
+        return;
+    }
+
+    let by_path = store
+        .files()
+        .into_iter()
+        .filter_map(|(url, entry)| Some((entry.path.clone()?, (url, entry))))
+        .collect::<HashMap<_, _>>();
+
+    for (path, decoded_size) in decoded {
+        if let Some((url, entry)) = by_path.get(path) {
+            store.record_file(
+                url,
+                ManifestEntry {
+                    decoded_size: Some(*decoded_size),
+                    ..entry.clone()
+                },
+            );
+        }
+    }
+}
+
+/// Returns the disk space `links` will take once they're downloaded and decoded
+/// The ratio of the compressed files of every category comes from the decoded sizes in the manifest
+/// (1x until one is known), files whose size is unknown are not counted
+///
+/// # Arguments
+/// * `files`   -   Every file in the manifest
+/// * `links`   -   Files that will be downloaded
+pub fn forecast<'a>(
+    files: &[(String, ManifestEntry)],
+    links: impl Iterator<Item = &'a RemoteFile>,
+) -> u64 {
+    let mut known = BTreeMap::<&'static str, Ratio>::new();
+    for (_, entry) in files {
+        let (Some(path), Some(decoded)) = (&entry.path, entry.decoded_size) else {
+            continue;
+        };
+        let ratio = known.entry(mirror_stats::category(path)).or_default();
+        ratio.files += 1;
+        ratio.compressed += entry.size;
+        ratio.decoded += decoded;
+    }
+
+    links
+        .filter_map(|file| {
+            let size = file.size?;
+            if Format::from_name(&file.rel_path).is_none() {
+                return Some(size);
+            }
+            let ratio = known
+                .get(mirror_stats::category(Path::new(&file.rel_path)))
+                .map_or(1.0, Ratio::ratio);
+            Some((size as f64 * ratio) as u64)
+        })
+        .sum()
+}
@@ -0,0 +1,93 @@
+use std::{fs, io, path::Path};
+
+/// Clones the file `src` to `dst` copy-on-write: both share their blocks until one of them is written
+/// Fails with `io::ErrorKind::Unsupported` if the filesystem (or the platform) can't clone,
+/// e.g. ext4, NTFS or a destination on another filesystem than the source
+///
+/// # Arguments
+/// * `src`     -   File that is cloned
+/// * `dst`     -   Path of the clone (must not exist)
+#[cfg(target_os = "linux")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    /// `_IOW(0x94, 9, int)`, supported by btrfs, XFS (with reflink=1) and bcachefs
+    const FICLONE: u64 = 0x40049409;
+
+    let src_file = fs::File::open(src)?;
+    let dst_file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(dst)?;
+
+    // SAFETY: both descriptors stay open for the duration of the call
+    if unsafe { libc::ioctl(dst_file.as_raw_fd(), FICLONE as _, src_file.as_raw_fd()) } == 0 {
+        return Ok(());
+    }
+
+    let e = io::Error::last_os_error();
+    drop(dst_file);
+    let _ = fs::remove_file(dst);
+
+    match e.raw_os_error() {
+        Some(libc::EOPNOTSUPP) | Some(libc::EXDEV) | Some(libc::EINVAL) | Some(libc::ENOTTY) => {
+            Err(io::Error::new(io::ErrorKind::Unsupported, e))
+        }
+        _ => Err(e),
+    }
+}
+
+/// Clones the file `src` to `dst` copy-on-write with `clonefile` (APFS)
+///
+/// # Arguments
+/// * `src`     -   File that is cloned
+/// * `dst`     -   Path of the clone (must not exist)
+#[cfg(target_os = "macos")]
+pub fn clone_file(src: &Path, dst: &Path) -> io::Result<()> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let to_c = |path: &Path| {
+        CString::new(path.as_os_str().as_bytes())
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+    };
+    let (src, dst) = (to_c(src)?, to_c(dst)?);
+
+    // SAFETY: both paths are valid C strings
+    if unsafe { libc::clonefile(src.as_ptr(), dst.as_ptr(), 0) } == 0 {
+        return Ok(());
+    }
+
+    let e = io::Error::last_os_error();
+    match e.raw_os_error() {
+        Some(libc::ENOTSUP) | Some(libc::EXDEV) => {
+            Err(io::Error::new(io::ErrorKind::Unsupported, e))
+        }
+        _ => Err(e),
+    }
+}
+
+/// Cloning is not implemented on this platform (ReFS block cloning needs the Windows API)
+///
+/// # Arguments
+/// * `src`     -   File that is cloned
+/// * `dst`     -   Path of the clone
+#[cfg(not(any(target_os = "linux", target_os = "macos")))]
+pub fn clone_file(_src: &Path, _dst: &Path) -> io::Result<()> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "copy-on-write clones are not supported on this platform",
+    ))
+}
+
+/// Returns true if the regular file `dst` no longer has the size of `src`
+/// A clone is a copy, so it doesn't follow a new version of its source like a link does
+///
+/// # Arguments
+/// * `src`     -   File of the mirror
+/// * `dst`     -   Clone of the file in a game directory
+pub fn is_stale(src: &Path, dst: &Path) -> bool {
+    match (fs::metadata(src), fs::symlink_metadata(dst)) {
+        (Ok(src), Ok(dst)) => dst.is_file() && src.len() != dst.len(),
+        _ => false,
+    }
+}
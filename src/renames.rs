@@ -0,0 +1,121 @@
+use crate::{
+    files::RemoteFile,
+    fingerprint,
+    repair::{self, CHUNK_SIZE},
+    state_store::ManifestEntry,
+    transport::Transport,
+};
+use reqwest::StatusCode;
+use std::{
+    collections::{HashMap, HashSet},
+    io::Read,
+};
+
+/// Rename is a file the fastdl now serves under another link, with the same content
+#[derive(Debug)]
+pub struct Rename {
+    /// Link the file was synced from
+    pub from: String,
+    /// Link the fastdl serves it under now
+    pub to: String,
+    /// Manifest entry of the synced file
+    pub entry: ManifestEntry,
+}
+
+/// Returns the extensions of the file name of `link` (e.g. ".bsp.bz2"), renames never change them
+fn extensions(link: &str) -> &str {
+    let name = link.rsplit('/').next().unwrap_or(link);
+    name.find('.').map_or("", |pos| &name[pos..])
+}
+
+/// Returns true if the chunks `indexes` of the file at `url` match the checksums of `entry`
+/// Hosts that ignore ranged requests are never asked, the whole file would be sent for every chunk
+///
+/// # Arguments
+/// * `transport`   -   Sends the ranged requests
+/// * `url`         -   Link of the remote file
+/// * `entry`       -   Manifest entry of the synced file (same size as the remote file)
+/// * `indexes`     -   Chunks that are compared
+fn chunks_match(
+    transport: &Transport,
+    url: &str,
+    entry: &ManifestEntry,
+    indexes: &[usize],
+) -> bool {
+    let host = reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default();
+    if fingerprint::ranges(&host) == Some(false) {
+        return false;
+    }
+
+    indexes.iter().all(|&idx| {
+        let Some(expected) = entry.chunks.get(idx) else {
+            return false;
+        };
+        let start = (idx * CHUNK_SIZE) as u64;
+        let end = (start + CHUNK_SIZE as u64).min(entry.size) - 1;
+
+        let Ok(mut resp) = transport.get_range(url, start, end) else {
+            return false;
+        };
+        let mut chunk = Vec::with_capacity((end - start + 1) as usize);
+        resp.status() == StatusCode::PARTIAL_CONTENT
+            && resp.read_to_end(&mut chunk).is_ok()
+            && repair::checksums(&chunk).first() == Some(expected)
+    })
+}
+
+/// Finds the links of `links` whose file the mirror already has under a link the fastdl no longer lists
+/// A candidate has the same size and extensions as the new link, and the first and last chunks of the
+/// new link (two ranged requests) must match the checksums the manifest keeps of the synced file,
+/// so a re-upload under the same name with other content is never mistaken for a rename
+///
+/// # Arguments
+/// * `links`       -   Files that will be downloaded
+/// * `listed`      -   Every link the fastdl lists now
+/// * `mirrored`    -   Manifest entries of the synced files of the fastdl whose local copy is still there
+/// * `transport`   -   Sends the ranged requests
+pub fn detect(
+    links: &HashSet<RemoteFile>,
+    listed: &HashSet<String>,
+    mirrored: Vec<(String, ManifestEntry)>,
+    transport: &Transport,
+) -> Vec<Rename> {
+    // Files the fastdl stopped listing, by size
+    let mut vanished = HashMap::<u64, Vec<(String, ManifestEntry)>>::new();
+    for (link, entry) in mirrored {
+        if entry.size > 0 && !entry.chunks.is_empty() && !listed.contains(&link) {
+            vanished.entry(entry.size).or_default().push((link, entry));
+        }
+    }
+    if vanished.is_empty() {
+        return Vec::new();
+    }
+
+    let mut renames = Vec::new();
+    for file in links {
+        let Some(candidates) = file.size.and_then(|size| vanished.get_mut(&size)) else {
+            continue;
+        };
+
+        let found = candidates.iter().position(|(link, entry)| {
+            let last = entry.chunks.len() - 1;
+            let indexes = if last == 0 { vec![0] } else { vec![0, last] };
+
+            extensions(link) == extensions(&file.url)
+                && chunks_match(transport, &file.url, entry, &indexes)
+        });
+        if let Some(idx) = found {
+            let (from, entry) = candidates.swap_remove(idx);
+            renames.push(Rename {
+                from,
+                to: file.url.clone(),
+                entry,
+            });
+        }
+    }
+
+    renames
+}
@@ -0,0 +1,260 @@
+use crate::blocklist::sha256_hex;
+use crate::net;
+use crate::state_store::{unix_now, ManifestEntry, StateStore};
+use crate::throttle::Throttle;
+use reqwest::{blocking::Client, header::RANGE, StatusCode};
+use std::{
+    error::Error,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+    sync::Arc,
+};
+
+/// Size of the chunks a file is checksummed (and repaired) in
+pub const CHUNK_SIZE: usize = crate::MB_SIZE;
+/// Number of hex characters of the sha256 kept per chunk (64 bits is plenty to spot damage)
+const CHECKSUM_LEN: usize = 16;
+
+/// Outcome of verifying one file of the mirror
+#[derive(Debug)]
+pub enum Outcome {
+    /// Every chunk matched its checksum
+    Intact,
+    /// Only the damaged chunks were downloaded again
+    Repaired(usize),
+    /// The whole file was downloaded again (no ranges, missing file or changed remote)
+    Redownloaded,
+    /// The file could not be verified or repaired
+    Failed(String),
+}
+
+/// Returns the checksum of every `CHUNK_SIZE` chunk of `bytes`
+///
+/// # Arguments
+/// * `bytes`   -   Content of the whole file
+pub fn checksums(bytes: &[u8]) -> Vec<String> {
+    bytes.chunks(CHUNK_SIZE).map(checksum).collect()
+}
+
+/// Returns the checksum of a single chunk
+fn checksum(chunk: &[u8]) -> String {
+    sha256_hex(chunk)[..CHECKSUM_LEN].to_string()
+}
+
+/// Returns the length of chunk `idx` of a file that is `size` bytes long
+fn chunk_len(idx: usize, size: u64) -> usize {
+    (size - (idx * CHUNK_SIZE) as u64).min(CHUNK_SIZE as u64) as usize
+}
+
+/// Returns the indexes of the chunks of the local file that don't match the manifest
+/// Chunks past the end of a truncated file count as damaged
+///
+/// # Arguments
+/// * `path`    -   Local copy of the file
+/// * `entry`   -   Manifest entry with the expected size and checksums
+pub fn damaged_chunks(path: &Path, entry: &ManifestEntry) -> io::Result<Vec<usize>> {
+    let mut file = File::open(path)?;
+    let mut buf = vec![0; CHUNK_SIZE];
+
+    let damaged = entry
+        .chunks
+        .iter()
+        .enumerate()
+        .filter(|(idx, expected)| {
+            let chunk = &mut buf[..chunk_len(*idx, entry.size)];
+
+            // Seek before every read, a failed read leaves the position undefined
+            let intact = file
+                .seek(SeekFrom::Start((idx * CHUNK_SIZE) as u64))
+                .is_ok()
+                && file.read_exact(chunk).is_ok()
+                && checksum(chunk) == **expected;
+
+            !intact
+        })
+        .map(|(idx, _)| idx)
+        .collect::<Vec<_>>();
+
+    // Leftover bytes after the last chunk damage the file too
+    if damaged.is_empty() && file.metadata()?.len() != entry.size {
+        return Ok(vec![entry.chunks.len().saturating_sub(1)]);
+    }
+
+    Ok(damaged)
+}
+
+/// Downloads the damaged chunks with ranged requests and writes them in place
+/// Returns false (without changing the file) if the server ignores ranges
+///
+/// # Arguments
+/// * `client`      -   Client used to send the requests
+/// * `url`         -   Download link of the file
+/// * `path`        -   Local copy of the file
+/// * `entry`       -   Manifest entry with the expected size and checksums
+/// * `damaged`     -   Indexes of the damaged chunks
+/// * `throttle`    -   Speed caps the downloads share
+fn repair_chunks(
+    client: &Client,
+    url: &str,
+    path: &Path,
+    entry: &ManifestEntry,
+    damaged: &[usize],
+    throttle: &Throttle,
+) -> Result<bool, Box<dyn Error>> {
+    let mut chunks = Vec::with_capacity(damaged.len());
+
+    // Fetch every chunk before writing, so a server without ranges leaves the file untouched
+    for idx in damaged.iter().copied() {
+        let start = idx * CHUNK_SIZE;
+        let end = start + chunk_len(idx, entry.size) - 1;
+
+        let resp = client
+            .get(url)
+            .header(RANGE, format!("bytes={}-{}", start, end))
+            .send()?;
+        if resp.status() != StatusCode::PARTIAL_CONTENT {
+            return Ok(false);
+        }
+
+        let bytes = crate::read_body(resp, throttle, None)?;
+        if checksum(&bytes) != entry.chunks[idx] {
+            return Err(format!("chunk {} changed on the server", idx).into());
+        }
+
+        chunks.push((start, bytes));
+    }
+
+    let mut file = OpenOptions::new().write(true).open(path)?;
+    for (start, bytes) in chunks {
+        file.seek(SeekFrom::Start(start as u64))?;
+        file.write_all(&bytes)?;
+    }
+    file.set_len(entry.size)?;
+
+    Ok(true)
+}
+
+/// Downloads the whole file again and records its new checksums
+///
+/// # Arguments
+/// * `client`      -   Client used to send the request
+/// * `url`         -   Download link of the file
+/// * `path`        -   Local copy of the file
+/// * `archived_from`   -   Wayback Machine copy the file came from (see `ManifestEntry::archived_from`)
+/// * `store`       -   State store the new manifest entry is recorded in
+/// * `throttle`    -   Speed caps the downloads share
+fn redownload(
+    client: &Client,
+    url: &str,
+    path: &Path,
+    archived_from: Option<&str>,
+    store: &Arc<dyn StateStore>,
+    throttle: &Throttle,
+) -> Result<(), Box<dyn Error>> {
+    // Archived files are no longer on the fastdl, so they're fetched from the archive again
+    let fetch_url = archived_from.unwrap_or(url);
+    let bytes = crate::read_body(
+        client.get(fetch_url).send()?.error_for_status()?,
+        throttle,
+        None,
+    )?;
+
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, &bytes)?;
+
+    store.record_file(
+        url,
+        ManifestEntry {
+            size: bytes.len() as u64,
+            synced_at: unix_now(),
+            path: Some(path.to_path_buf()),
+            chunks: checksums(&bytes),
+            archived_from: archived_from.map(String::from),
+            decoded_size: None,
+            sha256: Some(sha256_hex(&bytes)),
+        },
+    );
+
+    Ok(())
+}
+
+/// Verifies one file against its manifest entry, repairing the damaged chunks if there are any
+///
+/// # Arguments
+/// * `client`      -   Client used to send the requests
+/// * `url`         -   Download link of the file
+/// * `entry`       -   Manifest entry of the file
+/// * `store`       -   State store the file is recorded in
+/// * `throttle`    -   Speed caps the downloads share
+pub fn verify_file(
+    client: &Client,
+    url: &str,
+    entry: &ManifestEntry,
+    store: &Arc<dyn StateStore>,
+    throttle: &Throttle,
+) -> Option<Outcome> {
+    let path = entry.path.as_deref()?;
+
+    // bz2 files are removed once they're decoded, there's nothing left to verify
+    if entry.chunks.is_empty() || (!path.exists() && path.extension().is_some_and(|e| e == "bz2")) {
+        return None;
+    }
+
+    let archived_from = entry.archived_from.as_deref();
+    let fetch_url = archived_from.unwrap_or(url);
+
+    let result = match damaged_chunks(path, entry) {
+        Ok(damaged) if damaged.is_empty() => Ok(Outcome::Intact),
+        Ok(damaged) => match repair_chunks(client, fetch_url, path, entry, &damaged, throttle) {
+            Ok(true) => Ok(Outcome::Repaired(damaged.len())),
+            // No ranges or the file changed on the server: fall back to a full download
+            Ok(false) | Err(_) => redownload(client, url, path, archived_from, store, throttle)
+                .map(|_| Outcome::Redownloaded),
+        },
+        Err(_) => redownload(client, url, path, archived_from, store, throttle)
+            .map(|_| Outcome::Redownloaded),
+    };
+
+    Some(result.unwrap_or_else(|e| Outcome::Failed(e.to_string())))
+}
+
+/// Verifies every file of the manifest, printing every file that was not intact
+///
+/// # Arguments
+/// * `store`       -   State store with the manifest of the mirror
+/// * `throttle`    -   Speed caps the downloads share
+pub fn verify_mirror(store: &Arc<dyn StateStore>, throttle: &Throttle) {
+    let client = net::client();
+    let (mut intact, mut repaired, mut failed) = (0, 0, 0);
+
+    for (url, entry) in store.files() {
+        match verify_file(&client, &url, &entry, store, throttle) {
+            None => {}
+            Some(Outcome::Intact) => intact += 1,
+            Some(Outcome::Repaired(chunks)) => {
+                repaired += 1;
+                println!("Repaired {} chunk(s):\t{}", chunks, url);
+            }
+            Some(Outcome::Redownloaded) => {
+                repaired += 1;
+                println!("Downloaded again:\t{}", url);
+            }
+            Some(Outcome::Failed(e)) => {
+                failed += 1;
+                println!("Failed to repair:\t{} ({})", url, e);
+            }
+        }
+    }
+
+    if let Err(err) = store.save() {
+        eprintln!("Failed to save the state store: {}", err);
+    }
+
+    println!(
+        "\n{} intact, {} repaired, {} failed",
+        intact, repaired, failed
+    );
+}
@@ -0,0 +1,126 @@
+use crate::{bsp, net, state_store::ManifestEntry, MB_SIZE};
+use regex::Regex;
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    time::{Duration, UNIX_EPOCH},
+};
+
+/// MapRow is a single map in the report
+pub struct MapRow {
+    /// Map name without any extension (e.g. "ze_example_v1")
+    pub name: String,
+    /// Downloaded size in bytes
+    pub size: u64,
+    /// Unix time the map was downloaded
+    pub synced_at: u64,
+    /// Tier scraped from a community map database (if requested and found)
+    pub tier: Option<String>,
+    /// What the entities of the map need from the server (None if the decoded map is not on disk)
+    pub requirements: Option<String>,
+}
+
+/// Returns the map name of a download link, or None if the link is not a map
+///
+/// # Arguments
+/// * `url`     -   Download link of the file
+pub fn map_name(url: &str) -> Option<String> {
+    let file_name = url.rsplit('/').next()?;
+    let name = file_name.strip_suffix(".bz2").unwrap_or(file_name);
+
+    name.strip_suffix(".bsp").map(|s| s.to_string())
+}
+
+/// Returns a MapRow for every map in the manifest, sorted by name
+///
+/// # Arguments
+/// * `files`   -   Every downloaded file in the manifest
+/// * `map_db`  -   Url template of a community map database page (`{map}` is replaced by the map name)
+pub fn collect_rows(files: &[(String, ManifestEntry)], map_db: Option<&str>) -> Vec<MapRow> {
+    let mut rows = files
+        .iter()
+        .filter_map(|(url, entry)| {
+            map_name(url).map(|name| MapRow {
+                tier: map_db.and_then(|db| scrape_tier(db, &name)),
+                requirements: entry
+                    .path
+                    .as_deref()
+                    .and_then(Path::parent)
+                    .and_then(|dir| bsp::scan(&dir.join(format!("{}.bsp", name))).ok())
+                    .map(|scan| scan.to_string()),
+                name,
+                size: entry.size,
+                synced_at: entry.synced_at,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    rows.sort_by(|a, b| a.name.cmp(&b.name));
+    rows
+}
+
+/// Scrapes the tier of a map from a community map database page
+/// The page is expected to contain something along the lines of "Tier: 3"
+///
+/// # Arguments
+/// * `map_db`  -   Url template of the page (`{map}` is replaced by the map name)
+/// * `map`     -   Map name
+fn scrape_tier(map_db: &str, map: &str) -> Option<String> {
+    let re = Regex::new(r"(?i)tier\s*:?\s*(\d+)").unwrap();
+    let page = net::get(&map_db.replace("{map}", map)).ok()?.text().ok()?;
+
+    re.captures(&page).map(|c| c[1].to_string())
+}
+
+/// Writes the map report to `path` (HTML if the path ends with ".html", Markdown otherwise)
+///
+/// # Arguments
+/// * `path`    -   Path of the report
+/// * `rows`    -   Maps that are written to the report
+pub fn write_report(path: &Path, rows: &[MapRow]) -> Result<(), Box<dyn Error>> {
+    let is_html = path.extension().is_some_and(|ext| ext == "html");
+    let total = rows.iter().map(|row| row.size).sum::<u64>() as f32 / MB_SIZE as f32;
+    let cells = |row: &MapRow| {
+        [
+            row.name.clone(),
+            format!("{:.2} MB", row.size as f32 / MB_SIZE as f32),
+            httpdate::fmt_http_date(UNIX_EPOCH + Duration::from_secs(row.synced_at)),
+            row.tier.clone().unwrap_or_else(|| String::from("-")),
+            row.requirements
+                .clone()
+                .unwrap_or_else(|| String::from("-")),
+        ]
+    };
+
+    let mut out = String::new();
+
+    if is_html {
+        out.push_str("<html><body>\n<h1>Synced Maps</h1>\n<table>\n");
+        out.push_str("<tr><th>Map</th><th>Size</th><th>Downloaded</th><th>Tier</th><th>Server requirements</th></tr>\n");
+        for row in rows {
+            out.push_str("<tr>");
+            for cell in cells(row) {
+                out.push_str(&format!("<td>{}</td>", cell));
+            }
+            out.push_str("</tr>\n");
+        }
+        out.push_str(&format!(
+            "</table>\n<p>{} maps, {:.2} MB</p>\n</body></html>\n",
+            rows.len(),
+            total
+        ));
+    } else {
+        out.push_str(
+            "# Synced Maps\n\n| Map | Size | Downloaded | Tier | Server requirements |\n| --- | --- | --- | --- | --- |\n",
+        );
+        for row in rows {
+            out.push_str(&format!("| {} |\n", cells(row).join(" | ")));
+        }
+        out.push_str(&format!("\n{} maps, {:.2} MB\n", rows.len(), total));
+    }
+
+    fs::write(path, out)?;
+
+    Ok(())
+}
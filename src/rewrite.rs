@@ -0,0 +1,92 @@
+use regex::Regex;
+use serde::Deserialize;
+use url::Url;
+
+/// RuleSpec is a rewrite rule as written in a profile
+#[derive(Clone, Debug, Deserialize)]
+pub struct RuleSpec {
+    /// Host whose links are rewritten (every host if None)
+    pub host: Option<String>,
+    /// Regular expression matched against the whole link
+    pub find: String,
+    /// Replacement of the match (`$1`, `${name}` for the groups)
+    pub replace: String,
+}
+
+/// Rule rewrites the links of a host that match a regular expression
+#[derive(Clone, Debug)]
+struct Rule {
+    /// Lowercased host whose links are rewritten (every host if None)
+    host: Option<String>,
+    /// Regular expression matched against the whole link
+    find: Regex,
+    /// Replacement of the match
+    replace: String,
+}
+
+/// Rewrites turn the links found in the listings into the links the files are really served from,
+/// for mirrors whose listings don't point at the downloads (`--rewrite <host> <find> <replace>`,
+/// or `"rewrites"` in a profile)
+/// Every rule whose host matches is applied in order, each to the result of the previous one
+#[derive(Clone, Debug, Default)]
+pub struct Rewrites {
+    /// Rules in the order they were given
+    rules: Vec<Rule>,
+}
+
+impl Rewrites {
+    /// Adds a rule after the others
+    ///
+    /// # Arguments
+    /// * `host`    -   Host whose links are rewritten (`*` for every host)
+    /// * `find`    -   Regular expression matched against the whole link
+    /// * `replace` -   Replacement of the match (`$1`, `${name}` for the groups)
+    pub fn insert(&mut self, host: &str, find: &str, replace: &str) -> Result<(), String> {
+        let find =
+            Regex::new(find).map_err(|err| format!("Invalid rewrite pattern {}: {}", find, err))?;
+
+        self.rules.push(Rule {
+            host: (host != "*").then(|| host.to_lowercase()),
+            find,
+            replace: replace.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Adds a rule written in a profile
+    pub fn insert_spec(&mut self, spec: &RuleSpec) -> Result<(), String> {
+        self.insert(
+            spec.host.as_deref().unwrap_or("*"),
+            &spec.find,
+            &spec.replace,
+        )
+    }
+
+    /// Returns true if there are no rules
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns `url` rewritten by every rule of its host
+    /// A rewrite that doesn't give a valid url is ignored
+    ///
+    /// # Arguments
+    /// * `url` -   Link found in a listing (or a sitemap)
+    pub fn apply(&self, url: Url) -> Url {
+        self.rules.iter().fold(url, |url, rule| {
+            let host_matches = rule
+                .host
+                .as_deref()
+                .is_none_or(|host| url.host_str().is_some_and(|h| h.eq_ignore_ascii_case(host)));
+            if !host_matches {
+                return url;
+            }
+
+            let rewritten = rule
+                .find
+                .replace_all(url.as_str(), rule.replace.as_str())
+                .into_owned();
+            Url::parse(&rewritten).unwrap_or(url)
+        })
+    }
+}
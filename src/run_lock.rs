@@ -0,0 +1,91 @@
+use crate::{meta_dir, state_store::unix_now};
+use fs2::FileExt;
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Seek, SeekFrom, Write},
+    path::Path,
+};
+
+/// Name of the lock file stored in the output directory
+pub const LOCK_FILE: &str = "fastdl.lock";
+
+/// RunLock keeps other instances from syncing into the same output directory while it's held
+/// The OS releases the lock when the process dies, so a lock file left behind by a crash is stale
+/// and taken over; the file itself only tells the next instance who holds the lock
+/// The file is emptied instead of removed when the run finishes: removing it would let two waiting
+/// instances lock different files
+pub struct RunLock {
+    /// Locked file (the lock is released when it's closed)
+    file: File,
+}
+
+/// Returns the "pid started_at" line of the instance holding the lock in a readable form
+fn describe_holder(contents: &str) -> String {
+    let mut fields = contents.split_whitespace();
+
+    match (
+        fields.next(),
+        fields.next().and_then(|t| t.parse::<u64>().ok()),
+    ) {
+        (Some(pid), Some(started_at)) => format!(
+            "pid {}, started {} s ago",
+            pid,
+            unix_now().saturating_sub(started_at)
+        ),
+        _ => String::from("unknown process"),
+    }
+}
+
+impl RunLock {
+    /// Locks `dir` for this run, failing with a readable error if another instance is syncing into it
+    ///
+    /// # Arguments
+    /// * `dir`     -   Output directory
+    pub fn acquire(dir: &Path) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let path = meta_dir::path(dir, LOCK_FILE);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&path)?;
+
+        let mut previous = String::new();
+        let _ = file.read_to_string(&mut previous);
+
+        if file.try_lock_exclusive().is_err() {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                format!(
+                    "Another instance is already syncing into {} ({}). Wait for it to finish or stop it; \
+                     overlapping syncs corrupt each other's partial files and manifest",
+                    dir.display(),
+                    describe_holder(&previous)
+                ),
+            ));
+        }
+
+        if !previous.trim().is_empty() {
+            println!(
+                "Took over the stale lock of {} ({} did not finish)",
+                dir.display(),
+                describe_holder(&previous)
+            );
+        }
+
+        file.set_len(0)?;
+        file.seek(SeekFrom::Start(0))?;
+        writeln!(file, "{} {}", std::process::id(), unix_now())?;
+
+        Ok(Self { file })
+    }
+}
+
+impl Drop for RunLock {
+    fn drop(&mut self) {
+        // Emptied before it's unlocked, so the next instance does not mistake this run for a crashed one
+        let _ = self.file.set_len(0);
+        let _ = self.file.unlock();
+    }
+}
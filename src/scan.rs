@@ -0,0 +1,55 @@
+use crate::{meta_dir::META_DIR, snapshot::SNAPSHOTS_DIR};
+use jwalk::WalkDir;
+use std::path::{Path, PathBuf};
+
+/// Directories that belong to the OS and never contain anything synced
+const SYSTEM_DIRS: &[&str] = &["$RECYCLE.BIN", "System Volume Information", "lost+found"];
+
+/// ScanPolicy decides which entries a local scan walks into
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ScanPolicy {
+    /// Follow symlinked directories and files (`--follow-symlinks`)
+    pub follow_symlinks: bool,
+    /// Skip hidden (dot) and system directories (`--skip-hidden`)
+    pub skip_hidden: bool,
+}
+
+impl ScanPolicy {
+    /// Returns true if the entry named `name` should not be scanned
+    /// The state directory is never scanned, nor snapshots left at the top by older releases:
+    /// their files are hardlinks of the mirror's
+    ///
+    /// # Arguments
+    /// * `name`    -   File or directory name of the entry
+    fn skips(&self, name: &str) -> bool {
+        name == META_DIR
+            || name == SNAPSHOTS_DIR
+            || self.skip_hidden && (name.starts_with('.') || SYSTEM_DIRS.contains(&name))
+    }
+}
+
+/// Recursively collects the files below `root` that match `wanted`, reading directories in parallel
+/// `root` itself is returned if it is a matching file
+///
+/// # Arguments
+/// * `root`    -   Directory (or single file) that is scanned
+/// * `policy`  -   Which symlinks and hidden entries are walked into
+/// * `wanted`  -   Returns true for the file names that are collected
+pub fn find_files<F: Fn(&str) -> bool>(root: &Path, policy: ScanPolicy, wanted: F) -> Vec<PathBuf> {
+    WalkDir::new(root)
+        .follow_links(policy.follow_symlinks)
+        .skip_hidden(false)
+        .process_read_dir(move |_, _, _, children| {
+            children.retain(|child| {
+                child.as_ref().map_or(true, |entry| {
+                    !policy.skips(&entry.file_name.to_string_lossy())
+                })
+            });
+        })
+        .into_iter()
+        .flatten()
+        .filter(|entry| !entry.file_type.is_dir())
+        .filter(|entry| wanted(entry.file_name.to_str().unwrap_or_default().trim()))
+        .map(|entry| entry.path())
+        .collect()
+}
@@ -0,0 +1,151 @@
+use std::str::FromStr;
+
+/// Names of the days of the week, starting on Monday
+const DAY_NAMES: [&str; 7] = ["mon", "tue", "wed", "thu", "fri", "sat", "sun"];
+/// Number of minutes in a day
+const DAY_MINUTES: u32 = 24 * 60;
+
+/// Window is a weekly time window (`[days] HH:MM-HH:MM`, in UTC)
+/// A window ending before it starts runs past midnight into the next day
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Window {
+    /// Days the window starts on (Monday first)
+    days: [bool; 7],
+    /// Minute of the day the window starts at
+    start: u32,
+    /// Minute of the day the window ends at (the whole day if it equals `start`)
+    end: u32,
+}
+
+/// Returns the index (Monday = 0) of a day name such as "mon" or "Monday"
+fn parse_day(day: &str) -> Result<usize, String> {
+    let day = day.to_lowercase();
+
+    DAY_NAMES
+        .iter()
+        .position(|name| day.starts_with(name))
+        .ok_or_else(|| format!("Unknown day: {}", day))
+}
+
+/// Returns the days of a day list such as "*", "Mon-Fri" or "Sat,Sun"
+fn parse_days(days: &str) -> Result<[bool; 7], String> {
+    if days == "*" {
+        return Ok([true; 7]);
+    }
+
+    let mut selected = [false; 7];
+
+    for item in days.split(',') {
+        match item.split_once('-') {
+            // Ranges may wrap around the week (e.g. "Fri-Mon")
+            Some((first, last)) => {
+                let (first, last) = (parse_day(first)?, parse_day(last)?);
+                let mut day = first;
+                loop {
+                    selected[day] = true;
+                    if day == last {
+                        break;
+                    }
+                    day = (day + 1) % 7;
+                }
+            }
+            None => selected[parse_day(item)?] = true,
+        }
+    }
+
+    Ok(selected)
+}
+
+/// Returns the minute of the day of a time such as "02:30"
+fn parse_time(time: &str) -> Result<u32, String> {
+    let invalid = || format!("Invalid time: {} (expected HH:MM)", time);
+    let (hours, minutes) = time.split_once(':').ok_or_else(invalid)?;
+    let hours = hours.parse::<u32>().map_err(|_| invalid())?;
+    let minutes = minutes.parse::<u32>().map_err(|_| invalid())?;
+
+    // "24:00" is allowed so a window can end at midnight
+    if minutes >= 60 || hours * 60 + minutes > DAY_MINUTES {
+        return Err(invalid());
+    }
+
+    Ok((hours * 60 + minutes) % DAY_MINUTES)
+}
+
+impl FromStr for Window {
+    type Err = String;
+
+    /// Parses "Mon-Fri 02:00-06:00", "Sat,Sun *" or "22:00-02:00" (every day)
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parts = s.split_whitespace().collect::<Vec<_>>();
+        let (days, times) = match parts.as_slice() {
+            [times] => ("*", *times),
+            [days, times] => (*days, *times),
+            _ => return Err(format!("Invalid time window: {}", s)),
+        };
+
+        let (start, end) = match times {
+            "*" => (0, 0),
+            _ => {
+                let (start, end) = times
+                    .split_once('-')
+                    .ok_or_else(|| format!("Invalid time window: {} (expected HH:MM-HH:MM)", s))?;
+                (parse_time(start)?, parse_time(end)?)
+            }
+        };
+
+        Ok(Self {
+            days: parse_days(days)?,
+            start,
+            end,
+        })
+    }
+}
+
+impl Window {
+    /// Returns true if the window is open at `minute` of `weekday`
+    ///
+    /// # Arguments
+    /// * `weekday` -   Day of the week (Monday = 0)
+    /// * `minute`  -   Minute of the day
+    pub fn contains(&self, weekday: usize, minute: u32) -> bool {
+        let yesterday = (weekday + 6) % 7;
+
+        if self.start == self.end {
+            self.days[weekday]
+        } else if self.start < self.end {
+            self.days[weekday] && (self.start..self.end).contains(&minute)
+        } else {
+            // Runs past midnight: the end belongs to the day after the one it started on
+            (self.days[weekday] && minute >= self.start)
+                || (self.days[yesterday] && minute < self.end)
+        }
+    }
+}
+
+/// Schedule decides when watch mode may sync
+#[derive(Clone, Debug, Default)]
+pub struct Schedule {
+    /// Windows during which syncing is allowed (always allowed if empty) (`--sync-window <window>`)
+    pub windows: Vec<Window>,
+    /// Windows during which syncing is never allowed (`--quiet-hours <window>`)
+    pub quiet_hours: Vec<Window>,
+}
+
+impl Schedule {
+    /// Returns true if a sync may start at `unix_time`
+    ///
+    /// # Arguments
+    /// * `unix_time`   -   Seconds since the unix epoch (UTC)
+    pub fn allows(&self, unix_time: u64) -> bool {
+        let days = unix_time / 86400;
+        // 1970-01-01 was a Thursday
+        let weekday = ((days + 3) % 7) as usize;
+        let minute = ((unix_time % 86400) / 60) as u32;
+
+        let in_window =
+            self.windows.is_empty() || self.windows.iter().any(|w| w.contains(weekday, minute));
+        let in_quiet_hours = self.quiet_hours.iter().any(|w| w.contains(weekday, minute));
+
+        in_window && !in_quiet_hours
+    }
+}
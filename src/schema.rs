@@ -0,0 +1,440 @@
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::{fs, io, path::Path};
+
+/// Key of the schema version in every JSON state file (files without it are version 0)
+pub const VERSION_KEY: &str = "schema_version";
+
+/// Migration upgrades a JSON state file by one version
+type Migration = fn(&mut Value) -> Result<(), String>;
+
+/// Schema is the versioned format of a JSON state file
+/// `migrations[n]` upgrades a file from version n to n + 1, so the current version is the number of migrations
+pub struct Schema {
+    /// Name of the state file in error messages
+    pub name: &'static str,
+    /// Every migration in order, never edit or remove one that was released
+    migrations: &'static [Migration],
+}
+
+/// Schema of the manifest (`fastdl_manifest.json`)
+pub const MANIFEST: Schema = Schema {
+    name: "manifest",
+    migrations: &[
+        manifest_v1,
+        manifest_v2,
+        manifest_v3,
+        manifest_v4,
+        manifest_v5,
+    ],
+};
+
+/// Schema of the persisted download queue (`fastdl_queue.json`)
+pub const QUEUE: Schema = Schema {
+    name: "queue",
+    migrations: &[queue_v1],
+};
+
+/// Schema of the previous crawl kept by `changes` (`fastdl_crawl.json`)
+pub const CRAWL: Schema = Schema {
+    name: "previous crawl",
+    migrations: &[crawl_v1],
+};
+
+/// Schema of the measured download and decode parallelism (`fastdl_tuning.json`)
+pub const TUNING: Schema = Schema {
+    name: "tuning",
+    migrations: &[],
+};
+
+/// Schema of the journal of planned deletions (`fastdl_delete_journal.json`)
+pub const DELETE_JOURNAL: Schema = Schema {
+    name: "deletion journal",
+    migrations: &[],
+};
+
+/// Schema of the checksums of the published files (`fastdl_sums.json`)
+pub const SUMS: Schema = Schema {
+    name: "checksum cache",
+    migrations: &[],
+};
+
+/// Version 1 spells out every field of the manifest entries (older releases left out `path` and `chunks`)
+fn manifest_v1(value: &mut Value) -> Result<(), String> {
+    let manifest = value
+        .as_object_mut()
+        .ok_or("the manifest is not an object")?;
+    manifest.entry("last_sync").or_insert(Value::from(0));
+
+    let files = manifest
+        .entry("files")
+        .or_insert_with(|| Value::Object(Default::default()))
+        .as_object_mut()
+        .ok_or("the files of the manifest are not an object")?;
+
+    for entry in files.values_mut() {
+        let entry = entry
+            .as_object_mut()
+            .ok_or("a manifest entry is not an object")?;
+        entry.entry("path").or_insert(Value::Null);
+        entry.entry("chunks").or_insert(Value::Array(Vec::new()));
+    }
+
+    Ok(())
+}
+
+/// Version 2 records where archived copies were downloaded from (`archived_from`)
+fn manifest_v2(value: &mut Value) -> Result<(), String> {
+    let files = value
+        .get_mut("files")
+        .and_then(Value::as_object_mut)
+        .ok_or("the files of the manifest are not an object")?;
+
+    for entry in files.values_mut() {
+        entry
+            .as_object_mut()
+            .ok_or("a manifest entry is not an object")?
+            .entry("archived_from")
+            .or_insert(Value::Null);
+    }
+
+    Ok(())
+}
+
+/// Version 3 records the decoded size of the decoded downloads (`decoded_size`)
+fn manifest_v3(value: &mut Value) -> Result<(), String> {
+    let files = value
+        .get_mut("files")
+        .and_then(Value::as_object_mut)
+        .ok_or("the files of the manifest are not an object")?;
+
+    for entry in files.values_mut() {
+        entry
+            .as_object_mut()
+            .ok_or("a manifest entry is not an object")?
+            .entry("decoded_size")
+            .or_insert(Value::Null);
+    }
+
+    Ok(())
+}
+
+/// Version 4 keeps what the syncs of the mirror downloaded and saved (`bandwidth`)
+fn manifest_v4(value: &mut Value) -> Result<(), String> {
+    value
+        .as_object_mut()
+        .ok_or("the manifest is not an object")?
+        .entry("bandwidth")
+        .or_insert_with(|| serde_json::json!({ "downloaded": 0, "saved": 0 }));
+
+    Ok(())
+}
+
+/// Version 5 records the sha256 of the downloads (`sha256`)
+fn manifest_v5(value: &mut Value) -> Result<(), String> {
+    let files = value
+        .get_mut("files")
+        .and_then(Value::as_object_mut)
+        .ok_or("the files of the manifest are not an object")?;
+
+    for entry in files.values_mut() {
+        entry
+            .as_object_mut()
+            .ok_or("a manifest entry is not an object")?
+            .entry("sha256")
+            .or_insert(Value::Null);
+    }
+
+    Ok(())
+}
+
+/// Version 1 only adds the schema version to the queue
+fn queue_v1(value: &mut Value) -> Result<(), String> {
+    value
+        .as_object_mut()
+        .ok_or("the queue is not an object")?
+        .entry("pending")
+        .or_insert_with(|| Value::Object(Default::default()));
+
+    Ok(())
+}
+
+/// Version 1 keeps the fingerprint of every crawled host (`hosts`)
+fn crawl_v1(value: &mut Value) -> Result<(), String> {
+    value
+        .as_object_mut()
+        .ok_or("the previous crawl is not an object")?
+        .entry("hosts")
+        .or_insert_with(|| Value::Object(Default::default()));
+
+    Ok(())
+}
+
+impl Schema {
+    /// Returns the version this release reads and writes
+    pub fn version(&self) -> u32 {
+        self.migrations.len() as u32
+    }
+
+    /// Upgrades `value` to the current version
+    /// Fails if it was written by a newer release, which this release can't read without losing data
+    ///
+    /// # Arguments
+    /// * `value`   -   Parsed state file
+    pub fn migrate(&self, value: &mut Value) -> Result<(), String> {
+        let version = value.get(VERSION_KEY).and_then(Value::as_u64).unwrap_or(0) as u32;
+
+        if version > self.version() {
+            return Err(format!(
+                "The {} was written by a newer release (schema {}, this release reads up to {}), please update",
+                self.name,
+                version,
+                self.version()
+            ));
+        }
+
+        for (from, migration) in self.migrations.iter().enumerate().skip(version as usize) {
+            migration(value).map_err(|e| {
+                format!(
+                    "Failed to migrate the {} from schema {}: {}",
+                    self.name, from, e
+                )
+            })?;
+        }
+
+        if let Some(object) = value.as_object_mut() {
+            object.insert(VERSION_KEY.to_string(), Value::from(self.version()));
+        }
+
+        Ok(())
+    }
+
+    /// Reads the state file at `path`, migrating it to the current version
+    /// Returns None if the file does not exist
+    /// A file that needs a migration is backed up next to itself first (e.g. `fastdl_manifest.json.v0.bak`)
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the state file
+    pub fn load<T: DeserializeOwned>(&self, path: &Path) -> Result<Option<T>, String> {
+        let text = match fs::read_to_string(path) {
+            Ok(text) => text,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(format!("Failed to read {}: {}", path.display(), e)),
+        };
+
+        let mut value = serde_json::from_str::<Value>(&text)
+            .map_err(|e| format!("Failed to parse {}: {}", path.display(), e))?;
+        let version = value.get(VERSION_KEY).and_then(Value::as_u64).unwrap_or(0);
+
+        if version < self.version() as u64 {
+            let backup = path.with_extension(format!("json.v{}.bak", version));
+            fs::copy(path, &backup)
+                .map_err(|e| format!("Failed to back up {}: {}", path.display(), e))?;
+        }
+
+        self.migrate(&mut value)?;
+
+        serde_json::from_value(value)
+            .map(Some)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))
+    }
+}
+
+/// Migration upgrades the sqlite state store by one version
+#[cfg(feature = "sqlite")]
+type SqliteMigration = fn(&rusqlite::Connection) -> rusqlite::Result<()>;
+
+/// `SQLITE_MIGRATIONS[n]` upgrades the sqlite state store from `user_version` n to n + 1
+#[cfg(feature = "sqlite")]
+const SQLITE_MIGRATIONS: &[SqliteMigration] = &[sqlite_v1, sqlite_v2, sqlite_v3, sqlite_v4];
+
+/// Version 1 creates the tables, adding the repair columns to databases created before chunk repair
+#[cfg(feature = "sqlite")]
+fn sqlite_v1(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(
+        "
+        CREATE TABLE IF NOT EXISTS visited (path TEXT PRIMARY KEY);
+        CREATE TABLE IF NOT EXISTS files (
+            url TEXT PRIMARY KEY,
+            size INTEGER NOT NULL,
+            synced_at INTEGER NOT NULL,
+            path TEXT,
+            chunks TEXT NOT NULL DEFAULT ''
+        );
+        CREATE TABLE IF NOT EXISTS meta (key TEXT PRIMARY KEY, value INTEGER NOT NULL);
+        ",
+    )?;
+
+    // Adding a column that exists fails harmlessly
+    let _ = conn.execute("ALTER TABLE files ADD COLUMN path TEXT", []);
+    let _ = conn.execute(
+        "ALTER TABLE files ADD COLUMN chunks TEXT NOT NULL DEFAULT ''",
+        [],
+    );
+
+    Ok(())
+}
+
+/// Version 2 adds the Wayback Machine copy archived files were downloaded from
+#[cfg(feature = "sqlite")]
+fn sqlite_v2(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute("ALTER TABLE files ADD COLUMN archived_from TEXT", [])?;
+
+    Ok(())
+}
+
+/// Version 3 adds the decoded size of the decoded downloads
+#[cfg(feature = "sqlite")]
+fn sqlite_v3(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute("ALTER TABLE files ADD COLUMN decoded_size INTEGER", [])?;
+
+    Ok(())
+}
+
+/// Version 4 adds the sha256 of the downloads
+#[cfg(feature = "sqlite")]
+fn sqlite_v4(conn: &rusqlite::Connection) -> rusqlite::Result<()> {
+    conn.execute("ALTER TABLE files ADD COLUMN sha256 TEXT", [])?;
+
+    Ok(())
+}
+
+/// Upgrades the sqlite state store to the current version (kept in `PRAGMA user_version`)
+/// Every migration runs in its own transaction, so an interrupted upgrade resumes where it stopped
+///
+/// # Arguments
+/// * `conn`    -   Connection to the state store
+#[cfg(feature = "sqlite")]
+pub fn migrate_sqlite(conn: &mut rusqlite::Connection) -> Result<(), String> {
+    let version = conn
+        .query_row("PRAGMA user_version", [], |r| r.get::<_, i64>(0))
+        .map_err(|e| e.to_string())? as usize;
+
+    if version > SQLITE_MIGRATIONS.len() {
+        return Err(format!(
+            "The sqlite state store was written by a newer release (schema {}, this release reads up to {}), please update",
+            version,
+            SQLITE_MIGRATIONS.len()
+        ));
+    }
+
+    for (from, migration) in SQLITE_MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        migration(&tx)
+            .and_then(|_| tx.pragma_update(None, "user_version", (from + 1) as i64))
+            .and_then(|_| tx.commit())
+            .map_err(|e| {
+                format!(
+                    "Failed to migrate the sqlite state store from schema {}: {}",
+                    from, e
+                )
+            })?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::state_store::{JsonStore, StateStore, MANIFEST_FILE};
+    use std::{fs, path::PathBuf};
+
+    /// Returns an empty directory for the test `name`
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fastdl_{}_{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn migrates_a_v1_manifest() {
+        let dir = test_dir("schema_manifest_v1");
+        let path = dir.join(MANIFEST_FILE);
+        fs::write(
+            &path,
+            r#"{
+                "schema_version": 1,
+                "last_sync": 1700000000,
+                "files": {
+                    "https://fastdl.example.com/maps/ze_a.bsp.bz2": {
+                        "size": 42,
+                        "synced_at": 1690000000,
+                        "path": "maps/ze_a.bsp.bz2",
+                        "chunks": ["abc"]
+                    }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        let store = JsonStore::open(&path).unwrap();
+        let entry = store
+            .file("https://fastdl.example.com/maps/ze_a.bsp.bz2")
+            .unwrap();
+        assert_eq!(store.last_sync(), 1700000000);
+        assert_eq!(store.bandwidth(), Default::default());
+        assert_eq!((entry.size, entry.synced_at), (42, 1690000000));
+        assert_eq!(entry.path, Some(PathBuf::from("maps/ze_a.bsp.bz2")));
+        assert_eq!(entry.chunks, ["abc"]);
+        assert_eq!(
+            (entry.archived_from, entry.decoded_size, entry.sha256),
+            (None, None, None)
+        );
+        // The file written by the older release is kept
+        assert!(dir.join(format!("{}.v1.bak", MANIFEST_FILE)).exists());
+
+        store.save().unwrap();
+        let saved =
+            serde_json::from_str::<serde_json::Value>(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(saved[super::VERSION_KEY], super::MANIFEST.version());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[test]
+    fn migrates_a_v1_sqlite_store() {
+        use crate::state_store::{SqliteStore, SQLITE_FILE};
+
+        let dir = test_dir("schema_sqlite_v1");
+        let path = dir.join(SQLITE_FILE);
+        {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            super::sqlite_v1(&conn).unwrap();
+            conn.pragma_update(None, "user_version", 1).unwrap();
+            conn.execute(
+                "INSERT INTO files (url, size, synced_at, path, chunks) VALUES (?1, 42, 1690000000, ?2, 'abc,def')",
+                ["https://fastdl.example.com/maps/ze_a.bsp.bz2", "maps/ze_a.bsp.bz2"],
+            )
+            .unwrap();
+            conn.execute(
+                "INSERT INTO meta (key, value) VALUES ('last_sync', 1700000000)",
+                [],
+            )
+            .unwrap();
+        }
+
+        let store = SqliteStore::open(&path).unwrap();
+        let entry = store
+            .file("https://fastdl.example.com/maps/ze_a.bsp.bz2")
+            .unwrap();
+        assert_eq!(store.last_sync(), 1700000000);
+        assert_eq!((entry.size, entry.synced_at), (42, 1690000000));
+        assert_eq!(entry.path, Some(PathBuf::from("maps/ze_a.bsp.bz2")));
+        assert_eq!(entry.chunks, ["abc", "def"]);
+        assert_eq!(
+            (entry.archived_from, entry.decoded_size, entry.sha256),
+            (None, None, None)
+        );
+        drop(store);
+
+        let version = rusqlite::Connection::open(&path)
+            .unwrap()
+            .query_row("PRAGMA user_version", [], |r| r.get::<_, i64>(0))
+            .unwrap();
+        assert_eq!(version as usize, super::SQLITE_MIGRATIONS.len());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
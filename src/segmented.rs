@@ -0,0 +1,234 @@
+use crate::{
+    blocklist::sha256_hex,
+    durability, fd_limit, fingerprint,
+    repair::{self, CHUNK_SIZE},
+    throttle::Throttle,
+    transport::Transport,
+    MB_SIZE,
+};
+use fs2::FileExt;
+use reqwest::{blocking::Response, header::ACCEPT_RANGES, StatusCode};
+use sha2::{Digest as _, Sha256};
+use std::{
+    fs::File,
+    io::{self, Read},
+    path::Path,
+    thread,
+    time::Instant,
+};
+
+/// Files smaller than this are downloaded in a single request
+pub const MIN_SIZE: u64 = 100 * MB_SIZE as u64;
+/// Size of the reads of every segment
+const READ_SIZE: usize = 256 * 1024;
+/// Bytes a digest keeps of the start of a file
+const HEAD_LEN: usize = 512;
+
+/// Returns the size of the file `response` serves if it's worth downloading in `segments` segments:
+/// the server supports ranges (and did not ignore them before) and the file is at least `MIN_SIZE`
+///
+/// # Arguments
+/// * `response`    -   Response of the plain GET request of the file
+/// * `segments`    -   Number of segments (`--segments <n>`, 1 downloads everything in one request)
+pub fn segmented_size(response: &Response, segments: usize) -> Option<u64> {
+    let accepts_ranges = response
+        .headers()
+        .get(ACCEPT_RANGES)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.eq_ignore_ascii_case("bytes"))
+        && fingerprint::ranges(response.url().host_str().unwrap_or_default()) != Some(false);
+    let size = response.content_length()?;
+
+    (segments > 1 && accepts_ranges && size >= MIN_SIZE).then_some(size)
+}
+
+/// Writes `buf` to `file` at `offset` without moving a shared cursor, so segments can write concurrently
+#[cfg(unix)]
+fn write_at(file: &File, buf: &[u8], offset: u64) -> io::Result<()> {
+    std::os::unix::fs::FileExt::write_all_at(file, buf, offset)
+}
+
+/// Writes `buf` to `file` at `offset` without moving a shared cursor, so segments can write concurrently
+#[cfg(windows)]
+fn write_at(file: &File, mut buf: &[u8], mut offset: u64) -> io::Result<()> {
+    while !buf.is_empty() {
+        let n = std::os::windows::fs::FileExt::seek_write(file, buf, offset)?;
+        if n == 0 {
+            return Err(io::ErrorKind::WriteZero.into());
+        }
+        buf = &buf[n..];
+        offset += n as u64;
+    }
+
+    Ok(())
+}
+
+/// Downloads the bytes `start..=end` of `url` straight into their place in `file`
+///
+/// # Arguments
+/// * `transport`   -   Sends the ranged request
+/// * `throttle`    -   Global and per-host speed caps
+/// * `url`         -   Url of the file
+/// * `file`        -   Preallocated file
+/// * `start`       -   Offset of the first byte of the segment
+/// * `end`         -   Offset of the last byte of the segment (inclusive)
+/// * `deadline`    -   When the download is given up (no limit if None)
+fn fetch_segment(
+    transport: &Transport,
+    throttle: &Throttle,
+    url: &str,
+    file: &File,
+    (start, end): (u64, u64),
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let mut resp = transport
+        .get_range(url, start, end)
+        .map_err(io::Error::other)?;
+    if resp.status() != StatusCode::PARTIAL_CONTENT {
+        return Err(io::Error::other("the server ignored the range"));
+    }
+
+    let host = resp.url().host_str().unwrap_or_default().to_string();
+    let mut chunk = vec![0u8; READ_SIZE];
+    let mut offset = start;
+
+    while offset <= end {
+        let n = resp.read(&mut chunk)?;
+        if n == 0 {
+            break;
+        }
+
+        // A server sending more than the range must not overwrite the next segment
+        let n = n.min((end + 1 - offset) as usize);
+        write_at(file, &chunk[..n], offset)?;
+        offset += n as u64;
+        throttle.consume(&host, n as u64);
+
+        if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+            return Err(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "Download took longer than its time budget",
+            ));
+        }
+    }
+
+    if offset != end + 1 {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+
+    Ok(())
+}
+
+/// Downloads `url` into `path` with `segments` concurrent ranged requests
+/// The file is preallocated to `size` and every segment writes its bytes in place, so nothing is stitched
+/// Fails (like `read_body`, with `TimedOut` once `deadline` passed) if any segment fails
+///
+/// # Arguments
+/// * `transport`   -   Sends the ranged requests
+/// * `throttle`    -   Global and per-host speed caps
+/// * `url`         -   Url of the file
+/// * `path`        -   Path the file is written to
+/// * `size`        -   Size of the file (its `Content-Length`)
+/// * `segments`    -   Number of concurrent requests
+/// * `deadline`    -   When the download is given up (no limit if None)
+pub fn download(
+    transport: &Transport,
+    throttle: &Throttle,
+    url: &str,
+    path: &Path,
+    size: u64,
+    segments: usize,
+    deadline: Option<Instant>,
+) -> io::Result<()> {
+    let file = durability::create(path)?;
+    // Reserving the blocks up front keeps a large file from fragmenting and fails early on a full disk
+    file.allocate(size)?;
+    file.set_len(size)?;
+
+    let segment_len = size.div_ceil(segments as u64);
+    let ranges = (0..size)
+        .step_by(segment_len as usize)
+        .map(|start| (start, (start + segment_len).min(size) - 1))
+        .collect::<Vec<_>>();
+
+    let result = thread::scope(|scope| {
+        let handles = ranges
+            .into_iter()
+            .map(|range| {
+                let file = &file;
+                scope.spawn(move || fetch_segment(transport, throttle, url, file, range, deadline))
+            })
+            .collect::<Vec<_>>();
+
+        handles.into_iter().try_for_each(|handle| {
+            handle
+                .join()
+                .unwrap_or_else(|_| Err(io::Error::other("a segment panicked")))
+        })
+    });
+
+    result.and_then(|_| durability::sync_file(&file))
+}
+
+/// Digest is what a sync keeps of a download: its size, sha256, chunk checksums and first bytes
+#[derive(Debug, Clone)]
+pub struct Digest {
+    /// Size of the file
+    pub size: u64,
+    /// Hex sha256 of the whole file
+    pub sha256: String,
+    /// Checksum of every `CHUNK_SIZE` chunk (see `repair::checksums`)
+    pub chunks: Vec<String>,
+    /// First bytes of the file, enough to sniff its kind
+    pub head: Vec<u8>,
+}
+
+impl Digest {
+    /// Returns the digest of a download held in memory
+    ///
+    /// # Arguments
+    /// * `bytes`   -   Content of the file
+    pub fn of(bytes: &[u8]) -> Self {
+        Self {
+            size: bytes.len() as u64,
+            sha256: sha256_hex(bytes),
+            chunks: repair::checksums(bytes),
+            head: bytes[..bytes.len().min(HEAD_LEN)].to_vec(),
+        }
+    }
+
+    /// Returns the digest of the file at `path` (a segmented download), read one chunk at a time
+    /// so a file of several GB is never held in memory
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the downloaded file
+    pub fn of_file(path: &Path) -> io::Result<Self> {
+        let mut file = fd_limit::retry(|| File::open(path))?;
+        let mut hasher = Sha256::new();
+        let mut digest = Self {
+            size: 0,
+            sha256: String::new(),
+            chunks: Vec::new(),
+            head: Vec::new(),
+        };
+
+        let mut chunk = Vec::with_capacity(CHUNK_SIZE);
+        loop {
+            chunk.clear();
+            io::copy(&mut (&mut file).take(CHUNK_SIZE as u64), &mut chunk)?;
+            if chunk.is_empty() {
+                break;
+            }
+
+            if digest.head.is_empty() {
+                digest.head = chunk[..chunk.len().min(HEAD_LEN)].to_vec();
+            }
+            io::copy(&mut chunk.as_slice(), &mut hasher)?;
+            digest.chunks.extend(repair::checksums(&chunk));
+            digest.size += chunk.len() as u64;
+        }
+
+        digest.sha256 = format!("{:x}", hasher.finalize());
+        Ok(digest)
+    }
+}
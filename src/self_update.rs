@@ -0,0 +1,219 @@
+use crate::{blocklist, net};
+use reqwest::blocking::Client;
+use serde::Deserialize;
+use std::{env, fs, io::Cursor, path::Path};
+
+/// GitHub repository the releases are published on
+pub const REPO: &str = "ovY9jkhTEUpllGPJRrKU/CSS-GFL-ZE-Downloader";
+/// Name of the release asset listing the sha256 of every binary (`sha256sum` format)
+pub const CHECKSUMS_ASSET: &str = "SHA256SUMS";
+/// Name of the release asset holding the minisign signature of `SHA256SUMS`
+pub const SIGNATURE_ASSET: &str = "SHA256SUMS.minisig";
+/// Minisign public key (base64) the releases are signed with, embedded by the release builds
+/// (`CSS_DOWNLOADER_RELEASE_KEY`); a build without it can't update itself
+pub const RELEASE_KEY: Option<&str> = option_env!("CSS_DOWNLOADER_RELEASE_KEY");
+/// Version of the running binary
+pub const CURRENT_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// Release is the part of a GitHub release the update needs
+#[derive(Debug, Deserialize)]
+struct Release {
+    /// Tag of the release (e.g. "v0.2.0")
+    tag_name: String,
+    /// Files attached to the release
+    assets: Vec<Asset>,
+}
+
+/// Asset is a file attached to a GitHub release
+#[derive(Debug, Deserialize)]
+struct Asset {
+    /// File name of the asset
+    name: String,
+    /// Url the asset is downloaded from
+    browser_download_url: String,
+}
+
+/// Update is what `self-update` did
+#[derive(Debug, PartialEq, Eq)]
+pub enum Update {
+    /// The running binary is the latest release (its version)
+    UpToDate(String),
+    /// The running binary was replaced (the old and the new version)
+    Updated(String, String),
+}
+
+/// Returns the name of the release asset built for this platform (e.g. "css-downloader-x86_64-windows.exe")
+pub fn asset_name() -> String {
+    format!(
+        "css-downloader-{}-{}{}",
+        env::consts::ARCH,
+        env::consts::OS,
+        env::consts::EXE_SUFFIX
+    )
+}
+
+/// Returns the numbers of a version or tag ("v1.10.2" -> [1, 10, 2]), so "1.10" is newer than "1.9"
+fn version_numbers(version: &str) -> Vec<u64> {
+    version
+        .trim_start_matches('v')
+        .split('.')
+        .map(|part| {
+            part.chars()
+                .take_while(char::is_ascii_digit)
+                .collect::<String>()
+                .parse()
+                .unwrap_or(0)
+        })
+        .collect()
+}
+
+/// Returns the sha256 listed for `name` in a `sha256sum` file ("<hex>  <name>" or "<hex> *<name>" per line)
+fn listed_checksum(checksums: &str, name: &str) -> Option<String> {
+    checksums.lines().find_map(|line| {
+        let (hash, file) = line.split_once(char::is_whitespace)?;
+        (file.trim_start().trim_start_matches('*') == name).then(|| hash.to_lowercase())
+    })
+}
+
+/// Returns an error unless `signature` is a minisign signature of `checksums` made with the release key
+///
+/// # Arguments
+/// * `checksums`   -   `SHA256SUMS` of the release as it was downloaded
+/// * `signature`   -   `SHA256SUMS.minisig` of the release
+fn verify_signature(checksums: &[u8], signature: &[u8]) -> Result<(), String> {
+    let key = RELEASE_KEY.ok_or(
+        "This build has no release key and can't verify updates, download the release by hand",
+    )?;
+    let public_key = minisign::PublicKey::from_base64(key)
+        .map_err(|e| format!("The embedded release key is invalid: {}", e))?;
+    let signature = minisign::SignatureBox::from_string(&String::from_utf8_lossy(signature))
+        .map_err(|e| format!("{} is not a minisign signature: {}", SIGNATURE_ASSET, e))?;
+
+    minisign::verify(
+        &public_key,
+        &signature,
+        Cursor::new(checksums),
+        true,
+        false,
+        false,
+    )
+    .map_err(|e| {
+        format!(
+            "{} does not match the release key, nothing was replaced: {}",
+            SIGNATURE_ASSET, e
+        )
+    })
+}
+
+/// Downloads `url` in full, failing on an error status
+fn fetch(client: &Client, url: &str) -> Result<Vec<u8>, String> {
+    client
+        .get(url)
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .and_then(|resp| resp.bytes())
+        .map(|bytes| bytes.to_vec())
+        .map_err(|e| format!("Failed to download {}: {}", url, e))
+}
+
+/// Replaces the executable at `exe` with `binary`
+/// The new binary is written next to it first, so a failed write never leaves a broken executable.
+/// Windows can't overwrite a running executable but can rename it, so the old one is moved aside to `<exe>.old`
+/// and removed by `cleanup` on the next run; it's moved back if the new one can't take its place
+fn replace_exe(exe: &Path, binary: &[u8]) -> std::io::Result<()> {
+    let new_path = exe.with_extension("new");
+    fs::write(&new_path, binary)?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&new_path, fs::Permissions::from_mode(0o755))?;
+    }
+
+    #[cfg(windows)]
+    {
+        let old_path = exe.with_extension("old");
+        fs::rename(exe, &old_path)?;
+        return fs::rename(&new_path, exe).map_err(|err| {
+            let _ = fs::rename(&old_path, exe);
+            err
+        });
+    }
+
+    #[cfg(not(windows))]
+    fs::rename(&new_path, exe)
+}
+
+/// Removes the executable a previous update on Windows moved aside, once the swap is known to have succeeded:
+/// the new binary took its place (no `<exe>.new` is left) and it's not the one running
+pub fn cleanup() {
+    let Ok(exe) = env::current_exe() else {
+        return;
+    };
+    let old_path = exe.with_extension("old");
+
+    if exe != old_path && exe.is_file() && !exe.with_extension("new").exists() {
+        let _ = fs::remove_file(old_path);
+    }
+}
+
+/// Replaces the running executable with the latest GitHub release if it's newer
+/// The binary is only installed if the release's `SHA256SUMS` is signed with the embedded release key
+/// and the sha256 of the binary matches the one it lists
+pub fn self_update() -> Result<Update, String> {
+    let client = net::client_builder()
+        // The GitHub API rejects requests without a user agent
+        .user_agent(format!("css-downloader/{}", CURRENT_VERSION))
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let release = fetch(
+        &client,
+        &format!("https://api.github.com/repos/{}/releases/latest", REPO),
+    )?;
+    let release = serde_json::from_slice::<Release>(&release)
+        .map_err(|e| format!("Unexpected release information: {}", e))?;
+
+    if version_numbers(&release.tag_name) <= version_numbers(CURRENT_VERSION) {
+        return Ok(Update::UpToDate(CURRENT_VERSION.to_string()));
+    }
+
+    let name = asset_name();
+    let find = |asset_name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == asset_name)
+            .ok_or_else(|| format!("Release {} has no {}", release.tag_name, asset_name))
+    };
+    let binary_asset = find(&name)?;
+    let checksums_asset = find(CHECKSUMS_ASSET)?;
+    let signature_asset = find(SIGNATURE_ASSET)?;
+
+    let checksums = fetch(&client, &checksums_asset.browser_download_url)?;
+    verify_signature(
+        &checksums,
+        &fetch(&client, &signature_asset.browser_download_url)?,
+    )?;
+    let checksums = String::from_utf8_lossy(&checksums).into_owned();
+    let expected = listed_checksum(&checksums, &name)
+        .ok_or_else(|| format!("{} does not list {}", CHECKSUMS_ASSET, name))?;
+
+    let binary = fetch(&client, &binary_asset.browser_download_url)?;
+    let actual = blocklist::sha256_hex(&binary);
+    if actual != expected {
+        return Err(format!(
+            "Checksum mismatch for {} (expected {}, got {}), nothing was replaced",
+            name, expected, actual
+        ));
+    }
+
+    let exe = env::current_exe().map_err(|e| e.to_string())?;
+    replace_exe(&exe, &binary)
+        .map_err(|e| format!("Failed to replace {}: {}", exe.display(), e))?;
+
+    Ok(Update::Updated(
+        CURRENT_VERSION.to_string(),
+        release.tag_name.trim_start_matches('v').to_string(),
+    ))
+}
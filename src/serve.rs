@@ -0,0 +1,468 @@
+use crate::{
+    changes::escape,
+    meta_dir::META_DIR,
+    metrics::{Metrics, METRICS},
+};
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, CONTROLS};
+use std::{
+    fs::{self, File},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    net::{TcpListener, TcpStream},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+/// Time a client has to send its request, so an idle connection never holds a thread
+pub const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Time a client has to take the bytes of a write, so a client that stopped reading never holds a thread
+const WRITE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Size of the request line and headers read at most, a request that never ends is refused
+const MAX_HEADER_BYTES: u64 = 16 * 1024;
+/// Connections answered at once, the others get a 503
+const MAX_CONNECTIONS: usize = 64;
+
+/// Characters that are percent-encoded in the links of a directory listing
+const NAME_SET: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'\'')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Serves the local mirror in `root` over HTTP so it can be used as a `sv_downloadurl`
+/// Every connection is handled on its own thread
+///
+/// # Arguments
+/// * `addr`    -   Address to listen on (e.g. "0.0.0.0:8080")
+/// * `root`    -   Directory that is served
+pub fn serve(addr: &str, root: &Path) -> io::Result<()> {
+    let listener = TcpListener::bind(addr)?;
+    println!("Serving {} on http://{}/", root.display(), addr);
+
+    let connections = Arc::new(AtomicUsize::new(0));
+
+    for mut stream in listener.incoming().flatten() {
+        if connections.fetch_add(1, Ordering::Relaxed) >= MAX_CONNECTIONS {
+            connections.fetch_sub(1, Ordering::Relaxed);
+            let _ = stream.set_write_timeout(Some(READ_TIMEOUT));
+            let _ = respond(
+                &mut stream,
+                "503 Service Unavailable",
+                "text/plain",
+                b"Too many connections",
+                false,
+            );
+            continue;
+        }
+
+        let root = root.to_path_buf();
+        let connections = Arc::clone(&connections);
+
+        std::thread::spawn(move || {
+            // A client hanging up mid-transfer is not our problem
+            let _ = stream
+                .set_write_timeout(Some(WRITE_TIMEOUT))
+                .and_then(|_| handle_connection(stream, &root));
+            connections.fetch_sub(1, Ordering::Relaxed);
+        });
+    }
+
+    Ok(())
+}
+
+/// Reads a single request from `stream` and answers it
+///
+/// # Arguments
+/// * `stream`  -   Connection to the client
+/// * `root`    -   Directory that is served
+fn handle_connection(mut stream: TcpStream, root: &Path) -> io::Result<()> {
+    let request = read_request(&mut stream)?;
+    let (method, target) = (request.method.as_str(), request.target.as_str());
+    let head_only = method == "HEAD";
+
+    Metrics::add(&METRICS.requests_served, 1);
+
+    if method != "GET" && !head_only {
+        return respond(
+            &mut stream,
+            "405 Method Not Allowed",
+            "text/plain",
+            b"Method Not Allowed",
+            false,
+        );
+    }
+
+    // Prometheus scrape endpoint
+    if target == "/metrics" {
+        return respond(
+            &mut stream,
+            "200 OK",
+            "text/plain; version=0.0.4",
+            METRICS.render().as_bytes(),
+            head_only,
+        );
+    }
+
+    match resolve_path(root, target) {
+        Some(path) if path.is_dir() => {
+            let listing = dir_listing(&path, target)?;
+            respond(
+                &mut stream,
+                "200 OK",
+                "text/html",
+                listing.as_bytes(),
+                head_only,
+            )
+        }
+        Some(path) if path.is_file() => {
+            let mut file = File::open(&path)?;
+            let len = file.metadata()?.len();
+
+            // Several ranges or another unit are answered with the whole file
+            let range = request
+                .header("range")
+                .filter(|range| range.trim().starts_with("bytes=") && !range.contains(','));
+            let (start, end) = match range.map(|range| byte_range(range, len)) {
+                Some(Some((start, end))) => {
+                    write!(
+                        stream,
+                        "HTTP/1.1 206 Partial Content\r\nContent-Type: application/octet-stream\r\nContent-Range: bytes {}-{}/{}\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+                        start,
+                        end,
+                        len,
+                        end - start + 1
+                    )?;
+                    (start, end)
+                }
+                Some(None) => {
+                    return write!(
+                        stream,
+                        "HTTP/1.1 416 Range Not Satisfiable\r\nContent-Range: bytes */{}\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        len
+                    );
+                }
+                None => {
+                    write!(
+                        stream,
+                        "HTTP/1.1 200 OK\r\nContent-Type: application/octet-stream\r\nContent-Length: {}\r\nAccept-Ranges: bytes\r\nConnection: close\r\n\r\n",
+                        len
+                    )?;
+                    (0, len.saturating_sub(1))
+                }
+            };
+
+            if !head_only && len > 0 {
+                file.seek(SeekFrom::Start(start))?;
+                let sent = io::copy(&mut file.take(end - start + 1), &mut stream)?;
+                Metrics::add(&METRICS.bytes_served, sent);
+            }
+
+            Ok(())
+        }
+        _ => respond(
+            &mut stream,
+            "404 Not Found",
+            "text/plain",
+            b"Not Found",
+            head_only,
+        ),
+    }
+}
+
+/// Request is the request line and the headers of an HTTP request (bodies are never read)
+#[derive(Debug, Default)]
+pub struct Request {
+    /// Method (e.g. "GET")
+    pub method: String,
+    /// Request target (e.g. "/cstrike/maps/ze_example.bsp?x=1")
+    pub target: String,
+    /// Lowercased header name -> value
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /// Returns a request with `headers` (name, value), as `read_request` would read it
+    #[cfg(test)]
+    pub fn new(method: &str, target: &str, headers: &[(&str, &str)]) -> Self {
+        Self {
+            method: method.to_string(),
+            target: target.to_string(),
+            headers: headers
+                .iter()
+                .map(|(name, value)| (name.to_lowercase(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    /// Returns the value of the header `name` (case-insensitive), if it was sent
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// Reads the request line and the headers of the request sent on `stream`
+/// Fails if the client takes longer than `READ_TIMEOUT` or sends more than `MAX_HEADER_BYTES`
+///
+/// # Arguments
+/// * `stream`  -   Connection to the client
+pub fn read_request(stream: &mut TcpStream) -> io::Result<Request> {
+    stream.set_read_timeout(Some(READ_TIMEOUT))?;
+    let mut reader = BufReader::new(stream.try_clone()?.take(MAX_HEADER_BYTES));
+
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line)?;
+
+    let mut headers = Vec::new();
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header)? == 0 {
+            // The limit (or the client) ended the headers before the blank line
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "incomplete or oversized request",
+            ));
+        }
+        if header.trim().is_empty() {
+            break;
+        }
+        if let Some((name, value)) = header.split_once(':') {
+            headers.push((name.trim().to_lowercase(), value.trim().to_string()));
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    Ok(Request {
+        method: parts.next().unwrap_or_default().to_string(),
+        target: parts.next().unwrap_or("/").to_string(),
+        headers,
+    })
+}
+
+/// Returns the first and last byte of the `Range` header `range` in a file of `len` bytes
+/// None if the range can't be satisfied; a single range is supported (`bytes=0-99`, `bytes=100-`, `bytes=-100`)
+///
+/// # Arguments
+/// * `range`   -   Value of the `Range` header
+/// * `len`     -   Size of the file
+fn byte_range(range: &str, len: u64) -> Option<(u64, u64)> {
+    let (start, end) = range.trim().strip_prefix("bytes=")?.split_once('-')?;
+    let (start, end) = (start.trim(), end.trim());
+
+    let (start, end) = match (start.is_empty(), end.is_empty()) {
+        // Suffix range: the last `end` bytes
+        (true, false) => {
+            let suffix = end.parse::<u64>().ok()?;
+            (len.saturating_sub(suffix), len.checked_sub(1)?)
+        }
+        (false, true) => (start.parse::<u64>().ok()?, len.checked_sub(1)?),
+        (false, false) => {
+            let end = end.parse::<u64>().ok()?.min(len.checked_sub(1)?);
+            (start.parse::<u64>().ok()?, end)
+        }
+        (true, true) => return None,
+    };
+
+    (start <= end && end < len).then_some((start, end))
+}
+
+/// Returns the local path of the request `target`, or None if it escapes `root`
+/// or points into the metadata directory of the mirror (manifest, tokens, ...)
+///
+/// # Arguments
+/// * `root`    -   Directory that is served
+/// * `target`  -   Request target (e.g. "/cstrike/maps/ze_example.bsp?x=1")
+fn resolve_path(root: &Path, target: &str) -> Option<PathBuf> {
+    let path = target.split(['?', '#']).next()?;
+    let mut local = root.to_path_buf();
+
+    for segment in path.split('/').filter(|s| !s.is_empty()) {
+        let segment = percent_decode_str(segment).decode_utf8().ok()?;
+
+        // Never allow leaving the served directory
+        if segment == "." || segment == ".." || segment.contains(['/', '\\', ':']) {
+            return None;
+        }
+        if segment.eq_ignore_ascii_case(META_DIR) {
+            return None;
+        }
+
+        local.push(&*segment);
+    }
+
+    Some(local)
+}
+
+/// Returns an HTML directory listing of `dir` (similar to the listings the crawler reads)
+///
+/// # Arguments
+/// * `dir`     -   Directory that is listed
+/// * `target`  -   Request target the listing is served at
+fn dir_listing(dir: &Path, target: &str) -> io::Result<String> {
+    let path = target.split(['?', '#']).next().unwrap_or("/");
+    let base = if path.ends_with('/') {
+        path.to_string()
+    } else {
+        format!("{}/", path)
+    };
+
+    let mut names = fs::read_dir(dir)?
+        .flatten()
+        .filter(|entry| !entry.file_name().eq_ignore_ascii_case(META_DIR))
+        .map(|entry| {
+            let name = entry.file_name().to_string_lossy().to_string();
+            if entry.path().is_dir() {
+                format!("{}/", name)
+            } else {
+                name
+            }
+        })
+        .collect::<Vec<_>>();
+    names.sort();
+
+    let mut html = format!(
+        "<html><body><h1>Index of {}</h1>\n<a href=\"../\">../</a><br>\n",
+        escape(&base)
+    );
+    for name in names {
+        // Names can hold any character, the link is percent-encoded and the text escaped
+        html.push_str(&format!(
+            "<a href=\"{}{}\">{}</a><br>\n",
+            escape(&base),
+            utf8_percent_encode(&name, NAME_SET),
+            escape(&name)
+        ));
+    }
+    html.push_str("</body></html>\n");
+
+    Ok(html)
+}
+
+/// Writes a complete response with a small body
+///
+/// # Arguments
+/// * `stream`          -   Connection to the client
+/// * `status`          -   Status line (e.g. "404 Not Found")
+/// * `content_type`    -   Value of the `Content-Type` header
+/// * `body`            -   Body of the response
+/// * `head_only`       -   Only write the headers (HEAD request)
+pub fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &[u8],
+    head_only: bool,
+) -> io::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        status,
+        content_type,
+        body.len()
+    )?;
+
+    if !head_only {
+        stream.write_all(body)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_byte_ranges() {
+        assert_eq!(byte_range("bytes=0-99", 1000), Some((0, 99)));
+        assert_eq!(byte_range("bytes=100-", 1000), Some((100, 999)));
+        assert_eq!(byte_range("bytes=-100", 1000), Some((900, 999)));
+        // The end is clamped to the file, a suffix longer than the file is the whole file
+        assert_eq!(byte_range("bytes=900-5000", 1000), Some((900, 999)));
+        assert_eq!(byte_range("bytes=-5000", 1000), Some((0, 999)));
+    }
+
+    #[test]
+    fn rejects_unsatisfiable_byte_ranges() {
+        assert_eq!(byte_range("bytes=1000-", 1000), None);
+        assert_eq!(byte_range("bytes=500-100", 1000), None);
+        assert_eq!(byte_range("bytes=-", 1000), None);
+        assert_eq!(byte_range("bytes=0-0", 0), None);
+        assert_eq!(byte_range("bytes=0-1,5-9", 1000), None);
+        assert_eq!(byte_range("items=0-99", 1000), None);
+    }
+
+    #[test]
+    fn resolves_paths_inside_of_the_root() {
+        let root = Path::new("/srv/fastdl");
+
+        assert_eq!(
+            resolve_path(root, "/cstrike/maps/ze_example.bsp.bz2?x=1#top"),
+            Some(root.join("cstrike/maps/ze_example.bsp.bz2"))
+        );
+        assert_eq!(
+            resolve_path(root, "/sound/ze%20boss.wav"),
+            Some(root.join("sound/ze boss.wav"))
+        );
+        assert_eq!(resolve_path(root, "/"), Some(root.to_path_buf()));
+    }
+
+    #[test]
+    fn refuses_paths_escaping_the_root() {
+        let root = Path::new("/srv/fastdl");
+
+        for target in [
+            "/../etc/passwd",
+            "/maps/../../etc/passwd",
+            "/%2e%2e/etc/passwd",
+            "/maps/%2E%2E%2F%2E%2E%2Fetc",
+            "/maps/..%5c..%5cwindows",
+            "/C:/windows",
+            "/.fastdl/fastdl_control_token",
+            "/maps/.FASTDL/fastdl_manifest.json",
+            "/%ff",
+        ] {
+            assert_eq!(resolve_path(root, target), None, "{}", target);
+        }
+    }
+
+    #[test]
+    fn refuses_oversized_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let _ = stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n");
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let _ = stream.write_all(b"GET / HTTP/1.1\r\n");
+            let _ = stream.write_all(&vec![b'a'; MAX_HEADER_BYTES as usize + 1]);
+        });
+
+        let (mut stream, _) = listener.accept().unwrap();
+        let request = read_request(&mut stream).unwrap();
+        assert_eq!(
+            (request.method.as_str(), request.target.as_str()),
+            ("GET", "/")
+        );
+        assert_eq!(request.header("HOST"), Some("localhost"));
+
+        let (mut stream, _) = listener.accept().unwrap();
+        assert!(read_request(&mut stream).is_err());
+
+        client.join().unwrap();
+    }
+}
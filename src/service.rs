@@ -0,0 +1,178 @@
+use std::{
+    io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+/// Name the background service is registered under
+pub const SERVICE_NAME: &str = "css-fastdl-sync";
+
+/// Runs `program` with `args`, failing if it exits with an error
+fn run(program: &str, args: &[&str]) -> io::Result<()> {
+    let status = Command::new(program).args(args).status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(io::Error::other(format!(
+            "{} {} failed ({})",
+            program,
+            args.join(" "),
+            status
+        )))
+    }
+}
+
+/// Runs systemctl with `args`, on the user's service manager if `user` is true
+fn systemctl(user: bool, args: &[&str]) -> io::Result<()> {
+    let mut all_args = Vec::new();
+    if user {
+        all_args.push("--user");
+    }
+    all_args.extend_from_slice(args);
+
+    run("systemctl", &all_args)
+}
+
+/// Quotes `arg` for a command line if it contains spaces
+fn quote(arg: &str) -> String {
+    if arg.contains(' ') {
+        format!("\"{}\"", arg)
+    } else {
+        arg.to_string()
+    }
+}
+
+/// Returns the systemd unit that runs the program in watch mode from `work_dir`
+///
+/// # Arguments
+/// * `exe`         -   Path of the program
+/// * `args`        -   Arguments of the watch mode (must contain `--watch`)
+/// * `work_dir`    -   Directory the mirror is synced into
+pub fn systemd_unit(exe: &Path, args: &[String], work_dir: &Path) -> String {
+    format!(
+        "[Unit]
+Description=CS:S FastDL mirror sync
+Wants=network-online.target
+After=network-online.target
+
+[Service]
+ExecStart={} {} --service
+WorkingDirectory={}
+Restart=on-failure
+RestartSec=30
+
+[Install]
+WantedBy=default.target
+",
+        quote(&exe.to_string_lossy()),
+        args.iter()
+            .map(|arg| quote(arg))
+            .collect::<Vec<_>>()
+            .join(" "),
+        work_dir.display(),
+    )
+}
+
+/// Returns where the systemd unit is installed (system-wide as root, for the user otherwise)
+fn systemd_unit_path() -> (PathBuf, bool) {
+    let is_root = std::env::var("USER").is_ok_and(|user| user == "root");
+
+    if is_root {
+        (PathBuf::from("/etc/systemd/system"), false)
+    } else {
+        let config_dir = std::env::var("XDG_CONFIG_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|_| {
+                PathBuf::from(std::env::var("HOME").unwrap_or_default()).join(".config")
+            });
+        (config_dir.join("systemd/user"), true)
+    }
+}
+
+/// Registers watch mode as a background service that starts with the system and runs from `work_dir`
+/// Windows gets a scheduled task running as SYSTEM at startup, Linux a systemd unit
+/// Returns a description of what was installed
+///
+/// # Arguments
+/// * `args`        -   Arguments of the watch mode (must contain `--watch`)
+/// * `work_dir`    -   Directory the mirror is synced into
+pub fn install(args: &[String], work_dir: &Path) -> io::Result<String> {
+    let exe = std::env::current_exe()?;
+
+    if cfg!(windows) {
+        let command = format!(
+            "cmd /C cd /D {} && {} {} --service",
+            quote(&work_dir.to_string_lossy()),
+            quote(&exe.to_string_lossy()),
+            args.iter()
+                .map(|arg| quote(arg))
+                .collect::<Vec<_>>()
+                .join(" "),
+        );
+        run(
+            "schtasks",
+            &[
+                "/Create",
+                "/F",
+                "/SC",
+                "ONSTART",
+                "/RU",
+                "SYSTEM",
+                "/TN",
+                SERVICE_NAME,
+                "/TR",
+                &command,
+            ],
+        )?;
+        run("schtasks", &["/Run", "/TN", SERVICE_NAME])?;
+
+        Ok(format!("Scheduled task {} (runs at startup)", SERVICE_NAME))
+    } else {
+        let (unit_dir, user) = systemd_unit_path();
+        let unit_path = unit_dir.join(format!("{}.service", SERVICE_NAME));
+
+        std::fs::create_dir_all(&unit_dir)?;
+        std::fs::write(&unit_path, systemd_unit(&exe, args, work_dir))?;
+        systemctl(user, &["daemon-reload"])?;
+        systemctl(user, &["enable", "--now", SERVICE_NAME])?;
+
+        Ok(format!(
+            "systemd unit {} (logs: journalctl {}-u {})",
+            unit_path.display(),
+            if user { "--user " } else { "" },
+            SERVICE_NAME
+        ))
+    }
+}
+
+/// Stops and removes the background service registered by `install`
+pub fn uninstall() -> io::Result<()> {
+    if cfg!(windows) {
+        // The task may not be running
+        let _ = run("schtasks", &["/End", "/TN", SERVICE_NAME]);
+        run("schtasks", &["/Delete", "/F", "/TN", SERVICE_NAME])
+    } else {
+        let (unit_dir, user) = systemd_unit_path();
+
+        systemctl(user, &["disable", "--now", SERVICE_NAME])?;
+        std::fs::remove_file(unit_dir.join(format!("{}.service", SERVICE_NAME)))?;
+        systemctl(user, &["daemon-reload"])
+    }
+}
+
+/// Writes `message` to the native log of the platform (the Application event log on Windows,
+/// stdout on Linux where journald collects it)
+///
+/// # Arguments
+/// * `message` -   Line that is logged
+pub fn log(message: &str) {
+    if cfg!(windows) {
+        let _ = Command::new("eventcreate")
+            .args(["/T", "INFORMATION", "/ID", "1", "/L", "APPLICATION"])
+            .args(["/SO", SERVICE_NAME, "/D", message])
+            .output();
+    } else {
+        println!("{}", message);
+    }
+}
@@ -0,0 +1,34 @@
+use reqwest::StatusCode;
+use url::Url;
+
+/// Query parameters CDNs use to sign time-limited urls (S3, CloudFront, Cloudflare, Bunny, ...)
+const SIGNATURE_PARAMS: &[&str] = &[
+    "x-amz-signature",
+    "x-amz-expires",
+    "signature",
+    "expires",
+    "exp",
+    "token",
+    "verify",
+    "md5",
+];
+
+/// Returns true if `url` carries a signature that expires
+///
+/// # Arguments
+/// * `url`     -   Url a listing link was redirected to
+pub fn is_signed(url: &Url) -> bool {
+    url.query_pairs()
+        .any(|(key, _)| SIGNATURE_PARAMS.contains(&key.to_lowercase().as_str()))
+}
+
+/// Returns true if `status` is what CDNs answer once a signature expired
+///
+/// # Arguments
+/// * `status`  -   Status of the download response
+pub fn is_expired(status: StatusCode) -> bool {
+    matches!(
+        status,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN | StatusCode::GONE
+    )
+}
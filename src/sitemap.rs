@@ -0,0 +1,141 @@
+use crate::files::RemoteFile;
+use crate::net;
+use regex::Regex;
+use std::collections::HashSet;
+use url::Url;
+
+/// Name of the sitemap looked up in the fastdl directory and at the root of the host
+pub const SITEMAP_FILE: &str = "sitemap.xml";
+/// Depth of nested sitemap indexes that is followed
+const MAX_DEPTH: usize = 2;
+
+/// Returns every file a sitemap of `root_url` lists, or None if the fastdl has no sitemap
+/// The sitemap in the fastdl directory is tried first, then the one at the root of the host
+/// Only files under `root_url` are kept; directories (locations ending with "/") are skipped
+/// A sitemap that lists no file under `root_url` counts as no sitemap
+///
+/// # Arguments
+/// * `root_url`    -   Fastdl url the crawl starts at
+pub fn discover(root_url: &str) -> Option<HashSet<RemoteFile>> {
+    let root = Url::parse(root_url).ok()?;
+    let candidates = [
+        root.join(SITEMAP_FILE).ok()?,
+        root.join("/").ok()?.join(SITEMAP_FILE).ok()?,
+    ];
+
+    candidates.iter().find_map(|sitemap| {
+        let mut files = HashSet::new();
+        read_sitemap(sitemap.as_str(), root_url, 0, &mut files)?;
+
+        // A sitemap of the host that lists nothing under the fastdl is not its sitemap
+        (!files.is_empty()).then_some(files)
+    })
+}
+
+/// Adds the files of the sitemap (or sitemap index) at `url` to `files`
+/// Returns None if `url` is not a sitemap
+///
+/// # Arguments
+/// * `url`         -   Url of the sitemap
+/// * `root_url`    -   Fastdl url the crawl starts at
+/// * `depth`       -   Number of sitemap indexes this sitemap is nested in
+/// * `files`       -   Files found so far
+fn read_sitemap(
+    url: &str,
+    root_url: &str,
+    depth: usize,
+    files: &mut HashSet<RemoteFile>,
+) -> Option<()> {
+    let resp = net::get(url).ok()?;
+    if !resp.status().is_success() {
+        return None;
+    }
+    let body = resp.text().ok()?;
+
+    // Listings and error pages served under the sitemap's name are not sitemaps
+    let is_index = body.contains("<sitemapindex");
+    if !is_index && !body.contains("<urlset") {
+        return None;
+    }
+
+    let entry = Regex::new(r"(?s)<(?:url|sitemap)>(.*?)</(?:url|sitemap)>").unwrap();
+    let loc = Regex::new(r"(?s)<loc>\s*(.*?)\s*</loc>").unwrap();
+    let lastmod = Regex::new(r"(?s)<lastmod>\s*(.*?)\s*</lastmod>").unwrap();
+
+    for entry in entry.captures_iter(&body) {
+        let Some(location) = loc.captures(&entry[1]).map(|c| unescape(&c[1])) else {
+            continue;
+        };
+
+        if is_index {
+            // A nested sitemap that can't be read makes the listing incomplete
+            if depth >= MAX_DEPTH {
+                return None;
+            }
+            read_sitemap(&location, root_url, depth + 1, files)?;
+        } else if location.starts_with(root_url) && !location.ends_with('/') {
+            files.insert(RemoteFile {
+                mtime: lastmod
+                    .captures(&entry[1])
+                    .and_then(|c| parse_w3c_date(&c[1])),
+                ..RemoteFile::new(&location, root_url)
+            });
+        }
+    }
+
+    Some(())
+}
+
+/// Returns `text` with the five predefined XML entities replaced
+fn unescape(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+/// Returns the unix time of a W3C datetime ("2023-08-14", "2023-08-14T10:20:30Z", "2023-08-14T10:20:30+02:00")
+///
+/// # Arguments
+/// * `date`    -   Value of a `<lastmod>` element
+fn parse_w3c_date(date: &str) -> Option<u64> {
+    let re = Regex::new(
+        r"^(\d{4})-(\d{2})-(\d{2})(?:T(\d{2}):(\d{2})(?::(\d{2})(?:\.\d+)?)?(Z|([+-])(\d{2}):(\d{2}))?)?$",
+    )
+    .unwrap();
+    let c = re.captures(date.trim())?;
+    let num = |i: usize| c.get(i).map_or(Some(0), |m| m.as_str().parse::<i64>().ok());
+
+    let (year, month, day) = (num(1)?, num(2)?, num(3)?);
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return None;
+    }
+
+    // Days since 1970-01-01 of the proleptic Gregorian calendar
+    let (y, m) = if month <= 2 {
+        (year - 1, month + 9)
+    } else {
+        (year, month - 3)
+    };
+    let era = y.div_euclid(400);
+    let yoe = y - era * 400;
+    let doy = (153 * m + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    let days = era * 146097 + doe - 719468;
+
+    let offset = match c.get(8).map(|m| m.as_str()) {
+        Some(sign) => {
+            let offset = num(9)? * 3600 + num(10)? * 60;
+            if sign == "-" {
+                -offset
+            } else {
+                offset
+            }
+        }
+        None => 0,
+    };
+
+    let secs = days * 86400 + num(4)? * 3600 + num(5)? * 60 + num(6)? - offset;
+    u64::try_from(secs).ok()
+}
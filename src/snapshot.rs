@@ -0,0 +1,244 @@
+use crate::{
+    meta_dir::{self, META_DIR},
+    run_lock::LOCK_FILE,
+    state_store::unix_now,
+    MB_SIZE,
+};
+use std::{
+    collections::HashSet,
+    fs, io,
+    path::{Path, PathBuf},
+};
+use walkdir::WalkDir;
+
+/// Directory of the output directory the snapshots are kept in (never synced, scanned or snapshotted)
+pub const SNAPSHOTS_DIR: &str = "fastdl_snapshots";
+
+/// Action is what `snapshot` does
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Action {
+    /// Hardlink every file of the mirror into a new snapshot (`snapshot create <name>`)
+    Create(String),
+    /// Make the mirror identical to a snapshot again (`snapshot restore <name>`)
+    Restore(String),
+    /// Remove a snapshot (`snapshot delete <name>`)
+    Delete(String),
+    /// Print every snapshot (`snapshot list`)
+    List,
+}
+
+impl Action {
+    /// Parses the arguments given after `snapshot`
+    pub fn parse(args: &[String]) -> Result<Self, String> {
+        let action = match args {
+            [action, name] if action == "create" => Self::Create(name.clone()),
+            [action, name] if action == "restore" => Self::Restore(name.clone()),
+            [action, name] if action == "delete" => Self::Delete(name.clone()),
+            [action] if action == "list" => Self::List,
+            _ => {
+                return Err(String::from(
+                    "snapshot needs create <name>, restore <name>, delete <name> or list",
+                ))
+            }
+        };
+
+        match &action {
+            Action::Create(name) | Action::Restore(name) | Action::Delete(name)
+                if name.is_empty() || name.starts_with('.') || name.contains(['/', '\\', ':']) =>
+            {
+                Err(format!("Invalid snapshot name: {}", name))
+            }
+            _ => Ok(action),
+        }
+    }
+}
+
+/// Returns the directory the snapshots of the mirror in `out_dir` are kept in
+fn snapshots_dir(out_dir: &Path) -> PathBuf {
+    meta_dir::path(out_dir, SNAPSHOTS_DIR)
+}
+
+/// Returns true if the entry at `rel_path` of a mirror or snapshot is never part of a snapshot:
+/// the snapshots themselves and the lock file
+fn is_excluded(rel_path: &Path) -> bool {
+    let meta_dir = Path::new(META_DIR);
+
+    rel_path == meta_dir.join(SNAPSHOTS_DIR) || rel_path == meta_dir.join(LOCK_FILE)
+}
+
+/// Returns the relative path of every file of `root`, without the snapshots and the lock file
+///
+/// # Arguments
+/// * `root`    -   Mirror or snapshot directory
+fn files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for entry in WalkDir::new(root)
+        .into_iter()
+        .filter_entry(|entry| !is_excluded(entry.path().strip_prefix(root).unwrap()))
+    {
+        let entry = entry?;
+        if entry.file_type().is_dir() {
+            continue;
+        }
+
+        files.push(entry.path().strip_prefix(root).unwrap().to_path_buf());
+    }
+
+    Ok(files)
+}
+
+/// Returns true if the file at `rel_path` is one of the state files of the output directory
+/// (in `.fastdl/`, or at the top of snapshots taken by older releases)
+/// They're small and some (the sqlite database) are written in place, so they're copied instead of linked
+fn is_state_file(rel_path: &Path) -> bool {
+    rel_path.starts_with(META_DIR)
+        || rel_path.components().count() == 1
+            && rel_path
+                .to_str()
+                .is_some_and(|name| name.starts_with("fastdl_"))
+}
+
+/// Links `rel_path` of `src_root` into `dst_root`, copying it if it's a state file or the file system has no hardlinks
+fn link_or_copy(src_root: &Path, dst_root: &Path, rel_path: &Path) -> io::Result<()> {
+    let (src, dst) = (src_root.join(rel_path), dst_root.join(rel_path));
+    if let Some(parent) = dst.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if is_state_file(rel_path) {
+        return fs::copy(&src, &dst).map(|_| ());
+    }
+
+    fs::hard_link(&src, &dst).or_else(|_| fs::copy(&src, &dst).map(|_| ()))
+}
+
+/// Hardlinks every file of the mirror in `out_dir` into the snapshot `name`
+/// The snapshot takes no space until the mirror changes: files are replaced, never rewritten in place
+/// Returns the number of files in the snapshot
+///
+/// # Arguments
+/// * `out_dir` -   Output directory of the mirror
+/// * `name`    -   Name of the snapshot
+pub fn create(out_dir: &Path, name: &str) -> io::Result<usize> {
+    let snapshot_dir = snapshots_dir(out_dir).join(name);
+    if snapshot_dir.exists() {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("Snapshot {} already exists", name),
+        ));
+    }
+
+    let files = files(out_dir)?;
+    for file in &files {
+        link_or_copy(out_dir, &snapshot_dir, file)?;
+    }
+    fs::create_dir_all(&snapshot_dir)?;
+
+    Ok(files.len())
+}
+
+/// Makes the mirror in `out_dir` identical to the snapshot `name` (manifest and queue included)
+/// The current state is snapshotted first, so a restore can be undone
+/// Returns the name of that snapshot
+///
+/// # Arguments
+/// * `out_dir` -   Output directory of the mirror
+/// * `name`    -   Name of the snapshot
+pub fn restore(out_dir: &Path, name: &str) -> io::Result<String> {
+    let snapshot_dir = snapshots_dir(out_dir).join(name);
+    if !snapshot_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No snapshot named {}", name),
+        ));
+    }
+
+    let backup = format!("before-restore-{}", unix_now());
+    create(out_dir, &backup)?;
+
+    let snapshot_files = files(&snapshot_dir)?;
+    let kept = snapshot_files.iter().collect::<HashSet<_>>();
+
+    // Files the snapshot does not have are removed, every other file is linked again from the snapshot
+    for file in files(out_dir)?.iter().filter(|file| !kept.contains(file)) {
+        fs::remove_file(out_dir.join(file))?;
+    }
+    for file in &snapshot_files {
+        let path = out_dir.join(file);
+        match fs::remove_file(&path) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => return Err(err),
+            _ => {}
+        }
+        link_or_copy(&snapshot_dir, out_dir, file)?;
+    }
+
+    // Directories emptied by the restore, deepest first
+    for entry in WalkDir::new(out_dir)
+        .contents_first(true)
+        .min_depth(1)
+        .into_iter()
+        .filter_entry(|entry| !is_excluded(entry.path().strip_prefix(out_dir).unwrap()))
+        .flatten()
+        .filter(|entry| entry.file_type().is_dir())
+    {
+        let _ = fs::remove_dir(entry.path());
+    }
+
+    Ok(backup)
+}
+
+/// Removes the snapshot `name` (the files stay in the mirror and in the other snapshots)
+///
+/// # Arguments
+/// * `out_dir` -   Output directory of the mirror
+/// * `name`    -   Name of the snapshot
+pub fn delete(out_dir: &Path, name: &str) -> io::Result<()> {
+    let snapshot_dir = snapshots_dir(out_dir).join(name);
+    if !snapshot_dir.is_dir() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No snapshot named {}", name),
+        ));
+    }
+
+    fs::remove_dir_all(snapshot_dir)
+}
+
+/// Prints every snapshot of the mirror in `out_dir` with its number of files and size
+///
+/// # Arguments
+/// * `out_dir` -   Output directory of the mirror
+pub fn list(out_dir: &Path) -> io::Result<()> {
+    let Ok(entries) = fs::read_dir(snapshots_dir(out_dir)) else {
+        println!("No snapshots in {}", out_dir.display());
+        return Ok(());
+    };
+
+    let mut names = entries
+        .flatten()
+        .filter(|entry| entry.path().is_dir())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect::<Vec<_>>();
+    names.sort();
+
+    println!("Snapshots of {}:", out_dir.display());
+    for name in names {
+        let snapshot_dir = snapshots_dir(out_dir).join(&name);
+        let files = files(&snapshot_dir)?;
+        let size = files
+            .iter()
+            .filter_map(|file| fs::metadata(snapshot_dir.join(file)).ok())
+            .map(|meta| meta.len())
+            .sum::<u64>();
+
+        println!(
+            "  {}\t{} files\t{:.2} MB",
+            name,
+            files.len(),
+            size as f32 / MB_SIZE as f32
+        );
+    }
+
+    Ok(())
+}
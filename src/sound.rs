@@ -0,0 +1,98 @@
+use crate::files::RemoteFile;
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+/// Files smaller than this are latency-bound: the request costs more than the transfer
+pub const SMALL_FILE_SIZE: u64 = 256 * 1024;
+/// Asset directory of the sounds, thousands of tiny `.wav`/`.mp3` files on most fastdls
+const SOUND_DIR: &str = "sound";
+
+/// Returns true if `file` is small enough to be downloaded by the small-file workers
+/// Sounds whose size the crawl did not learn (e.g. with `--fast-crawl`) are assumed to be small
+///
+/// # Arguments
+/// * `file`    -   File found during the crawl
+pub fn is_small(file: &RemoteFile) -> bool {
+    match file.size {
+        Some(size) => size < SMALL_FILE_SIZE,
+        None => file
+            .rel_path
+            .split('/')
+            .any(|segment| segment.eq_ignore_ascii_case(SOUND_DIR)),
+    }
+}
+
+/// DuplicateRule drops the files of one extension that have a copy with another extension
+/// (`--drop-duplicate mp3:wav` drops `sound/x/y.mp3` if `sound/x/y.wav` is also served)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DuplicateRule {
+    /// Lowercased extension of the copies that are dropped
+    pub drop: String,
+    /// Lowercased extension of the copies that are kept
+    pub keep: String,
+}
+
+impl FromStr for DuplicateRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let normalize = |ext: &str| ext.trim().trim_start_matches('.').to_lowercase();
+
+        match s.split_once(':') {
+            Some((drop, keep)) if !normalize(drop).is_empty() && !normalize(keep).is_empty() => {
+                Ok(Self {
+                    drop: normalize(drop),
+                    keep: normalize(keep),
+                })
+            }
+            _ => Err(format!(
+                "Invalid duplicate rule: {} (expected <dropped ext>:<kept ext>, e.g. mp3:wav)",
+                s
+            )),
+        }
+    }
+}
+
+/// Returns the lowercased relative path of a file without its extension and `.bz2`, and the extension
+/// ("sound/ze/Boss.wav.bz2" -> ("sound/ze/boss", "wav"))
+fn stem_and_extension(file: &RemoteFile) -> Option<(String, String)> {
+    let path = file.rel_path.to_lowercase();
+    let path = path.strip_suffix(".bz2").unwrap_or(&path);
+    let (stem, ext) = path.rsplit_once('.')?;
+
+    (!stem.ends_with('/')).then(|| (stem.to_string(), ext.to_string()))
+}
+
+/// Removes every file that a rule drops in favor of a copy with the kept extension
+/// Returns the number of files that were removed
+///
+/// # Arguments
+/// * `links`   -   Files found during the crawl
+/// * `rules`   -   Duplicate rules (`--drop-duplicate`)
+pub fn drop_duplicates(links: &mut HashSet<RemoteFile>, rules: &[DuplicateRule]) -> usize {
+    if rules.is_empty() {
+        return 0;
+    }
+
+    // Stem -> every extension it's served with
+    let mut extensions = HashMap::<String, HashSet<String>>::new();
+    for (stem, ext) in links.iter().filter_map(stem_and_extension) {
+        extensions.entry(stem).or_default().insert(ext);
+    }
+
+    let len = links.len();
+    links.retain(|file| {
+        let Some((stem, ext)) = stem_and_extension(file) else {
+            return true;
+        };
+        let served = &extensions[&stem];
+
+        !rules
+            .iter()
+            .any(|rule| rule.drop == ext && served.contains(&rule.keep))
+    });
+
+    len - links.len()
+}
@@ -0,0 +1,130 @@
+use crate::files::RemoteFile;
+use crate::report::map_name;
+use std::{collections::HashSet, fs, io, path::Path};
+
+/// Returns the lowercased map name of a list entry, without the optional extensions
+fn normalize(entry: &str) -> String {
+    let entry = entry.strip_suffix(".bz2").unwrap_or(entry);
+    entry.strip_suffix(".bsp").unwrap_or(entry).to_lowercase()
+}
+
+/// Returns the map names listed in `path` (one per line, lowercased)
+/// Empty lines and lines starting with "#" are ignored, extensions are optional
+///
+/// # Arguments
+/// * `path`    -   Path of the map list
+pub fn load_map_list(path: &Path) -> io::Result<HashSet<String>> {
+    let maps = fs::read_to_string(path)?
+        .lines()
+        .map(|line| line.trim())
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(normalize)
+        .collect();
+
+    Ok(maps)
+}
+
+/// Returns the tokens of a KeyValues text: quoted or bare strings and braces, without `//` comments
+fn keyvalues_tokens(text: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+
+    for line in text.lines() {
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                '/' if chars.peek() == Some(&'/') => break,
+                '{' | '}' => tokens.push(c.to_string()),
+                '"' => tokens.push(chars.by_ref().take_while(|&c| c != '"').collect()),
+                c if c.is_whitespace() => {}
+                c => {
+                    let mut token = c.to_string();
+                    while let Some(&c) = chars.peek() {
+                        if c.is_whitespace() || c == '{' || c == '}' || c == '"' {
+                            break;
+                        }
+                        token.push(c);
+                        chars.next();
+                    }
+                    tokens.push(token);
+                }
+            }
+        }
+    }
+
+    tokens
+}
+
+/// Returns the map names of a list exported by SourceMod's mapchooser plugins (lowercased)
+/// KeyValues files (e.g. the mapchooser_extended maps config) list the maps as the sections of the
+/// root section, plain files (mapcycle.txt, nomination exports) have one map per line
+///
+/// # Arguments
+/// * `path`    -   Path of the exported list
+pub fn load_nominations(path: &Path) -> io::Result<HashSet<String>> {
+    let text = fs::read_to_string(path)?;
+
+    if !text.contains('{') {
+        let maps = text
+            .lines()
+            .filter_map(|line| line.split("//").next()?.split_whitespace().next())
+            .map(|entry| entry.trim_matches('"'))
+            .filter(|entry| !entry.is_empty() && !entry.starts_with(['#', ';']))
+            .map(normalize)
+            .collect();
+        return Ok(maps);
+    }
+
+    let tokens = keyvalues_tokens(&text);
+    let mut maps = HashSet::new();
+    let mut depth = 0;
+    for (i, token) in tokens.iter().enumerate() {
+        match token.as_str() {
+            "{" => depth += 1,
+            "}" => depth -= 1,
+            // A key opening a section inside of the root section is a map
+            key if depth == 1 && tokens.get(i + 1).map(String::as_str) == Some("{") => {
+                maps.insert(normalize(key));
+            }
+            _ => {}
+        }
+    }
+
+    Ok(maps)
+}
+
+/// Returns the lowercased name of the map a file in a `maps` directory belongs to, if it's not a map itself
+/// (`maps/ze_x.nav`, `maps/cfg/ze_x.cfg`, `maps/ze_x_particles.txt`, ...)
+fn companion_stem(url: &str) -> Option<String> {
+    let mut segments = url.rsplit('/');
+    let name = segments.next()?;
+    if !segments.any(|segment| segment.eq_ignore_ascii_case("maps")) || map_name(url).is_some() {
+        return None;
+    }
+
+    let name = name.strip_suffix(".bz2").unwrap_or(name).to_lowercase();
+    Some(name.split('.').next().unwrap_or_default().to_string())
+}
+
+/// Removes every map link that is not in `maps`, along with the files of the `maps` directories that
+/// belong to those maps (navigation meshes, overviews, configs); shared content is kept
+/// Returns the number of links that were removed
+///
+/// # Arguments
+/// * `links`   -   Files found during the crawl
+/// * `maps`    -   Lowercased map names that should be synced
+pub fn retain_maps(links: &mut HashSet<RemoteFile>, maps: &HashSet<String>) -> usize {
+    let len = links.len();
+
+    links.retain(|file| {
+        if let Some(name) = map_name(&file.url) {
+            return maps.contains(&name.to_lowercase());
+        }
+
+        companion_stem(&file.url).is_none_or(|stem| {
+            maps.iter()
+                .any(|map| stem == *map || stem.starts_with(&format!("{}_", map)))
+        })
+    });
+
+    len - links.len()
+}
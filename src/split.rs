@@ -0,0 +1,112 @@
+use crate::{
+    decoder::Format,
+    durability,
+    files::RemoteFile,
+    scan::{self, ScanPolicy},
+};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// Digits of the number of a part (`.001`)
+const PART_DIGITS: usize = 3;
+
+/// Returns the name of the whole archive and the number of the part if `name` is a part of a split
+/// archive ("ze_x.bsp.bz2.002" -> ("ze_x.bsp.bz2", 2))
+///
+/// # Arguments
+/// * `name`    -   File name, path or url
+pub fn part_of(name: &str) -> Option<(&str, u32)> {
+    let (archive, number) = name.rsplit_once('.')?;
+    if number.len() != PART_DIGITS || !number.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+
+    // Only compressed archives are split, a plain "x.001" is left alone
+    Format::from_name(archive)?;
+
+    match number.parse() {
+        Ok(0) | Err(_) => None,
+        Ok(number) => Some((archive, number)),
+    }
+}
+
+/// Groups parts by the archive they belong to, with the numbers of the parts that are there
+fn group<'a>(names: impl Iterator<Item = &'a str>) -> BTreeMap<&'a str, Vec<u32>> {
+    let mut sets = BTreeMap::<&str, Vec<u32>>::new();
+    for (archive, number) in names.filter_map(part_of) {
+        sets.entry(archive).or_default().push(number);
+    }
+    for numbers in sets.values_mut() {
+        numbers.sort_unstable();
+        numbers.dedup();
+    }
+
+    sets
+}
+
+/// Returns the numbers missing from 1 up to the last part
+fn gaps(numbers: &[u32]) -> Vec<u32> {
+    let present = numbers.iter().collect::<HashSet<_>>();
+    let last = numbers.last().copied().unwrap_or(0);
+
+    (1..last).filter(|n| !present.contains(n)).collect()
+}
+
+/// Returns the url of every part missing from the split archives found during the crawl
+/// (a part is missing if a later part is listed; a missing last part only shows when decoding)
+///
+/// # Arguments
+/// * `links`   -   Files found during the crawl
+pub fn missing_parts(links: &HashSet<RemoteFile>) -> Vec<String> {
+    group(links.iter().map(|file| file.url.as_str()))
+        .into_iter()
+        .flat_map(|(archive, numbers)| {
+            gaps(&numbers)
+                .into_iter()
+                .map(move |n| format!("{}.{:03}", archive, n))
+        })
+        .collect()
+}
+
+/// Concatenates the downloaded parts of every split archive below `roots` into the whole archive,
+/// so the decoder finds it, then removes the parts
+/// Returns the archives whose parts have gaps (they're left as they are)
+///
+/// # Arguments
+/// * `roots`   -   Directories that are searched
+/// * `scan`    -   Which symlinks and hidden directories are walked into
+pub fn join_parts(roots: &[PathBuf], scan: ScanPolicy) -> io::Result<Vec<PathBuf>> {
+    let paths = roots
+        .iter()
+        .flat_map(|root| scan::find_files(root, scan, |name| part_of(name).is_some()))
+        .filter_map(|path| path.to_str().map(str::to_string))
+        .collect::<Vec<_>>();
+
+    let mut incomplete = Vec::new();
+    for (archive, numbers) in group(paths.iter().map(String::as_str)) {
+        if !gaps(&numbers).is_empty() {
+            incomplete.push(PathBuf::from(archive));
+            continue;
+        }
+
+        let parts = numbers
+            .iter()
+            .map(|n| PathBuf::from(format!("{}.{:03}", archive, n)))
+            .collect::<Vec<_>>();
+        let mut output = durability::create(Path::new(archive))?;
+        for part in &parts {
+            io::copy(&mut File::open(part)?, &mut output)?;
+        }
+        durability::sync_file(&output)?;
+
+        for part in &parts {
+            fs::remove_file(part)?;
+        }
+    }
+
+    Ok(incomplete)
+}
@@ -0,0 +1,498 @@
+use crate::{bandwidth::Bandwidth, durability, meta_dir, schema};
+#[cfg(feature = "sqlite")]
+use rusqlite::OptionalExtension;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Name of the JSON manifest stored in the output directory
+pub const MANIFEST_FILE: &str = "fastdl_manifest.json";
+/// Name of the sqlite database stored in the output directory
+#[cfg(feature = "sqlite")]
+pub const SQLITE_FILE: &str = "fastdl_state.sqlite";
+
+/// Returns the current time as seconds since the unix epoch
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs())
+}
+
+/// ManifestEntry stores what we know about a file that was downloaded
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// Size of the downloaded file in bytes
+    pub size: u64,
+    /// Unix time the file was downloaded
+    pub synced_at: u64,
+    /// Local path the file was written to
+    #[serde(default)]
+    pub path: Option<PathBuf>,
+    /// Checksum of every `repair::CHUNK_SIZE` chunk of the file, used to repair only the damaged ranges
+    #[serde(default)]
+    pub chunks: Vec<String>,
+    /// Wayback Machine copy the file was downloaded from because the fastdl no longer has it (`--wayback`)
+    #[serde(default)]
+    pub archived_from: Option<String>,
+    /// Size of the file once it was decoded (None if it was not decoded), gives its compression ratio
+    #[serde(default)]
+    pub decoded_size: Option<u64>,
+    /// Hex sha256 of the downloaded file, compared to the checksums the fastdl publishes (`SHA256SUMS`)
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
+
+/// Manifest stores every downloaded file (keyed by its download link) and the last sync time
+/// Its format is versioned by `schema::MANIFEST`, older manifests are migrated when they're loaded
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Manifest {
+    /// Version of the format the manifest was written in
+    #[serde(default)]
+    pub schema_version: u32,
+    /// Unix time of the last successful sync
+    pub last_sync: u64,
+    /// What every sync of the mirror downloaded and saved
+    #[serde(default)]
+    pub bandwidth: Bandwidth,
+    /// Download link -> info about the downloaded file
+    pub files: HashMap<String, ManifestEntry>,
+}
+
+/// StateStore keeps the visited paths of a crawl and the manifest of downloaded files
+pub trait StateStore: Send + Sync {
+    /// Forgets every visited path so a new crawl starts fresh
+    fn clear_visited(&self);
+    /// Marks `path` as visited, returning false if it was already visited
+    fn visit(&self, path: &str) -> bool;
+    /// Returns true if `path` was visited
+    fn is_visited(&self, path: &str) -> bool;
+    /// Returns the number of visited paths
+    fn visited_len(&self) -> usize;
+    /// Records a downloaded file in the manifest
+    fn record_file(&self, url: &str, entry: ManifestEntry);
+    /// Removes a file from the manifest
+    fn forget_file(&self, url: &str);
+    /// Returns the manifest entry of a downloaded file
+    fn file(&self, url: &str) -> Option<ManifestEntry>;
+    /// Returns every downloaded file in the manifest
+    fn files(&self) -> Vec<(String, ManifestEntry)>;
+    /// Returns the unix time of the last successful sync (0 if there was none)
+    fn last_sync(&self) -> u64;
+    /// Records the current time as the last successful sync and persists the state
+    fn finish_sync(&self) -> Result<(), Box<dyn Error>>;
+    /// Persists the state without touching the last sync time
+    fn save(&self) -> Result<(), Box<dyn Error>>;
+    /// Returns what every sync of the mirror downloaded and saved
+    fn bandwidth(&self) -> Bandwidth;
+    /// Adds `bandwidth` to what the syncs of the mirror downloaded and saved
+    fn add_bandwidth(&self, bandwidth: Bandwidth);
+}
+
+/// JsonStore keeps the visited paths in memory and the manifest in a JSON file
+pub struct JsonStore {
+    /// Path of the JSON manifest
+    path: PathBuf,
+    /// Paths that were visited during the current crawl
+    visited: Mutex<HashSet<String>>,
+    /// Manifest that is written to `path` when the sync finishes
+    manifest: Mutex<Manifest>,
+}
+
+impl JsonStore {
+    /// Returns a JsonStore with the manifest at `path` loaded (or an empty one if it does not exist)
+    /// Fails instead of starting over if the manifest can't be read, so it's never overwritten
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the JSON manifest
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let manifest = schema::MANIFEST.load(path)?.unwrap_or_default();
+
+        Ok(Self {
+            path: path.to_path_buf(),
+            visited: Mutex::new(HashSet::new()),
+            manifest: Mutex::new(manifest),
+        })
+    }
+}
+
+impl StateStore for JsonStore {
+    fn clear_visited(&self) {
+        self.visited.lock().unwrap().clear();
+    }
+
+    fn visit(&self, path: &str) -> bool {
+        self.visited.lock().unwrap().insert(path.to_string())
+    }
+
+    fn is_visited(&self, path: &str) -> bool {
+        self.visited.lock().unwrap().contains(path)
+    }
+
+    fn visited_len(&self) -> usize {
+        self.visited.lock().unwrap().len()
+    }
+
+    fn record_file(&self, url: &str, entry: ManifestEntry) {
+        self.manifest
+            .lock()
+            .unwrap()
+            .files
+            .insert(url.to_string(), entry);
+    }
+
+    fn forget_file(&self, url: &str) {
+        self.manifest.lock().unwrap().files.remove(url);
+    }
+
+    fn file(&self, url: &str) -> Option<ManifestEntry> {
+        self.manifest.lock().unwrap().files.get(url).cloned()
+    }
+
+    fn files(&self) -> Vec<(String, ManifestEntry)> {
+        self.manifest
+            .lock()
+            .unwrap()
+            .files
+            .iter()
+            .map(|(url, entry)| (url.clone(), entry.clone()))
+            .collect()
+    }
+
+    fn last_sync(&self) -> u64 {
+        self.manifest.lock().unwrap().last_sync
+    }
+
+    fn finish_sync(&self) -> Result<(), Box<dyn Error>> {
+        self.manifest.lock().unwrap().last_sync = unix_now();
+        self.save()
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        let mut manifest = self.manifest.lock().unwrap();
+        manifest.schema_version = schema::MANIFEST.version();
+
+        // Write to a temporary file first so a crash never leaves a half-written manifest
+        durability::write_atomic(
+            &self.path,
+            serde_json::to_string_pretty(&*manifest)?.as_bytes(),
+        )?;
+
+        Ok(())
+    }
+
+    fn bandwidth(&self) -> Bandwidth {
+        self.manifest.lock().unwrap().bandwidth
+    }
+
+    fn add_bandwidth(&self, bandwidth: Bandwidth) {
+        self.manifest.lock().unwrap().bandwidth += bandwidth;
+    }
+}
+
+/// SqliteStore keeps the visited paths and the manifest in a sqlite database with indexed lookups
+/// Used for mirrors with hundreds of thousands of files where the in-memory sets get unwieldy
+/// Changes are made in a transaction that `save` commits, so a crash never leaves half of them behind
+#[cfg(feature = "sqlite")]
+pub struct SqliteStore {
+    /// Connection to the database (rusqlite connections are not Sync)
+    conn: Mutex<rusqlite::Connection>,
+    /// First change that failed since the last save, returned by the next save
+    failed: Mutex<Option<rusqlite::Error>>,
+}
+
+#[cfg(feature = "sqlite")]
+impl SqliteStore {
+    /// Returns a SqliteStore with the database at `path`, creating or migrating the tables
+    ///
+    /// # Arguments
+    /// * `path`    -   Path of the sqlite database
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let mut conn = rusqlite::Connection::open(path).map_err(|e| e.to_string())?;
+        conn.execute_batch("PRAGMA journal_mode = WAL;")
+            .map_err(|e| e.to_string())?;
+        // In WAL mode NORMAL can lose the last commits on a power loss but never corrupts the database
+        conn.pragma_update(
+            None,
+            "synchronous",
+            if durability::fsync() {
+                "FULL"
+            } else {
+                "NORMAL"
+            },
+        )
+        .map_err(|e| e.to_string())?;
+        schema::migrate_sqlite(&mut conn)?;
+        conn.execute_batch("BEGIN").map_err(|e| e.to_string())?;
+
+        Ok(Self {
+            conn: Mutex::new(conn),
+            failed: Mutex::new(None),
+        })
+    }
+
+    /// Commits the changes made since the last commit and starts the transaction of the next ones
+    /// Fails with the first change that could not be made, the others are committed anyway
+    pub fn commit(&self) -> rusqlite::Result<()> {
+        let conn = self.conn.lock().unwrap();
+        // Some errors (e.g. a full disk) roll the whole transaction back
+        if !conn.is_autocommit() {
+            conn.execute_batch("COMMIT")?;
+        }
+        conn.execute_batch("BEGIN")?;
+
+        match self.failed.lock().unwrap().take() {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs the change `f`, keeping its error for the next save
+    fn change<T>(&self, f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>) -> Option<T> {
+        let result = f(&self.conn.lock().unwrap());
+        result
+            .map_err(|err| {
+                self.failed.lock().unwrap().get_or_insert(err);
+            })
+            .ok()
+    }
+
+    /// Runs the query `f`, returning `default` if it fails
+    fn query<T>(
+        &self,
+        default: T,
+        f: impl FnOnce(&rusqlite::Connection) -> rusqlite::Result<T>,
+    ) -> T {
+        let result = f(&self.conn.lock().unwrap());
+        result.unwrap_or_else(|err| {
+            eprintln!("Failed to read the state store: {}", err);
+            default
+        })
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl StateStore for SqliteStore {
+    fn clear_visited(&self) {
+        self.change(|conn| conn.execute("DELETE FROM visited", []));
+    }
+
+    fn visit(&self, path: &str) -> bool {
+        // A path that can't be recorded is crawled (again) rather than skipped
+        self.change(|conn| conn.execute("INSERT OR IGNORE INTO visited (path) VALUES (?1)", [path]))
+            .is_none_or(|inserted| inserted > 0)
+    }
+
+    fn is_visited(&self, path: &str) -> bool {
+        self.query(false, |conn| {
+            conn.query_row("SELECT 1 FROM visited WHERE path = ?1", [path], |_| Ok(()))
+                .optional()
+                .map(|row| row.is_some())
+        })
+    }
+
+    fn visited_len(&self) -> usize {
+        self.query(0, |conn| {
+            conn.query_row("SELECT COUNT(*) FROM visited", [], |r| r.get::<_, i64>(0))
+        }) as usize
+    }
+
+    fn record_file(&self, url: &str, entry: ManifestEntry) {
+        self.change(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO files (url, size, synced_at, path, chunks, archived_from, decoded_size, sha256)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)",
+                rusqlite::params![
+                    url,
+                    entry.size as i64,
+                    entry.synced_at as i64,
+                    entry.path.as_ref().and_then(|p| p.to_str()),
+                    entry.chunks.join(","),
+                    entry.archived_from,
+                    entry.decoded_size.map(|size| size as i64),
+                    entry.sha256,
+                ],
+            )
+        });
+    }
+
+    fn forget_file(&self, url: &str) {
+        self.change(|conn| conn.execute("DELETE FROM files WHERE url = ?1", [url]));
+    }
+
+    fn file(&self, url: &str) -> Option<ManifestEntry> {
+        self.query(None, |conn| {
+            conn.query_row(
+                "SELECT size, synced_at, path, chunks, archived_from, decoded_size, sha256 FROM files WHERE url = ?1",
+                [url],
+                |r| entry_from_row(r, 0),
+            )
+            .optional()
+        })
+    }
+
+    fn files(&self) -> Vec<(String, ManifestEntry)> {
+        self.query(Vec::new(), |conn| {
+            conn.prepare(
+                "SELECT url, size, synced_at, path, chunks, archived_from, decoded_size, sha256 FROM files ORDER BY url",
+            )?
+            .query_map([], |r| Ok((r.get::<_, String>(0)?, entry_from_row(r, 1)?)))?
+            .collect()
+        })
+    }
+
+    fn last_sync(&self) -> u64 {
+        self.query(0, |conn| {
+            conn.query_row("SELECT value FROM meta WHERE key = 'last_sync'", [], |r| {
+                r.get::<_, i64>(0)
+            })
+            .optional()
+            .map(|value| value.unwrap_or(0))
+        }) as u64
+    }
+
+    fn finish_sync(&self) -> Result<(), Box<dyn Error>> {
+        self.change(|conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('last_sync', ?1)",
+                [unix_now() as i64],
+            )
+        });
+
+        Ok(self.commit()?)
+    }
+
+    fn save(&self) -> Result<(), Box<dyn Error>> {
+        Ok(self.commit()?)
+    }
+
+    fn bandwidth(&self) -> Bandwidth {
+        self.query(Bandwidth::default(), |conn| {
+            let meta = |key: &str| {
+                conn.query_row("SELECT value FROM meta WHERE key = ?1", [key], |r| {
+                    r.get::<_, i64>(0)
+                })
+                .optional()
+                .map(|value| value.unwrap_or(0) as u64)
+            };
+
+            Ok(Bandwidth {
+                downloaded: meta("bytes_downloaded")?,
+                saved: meta("bytes_saved")?,
+            })
+        })
+    }
+
+    fn add_bandwidth(&self, bandwidth: Bandwidth) {
+        self.change(|conn| {
+            for (key, bytes) in [
+                ("bytes_downloaded", bandwidth.downloaded),
+                ("bytes_saved", bandwidth.saved),
+            ] {
+                conn.execute(
+                    "INSERT INTO meta (key, value) VALUES (?1, ?2) \
+                     ON CONFLICT(key) DO UPDATE SET value = value + ?2",
+                    rusqlite::params![key, bytes as i64],
+                )?;
+            }
+            Ok(())
+        });
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl Drop for SqliteStore {
+    fn drop(&mut self) {
+        // The changes of a run that ends without a save are kept as well
+        if let Err(err) = self.commit() {
+            eprintln!("Failed to save the state store: {}", err);
+        }
+    }
+}
+
+/// Returns the ManifestEntry stored in the columns of `row` starting at `first`
+/// (size, synced_at, path, chunks, archived_from, decoded_size, sha256)
+///
+/// # Arguments
+/// * `row`     -   Row of the files table
+/// * `first`   -   Index of the size column
+#[cfg(feature = "sqlite")]
+fn entry_from_row(row: &rusqlite::Row, first: usize) -> rusqlite::Result<ManifestEntry> {
+    let chunks = row.get::<_, String>(first + 3)?;
+
+    Ok(ManifestEntry {
+        size: row.get::<_, i64>(first)? as u64,
+        synced_at: row.get::<_, i64>(first + 1)? as u64,
+        path: row.get::<_, Option<String>>(first + 2)?.map(PathBuf::from),
+        chunks: chunks
+            .split(',')
+            .filter(|c| !c.is_empty())
+            .map(String::from)
+            .collect(),
+        archived_from: row.get::<_, Option<String>>(first + 4)?,
+        decoded_size: row
+            .get::<_, Option<i64>>(first + 5)?
+            .map(|size| size as u64),
+        sha256: row.get::<_, Option<String>>(first + 6)?,
+    })
+}
+
+/// Opens the state store of the output directory `dir` (kept in its `.fastdl/` directory)
+/// The sqlite store is used when the `sqlite` feature is enabled, otherwise the JSON manifest is used
+///
+/// # Arguments
+/// * `dir`     -   Output directory the state belongs to
+pub fn open_store(dir: &Path) -> Arc<dyn StateStore> {
+    #[cfg(feature = "sqlite")]
+    return Arc::new(
+        SqliteStore::open(&meta_dir::path(dir, SQLITE_FILE))
+            .expect("Failed to open the sqlite state store"),
+    );
+
+    #[cfg(not(feature = "sqlite"))]
+    return Arc::new(
+        JsonStore::open(&meta_dir::path(dir, MANIFEST_FILE))
+            .expect("Failed to open the JSON manifest"),
+    );
+}
+
+#[cfg(all(test, feature = "sqlite"))]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn sqlite_store_commits_on_save() {
+        let dir = std::env::temp_dir().join(format!("fastdl_sqlite_store_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join(SQLITE_FILE);
+        let store = SqliteStore::open(&path).unwrap();
+        let committed = || {
+            rusqlite::Connection::open(&path)
+                .unwrap()
+                .query_row("SELECT COUNT(*) FROM files", [], |r| r.get::<_, i64>(0))
+                .unwrap()
+        };
+
+        store.record_file(
+            "https://fastdl.example.com/maps/ze_a.bsp.bz2",
+            ManifestEntry::default(),
+        );
+        store.record_file(
+            "https://fastdl.example.com/maps/ze_b.bsp.bz2",
+            ManifestEntry::default(),
+        );
+        assert_eq!(committed(), 0);
+
+        store.save().unwrap();
+        assert_eq!(committed(), 2);
+        assert_eq!(store.files().len(), 2);
+
+        drop(store);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,167 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+/// Seconds between two samples of a SpeedMeter
+const SAMPLE_SECS: f64 = 0.5;
+/// Weight of the newest sample in the exponential moving average
+const EMA_ALPHA: f64 = 0.3;
+/// Idle seconds after which a RateLimiter forgets the bytes it already let through
+const IDLE_RESET_SECS: f64 = 1.0;
+
+/// MeterState is the mutable part of a SpeedMeter
+struct MeterState {
+    /// Start of the current sample
+    sample_start: Instant,
+    /// Bytes transferred during the current sample
+    sample_bytes: u64,
+    /// Smoothed speed in bytes per second
+    ema: f64,
+}
+
+/// SpeedMeter smooths a transfer speed with an exponential moving average
+pub struct SpeedMeter {
+    state: Mutex<MeterState>,
+}
+
+impl SpeedMeter {
+    /// Returns a SpeedMeter that has not seen any bytes
+    pub fn new() -> Self {
+        Self {
+            state: Mutex::new(MeterState {
+                sample_start: Instant::now(),
+                sample_bytes: 0,
+                ema: 0.0,
+            }),
+        }
+    }
+
+    /// Records `n` transferred bytes
+    pub fn record(&self, n: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.sample_bytes += n;
+
+        let elapsed = state.sample_start.elapsed().as_secs_f64();
+        if elapsed >= SAMPLE_SECS {
+            let speed = state.sample_bytes as f64 / elapsed;
+
+            state.ema = if state.ema == 0.0 {
+                speed
+            } else {
+                EMA_ALPHA * speed + (1.0 - EMA_ALPHA) * state.ema
+            };
+            state.sample_start = Instant::now();
+            state.sample_bytes = 0;
+        }
+    }
+
+    /// Returns the smoothed speed in bytes per second
+    pub fn speed(&self) -> f64 {
+        self.state.lock().unwrap().ema
+    }
+}
+
+impl Default for SpeedMeter {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// RateLimiter caps the number of bytes per second that go through it
+pub struct RateLimiter {
+    /// Bytes per second
+    rate: u64,
+    /// Start of the current window and the bytes let through since then
+    window: Mutex<(Instant, u64)>,
+}
+
+impl RateLimiter {
+    /// Returns a RateLimiter letting `rate` bytes per second through
+    pub fn new(rate: u64) -> Self {
+        Self {
+            rate,
+            window: Mutex::new((Instant::now(), 0)),
+        }
+    }
+
+    /// Accounts for `n` bytes and sleeps until they fit in the rate
+    pub fn consume(&self, n: u64) {
+        let wait = {
+            let mut window = self.window.lock().unwrap();
+            let elapsed = window.0.elapsed().as_secs_f64();
+            let expected = window.1 as f64 / self.rate as f64;
+
+            // Don't let an idle limiter build up a burst
+            if elapsed > expected + IDLE_RESET_SECS {
+                *window = (Instant::now(), 0);
+            }
+
+            window.1 += n;
+            Duration::from_secs_f64(window.1 as f64 / self.rate as f64)
+                .checked_sub(window.0.elapsed())
+        };
+
+        if let Some(wait) = wait {
+            std::thread::sleep(wait);
+        }
+    }
+}
+
+/// Throttle applies the global and per-host speed caps and measures the aggregate speed
+pub struct Throttle {
+    /// Cap shared by every download (`--limit-rate`)
+    global: Option<RateLimiter>,
+    /// Caps of single hosts (`--host-limit`)
+    hosts: HashMap<String, RateLimiter>,
+    /// Smoothed speed of every download combined
+    pub aggregate: SpeedMeter,
+}
+
+impl Throttle {
+    /// Returns a Throttle with the given caps (bytes per second)
+    ///
+    /// # Arguments
+    /// * `global`  -   Cap shared by every download
+    /// * `hosts`   -   Host -> cap of that host
+    pub fn new(global: Option<u64>, hosts: &HashMap<String, u64>) -> Self {
+        Self {
+            global: global.map(RateLimiter::new),
+            hosts: hosts
+                .iter()
+                .map(|(host, rate)| (host.clone(), RateLimiter::new(*rate)))
+                .collect(),
+            aggregate: SpeedMeter::new(),
+        }
+    }
+
+    /// Accounts for `n` bytes downloaded from `host`, sleeping if a cap is exceeded
+    pub fn consume(&self, host: &str, n: u64) {
+        self.aggregate.record(n);
+
+        if let Some(limiter) = self.hosts.get(host) {
+            limiter.consume(n);
+        }
+        if let Some(limiter) = &self.global {
+            limiter.consume(n);
+        }
+    }
+}
+
+/// Parses a rate such as "500K", "2M" or "1024" into bytes per second (also used for sizes like "1G")
+pub fn parse_rate(rate: &str) -> Option<u64> {
+    let rate = rate.trim();
+    let (number, multiplier) = match rate.chars().last()?.to_ascii_uppercase() {
+        'K' => (&rate[..rate.len() - 1], 1024),
+        'M' => (&rate[..rate.len() - 1], 1024 * 1024),
+        'G' => (&rate[..rate.len() - 1], 1024 * 1024 * 1024),
+        _ => (rate, 1),
+    };
+
+    number
+        .parse::<f64>()
+        .ok()
+        .filter(|n| *n > 0.0)
+        .map(|n| (n * multiplier as f64) as u64)
+}
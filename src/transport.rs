@@ -0,0 +1,191 @@
+use crate::{backoff::Backoff, fingerprint, net};
+use reqwest::{
+    blocking::{Client, Response},
+    StatusCode, Version,
+};
+use std::{collections::BTreeMap, sync::Mutex};
+
+/// Transport sends the download requests and counts which HTTP version each transfer used
+/// With the `http3` feature, hosts that advertise `h3` in `Alt-Svc` are downloaded over QUIC,
+/// falling back to HTTP/1.1 or HTTP/2 when QUIC fails
+pub struct Transport {
+    /// Client used for HTTP/1.1 and HTTP/2
+    client: Client,
+    /// Client used for HTTP/3 and the runtime that drives it
+    #[cfg(feature = "http3")]
+    h3: (tokio::runtime::Runtime, reqwest::Client),
+    /// Host -> whether HTTP/3 works on it (hosts are only tried once they advertise it)
+    #[cfg(feature = "http3")]
+    h3_hosts: Mutex<std::collections::HashMap<String, bool>>,
+    /// HTTP version -> number of transfers that used it
+    versions: Mutex<BTreeMap<String, usize>>,
+    /// Pauses the requests to hosts that rate-limit them
+    backoff: Backoff,
+}
+
+/// Returns the host of `url` (empty if it has none)
+fn host_of(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+impl Transport {
+    /// Returns a Transport with its clients built
+    pub fn new() -> Self {
+        Self {
+            client: net::client(),
+            #[cfg(feature = "http3")]
+            h3: (
+                tokio::runtime::Builder::new_multi_thread()
+                    .worker_threads(2)
+                    .enable_all()
+                    .build()
+                    .expect("Failed to start the HTTP/3 runtime"),
+                net::h3_client().expect("Failed to build the HTTP/3 client"),
+            ),
+            #[cfg(feature = "http3")]
+            h3_hosts: Mutex::new(std::collections::HashMap::new()),
+            versions: Mutex::new(BTreeMap::new()),
+            backoff: Backoff::new(),
+        }
+    }
+
+    /// Sends a GET request to `url` over the best protocol the host supports
+    /// Waits first while the host is rate-limited (429 or 503)
+    ///
+    /// # Arguments
+    /// * `url`     -   Url that is downloaded
+    pub fn get(&self, url: &str) -> reqwest::Result<Response> {
+        let host = host_of(url);
+        self.backoff.wait(&host);
+
+        #[cfg(feature = "http3")]
+        if let Some(resp) = self.get_h3(url) {
+            self.backoff.observe(&host, &resp);
+            self.count(resp.version());
+            return Ok(resp);
+        }
+
+        let resp = self.client.get(url).send()?;
+
+        #[cfg(feature = "http3")]
+        self.remember_alt_svc(&resp);
+
+        self.backoff.observe(&host, &resp);
+        self.count(resp.version());
+        Ok(resp)
+    }
+
+    /// Sends a GET request for the bytes `start..=end` of `url`
+    /// Ranged requests are rare, so they always go over HTTP/1.1 or HTTP/2
+    ///
+    /// # Arguments
+    /// * `url`     -   Url that is downloaded
+    /// * `start`   -   Offset of the first byte
+    /// * `end`     -   Offset of the last byte (inclusive)
+    pub fn get_range(&self, url: &str, start: u64, end: u64) -> reqwest::Result<Response> {
+        let host = host_of(url);
+        self.backoff.wait(&host);
+
+        let resp = self
+            .client
+            .get(url)
+            .header(reqwest::header::RANGE, format!("bytes={}-{}", start, end))
+            .send()?;
+
+        // A full response means the host ignores ranges, remembered for the next runs
+        if resp.status().is_success() {
+            fingerprint::record_ranges(&host, resp.status() == StatusCode::PARTIAL_CONTENT);
+        }
+
+        self.backoff.observe(&host, &resp);
+        self.count(resp.version());
+        Ok(resp)
+    }
+
+    /// Sends the request over HTTP/3 if the host advertised it and it did not fail before
+    /// Returns None (and stops trying the host) if QUIC fails
+    #[cfg(feature = "http3")]
+    fn get_h3(&self, url: &str) -> Option<Response> {
+        let host = reqwest::Url::parse(url).ok()?.host_str()?.to_string();
+
+        if self.h3_hosts.lock().unwrap().get(&host) != Some(&true) {
+            return None;
+        }
+
+        // The body is read on the runtime, the blocking response is built from it
+        let (runtime, client) = &self.h3;
+        let resp = runtime.block_on(async {
+            let resp = client.get(url).send().await.ok()?;
+            let mut builder = http::Response::builder()
+                .status(resp.status())
+                .version(resp.version());
+            for (name, value) in resp.headers() {
+                builder = builder.header(name, value);
+            }
+            let body = resp.bytes().await.ok()?;
+
+            builder.body(body).ok()
+        });
+
+        match resp {
+            Some(resp) => Some(Response::from(resp)),
+            None => {
+                self.h3_hosts.lock().unwrap().insert(host, false);
+                None
+            }
+        }
+    }
+
+    /// Marks the host of `resp` as HTTP/3 capable if it advertises `h3` in `Alt-Svc`
+    #[cfg(feature = "http3")]
+    fn remember_alt_svc(&self, resp: &Response) {
+        let advertises_h3 = resp
+            .headers()
+            .get(reqwest::header::ALT_SVC)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.split(',').any(|alt| alt.trim().starts_with("h3")));
+
+        if let Some(host) = resp.url().host_str().filter(|_| advertises_h3) {
+            // A host that failed over QUIC stays on TCP
+            self.h3_hosts
+                .lock()
+                .unwrap()
+                .entry(host.to_string())
+                .or_insert(true);
+        }
+    }
+
+    /// Counts one transfer over `version`
+    fn count(&self, version: Version) {
+        *self
+            .versions
+            .lock()
+            .unwrap()
+            .entry(format!("{:?}", version))
+            .or_default() += 1;
+    }
+
+    /// Returns how many transfers used each HTTP version (e.g. "HTTP/1.1: 120, HTTP/3.0: 30")
+    pub fn summary(&self) -> String {
+        let versions = self.versions.lock().unwrap();
+
+        if versions.is_empty() {
+            return String::from("no transfers");
+        }
+
+        versions
+            .iter()
+            .map(|(version, count)| format!("{}: {}", version, count))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+impl Default for Transport {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{config::Config, durability, fd_limit, net, schema, state_store::unix_now};
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use serde::{Deserialize, Serialize};
+use std::{
+    io::Read,
+    path::Path,
+    sync::OnceLock,
+    time::{Duration, Instant},
+};
+
+/// Name of the measured defaults, kept in the directory the program is started in
+pub const TUNING_FILE: &str = "fastdl_tuning.json";
+/// Time the body of the fastdl url is read for at most while measuring the link
+const PROBE_TIME: Duration = Duration::from_secs(3);
+/// Latency that adds one download worker (waiting on a slow link is hidden by more requests in flight)
+const LATENCY_PER_WORKER: u64 = 25;
+/// Fewest download workers picked by the measurement
+const MIN_DOWNLOAD_WORKERS: usize = 2;
+/// Most download workers picked by the measurement
+const MAX_DOWNLOAD_WORKERS: usize = 32;
+
+/// Tuning is the download and decode parallelism picked from the machine and the link (versioned by `schema::TUNING`)
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct Tuning {
+    /// Version of the format the tuning was written in
+    #[serde(default)]
+    schema_version: u32,
+    /// Number of cores of the machine
+    pub cores: usize,
+    /// Time to the first byte of the fastdl url in milliseconds (None if it was not measured)
+    pub latency_ms: Option<u64>,
+    /// Throughput of a single connection in Mbit/s (None if it was not measured)
+    pub mbps: Option<f64>,
+    /// Files downloaded at once
+    pub download_workers: usize,
+    /// Files decoded at once
+    pub decode_workers: usize,
+    /// Unix time of the measurement (0 if it was not measured)
+    pub probed_at: u64,
+}
+
+impl Tuning {
+    /// Returns the tuning of a machine whose link was not measured: a worker per core for both stages
+    fn unmeasured() -> Self {
+        let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+
+        Self {
+            cores,
+            download_workers: cores.clamp(MIN_DOWNLOAD_WORKERS, MAX_DOWNLOAD_WORKERS),
+            decode_workers: cores,
+            ..Self::default()
+        }
+    }
+
+    /// Returns the tuning measured on the fastdl url `url` (None if it can't be reached)
+    /// Decoding is bound by the CPU, so it gets a worker per core, downloading is bound by the latency,
+    /// so it gets more workers on a slow link to keep the bandwidth busy
+    ///
+    /// # Arguments
+    /// * `url` -   Fastdl url whose listing is downloaded
+    fn measure(url: &str) -> Option<Self> {
+        let start = Instant::now();
+        let mut resp = net::get(url).ok()?;
+        let latency_ms = start.elapsed().as_millis() as u64;
+
+        let body_start = Instant::now();
+        let mut buf = vec![0u8; 64 * 1024];
+        let mut bytes = 0;
+        while body_start.elapsed() < PROBE_TIME {
+            match resp.read(&mut buf) {
+                Ok(0) | Err(_) => break,
+                Ok(n) => bytes += n,
+            }
+        }
+        let secs = body_start.elapsed().as_secs_f64().max(0.001);
+
+        let download_workers = (MIN_DOWNLOAD_WORKERS + (latency_ms / LATENCY_PER_WORKER) as usize)
+            .clamp(MIN_DOWNLOAD_WORKERS, MAX_DOWNLOAD_WORKERS);
+
+        Some(Self {
+            latency_ms: Some(latency_ms),
+            mbps: Some(bytes as f64 * 8.0 / secs / 1_000_000.0),
+            download_workers,
+            probed_at: unix_now(),
+            ..Self::unmeasured()
+        })
+    }
+
+    /// Writes the tuning to `path`
+    fn save(&mut self, path: &Path) -> std::io::Result<()> {
+        self.schema_version = schema::TUNING.version();
+        durability::write_atomic(path, serde_json::to_string_pretty(self)?.as_bytes())
+    }
+}
+
+/// Returns the tuning kept in `dir` (a worker per core if there is none or it can't be read)
+///
+/// # Arguments
+/// * `dir`     -   Directory the tuning is kept in
+pub fn stored(dir: &Path) -> Tuning {
+    schema::TUNING
+        .load::<Tuning>(&dir.join(TUNING_FILE))
+        .ok()
+        .flatten()
+        .unwrap_or_else(Tuning::unmeasured)
+}
+
+/// Pools is where the downloads and decodes run
+struct Pools {
+    /// Workers downloading files
+    download: ThreadPool,
+    /// Workers decoding files
+    decode: ThreadPool,
+    /// Listings fetched at once
+    crawl_workers: usize,
+}
+
+/// Pools of the run, set once by `init`
+static POOLS: OnceLock<Pools> = OnceLock::new();
+
+/// Returns pools of `download` and `decode` workers
+fn pools(download: usize, decode: usize) -> Pools {
+    let pool = |workers: usize| {
+        ThreadPoolBuilder::new()
+            .num_threads(workers)
+            .build()
+            .expect("Failed to start the workers")
+    };
+
+    Pools {
+        download: pool(download),
+        decode: pool(decode),
+        crawl_workers: download,
+    }
+}
+
+/// Picks the download and decode parallelism of the run
+/// The first run measures the link on `url` and keeps the result in `fastdl_tuning.json`, later runs
+/// reuse it (`--retune` measures again). `--download-workers` and `--decode-workers` override it,
+/// `--compat` uses one worker each, and neither gets more workers than the open files limit allows
+///
+/// # Arguments
+/// * `config`  -   Command line options
+/// * `dir`     -   Directory the tuning is kept in
+/// * `url`     -   Fastdl url the link is measured on (None to only use a stored tuning)
+pub fn init(config: &Config, dir: &Path, url: Option<&str>) -> Result<(), String> {
+    let path = dir.join(TUNING_FILE);
+    let stored = schema::TUNING.load::<Tuning>(&path)?;
+
+    let tuning = match (stored, url) {
+        (Some(tuning), _) if !config.retune => tuning,
+        (stored, Some(url)) => match Tuning::measure(url) {
+            Some(mut tuning) => {
+                tuning
+                    .save(&path)
+                    .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+                println!(
+                    "Measured {} ms latency and {:.1} Mbit/s on {} cores: {} download and {} decode workers",
+                    tuning.latency_ms.unwrap_or_default(),
+                    tuning.mbps.unwrap_or_default(),
+                    tuning.cores,
+                    tuning.download_workers,
+                    tuning.decode_workers
+                );
+                tuning
+            }
+            None => stored.unwrap_or_else(Tuning::unmeasured),
+        },
+        (stored, None) => stored.unwrap_or_else(Tuning::unmeasured),
+    };
+
+    let (download, decode) = if config.compat {
+        (1, 1)
+    } else {
+        (
+            config.download_workers.unwrap_or(tuning.download_workers),
+            config.decode_workers.unwrap_or(tuning.decode_workers),
+        )
+    };
+    let _ = POOLS.set(pools(
+        download.clamp(1, fd_limit::workers()),
+        decode.clamp(1, fd_limit::workers()),
+    ));
+
+    Ok(())
+}
+
+/// Returns the pools of the run (a worker per core if `init` was not called)
+fn get() -> &'static Pools {
+    POOLS.get_or_init(|| {
+        let tuning = Tuning::unmeasured();
+        pools(tuning.download_workers, tuning.decode_workers)
+    })
+}
+
+/// Returns the pool the downloads run in
+pub fn download_pool() -> &'static ThreadPool {
+    &get().download
+}
+
+/// Returns the pool the decodes run in
+pub fn decode_pool() -> &'static ThreadPool {
+    &get().decode
+}
+
+/// Returns the number of listings the crawl fetches at once
+pub fn crawl_workers() -> usize {
+    get().crawl_workers
+}
@@ -0,0 +1,138 @@
+use crate::crawl_stats::CrawlStats;
+use crate::metrics::METRICS;
+use crate::MB_SIZE;
+use std::{
+    io::IsTerminal,
+    str::FromStr,
+    sync::{atomic::Ordering, Arc, Mutex, OnceLock},
+    time::{Duration, Instant},
+};
+
+/// ProgressMode is how progress is shown (`--progress <plain|fancy|none>`)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProgressMode {
+    /// Cursor-addressed console GUI
+    Fancy,
+    /// One status line every few seconds (dumb terminals, CI logs, pipes)
+    Plain,
+    /// No progress at all, only the final summary
+    None,
+}
+
+impl FromStr for ProgressMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fancy" => Ok(Self::Fancy),
+            "plain" => Ok(Self::Plain),
+            "none" => Ok(Self::None),
+            _ => Err(format!(
+                "Unknown progress mode: {} (expected plain, fancy or none)",
+                s
+            )),
+        }
+    }
+}
+
+/// Rows the console GUI draws on (the summary ends at row 44)
+pub const MIN_ROWS: u16 = 45;
+/// Columns the console GUI needs before its labels and values wrap
+pub const MIN_COLUMNS: u16 = 80;
+
+/// Progress mode picked when the program started
+static MODE: OnceLock<ProgressMode> = OnceLock::new();
+/// Stage shown in the plain status lines
+static STAGE: Mutex<&str> = Mutex::new("startup");
+
+/// Picks the progress mode: the requested one, otherwise fancy only on a real terminal
+///
+/// # Arguments
+/// * `requested`           -   Mode passed with `--progress`
+/// * `stdout_is_taken`     -   Something else (e.g. the NDJSON events) is written to stdout
+pub fn init(requested: Option<ProgressMode>, stdout_is_taken: bool) -> ProgressMode {
+    let dumb = std::env::var("TERM").is_ok_and(|term| term == "dumb");
+
+    let mode = requested.unwrap_or(if stdout_is_taken {
+        ProgressMode::None
+    } else if dumb || !std::io::stdout().is_terminal() {
+        ProgressMode::Plain
+    } else if let Some((columns, rows)) = too_small() {
+        // The fixed rows of the GUI would overlap and wrap into an unreadable mess
+        println!(
+            "The terminal is {}x{}, the progress GUI needs at least {}x{}: showing status lines instead (--progress fancy forces it)",
+            columns, rows, MIN_COLUMNS, MIN_ROWS
+        );
+        ProgressMode::Plain
+    } else {
+        ProgressMode::Fancy
+    });
+
+    *MODE.get_or_init(|| mode)
+}
+
+/// Returns the size of the terminal (columns, rows) if it's smaller than the console GUI needs
+/// None if it's large enough or its size can't be read (e.g. some Windows terminals), the GUI is tried then
+fn too_small() -> Option<(u16, u16)> {
+    let (terminal_size::Width(columns), terminal_size::Height(rows)) =
+        terminal_size::terminal_size()?;
+
+    (columns < MIN_COLUMNS || rows < MIN_ROWS).then_some((columns, rows))
+}
+
+/// Returns true if the cursor-addressed console GUI is drawn
+pub fn fancy() -> bool {
+    MODE.get().is_none_or(|mode| *mode == ProgressMode::Fancy)
+}
+
+/// Returns the escape sequence moving the cursor to (`x`, `y`), or nothing if the GUI is not drawn
+///
+/// # Arguments
+/// * `x`   -   Column
+/// * `y`   -   Row
+pub fn goto(x: i32, y: i32) -> String {
+    if fancy() {
+        term_cursor::Goto(x, y).to_string()
+    } else {
+        String::new()
+    }
+}
+
+/// Records the stage shown in the plain status lines
+///
+/// # Arguments
+/// * `stage`   -   Name of the stage (crawl, download, decode, ...)
+pub fn set_stage(stage: &'static str) {
+    *STAGE.lock().unwrap() = stage;
+}
+
+/// Returns the stage of the program (crawl, download, decode, ...)
+pub fn stage() -> &'static str {
+    *STAGE.lock().unwrap()
+}
+
+/// Prints a single-line status summary every `interval` seconds (plain mode only)
+///
+/// # Arguments
+/// * `interval`    -   Seconds between two status lines
+/// * `crawl_stats` -   Live crawl metrics
+pub fn spawn_status(interval: u64, crawl_stats: Arc<CrawlStats>) {
+    if MODE.get() != Some(&ProgressMode::Plain) {
+        return;
+    }
+
+    let start = Instant::now();
+    std::thread::spawn(move || loop {
+        std::thread::sleep(Duration::from_secs(interval.max(1)));
+
+        println!(
+            "[{:>6}s] {:<8} | {} | {} files ({:.2} MB) downloaded | {} decode failures",
+            start.elapsed().as_secs(),
+            STAGE.lock().unwrap(),
+            crawl_stats.summary(),
+            METRICS.files_downloaded.load(Ordering::Relaxed),
+            METRICS.bytes_downloaded.load(Ordering::Relaxed) as f32 / MB_SIZE as f32,
+            METRICS.decode_failures.load(Ordering::Relaxed),
+        );
+    });
+}
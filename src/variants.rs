@@ -0,0 +1,53 @@
+use crate::files::RemoteFile;
+use std::{collections::HashSet, str::FromStr};
+
+/// Variant is the copy of a file that is downloaded when a directory serves both
+/// e.g. `ze_map.bsp` and `ze_map.bsp.bz2` next to each other
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Variant {
+    /// Download the `.bz2` copy (smaller download, decoded afterwards)
+    #[default]
+    Bz2,
+    /// Download the already extracted copy (no decoding needed)
+    Raw,
+}
+
+impl FromStr for Variant {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "bz2" => Ok(Self::Bz2),
+            "raw" => Ok(Self::Raw),
+            _ => Err(format!("Unknown variant: {} (expected bz2 or raw)", s)),
+        }
+    }
+}
+
+/// Removes one copy of every file that is served both raw and as `.bz2`
+/// Returns the number of links that were removed
+///
+/// # Arguments
+/// * `links`   -   Files found during the crawl
+/// * `prefer`  -   Variant that is kept
+pub fn drop_duplicate_variants(links: &mut HashSet<RemoteFile>, prefer: Variant) -> usize {
+    // Pair every bz2 link with its raw link (if both were found)
+    let pairs = links
+        .iter()
+        .filter_map(|file| {
+            let raw = file.url.strip_suffix(".bz2")?;
+            links
+                .contains(raw)
+                .then(|| (file.url.clone(), raw.to_string()))
+        })
+        .collect::<Vec<_>>();
+
+    for (bz2, raw) in &pairs {
+        match prefer {
+            Variant::Bz2 => links.remove(raw.as_str()),
+            Variant::Raw => links.remove(bz2.as_str()),
+        };
+    }
+
+    pairs.len()
+}
@@ -0,0 +1,187 @@
+use crate::files::RemoteFile;
+use crate::mapname;
+use crate::report::map_name;
+use crate::scan::{self, ScanPolicy};
+use std::{
+    collections::{HashMap, HashSet},
+    fs,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+/// MapVersion is the version of a map, ordered from oldest to newest
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct MapVersion {
+    /// Every number of the version suffix (e.g. "v3_2" -> [3, 2])
+    pub numbers: Vec<u32>,
+    /// Number of suffix words (a "_fix" comes after the release it fixes)
+    pub words: usize,
+    /// Modification time, used when the names alone can't tell which map is newer
+    pub mtime: u64,
+}
+
+/// Splits a map name into its family and its version
+/// e.g. "ze_foo_bar_v3_fix" -> ("ze_foo_bar", [3] with 1 word)
+///
+/// # Arguments
+/// * `map`     -   Map name without any extension
+/// * `mtime`   -   Modification time of the map (0 if unknown)
+pub fn split_version(map: &str, mtime: u64) -> (String, MapVersion) {
+    let name = mapname::parse(map);
+    let version = MapVersion {
+        numbers: name.numbers(),
+        words: name.words(),
+        mtime,
+    };
+
+    (name.family, version)
+}
+
+/// Returns the names of every map that is not one of the `keep` newest versions of its family
+///
+/// # Arguments
+/// * `maps`    -   Map names and their modification time
+/// * `keep`    -   Number of versions kept per family
+pub fn outdated_maps<'a, I: IntoIterator<Item = (&'a str, u64)>>(
+    maps: I,
+    keep: usize,
+) -> HashSet<String> {
+    let mut families = HashMap::<String, Vec<(MapVersion, &str)>>::new();
+
+    for (map, mtime) in maps {
+        let (family, version) = split_version(map, mtime);
+        families.entry(family).or_default().push((version, map));
+    }
+
+    families
+        .into_values()
+        .flat_map(|mut versions| {
+            // Newest first
+            versions.sort_by(|a, b| b.cmp(a));
+            versions
+                .into_iter()
+                .skip(keep)
+                .map(|(_, map)| map.to_string())
+                .collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+/// Removes the links of maps that are not one of the `keep` newest versions of their family
+/// Returns the number of links that were removed
+///
+/// # Arguments
+/// * `links`   -   Files found during the crawl
+/// * `keep`    -   Number of versions kept per family
+pub fn retain_newest_links(links: &mut HashSet<RemoteFile>, keep: usize) -> usize {
+    // The modification time breaks ties between versions the names can't order
+    let names = links
+        .iter()
+        .filter_map(|file| Some((map_name(&file.url)?, file.mtime.unwrap_or(0))))
+        .collect::<Vec<_>>();
+    let outdated = outdated_maps(
+        names.iter().map(|(name, mtime)| (name.as_str(), *mtime)),
+        keep,
+    );
+    let len = links.len();
+
+    links.retain(|file| map_name(&file.url).is_none_or(|name| !outdated.contains(&name)));
+
+    len - links.len()
+}
+
+/// Deletes every local map that is not one of the `keep` newest versions of its family
+/// Versions are only compared inside of the same directory, and the state directory (with the hardlinked
+/// snapshot copies) is never scanned, so a copy of a map never counts as another version of it
+/// Returns the paths that were deleted
+///
+/// # Arguments
+/// * `dir`     -   Directory of the local mirror
+/// * `keep`    -   Number of versions kept per family
+/// * `policy`  -   Which symlinks and hidden entries are walked into
+pub fn prune_local(dir: &Path, keep: usize, policy: ScanPolicy) -> Vec<PathBuf> {
+    let mut by_dir = HashMap::<PathBuf, Vec<(String, u64, PathBuf)>>::new();
+
+    for path in scan::find_files(dir, policy, |name| name.ends_with(".bsp")) {
+        let Some(name) = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .and_then(|name| name.strip_suffix(".bsp"))
+            .map(str::to_string)
+        else {
+            continue;
+        };
+        let mtime = fs::metadata(&path)
+            .and_then(|meta| meta.modified())
+            .ok()
+            .and_then(|mtime| mtime.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |mtime| mtime.as_secs());
+        let parent = path.parent().unwrap_or(dir).to_path_buf();
+
+        by_dir.entry(parent).or_default().push((name, mtime, path));
+    }
+
+    by_dir
+        .into_values()
+        .flat_map(|maps| {
+            let outdated = outdated_maps(
+                maps.iter().map(|(name, mtime, _)| (name.as_str(), *mtime)),
+                keep,
+            );
+
+            maps.into_iter()
+                .filter(move |(name, _, _)| outdated.contains(name))
+        })
+        .filter(|(_, _, path)| fs::remove_file(path).is_ok())
+        .map(|(_, _, path)| path)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{meta_dir::META_DIR, snapshot::SNAPSHOTS_DIR};
+
+    #[test]
+    fn orders_versions() {
+        let outdated = outdated_maps(
+            [
+                ("ze_foo_v1", 0),
+                ("ze_foo_v2", 0),
+                ("ze_foo_v2_fix", 0),
+                ("ze_bar_v1", 0),
+            ],
+            1,
+        );
+        assert_eq!(
+            outdated,
+            HashSet::from(["ze_foo_v1".to_string(), "ze_foo_v2".to_string()])
+        );
+    }
+
+    #[test]
+    fn prune_ignores_snapshot_copies() {
+        let dir = std::env::temp_dir().join(format!("fastdl_prune_{}", std::process::id()));
+        let maps = dir.join("download").join("maps");
+        let snapshot = dir
+            .join(META_DIR)
+            .join(SNAPSHOTS_DIR)
+            .join("1700000000")
+            .join("download")
+            .join("maps");
+        fs::create_dir_all(&maps).unwrap();
+        fs::create_dir_all(&snapshot).unwrap();
+
+        fs::write(maps.join("ze_foo_v1.bsp"), b"v1").unwrap();
+        fs::write(maps.join("ze_foo_v2.bsp"), b"v2").unwrap();
+        fs::hard_link(maps.join("ze_foo_v2.bsp"), snapshot.join("ze_foo_v2.bsp")).unwrap();
+
+        let deleted = prune_local(&dir, 1, ScanPolicy::default());
+
+        assert_eq!(deleted, vec![maps.join("ze_foo_v1.bsp")]);
+        assert!(maps.join("ze_foo_v2.bsp").exists());
+        assert!(snapshot.join("ze_foo_v2.bsp").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
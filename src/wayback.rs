@@ -0,0 +1,56 @@
+use reqwest::blocking::Client;
+use serde::Deserialize;
+
+/// Endpoint of the Wayback Machine's availability API
+const AVAILABILITY_URL: &str = "https://archive.org/wayback/available";
+
+/// Availability is the answer of the availability API
+#[derive(Debug, Default, Deserialize)]
+struct Availability {
+    #[serde(default)]
+    archived_snapshots: Snapshots,
+}
+
+/// Snapshots holds the snapshot closest to the requested time
+#[derive(Debug, Default, Deserialize)]
+struct Snapshots {
+    closest: Option<Snapshot>,
+}
+
+/// Snapshot is an archived copy of a url
+#[derive(Debug, Deserialize)]
+struct Snapshot {
+    /// False if the snapshot can't be played back
+    available: bool,
+    /// HTTP status the url had when it was archived
+    status: String,
+    /// Time the url was archived ("20190412083311")
+    timestamp: String,
+}
+
+/// Returns the url of the newest archived copy of `url` in the Wayback Machine, None if it was never archived
+/// The url serves the original bytes ("id_"), not the archive's replay page with rewritten links
+///
+/// # Arguments
+/// * `client`  -   Client used to query the availability API
+/// * `url`     -   Link of the file that is no longer on the fastdl
+pub fn snapshot(client: &Client, url: &str) -> Option<String> {
+    let availability = client
+        .get(AVAILABILITY_URL)
+        .query(&[("url", url)])
+        .send()
+        .and_then(|resp| resp.error_for_status())
+        .ok()
+        .and_then(|resp| serde_json::from_reader::<_, Availability>(resp).ok())?;
+
+    // Error pages of the fastdl get archived too
+    let snapshot = availability
+        .archived_snapshots
+        .closest
+        .filter(|snapshot| snapshot.available && snapshot.status == "200")?;
+
+    Some(format!(
+        "https://web.archive.org/web/{}id_/{}",
+        snapshot.timestamp, url
+    ))
+}
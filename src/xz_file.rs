@@ -0,0 +1,33 @@
+use crate::decoder::{self, Decoder};
+use std::{cell::Cell, error::Error, fs::File, io::Write};
+use xz2::read::XzDecoder;
+
+/// XzFile stores the xz decoder which will decode the original file
+pub struct XzFile {
+    /// Decoder involved with doing most of the xz decoding
+    decoder: Cell<XzDecoder<File>>,
+}
+
+impl XzFile {
+    /// Returns a XzFile object which can decode the file
+    ///
+    /// # Arguments
+    /// * `f`   -   The xz file that would be read after you opened it
+    pub fn new(f: File) -> Self {
+        Self {
+            // Multi-stream so concatenated .xz files decode completely (like MultiBzDecoder)
+            decoder: Cell::new(XzDecoder::new_multi_decoder(f)),
+        }
+    }
+}
+
+impl Decoder for XzFile {
+    /// Decodes the file into `output` chunk by chunk
+    fn decode_into(
+        &mut self,
+        output: &mut dyn Write,
+        on_chunk: &mut dyn FnMut(u64),
+    ) -> Result<u64, Box<dyn Error>> {
+        decoder::copy_chunks(self.decoder.get_mut(), output, on_chunk)
+    }
+}
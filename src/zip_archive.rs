@@ -0,0 +1,246 @@
+#[cfg(feature = "zip")]
+use crate::{
+    fd_limit,
+    scan::{self, ScanPolicy},
+};
+use std::fmt;
+#[cfg(feature = "zip")]
+use std::{
+    fs::{self, File},
+    io,
+    path::{Path, PathBuf},
+};
+
+/// ArchivePasswords are the passwords of protected zip archives (`--archive-password <host|pattern> <password>`)
+/// A rule with a `*` is matched against the whole download link, any other against its host
+#[derive(Clone, Default)]
+pub struct ArchivePasswords {
+    /// Host or link pattern -> password, in the order they were given
+    rules: Vec<(String, String)>,
+}
+
+/// Passwords never end up in crash reports, which print the config
+impl fmt::Debug for ArchivePasswords {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_map()
+            .entries(
+                self.rules
+                    .iter()
+                    .map(|(pattern, _)| (pattern, "<redacted>")),
+            )
+            .finish()
+    }
+}
+
+/// Returns true if `text` matches `pattern`, where `*` stands for any (possibly empty) run of characters
+#[cfg(feature = "zip")]
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts = parts.collect::<Vec<_>>();
+    for (i, part) in parts.iter().enumerate() {
+        if i == parts.len() - 1 {
+            return rest.ends_with(part);
+        }
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.is_empty()
+}
+
+impl ArchivePasswords {
+    /// Adds the password of the archives of a host or matching a link pattern
+    ///
+    /// # Arguments
+    /// * `pattern`     -   Host (e.g. "dl.example.com") or link pattern (e.g. "*/maps/*.zip")
+    /// * `password`    -   Password of the archives
+    pub fn insert(&mut self, pattern: &str, password: &str) {
+        self.rules
+            .push((pattern.to_lowercase(), password.to_string()));
+    }
+
+    /// Returns true if no password was given
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Returns the password of the archive downloaded from `link` (the first matching rule)
+    ///
+    /// # Arguments
+    /// * `link`    -   Download link of the archive
+    #[cfg(feature = "zip")]
+    pub fn lookup(&self, link: &str) -> Option<&str> {
+        let link = link.to_lowercase();
+        let host = url::Url::parse(&link)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from));
+
+        self.rules
+            .iter()
+            .find(|(pattern, _)| {
+                if pattern.contains('*') {
+                    wildcard(pattern, &link)
+                } else {
+                    host.as_deref() == Some(pattern.as_str())
+                }
+            })
+            .map(|(_, password)| password.as_str())
+    }
+}
+
+/// Extracts the zip archive at `path` next to itself
+/// Entries already extracted (same size on disk) are skipped, so an archive kept in the mirror is
+/// only extracted again when it changes. Entries escaping the directory of the archive are ignored
+///
+/// # Arguments
+/// * `path`        -   Path of the archive
+/// * `password`    -   Password of the archive (None if it's not protected)
+#[cfg(feature = "zip")]
+fn extract(path: &Path, password: Option<&str>) -> Result<(), String> {
+    let file = fd_limit::retry(|| File::open(path)).map_err(|err| err.to_string())?;
+    let mut archive = zip::ZipArchive::new(file).map_err(|err| err.to_string())?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+
+    for i in 0..archive.len() {
+        let (target, size) = {
+            let entry = archive.by_index_raw(i).map_err(|err| err.to_string())?;
+            if entry.is_dir() {
+                continue;
+            }
+            let Some(name) = entry.enclosed_name() else {
+                continue;
+            };
+            (dir.join(name), entry.size())
+        };
+        if fs::metadata(&target).is_ok_and(|meta| meta.len() == size) {
+            continue;
+        }
+
+        let mut entry = match password {
+            Some(password) => archive
+                .by_index_decrypt(i, password.as_bytes())
+                .map_err(|err| err.to_string())?
+                .map_err(|_| String::from("wrong archive password"))?,
+            None => archive.by_index(i).map_err(|err| match err {
+                zip::result::ZipError::UnsupportedArchive(msg)
+                    if msg == zip::result::ZipError::PASSWORD_REQUIRED =>
+                {
+                    String::from("password-protected, no --archive-password matches it")
+                }
+                err => err.to_string(),
+            })?,
+        };
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+        }
+        let mut output =
+            fd_limit::retry(|| File::create(&target)).map_err(|err| err.to_string())?;
+        io::copy(&mut entry, &mut output).map_err(|err| {
+            let _ = fs::remove_file(&target);
+            err.to_string()
+        })?;
+    }
+
+    Ok(())
+}
+
+/// Extracts every zip archive below `roots`, returning the archives that failed with the reason
+///
+/// # Arguments
+/// * `roots`       -   Directories that are searched
+/// * `scan`        -   Which symlinks and hidden directories are walked into
+/// * `password`    -   Returns the password of the archive at a path (None if none matches)
+#[cfg(feature = "zip")]
+pub fn extract_all(
+    roots: &[PathBuf],
+    scan: ScanPolicy,
+    password: impl Fn(&Path) -> Option<String>,
+) -> Vec<(PathBuf, String)> {
+    let mut failed = Vec::new();
+
+    for root in roots {
+        let archives = scan::find_files(root, scan, |name| name.to_lowercase().ends_with(".zip"));
+        for archive in archives {
+            if let Err(reason) = extract(&archive, password(&archive).as_deref()) {
+                failed.push((archive, reason));
+            }
+        }
+    }
+
+    failed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn redacts_the_passwords() {
+        let mut passwords = ArchivePasswords::default();
+        passwords.insert("dl.example.com", "hunter2");
+
+        let debug = format!("{:?}", passwords);
+        assert!(!debug.contains("hunter2"));
+        assert!(debug.contains("dl.example.com"));
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn matches_wildcards() {
+        assert!(wildcard("*", ""));
+        assert!(wildcard("*.zip", "https://dl.example.com/maps/ze_a.zip"));
+        assert!(wildcard(
+            "*/maps/*.zip",
+            "https://dl.example.com/maps/ze_a.zip"
+        ));
+        assert!(wildcard("https://*/maps/*", "https://dl.example.com/maps/"));
+        assert!(wildcard("*a*a", "banana"));
+        assert!(wildcard("exact", "exact"));
+
+        assert!(!wildcard("exact", "exactly"));
+        assert!(!wildcard(
+            "*.zip",
+            "https://dl.example.com/maps/ze_a.zip.bz2"
+        ));
+        assert!(!wildcard(
+            "*/maps/*.zip",
+            "https://dl.example.com/sound/a.zip"
+        ));
+        assert!(!wildcard("ab*b", "ab"));
+        assert!(!wildcard("*ab*ab", "xab"));
+    }
+
+    #[cfg(feature = "zip")]
+    #[test]
+    fn looks_up_the_first_matching_rule() {
+        let mut passwords = ArchivePasswords::default();
+        passwords.insert("*/private/*.zip", "pattern");
+        passwords.insert("DL.example.com", "host");
+
+        assert_eq!(
+            passwords.lookup("https://dl.example.com/PRIVATE/a.zip"),
+            Some("pattern")
+        );
+        assert_eq!(
+            passwords.lookup("https://dl.example.com/maps/a.zip"),
+            Some("host")
+        );
+        assert_eq!(
+            passwords.lookup("https://other.example.com/maps/a.zip"),
+            None
+        );
+        // A host rule never matches a host that only contains it
+        assert_eq!(
+            passwords.lookup("https://dl.example.com.evil.net/a.zip"),
+            None
+        );
+    }
+}
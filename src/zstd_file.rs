@@ -0,0 +1,36 @@
+use crate::decoder::{self, Decoder};
+use std::{
+    cell::Cell,
+    error::Error,
+    fs::File,
+    io::{self, BufReader, Write},
+};
+
+/// ZstdFile stores the zstd decoder which will decode the original file
+pub struct ZstdFile {
+    /// Decoder involved with doing most of the zstd decoding
+    decoder: Cell<zstd::stream::read::Decoder<'static, BufReader<File>>>,
+}
+
+impl ZstdFile {
+    /// Returns a ZstdFile object which can decode the file
+    ///
+    /// # Arguments
+    /// * `f`   -   The zst file that would be read after you opened it
+    pub fn new(f: File) -> io::Result<Self> {
+        Ok(Self {
+            decoder: Cell::new(zstd::stream::read::Decoder::new(f)?),
+        })
+    }
+}
+
+impl Decoder for ZstdFile {
+    /// Decodes the file into `output` chunk by chunk
+    fn decode_into(
+        &mut self,
+        output: &mut dyn Write,
+        on_chunk: &mut dyn FnMut(u64),
+    ) -> Result<u64, Box<dyn Error>> {
+        decoder::copy_chunks(self.decoder.get_mut(), output, on_chunk)
+    }
+}